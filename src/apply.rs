@@ -0,0 +1,769 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Component, Path, PathBuf, Prefix},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use serde_json::json;
+
+use crate::cfg::{FuxiConfig, get_path_entry};
+use crate::cli::{DEFAULT_OVERWRITE_THRESHOLD, confirm_large};
+use crate::conflict::{ConflictStrategy, files_differ, should_overwrite};
+use crate::copy::{copy_file_or_path, copy_privileged, run_privileged};
+use crate::hardlink::relink;
+use crate::journal::Journal;
+use crate::manifest::ManifestEntry;
+use crate::object_store::hash_file;
+use crate::overlay::{ProfileLayout, join_rel};
+use crate::ownership::{OwnershipMap, restore_ownership};
+use crate::plugin::run_hooks;
+use crate::portable::decode_path;
+use crate::script;
+use crate::secret::{
+    ENCRYPTED_EXTENSIONS, EncryptionBackend, decrypted_name, encrypted_name, encryption_backend,
+};
+use crate::snapshot::{Snapshot, prune_snapshots};
+use crate::style;
+use crate::submodule::clone_submodules;
+use crate::template::{render, template_variables};
+use crate::xattrs::{restore_attributes, strip_quarantine};
+
+pub struct ApplyOptions {
+    pub strategy: ConflictStrategy,
+    pub dry_run: bool,
+    /// Where live files are copied before being overwritten.
+    pub snapshot: Option<Snapshot>,
+    /// Values substituted into template entries.
+    pub variables: HashMap<String, String>,
+    /// Backend used to decrypt secret entries.
+    pub encryption: Option<Box<dyn EncryptionBackend>>,
+    /// Records every live file before it's written.
+    pub journal: Option<Journal>,
+    /// Rehash every restored file and compare it with the backup.
+    pub verify: bool,
+    /// Users and groups restored owners are translated with.
+    pub ownership_map: OwnershipMap,
+}
+
+/// Decrypts and/or renders a repo file into a staging file that keeps the
+/// original's mtime, so it can be compared and copied like any other file.
+fn prepare_source(
+    entry: &ManifestEntry,
+    src_file: &Path,
+    staged: &Path,
+    options: &ApplyOptions,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if !entry.encrypted && !entry.template {
+        return Ok(src_file.to_path_buf());
+    }
+
+    if let Some(parent) = staged.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if entry.encrypted {
+        let backend = options
+            .encryption
+            .as_ref()
+            .ok_or("No encryption backend configured.")?;
+        if src_file.extension().and_then(|e| e.to_str()) != Some(backend.extension()) {
+            return Err(format!(
+                "{} was not encrypted with {}, change 'encryption_backend' in the config.",
+                src_file.display(),
+                backend.name()
+            )
+            .into());
+        }
+        backend
+            .decrypt(src_file, staged)
+            .map_err(|e| format!("Failed to decrypt {}: {}", src_file.display(), e))?;
+    } else {
+        fs::copy(src_file, staged)?;
+    }
+
+    if entry.template {
+        let template = fs::read_to_string(staged)
+            .map_err(|e| format!("Cannot read template {}: {}", src_file.display(), e))?;
+        let rendered = render(&template, &options.variables)
+            .map_err(|e| format!("Failed to render {}: {}", src_file.display(), e))?;
+        fs::write(staged, rendered)?;
+    }
+
+    let modified = fs::metadata(src_file)?.modified()?;
+    fs::File::options()
+        .write(true)
+        .open(staged)?
+        .set_modified(modified)?;
+    Ok(staged.to_path_buf())
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ApplySummary {
+    pub applied: usize,
+    pub skipped: usize,
+    pub missing: usize,
+    /// Files that didn't match the backup after being copied.
+    pub mismatched: usize,
+}
+
+/// `path` moved below `target`, e.g. `/home/me/.zshrc` to
+/// `/mnt/home/me/.zshrc`. A Windows drive becomes a directory, so
+/// `C:\Users\me` ends up in `<target>\C\Users\me`.
+pub fn reroot(target: &Path, path: &Path) -> PathBuf {
+    let mut rerooted = target.to_path_buf();
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => {
+                if let Prefix::Disk(drive) | Prefix::VerbatimDisk(drive) = prefix.kind() {
+                    rerooted.push((drive as char).to_string());
+                }
+            }
+            Component::Normal(part) => rerooted.push(part),
+            // never above the target
+            Component::ParentDir if rerooted != target => {
+                rerooted.pop();
+            }
+            _ => {}
+        }
+    }
+    rerooted
+}
+
+/// The entries with their source paths moved below `target`, for
+/// `apply --target-dir`.
+pub fn retarget(entries: &[ManifestEntry], target: &Path) -> Vec<ManifestEntry> {
+    entries
+        .iter()
+        .map(|entry| ManifestEntry {
+            source: reroot(target, Path::new(&entry.source))
+                .to_string_lossy()
+                .to_string(),
+            ..entry.clone()
+        })
+        .collect()
+}
+
+/// The entries whose paths are active on this machine, moved to the `target`
+/// their path entry restores them to.
+pub fn configured_entries(config: &FuxiConfig, entries: &[ManifestEntry]) -> Vec<ManifestEntry> {
+    let mut configured = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let path_entry = get_path_entry(config, &entry.source);
+        if path_entry.is_some_and(|path_entry| !path_entry.is_active()) {
+            println!("Skipped {}, it's disabled on this machine", entry.source);
+            continue;
+        }
+        match path_entry.and_then(|path_entry| path_entry.target.as_ref()) {
+            Some(target) => {
+                println!("Restoring {} to {}", entry.source, target);
+                configured.push(ManifestEntry {
+                    source: target.clone(),
+                    ..entry.clone()
+                });
+            }
+            None => configured.push(entry.clone()),
+        }
+    }
+    configured
+}
+
+/// Lists every `(repo file, live file)` pair a manifest entry restores.
+pub fn entry_files(
+    layout: &ProfileLayout,
+    entry: &ManifestEntry,
+) -> std::io::Result<Vec<(PathBuf, PathBuf)>> {
+    let dst_path = PathBuf::from(&entry.source);
+    let mut files: Vec<_> = layout
+        .resolve(&entry.repo_path)?
+        .into_iter()
+        .map(|(rel, src)| (decode_path(entry, &rel), src))
+        .collect();
+    if entry.encrypted {
+        if files.is_empty() {
+            for extension in ENCRYPTED_EXTENSIONS {
+                files = layout.resolve(
+                    &encrypted_name(Path::new(&entry.repo_path), extension).to_string_lossy(),
+                )?;
+                if !files.is_empty() {
+                    break;
+                }
+            }
+        } else {
+            // encrypted directories hold `<name>.<extension>` files
+            files = files
+                .into_iter()
+                .filter_map(|(rel, src)| decrypted_name(&rel).map(|rel| (rel, src)))
+                .collect();
+        }
+    }
+    Ok(files
+        .into_iter()
+        .map(|(rel, src)| (src, join_rel(&dst_path, &rel)))
+        .collect())
+}
+
+/// Copies `repo/<profile>/<repo_path>` (merged with this machine's overlay)
+/// back to the source path of each entry.
+pub fn apply_entries(
+    layout: &ProfileLayout,
+    entries: &[ManifestEntry],
+    options: &mut ApplyOptions,
+) -> Result<ApplySummary, Box<dyn std::error::Error>> {
+    let mut summary = ApplySummary::default();
+    let strategy = options.strategy;
+    let dry_run = options.dry_run;
+    // decrypted secrets are staged here: private to the user, and removed
+    // when it's dropped, however the apply ends
+    let staging_dir = tempfile::Builder::new()
+        .prefix(&format!("fuxi-apply-{}-", std::process::id()))
+        .tempdir()?;
+    let mut staged = 0;
+    let mut applied = Vec::new();
+
+    for entry in entries {
+        let stored = layout.exists(&entry.repo_path)
+            || (entry.encrypted
+                && ENCRYPTED_EXTENSIONS.iter().any(|extension| {
+                    layout.exists(
+                        &encrypted_name(Path::new(&entry.repo_path), extension).to_string_lossy(),
+                    )
+                }));
+        if !stored {
+            println!(
+                "{}",
+                style::warning(format!(
+                    "Warning: Backup path does not exist in repository: {}",
+                    layout.root.join(&entry.repo_path).display()
+                ))
+            );
+            summary.missing += 1;
+            continue;
+        }
+
+        let mut applied_files = Vec::new();
+        for (src_file, dst_file) in entry_files(layout, entry)? {
+            staged += 1;
+            let prepared = prepare_source(
+                entry,
+                &src_file,
+                &staging_dir.path().join(staged.to_string()),
+                options,
+            )?;
+
+            if dst_file.is_file() {
+                if !files_differ(&dst_file, &prepared)? {
+                    continue;
+                }
+
+                if dry_run {
+                    println!(
+                        "[Dry Run] {} differs from the backup (strategy: {})",
+                        style::path(dst_file.display()),
+                        strategy
+                    );
+                    continue;
+                }
+
+                if !should_overwrite(strategy, &dst_file, &prepared)? {
+                    println!("Skipped {}", style::path(dst_file.display()));
+                    summary.skipped += 1;
+                    continue;
+                }
+            }
+
+            if !dry_run {
+                if dst_file.is_file()
+                    && let Some(snapshot) = options.snapshot.as_mut()
+                {
+                    snapshot.save(&dst_file)?;
+                }
+                if let Some(journal) = options.journal.as_mut() {
+                    journal.writing(&dst_file)?;
+                }
+                let copied = if entry.sudo {
+                    copy_privileged(&prepared, &dst_file);
+                    false
+                } else {
+                    copy_file_or_path(&prepared, &dst_file)?
+                };
+                if copied {
+                    strip_quarantine(&dst_file);
+                }
+                applied_files.push(dst_file.clone());
+                if let Some(owner) = &entry.owner {
+                    restore_ownership(&dst_file, owner, &options.ownership_map, !copied)?;
+                }
+                println!(
+                    "Applied {} to {}",
+                    style::path(src_file.display()),
+                    style::path(dst_file.display())
+                );
+                // copies left to sudo are checked once they've run
+                if options.verify && copied && hash_file(&prepared)? != hash_file(&dst_file)? {
+                    println!(
+                        "Verification failed: {} doesn't match the backup",
+                        style::path(dst_file.display())
+                    );
+                    summary.mismatched += 1;
+                }
+            } else {
+                println!(
+                    "[Dry Run] Would apply {} to {}",
+                    style::path(src_file.display()),
+                    style::path(dst_file.display())
+                );
+            }
+            summary.applied += 1;
+        }
+        applied.push((entry, applied_files));
+    }
+
+    // the staged files are copied from, so before they're removed
+    run_privileged()?;
+    // after the copies and owners, changing the owner clears capabilities
+    for (entry, files) in &applied {
+        if files.is_empty() {
+            continue;
+        }
+        let relinked = relink(entry)?;
+        if relinked > 0 {
+            println!("Relinked {} hard link(s) in {}", relinked, entry.source);
+        }
+        restore_attributes(&layout.root, entry, files)?;
+    }
+    staging_dir.close()?;
+    Ok(summary)
+}
+
+/// Counts the live files applying `entries` would overwrite and create.
+/// Encrypted and template files are counted as overwritten whenever they
+/// exist, comparing them would mean decrypting or rendering them twice.
+pub fn count_changes(
+    layout: &ProfileLayout,
+    entries: &[ManifestEntry],
+    strategy: ConflictStrategy,
+) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+    let (mut overwritten, mut created) = (0, 0);
+    for entry in entries {
+        for (src_file, dst_file) in entry_files(layout, entry)? {
+            if !dst_file.is_file() {
+                created += 1;
+                continue;
+            }
+            let differs = entry.encrypted || entry.template || files_differ(&dst_file, &src_file)?;
+            let overwrites = match strategy {
+                ConflictStrategy::Skip => false,
+                ConflictStrategy::KeepNewer if !entry.encrypted && !entry.template => {
+                    should_overwrite(strategy, &dst_file, &src_file)?
+                }
+                _ => true,
+            };
+            if differs && overwrites {
+                overwritten += 1;
+            }
+        }
+    }
+    Ok((overwritten, created))
+}
+
+/// Whether `apply --yes` asked to overwrite any number of files without
+/// asking. Set once the command line is parsed.
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+pub fn set_assume_yes(yes: bool) {
+    ASSUME_YES.store(yes, Ordering::Relaxed);
+}
+
+/// Restores manifest entries, saving the live files it overwrites to a snapshot.
+pub fn apply_backup(
+    config: &FuxiConfig,
+    layout: &ProfileLayout,
+    entries: &[ManifestEntry],
+    backup_id: &str,
+    strategy: ConflictStrategy,
+    dry_run: bool,
+    verify: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let entries = &script::destinations(config, &configured_entries(config, entries))?;
+    let sources: Vec<&str> = entries.iter().map(|e| e.source.as_str()).collect();
+    // the prompt strategy asks about every file anyway
+    if !dry_run && strategy != ConflictStrategy::Prompt && !ASSUME_YES.load(Ordering::Relaxed) {
+        let (overwritten, created) = count_changes(layout, entries, strategy)?;
+        let summary = format!(
+            "Applying {} will overwrite {} file(s) and create {}.",
+            backup_id, overwritten, created
+        );
+        let threshold = config
+            .confirm_overwrite_threshold
+            .unwrap_or(DEFAULT_OVERWRITE_THRESHOLD);
+        let profile = config.selected_profile.as_deref().unwrap_or("yes");
+        if !confirm_large(&summary, overwritten, threshold, profile)? {
+            return Err("Apply cancelled, nothing was changed.".into());
+        }
+    }
+    if !dry_run {
+        run_hooks(
+            config,
+            "pre-apply",
+            json!({"backup_id": backup_id, "paths": sources}),
+        )?;
+    }
+    let snapshot = if dry_run {
+        None
+    } else {
+        Some(Snapshot::new(backup_id)?)
+    };
+    let journal = match &snapshot {
+        Some(snapshot) => Some(Journal::begin_apply(
+            backup_id, layout, entries, strategy, snapshot,
+        )?),
+        None => None,
+    };
+    let mut options = ApplyOptions {
+        strategy,
+        dry_run,
+        snapshot,
+        variables: template_variables(config),
+        encryption: Some(encryption_backend(config)),
+        journal,
+        verify,
+        ownership_map: config.ownership_map.clone().unwrap_or_default(),
+    };
+    // cloned first so the entries are applied on top of the checkouts
+    if let Some(repo_path) = config.backup_repo_path.as_deref() {
+        clone_submodules(Path::new(repo_path), layout, entries, dry_run)?;
+    }
+    let summary = apply_entries(layout, entries, &mut options)?;
+    if let Some(journal) = options.journal.take() {
+        journal.complete()?;
+    }
+    println!(
+        "{} file(s) applied, {} skipped, {} missing from the backup.",
+        summary.applied, summary.skipped, summary.missing
+    );
+    if !dry_run {
+        run_hooks(
+            config,
+            "post-apply",
+            json!({
+                "backup_id": backup_id,
+                "paths": sources,
+                "applied": summary.applied,
+                "skipped": summary.skipped,
+                "missing": summary.missing,
+            }),
+        )?;
+    }
+
+    if let Some(snapshot) = &options.snapshot
+        && snapshot.saved > 0
+    {
+        println!(
+            "Previous versions of {} file(s) saved to {}",
+            snapshot.saved,
+            style::path(snapshot.dir.display())
+        );
+        let evicted = prune_snapshots(config, false)?;
+        if !evicted.is_empty() {
+            println!("Pruned {} old snapshot(s).", evicted.len());
+        }
+    }
+
+    if verify && !dry_run {
+        if summary.mismatched > 0 {
+            return Err(format!(
+                "{} file(s) don't match the backup after being applied.",
+                summary.mismatched
+            )
+            .into());
+        }
+        println!("{}", style::success("All applied files match the backup."));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn options(strategy: ConflictStrategy, dry_run: bool) -> ApplyOptions {
+        ApplyOptions {
+            strategy,
+            dry_run,
+            snapshot: None,
+            variables: HashMap::new(),
+            encryption: None,
+            journal: None,
+            verify: false,
+            ownership_map: OwnershipMap::default(),
+        }
+    }
+
+    fn entry(source: &Path, repo_path: &str) -> ManifestEntry {
+        ManifestEntry {
+            source: source.to_string_lossy().to_string(),
+            repo_path: repo_path.to_string(),
+            preset: None,
+            template: false,
+            encrypted: false,
+            owner: None,
+            xattrs: false,
+            hardlinks: Vec::new(),
+            sudo: false,
+            encoded_names: Default::default(),
+        }
+    }
+
+    #[test]
+    fn restores_single_file_to_its_source() {
+        let tmp = tempfile::tempdir().unwrap();
+        let profile_dir = tmp.path().join("repo/main");
+        fs::create_dir_all(&profile_dir).unwrap();
+        fs::write(profile_dir.join(".zshrc"), "export A=1\n").unwrap();
+
+        let live = tmp.path().join("home/.zshrc");
+        fs::create_dir_all(live.parent().unwrap()).unwrap();
+        fs::write(&live, "export A=0\n").unwrap();
+
+        let summary = apply_entries(
+            &ProfileLayout::flat(&profile_dir),
+            &[entry(&live, ".zshrc")],
+            &mut options(ConflictStrategy::Overwrite, false),
+        )
+        .unwrap();
+
+        assert_eq!(summary.applied, 1);
+        assert_eq!(fs::read_to_string(&live).unwrap(), "export A=1\n");
+        // the repo root must not be copied over the file's directory
+        assert!(!tmp.path().join("home/main").exists());
+    }
+
+    #[test]
+    fn removes_staged_files_when_apply_fails() {
+        let tmp = tempfile::tempdir().unwrap();
+        let profile_dir = tmp.path().join("repo/main");
+        fs::create_dir_all(&profile_dir).unwrap();
+        // rendering fails on the unknown variable once the file is staged
+        let marker = format!("{{{{ missing_{} }}}}", std::process::id());
+        fs::write(profile_dir.join("secret.conf"), &marker).unwrap();
+        let live = tmp.path().join("home/secret.conf");
+        let mut template = entry(&live, "secret.conf");
+        template.template = true;
+
+        let result = apply_entries(
+            &ProfileLayout::flat(&profile_dir),
+            &[template],
+            &mut options(ConflictStrategy::Overwrite, false),
+        );
+
+        assert!(result.is_err());
+        let prefix = format!("fuxi-apply-{}-", std::process::id());
+        for staging in fs::read_dir(std::env::temp_dir()).unwrap().flatten() {
+            if !staging.file_name().to_string_lossy().starts_with(&prefix) {
+                continue;
+            }
+            for staged in fs::read_dir(staging.path()).into_iter().flatten().flatten() {
+                let content = fs::read_to_string(staged.path()).unwrap_or_default();
+                assert_ne!(
+                    content,
+                    marker,
+                    "{} was left behind",
+                    staged.path().display()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn counts_only_files_that_change() {
+        let tmp = tempfile::tempdir().unwrap();
+        let profile_dir = tmp.path().join("repo/main/nvim");
+        fs::create_dir_all(&profile_dir).unwrap();
+        for (name, content) in [("same", "a"), ("changed", "b"), ("new", "c")] {
+            fs::write(profile_dir.join(name), content).unwrap();
+        }
+        let live = tmp.path().join("home/nvim");
+        fs::create_dir_all(&live).unwrap();
+        fs::write(live.join("same"), "a").unwrap();
+        fs::write(live.join("changed"), "old").unwrap();
+
+        let layout = ProfileLayout::flat(&tmp.path().join("repo/main"));
+        let entries = [entry(&live, "nvim")];
+        assert_eq!(
+            count_changes(&layout, &entries, ConflictStrategy::Overwrite).unwrap(),
+            (1, 1)
+        );
+        assert_eq!(
+            count_changes(&layout, &entries, ConflictStrategy::Skip).unwrap(),
+            (0, 1)
+        );
+    }
+
+    #[test]
+    fn restores_same_named_files_from_their_own_paths() {
+        let tmp = tempfile::tempdir().unwrap();
+        let profile_dir = tmp.path().join("repo/main");
+        fs::create_dir_all(profile_dir.join(".config/foo")).unwrap();
+        fs::create_dir_all(profile_dir.join(".local/foo")).unwrap();
+        fs::write(profile_dir.join(".config/foo/config"), "a").unwrap();
+        fs::write(profile_dir.join(".local/foo/config"), "b").unwrap();
+
+        let first = tmp.path().join("home/.config/foo/config");
+        let second = tmp.path().join("home/.local/foo/config");
+        let summary = apply_entries(
+            &ProfileLayout::flat(&profile_dir),
+            &[
+                entry(&first, ".config/foo/config"),
+                entry(&second, ".local/foo/config"),
+            ],
+            &mut options(ConflictStrategy::Overwrite, false),
+        )
+        .unwrap();
+
+        assert_eq!(summary.applied, 2);
+        assert_eq!(fs::read_to_string(&first).unwrap(), "a");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "b");
+    }
+
+    #[test]
+    fn restores_nested_directories() {
+        let tmp = tempfile::tempdir().unwrap();
+        let profile_dir = tmp.path().join("repo/main");
+        fs::create_dir_all(profile_dir.join("nvim/lua/plugins")).unwrap();
+        fs::write(profile_dir.join("nvim/init.lua"), "require('a')").unwrap();
+        fs::write(profile_dir.join("nvim/lua/plugins/b.lua"), "return {}").unwrap();
+
+        let live = tmp.path().join("home/.config/nvim");
+        let summary = apply_entries(
+            &ProfileLayout::flat(&profile_dir),
+            &[entry(&live, "nvim")],
+            &mut options(ConflictStrategy::Overwrite, false),
+        )
+        .unwrap();
+
+        assert_eq!(summary.applied, 2);
+        assert_eq!(
+            fs::read_to_string(live.join("init.lua")).unwrap(),
+            "require('a')"
+        );
+        assert_eq!(
+            fs::read_to_string(live.join("lua/plugins/b.lua")).unwrap(),
+            "return {}"
+        );
+    }
+
+    #[test]
+    fn recreates_missing_sources_and_reports_missing_backups() {
+        let tmp = tempfile::tempdir().unwrap();
+        let profile_dir = tmp.path().join("repo/main");
+        fs::create_dir_all(&profile_dir).unwrap();
+        fs::write(profile_dir.join(".gitconfig"), "[user]").unwrap();
+
+        let live = tmp.path().join("home/.gitconfig");
+        let gone = tmp.path().join("home/.vimrc");
+        let summary = apply_entries(
+            &ProfileLayout::flat(&profile_dir),
+            &[entry(&live, ".gitconfig"), entry(&gone, ".vimrc")],
+            &mut options(ConflictStrategy::Overwrite, false),
+        )
+        .unwrap();
+
+        assert_eq!(summary.applied, 1);
+        assert_eq!(summary.missing, 1);
+        assert!(live.exists());
+        assert!(!gone.exists());
+    }
+
+    #[test]
+    fn overlay_files_win_over_base() {
+        let tmp = tempfile::tempdir().unwrap();
+        let profile_dir = tmp.path().join("repo/main");
+        fs::create_dir_all(profile_dir.join("base/git")).unwrap();
+        fs::create_dir_all(profile_dir.join("overlays/laptop/git")).unwrap();
+        fs::write(profile_dir.join("base/git/config"), "base").unwrap();
+        fs::write(profile_dir.join("base/git/ignore"), "*.o").unwrap();
+        fs::write(profile_dir.join("overlays/laptop/git/config"), "laptop").unwrap();
+
+        let live = tmp.path().join("home/.config/git");
+        let summary = apply_entries(
+            &ProfileLayout::new(&profile_dir, "laptop"),
+            &[entry(&live, "git")],
+            &mut options(ConflictStrategy::Overwrite, false),
+        )
+        .unwrap();
+
+        assert_eq!(summary.applied, 2);
+        assert_eq!(fs::read_to_string(live.join("config")).unwrap(), "laptop");
+        assert_eq!(fs::read_to_string(live.join("ignore")).unwrap(), "*.o");
+    }
+
+    #[test]
+    fn renders_template_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let profile_dir = tmp.path().join("repo/main");
+        fs::create_dir_all(&profile_dir).unwrap();
+        fs::write(profile_dir.join(".gitconfig"), "email = {{ email }}\n").unwrap();
+
+        let live = tmp.path().join(".gitconfig");
+        let mut template_entry = entry(&live, ".gitconfig");
+        template_entry.template = true;
+        let mut opts = options(ConflictStrategy::Overwrite, false);
+        opts.variables
+            .insert("email".to_string(), "me@example.com".to_string());
+
+        apply_entries(
+            &ProfileLayout::flat(&profile_dir),
+            &[template_entry],
+            &mut opts,
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&live).unwrap(),
+            "email = me@example.com\n"
+        );
+    }
+
+    #[test]
+    fn dry_run_and_skip_leave_live_files_untouched() {
+        let tmp = tempfile::tempdir().unwrap();
+        let profile_dir = tmp.path().join("repo/main");
+        fs::create_dir_all(&profile_dir).unwrap();
+        fs::write(profile_dir.join("a.cfg"), "new").unwrap();
+
+        let live = tmp.path().join("a.cfg");
+        fs::write(&live, "old").unwrap();
+        let entries = [entry(&live, "a.cfg")];
+
+        apply_entries(
+            &ProfileLayout::flat(&profile_dir),
+            &entries,
+            &mut options(ConflictStrategy::Overwrite, true),
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(&live).unwrap(), "old");
+
+        let summary = apply_entries(
+            &ProfileLayout::flat(&profile_dir),
+            &entries,
+            &mut options(ConflictStrategy::Skip, false),
+        )
+        .unwrap();
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(fs::read_to_string(&live).unwrap(), "old");
+    }
+
+    #[test]
+    fn reroots_sources_below_the_target() {
+        let target = Path::new("/mnt/image");
+        assert_eq!(
+            reroot(target, Path::new("/home/me/.zshrc")),
+            target.join("home/me/.zshrc")
+        );
+        // `..` can't climb out of the target
+        assert_eq!(
+            reroot(target, Path::new("/../../etc/passwd")),
+            target.join("etc/passwd")
+        );
+    }
+}
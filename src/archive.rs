@@ -0,0 +1,354 @@
+use std::{collections::HashMap, env, fs, path::Path, process::Command};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+
+use crate::apply::entry_files;
+use crate::bootstrap::restore_from_clone;
+use crate::cfg::{FuxiConfig, get_machine_name, get_profile_dir};
+use crate::manifest::{ManifestEntry, load_manifest};
+use crate::object_store::STATE_DIR;
+use crate::overlay::ProfileLayout;
+use crate::style;
+use crate::template::{render, template_variables};
+
+/// Describes an archive written by `fuxi export`, stored at its root.
+const INFO_FILE: &str = "fuxi-export.toml";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportInfo {
+    created: chrono::DateTime<chrono::Utc>,
+    machine: String,
+    backup: String,
+    fuxi_version: String,
+}
+
+fn run_tar(args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new("tar")
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run tar (is it installed?): {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "tar failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Packs the local backup directory, without git and backend bookkeeping,
+/// into a gzipped tarball.
+pub fn export_archive(
+    config: &FuxiConfig,
+    repo_path: &Path,
+    backup: &str,
+    output: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let info = ExportInfo {
+        created: chrono::Utc::now(),
+        machine: get_machine_name(config),
+        backup: backup.to_string(),
+        fuxi_version: env!("CARGO_PKG_VERSION").to_string(),
+    };
+    let staging = env::temp_dir().join(format!("fuxi-export-{}", std::process::id()));
+    fs::create_dir_all(&staging)?;
+    fs::write(staging.join(INFO_FILE), toml::to_string_pretty(&info)?)?;
+
+    let output = env::current_dir()?.join(output);
+    let result = run_tar(&[
+        "-czf",
+        &output.to_string_lossy(),
+        "--exclude=./.git",
+        &format!("--exclude=./{}", STATE_DIR),
+        "-C",
+        &staging.to_string_lossy(),
+        INFO_FILE,
+        "-C",
+        &repo_path.to_string_lossy(),
+        ".",
+    ]);
+    fs::remove_dir_all(&staging)?;
+    result?;
+    println!("Exported {} to {}", backup, style::path(output.display()));
+    Ok(())
+}
+
+/// Marks the end of a restore script and the start of its base64 payload.
+const SCRIPT_MARKER: &str = "__ARCHIVE__";
+
+const SH_RESTORE: &str = r#"set -e
+dry_run=false
+[ "$1" = "--dry-run" ] && dry_run=true
+saved="$HOME/.fuxi-restore-$(date +%Y%m%d%H%M%S)"
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+sed '1,/^__ARCHIVE__$/d' "$0" > "$tmp/archive.b64"
+base64 -d < "$tmp/archive.b64" > "$tmp/archive.tar.gz" 2>/dev/null ||
+    base64 -D < "$tmp/archive.b64" > "$tmp/archive.tar.gz"
+tar -xzf "$tmp/archive.tar.gz" -C "$tmp"
+
+restore() {
+    if [ -f "$2" ] && cmp -s "$tmp/files/$1" "$2"; then
+        return
+    fi
+    if $dry_run; then
+        echo "Would restore $2"
+        return
+    fi
+    if [ -f "$2" ]; then
+        mkdir -p "$(dirname "$saved$2")"
+        cp -p "$2" "$saved$2"
+    fi
+    mkdir -p "$(dirname "$2")"
+    cp -p "$tmp/files/$1" "$2"
+    echo "Restored $2"
+}
+
+"#;
+
+const PS_RESTORE: &str = r#"param([switch]$DryRun)
+$ErrorActionPreference = 'Stop'
+$saved = Join-Path $HOME ('.fuxi-restore-' + (Get-Date -Format 'yyyyMMddHHmmss'))
+$tmp = Join-Path ([IO.Path]::GetTempPath()) ([Guid]::NewGuid().ToString())
+New-Item -ItemType Directory -Path $tmp | Out-Null
+
+function Restore-File($Id, $Destination) {
+    $source = Join-Path $tmp "files\$Id"
+    $exists = Test-Path -LiteralPath $Destination -PathType Leaf
+    if ($exists -and (Get-FileHash -LiteralPath $source).Hash -eq (Get-FileHash -LiteralPath $Destination).Hash) {
+        return
+    }
+    if ($DryRun) {
+        Write-Output "Would restore $Destination"
+        return
+    }
+    if ($exists) {
+        $copy = Join-Path $saved ($Destination -replace ':', '')
+        New-Item -ItemType Directory -Force -Path (Split-Path $copy) | Out-Null
+        Copy-Item -LiteralPath $Destination $copy
+    }
+    New-Item -ItemType Directory -Force -Path (Split-Path $Destination) | Out-Null
+    Copy-Item -LiteralPath $source $Destination -Force
+    Write-Output "Restored $Destination"
+}
+
+try {
+    $script = Get-Content -Raw -LiteralPath $PSCommandPath
+    $payload = $script.Substring($script.LastIndexOf('__ARCHIVE__') + 11).Replace('#>', '')
+    [IO.File]::WriteAllBytes((Join-Path $tmp 'archive.tar.gz'), [Convert]::FromBase64String($payload))
+    tar -xzf (Join-Path $tmp 'archive.tar.gz') -C $tmp
+    if ($LASTEXITCODE -ne 0) { throw 'tar failed to unpack the bundled files.' }
+
+"#;
+
+/// `path` quoted for the script, relative to the home directory of whoever
+/// runs it when it's inside this user's.
+fn script_path(path: &Path, home: Option<&Path>, powershell: bool) -> String {
+    let rel = home.and_then(|home| path.strip_prefix(home).ok());
+    if powershell {
+        let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+        match rel {
+            Some(rel) => format!("(Join-Path $HOME {})", quote(&rel.to_string_lossy())),
+            None => quote(&path.to_string_lossy()),
+        }
+    } else {
+        let quote = |s: &str| format!("'{}'", s.replace('\'', r"'\''"));
+        match rel {
+            Some(rel) => format!(
+                "\"$HOME\"{}",
+                quote(&format!("/{}", rel.to_string_lossy().replace('\\', "/")))
+            ),
+            None => quote(&path.to_string_lossy()),
+        }
+    }
+}
+
+/// Copies the files of `entries` into `staging` and writes the script that
+/// restores them, with the files appended. Returns how many files it restores.
+fn write_script(
+    staging: &Path,
+    layout: &ProfileLayout,
+    entries: &[ManifestEntry],
+    variables: &HashMap<String, String>,
+    header: &[String],
+    output: &Path,
+    powershell: bool,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let files_dir = staging.join("files");
+    fs::create_dir_all(&files_dir)?;
+    let home = dirs::home_dir();
+    let mut restores = String::new();
+    let mut count = 0;
+    for entry in entries {
+        for (src, dst) in entry_files(layout, entry)? {
+            count += 1;
+            let bundled = files_dir.join(count.to_string());
+            if entry.template {
+                let rendered = render(&fs::read_to_string(&src)?, variables)
+                    .map_err(|e| format!("Failed to render {}: {}", src.display(), e))?;
+                fs::write(&bundled, rendered)?;
+            } else {
+                fs::copy(&src, &bundled)?;
+            }
+            let destination = script_path(&dst, home.as_deref(), powershell);
+            restores.push_str(&if powershell {
+                format!("    Restore-File {} {}\n", count, destination)
+            } else {
+                format!("restore {} {}\n", count, destination)
+            });
+        }
+    }
+    let payload = staging.join("payload.tar.gz");
+    run_tar(&[
+        "-czf",
+        &payload.to_string_lossy(),
+        "-C",
+        &staging.to_string_lossy(),
+        "files",
+    ])?;
+
+    let mut script = String::new();
+    if !powershell {
+        script.push_str("#!/bin/sh\n");
+    }
+    for line in header {
+        script.push_str(&format!("# {}\n", line));
+    }
+    if powershell {
+        script.push_str(PS_RESTORE);
+        script.push_str(&restores);
+        script.push_str(
+            "    if (Test-Path $saved) { Write-Output \"Previous versions saved to $saved\" }\n} finally {\n    Remove-Item -Recurse -Force -LiteralPath $tmp\n}\nexit\n<#\n",
+        );
+    } else {
+        script.push_str(SH_RESTORE);
+        script.push_str(&restores);
+        script
+            .push_str("[ -d \"$saved\" ] && echo \"Previous versions saved to $saved\"\nexit 0\n");
+    }
+    script.push_str(SCRIPT_MARKER);
+    script.push('\n');
+    let encoded = STANDARD.encode(fs::read(&payload)?);
+    for line in encoded.as_bytes().chunks(76) {
+        script.push_str(&String::from_utf8_lossy(line));
+        script.push('\n');
+    }
+    if powershell {
+        script.push_str("#>\n");
+    }
+    fs::write(output, script)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(output, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(count)
+}
+
+/// Writes a shell script, or a PowerShell one when `output` ends in `.ps1`,
+/// that restores the selected profile's files from an archive bundled inside
+/// it, for machines without fuxi. Templates are rendered with this machine's
+/// variables, secrets are left out.
+pub fn export_script(
+    config: &FuxiConfig,
+    repo_path: &Path,
+    backup: &str,
+    output: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let profile = config
+        .selected_profile
+        .as_ref()
+        .ok_or("No profile selected. Please select a profile before exporting.")?;
+    let profile_dir = get_profile_dir(config, repo_path, profile, None);
+    let manifest = load_manifest(&profile_dir)?
+        .ok_or_else(|| format!("Profile '{}' has no backup to export.", profile))?;
+    let layout = ProfileLayout::new(&profile_dir, &get_machine_name(config));
+    let powershell = output
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("ps1"));
+    let (secrets, entries): (Vec<_>, Vec<_>) =
+        manifest.entries.iter().cloned().partition(|e| e.encrypted);
+    let header = [
+        format!(
+            "Restores profile '{}' from backup {}, exported by fuxi {} on '{}' at {}.",
+            profile,
+            backup,
+            env!("CARGO_PKG_VERSION"),
+            get_machine_name(config),
+            chrono::Utc::now().format("%Y-%m-%d %H:%M UTC")
+        ),
+        format!(
+            "Needs tar{}, not fuxi. Run it with {} to only list the files.",
+            if powershell { "" } else { " and base64" },
+            if powershell { "-DryRun" } else { "--dry-run" }
+        ),
+        "Files it overwrites are copied to ~/.fuxi-restore-<date>/ first.".to_string(),
+    ];
+
+    let staging = env::temp_dir().join(format!("fuxi-export-{}", std::process::id()));
+    let result = write_script(
+        &staging,
+        &layout,
+        &entries,
+        &template_variables(config),
+        &header,
+        output,
+        powershell,
+    );
+    fs::remove_dir_all(&staging)?;
+    let count = result?;
+
+    println!(
+        "Wrote {} restoring {} file(s) of profile '{}'.",
+        style::path(output.display()),
+        count,
+        profile
+    );
+    if !secrets.is_empty() {
+        println!(
+            "Left out {} secret(s), they aren't written to the script in plain text.",
+            secrets.len()
+        );
+    }
+    if !manifest.registry.is_empty() {
+        println!(
+            "Left out {} registry key(s), restore them with 'fuxi apply'.",
+            manifest.registry.len()
+        );
+    }
+    Ok(())
+}
+
+/// Unpacks an archive from `fuxi export` into the local backup directory and
+/// offers to restore one of its profiles.
+pub fn import_archive(
+    config: &mut FuxiConfig,
+    repo_path: &Path,
+    archive: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let archive = archive.to_string_lossy();
+    let info: ExportInfo = toml::from_str(
+        &run_tar(&["-xzOf", &archive, INFO_FILE])
+            .map_err(|_| format!("{} was not created by 'fuxi export'.", archive))?,
+    )?;
+    println!(
+        "Importing backup {} exported from '{}' on {}.",
+        info.backup,
+        info.machine,
+        info.created.format("%Y-%m-%d %H:%M")
+    );
+
+    fs::create_dir_all(repo_path)?;
+    run_tar(&[
+        "-xzf",
+        &archive,
+        "-C",
+        &repo_path.to_string_lossy(),
+        &format!("--exclude={}", INFO_FILE),
+    ])?;
+    println!("Unpacked into {}", style::path(repo_path.display()));
+    println!("Run 'fuxi backup --push' to seed the remote with it.");
+    restore_from_clone(config, repo_path)
+}
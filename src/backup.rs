@@ -0,0 +1,527 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::json;
+
+use crate::cfg::{
+    FuxiConfig, get_machine_name, get_path_entry, get_profile_dir, get_selected_profile_paths,
+    is_template_path, repo_path, save_repo_config, update_last_backup_id,
+};
+use crate::copy::{
+    collect_files, copy_file_or_path, copy_privileged, run_privileged, skip_unchanged,
+    skipped_special_files, take_failures, take_hash_cache, tolerate,
+};
+use crate::exit::{config_error, set_partial};
+use crate::hash_cache::HashCache;
+use crate::journal::Journal;
+use crate::manifest::{
+    Manifest, load_manifest, manifest_entry, migrate_layout, repo_relative_path, save_manifest,
+};
+use crate::metadata::{BackupMetadata, save_metadata};
+use crate::moved::find_moved;
+use crate::orphans::warn_orphans;
+use crate::overlay::{ProfileLayout, join_rel};
+use crate::plugin::run_hooks;
+use crate::portable::{decode_path, make_portable};
+use crate::push_policy::record_backup;
+use crate::registry::{export_keys, profile_keys};
+use crate::script;
+use crate::secret::{encrypted_name, encryption_backend, is_secret_path};
+use crate::storage;
+use crate::style;
+use crate::submodule::{reference_nested_repos, references_nested_repos};
+use crate::verify::save_checksums;
+use crate::vss;
+use crate::watch::wildcard_match;
+use crate::xattrs::record_attributes;
+
+/// The `excludes` of the tracked path `path`.
+pub fn excludes(config: &FuxiConfig, path: &str) -> Vec<String> {
+    get_path_entry(config, path)
+        .and_then(|entry| entry.excludes.clone())
+        .unwrap_or_default()
+}
+
+/// Whether `rel`, a file below a tracked directory, matches one of its
+/// `excludes` by its name or its path relative to the directory.
+pub fn is_excluded(excludes: &[String], rel: &Path) -> bool {
+    let Some(name) = rel.file_name() else {
+        return false;
+    };
+    let name = name.to_string_lossy();
+    let rel = rel.to_string_lossy().replace('\\', "/");
+    excludes
+        .iter()
+        .any(|pattern| wildcard_match(pattern, &name) || wildcard_match(pattern, &rel))
+}
+
+/// Encrypts every file of a secret path into the repo, removing any plaintext
+/// copy left over from before the path was marked as secret.
+fn backup_secret(
+    config: &FuxiConfig,
+    layout: &ProfileLayout,
+    src_path: &Path,
+    relative_path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let backend = encryption_backend(config);
+    let files = if src_path.is_dir() {
+        collect_files(src_path)?
+    } else {
+        vec![PathBuf::new()]
+    };
+    let excludes = excludes(config, &src_path.to_string_lossy());
+
+    for rel in files {
+        if is_excluded(&excludes, &rel) || !script::filter(config, &join_rel(src_path, &rel))? {
+            continue;
+        }
+        let (repo_path, repo_rel) = if rel.as_os_str().is_empty() {
+            (
+                encrypted_name(relative_path, backend.extension()),
+                rel.clone(),
+            )
+        } else {
+            (
+                relative_path.to_path_buf(),
+                encrypted_name(&rel, backend.extension()),
+            )
+        };
+        let target = layout.backup_target(&repo_path.to_string_lossy(), &repo_rel);
+        backend.encrypt(&join_rel(src_path, &rel), &target)?;
+
+        let plaintext = join_rel(&layout.root.join(relative_path), &rel);
+        if plaintext.is_file() {
+            fs::remove_file(&plaintext)?;
+            println!(
+                "{}",
+                style::warning(format!(
+                    "Warning: Removed unencrypted copy {}, it may still exist in the repository history.",
+                    plaintext.display()
+                ))
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Copies one tracked path into the profile's layout in the repo.
+/// Returns `false` when the source doesn't exist or the hook script filtered
+/// all of it out.
+pub fn backup_path(
+    config: &FuxiConfig,
+    layout: &ProfileLayout,
+    path: &str,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let src_path = Path::new(path);
+    if !src_path.exists() {
+        println!(
+            "{}",
+            style::warning(format!(
+                "Warning: Source path does not exist: {}",
+                src_path.display()
+            ))
+        );
+        if let Some(moved) = find_moved(config, layout, path) {
+            println!(
+                "It looks like it moved to {}. Run 'fuxi path move {} {}' to track it there.",
+                style::path(moved.display()),
+                path,
+                style::path(moved.display())
+            );
+        }
+        return Ok(false);
+    }
+
+    let relative_path = repo_relative_path(src_path);
+    let relative_str = relative_path.to_string_lossy();
+    let dst_path = layout.root.join(&relative_path);
+    let excludes = excludes(config, path);
+
+    // templates are maintained in the repo, the live file is only their rendered output
+    if is_template_path(config, path) && layout.exists(&relative_str) {
+        println!("Skipped template {}", style::path(src_path.display()));
+    } else if is_secret_path(config, path) {
+        backup_secret(config, layout, src_path, &relative_path)?;
+        println!(
+            "Encrypted {} into {} with {}",
+            style::path(src_path.display()),
+            style::path(dst_path.display()),
+            encryption_backend(config).name()
+        );
+    } else if layout.overlay.is_some() || script::has_filter(config)? || !excludes.is_empty() {
+        // route each file to this machine's overlay when it overrides it
+        let files = if src_path.is_dir() {
+            collect_files(src_path)?
+        } else {
+            vec![PathBuf::new()]
+        };
+        let (mut excluded, mut filtered) = (0, 0);
+        for rel in &files {
+            let file = join_rel(src_path, rel);
+            if is_excluded(&excludes, rel) {
+                excluded += 1;
+                continue;
+            }
+            if !script::filter(config, &file)? {
+                filtered += 1;
+                continue;
+            }
+            let target = layout.backup_target(&relative_str, rel);
+            if let Err(e) = copy_file_or_path(&file, &target) {
+                tolerate(&file, e)?;
+            }
+        }
+        if excluded + filtered > 0 && excluded + filtered == files.len() {
+            println!(
+                "Skipped {}, its excludes or the hook script left out every file",
+                style::path(src_path.display())
+            );
+            return Ok(false);
+        }
+        if excluded > 0 {
+            println!("Left out {} file(s) matching its excludes", excluded);
+        }
+        if filtered > 0 {
+            println!("Left out {} file(s) the hook script filtered", filtered);
+        }
+        println!(
+            "Backed up {} to {}",
+            style::path(src_path.display()),
+            style::path(dst_path.display())
+        );
+    } else if get_path_entry(config, path).is_some_and(|entry| entry.sudo.unwrap_or(false)) {
+        copy_privileged(src_path, &dst_path);
+    } else {
+        copy_file_or_path(src_path, &dst_path)?;
+        println!(
+            "Backed up {} to {}",
+            style::path(src_path.display()),
+            style::path(dst_path.display())
+        );
+    }
+
+    // submodules only mean something to the git backend
+    if references_nested_repos()
+        && config.backend.as_deref().unwrap_or("git") == "git"
+        && !is_secret_path(config, path)
+        && let Some(repo_path) = config.backup_repo_path.as_deref()
+    {
+        reference_nested_repos(Path::new(repo_path), src_path, &dst_path)?;
+    }
+    Ok(true)
+}
+
+/// The paths of the selected profile to back up, once the config has
+/// everything a backup needs.
+fn paths_to_back_up(config: &FuxiConfig) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    if config.remote_url.is_none() {
+        return Err(config_error(
+            "Remote repository is not set. Please run 'fuxi init' first.",
+        ));
+    }
+
+    if config.selected_profile.is_none() {
+        return Err(config_error(
+            "No profile selected. Please select a profile before backing up.",
+        ));
+    }
+
+    let paths = get_selected_profile_paths(config);
+    if paths.is_empty() {
+        return Err(config_error(
+            "No paths configured for the selected profile.",
+        ));
+    }
+    Ok(paths)
+}
+
+/// Files a dry run found, over every tracked path.
+#[derive(Default)]
+struct Preview {
+    new: usize,
+    changed: usize,
+    unchanged: usize,
+}
+
+/// Prints what backing up `path` would copy into the repo, comparing each
+/// file with its backed up copy, and counts the files in `preview`.
+fn preview_path(
+    config: &FuxiConfig,
+    layout: &ProfileLayout,
+    previous: Option<&Manifest>,
+    path: &str,
+    preview: &mut Preview,
+    cache: &mut HashCache,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let src_path = Path::new(path);
+    if !src_path.exists() {
+        println!(
+            "{}",
+            style::warning(format!(
+                "Warning: Source path does not exist: {}",
+                src_path.display()
+            ))
+        );
+        return Ok(());
+    }
+    let relative_path = repo_relative_path(src_path);
+    let relative_str = relative_path.to_string_lossy();
+    if is_template_path(config, path) && layout.exists(&relative_str) {
+        println!(
+            "[Dry Run] Would skip template {}",
+            style::path(src_path.display())
+        );
+        return Ok(());
+    }
+    // encrypting again gives a different file every time, there's nothing to compare
+    if is_secret_path(config, path) {
+        println!(
+            "[Dry Run] Would encrypt {} into {} with {}",
+            style::path(src_path.display()),
+            layout.root.join(&relative_path).display(),
+            encryption_backend(config).name()
+        );
+        return Ok(());
+    }
+
+    // backed up files by where they're restored to, so encoded names match
+    let entry = previous.and_then(|manifest| manifest.entries.iter().find(|e| e.source == path));
+    let mut stored: HashMap<PathBuf, PathBuf> = layout
+        .resolve(&relative_str)?
+        .into_iter()
+        .map(|(rel, file)| match entry {
+            Some(entry) => (decode_path(entry, &rel), file),
+            None => (rel, file),
+        })
+        .collect();
+    let files = if src_path.is_dir() {
+        collect_files(src_path)?
+    } else {
+        vec![PathBuf::new()]
+    };
+    let excludes = excludes(config, path);
+    let (mut unchanged, mut left_out) = (0, 0);
+    for rel in &files {
+        let file = join_rel(src_path, rel);
+        let backed_up = stored.remove(rel);
+        if is_excluded(&excludes, rel) || !script::filter(config, &file)? {
+            left_out += 1;
+            continue;
+        }
+        match backed_up {
+            None => {
+                println!(
+                    "[Dry Run] Would copy {} to {}",
+                    style::path(file.display()),
+                    layout.backup_target(&relative_str, rel).display()
+                );
+                preview.new += 1;
+            }
+            // a file that can't be read is copied, or reported, by the backup
+            Some(copy) if cache.differ(&file, &copy).unwrap_or(true) => {
+                println!(
+                    "[Dry Run] Would update {} from {}",
+                    style::path(copy.display()),
+                    style::path(file.display())
+                );
+                preview.changed += 1;
+            }
+            Some(_) => unchanged += 1,
+        }
+    }
+    if unchanged > 0 {
+        println!(
+            "[Dry Run] Would skip {} unchanged file(s) of {}",
+            unchanged,
+            style::path(src_path.display())
+        );
+    }
+    if left_out > 0 {
+        println!(
+            "[Dry Run] Would leave out {} file(s) of {} matching its excludes or filtered by the hook script",
+            left_out,
+            style::path(src_path.display())
+        );
+    }
+    let mut gone: Vec<PathBuf> = stored.into_values().collect();
+    gone.sort();
+    for copy in gone {
+        println!(
+            "[Dry Run] {} is no longer in {}, the backup keeps it",
+            style::path(copy.display()),
+            style::path(src_path.display())
+        );
+    }
+    preview.unchanged += unchanged;
+    Ok(())
+}
+
+/// Prints which files a backup would copy into the repo, which it would skip
+/// as unchanged and which are only left in the repo, without writing
+/// anything.
+pub fn preview_backup(config: &FuxiConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let paths = paths_to_back_up(config)?;
+    let repo_path = Path::new(repo_path(config)?);
+    let selected_profile = config
+        .selected_profile
+        .as_ref()
+        .expect("Selected profile should be present");
+    let profile_dir = get_profile_dir(config, repo_path, selected_profile, None);
+    let layout = ProfileLayout::new(&profile_dir, &get_machine_name(config));
+    let previous = load_manifest(&profile_dir)?;
+
+    let mut preview = Preview::default();
+    let mut cache = HashCache::load();
+    for path in paths {
+        preview_path(
+            config,
+            &layout,
+            previous.as_ref(),
+            &path,
+            &mut preview,
+            &mut cache,
+        )?;
+    }
+    cache.save()?;
+    if preview.new + preview.changed == 0 {
+        println!(
+            "[Dry Run] No file changed since the last backup, its commit would only hold a new manifest and the backup's metadata."
+        );
+    } else {
+        println!(
+            "[Dry Run] The commit would add {} new and {} changed file(s) to {}, with the manifest and the backup's metadata. {} file(s) are unchanged.",
+            preview.new,
+            preview.changed,
+            style::path(profile_dir.display()),
+            preview.unchanged
+        );
+    }
+    Ok(())
+}
+
+/// Copies every tracked path of the selected profile into the repo and records
+/// the manifest and metadata. Returns the new backup ID.
+pub fn create_backup(
+    config: &FuxiConfig,
+    message: Option<String>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let backup_id = format!("backup_{}", chrono::Utc::now().format("%Y%m%d_%H%M%S"));
+    update_last_backup_id(&backup_id)?;
+
+    let repo_path = repo_path(config)?;
+    let repo_path = Path::new(repo_path);
+    let paths = paths_to_back_up(config)?;
+    run_hooks(
+        config,
+        "pre-backup",
+        json!({"backup_id": backup_id, "message": message, "paths": paths}),
+    )?;
+
+    let _shadows = vss::ShadowCopies;
+    // `fuxi watch` backs up many times in one process
+    let skipped_before = skipped_special_files();
+    let mut journal = Journal::begin_backup(&backup_id, message.clone())?;
+    storage::backend(config)?.prepare()?;
+
+    let mut manifest = Manifest::new(&backup_id);
+    let selected_profile = config
+        .selected_profile
+        .as_ref()
+        .expect("Selected profile should be present");
+    let profile_dir = get_profile_dir(config, repo_path, selected_profile, None);
+    let layout = ProfileLayout::new(&profile_dir, &get_machine_name(config));
+    let moved = migrate_layout(&profile_dir, &paths)?;
+    if moved > 0 {
+        journal.step(format!("Moved {} item(s) to the full path layout", moved))?;
+    }
+    let previous = load_manifest(&profile_dir)?;
+
+    take_failures();
+    skip_unchanged(HashCache::load());
+    for path in paths {
+        match backup_path(config, &layout, &path) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                tolerate(Path::new(&path), e)?;
+                continue;
+            }
+        }
+
+        manifest.entries.push(manifest_entry(config, &path));
+        journal.step(format!("Backed up {}", path))?;
+    }
+
+    run_privileged()?;
+    if let Some(cache) = take_hash_cache() {
+        cache.save()?;
+    }
+    let encode = config.portable_names.unwrap_or(true);
+    for entry in &mut manifest.entries {
+        let previous = previous
+            .as_ref()
+            .and_then(|previous| previous.entries.iter().find(|e| e.source == entry.source));
+        make_portable(&profile_dir, entry, previous, encode)?;
+    }
+    // backing up one group leaves the other paths as the last backup stored them
+    if config.selected_group.is_some()
+        && let Some(previous) = &previous
+    {
+        for entry in &previous.entries {
+            let tracked = get_path_entry(config, &entry.source).is_some_and(|e| e.is_active());
+            if tracked && !manifest.entries.iter().any(|e| e.source == entry.source) {
+                manifest.entries.push(entry.clone());
+            }
+        }
+    }
+    record_attributes(&layout.root, &manifest.entries)?;
+    manifest.registry = export_keys(&layout.root, &profile_keys(config, selected_profile))?;
+    save_manifest(&profile_dir, &manifest)?;
+    save_checksums(&profile_dir)?;
+    journal.step("Saved the manifest".to_string())?;
+
+    let metadata = BackupMetadata::new(
+        &backup_id,
+        selected_profile,
+        manifest.entries.iter().map(|e| e.source.clone()).collect(),
+        message,
+    )?;
+    save_metadata(repo_path, &metadata)?;
+    record_backup(config)?;
+    save_repo_config(repo_path, config)?;
+    journal.complete()?;
+    warn_orphans(config)?;
+
+    let failures = take_failures();
+    if !failures.is_empty() {
+        set_partial();
+        println!("{} file(s) could not be backed up:", failures.len());
+        for (path, error) in &failures {
+            println!("  {}: {}", style::path(path.display()), error);
+        }
+    }
+    let skipped = skipped_special_files() - skipped_before;
+    if skipped > 0 {
+        println!(
+            "Skipped {} special file(s), sockets, FIFOs and device nodes can't be backed up.",
+            skipped
+        );
+    }
+    run_hooks(
+        config,
+        "post-backup",
+        json!({
+            "backup_id": backup_id,
+            "files": manifest.entries.len(),
+            "failed": failures.len(),
+        }),
+    )?;
+    println!(
+        "{}",
+        style::success(format!("Backup '{}' created successfully!", backup_id))
+    );
+    Ok(backup_id)
+}
@@ -0,0 +1,477 @@
+use std::{
+    collections::HashMap,
+    env,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::apply::apply_backup;
+use crate::cfg::{
+    FuxiConfig, PathEntry, adopt_repo_config, get_machine_name, get_profile_dir,
+    get_profile_entries, load_repo_config, resolve_profile, save_config,
+};
+use crate::cli::{confirm, prompt};
+use crate::conflict::ConflictStrategy;
+use crate::git::{clone_repository, remote_branches, remote_url};
+use crate::machine::hostname;
+use crate::manifest::{Manifest, ManifestEntry, find_profiles, load_manifest};
+use crate::overlay::ProfileLayout;
+use crate::registry::import_keys;
+use crate::style;
+
+/// Tracks a profile from the repo locally, with the same presets, templates,
+/// secrets, extended attribute paths and registry keys it was backed up with.
+fn import_profile(config: &mut FuxiConfig, name: &str, manifest: &Manifest) {
+    let paths: Vec<PathEntry> = manifest
+        .entries
+        .iter()
+        .map(|e| PathEntry::new(e.source.clone()))
+        .collect();
+    config
+        .profiles
+        .get_or_insert_with(HashMap::new)
+        .entry(name.to_string())
+        .or_insert(paths);
+    if !manifest.registry.is_empty() {
+        config
+            .registry_keys
+            .get_or_insert_with(HashMap::new)
+            .entry(name.to_string())
+            .or_insert_with(|| manifest.registry.clone());
+    }
+    for entry in &manifest.entries {
+        if let Some(preset) = &entry.preset {
+            let members = config
+                .presets
+                .get_or_insert_with(HashMap::new)
+                .entry(preset.clone())
+                .or_default();
+            if !members.contains(&entry.source) {
+                members.push(entry.source.clone());
+            }
+        }
+        for (flag, list) in [
+            (entry.template, &mut config.templates),
+            (entry.encrypted, &mut config.secrets),
+            (entry.xattrs, &mut config.xattr_paths),
+        ] {
+            if !flag {
+                continue;
+            }
+            let list = list.get_or_insert_with(Vec::new);
+            if !list.contains(&entry.source) {
+                list.push(entry.source.clone());
+            }
+        }
+    }
+}
+
+fn apply_profile(
+    config: &FuxiConfig,
+    repo_path: &Path,
+    name: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let profile_dir = get_profile_dir(config, repo_path, name, None);
+    let manifest = match load_manifest(&profile_dir)? {
+        Some(manifest) => manifest,
+        None => {
+            let paths: Vec<String> = get_profile_entries(config, name)
+                .iter()
+                .map(|entry| entry.source.clone())
+                .collect();
+            Manifest::from_paths(&paths)
+        }
+    };
+    let layout = ProfileLayout::new(&profile_dir, &get_machine_name(config));
+    apply_backup(
+        config,
+        &layout,
+        &manifest.entries,
+        &manifest.backup_id,
+        config.conflict_strategy.unwrap_or_default(),
+        false,
+        false,
+    )?;
+    import_keys(&layout.root, &manifest.registry, &manifest.backup_id, false)
+}
+
+/// Lists the profiles of a freshly cloned repo and offers to track and apply one.
+pub fn restore_from_clone(
+    config: &mut FuxiConfig,
+    repo_path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let profiles = find_profiles(repo_path);
+    if profiles.is_empty() {
+        println!("The repository has no backups yet.");
+        return Ok(());
+    }
+
+    println!("Profiles in the repository:");
+    for (name, manifest) in &profiles {
+        println!(
+            "  {} ({} path(s), {})",
+            name,
+            manifest.entries.len(),
+            manifest.backup_id
+        );
+    }
+    let choice = prompt("Profile to restore on this machine (leave empty to skip)")?;
+    if choice.is_empty() {
+        return Ok(());
+    }
+    let names: Vec<String> = profiles.iter().map(|(name, _)| name.clone()).collect();
+    let choice = resolve_profile(&names, &choice)?;
+    let (name, manifest) = profiles
+        .iter()
+        .find(|(name, _)| *name == choice)
+        .expect("resolved to a profile of the repository");
+
+    import_profile(config, name, manifest);
+    config.selected_profile = Some(name.clone());
+    save_config(config)?;
+    println!("Profile '{}' created and selected.", name);
+
+    if confirm("Apply its files to this machine now?")? {
+        apply_profile(config, repo_path, name)?;
+    }
+    Ok(())
+}
+
+/// Picks the profile named after this machine's hostname, machine name or OS,
+/// falling back to the only profile or asking.
+fn pick_profile(
+    config: &FuxiConfig,
+    names: &[String],
+) -> Result<String, Box<dyn std::error::Error>> {
+    for candidate in [
+        hostname(),
+        get_machine_name(config),
+        env::consts::OS.to_string(),
+    ] {
+        if let Some(name) = names.iter().find(|n| n.eq_ignore_ascii_case(&candidate)) {
+            println!("Using profile '{}' for this machine.", name);
+            return Ok(name.clone());
+        }
+    }
+    if let [name] = names {
+        return Ok(name.clone());
+    }
+
+    println!("Available profiles: {}", names.join(", "));
+    let choice = prompt("Profile to apply on this machine")?;
+    resolve_profile(names, &choice)
+}
+
+/// Clones the backup repo, imports the config stored inside it, selects a
+/// profile for this machine and applies it.
+pub fn bootstrap(
+    repo: &str,
+    path: &Path,
+    profile: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if path.exists() && path.read_dir()?.next().is_some() {
+        return Err(format!("{} already exists and is not empty.", path.display()).into());
+    }
+
+    let url = remote_url(repo);
+    let mut config = FuxiConfig {
+        backup_repo_path: Some(path.to_string_lossy().to_string()),
+        remote_url: Some(url.clone()),
+        ..FuxiConfig::default()
+    };
+    let branches = remote_branches(&url).ok_or_else(|| format!("Could not reach {}", url))?;
+    let branch = branches
+        .contains(&config.git_branch)
+        .then_some(config.git_branch.as_str());
+    println!("Cloning {} into {}...", url, style::path(path.display()));
+    clone_repository(&url, path, branch)?;
+
+    if let Some(repo_config) = load_repo_config(path)? {
+        println!("Imported the fuxi configuration stored in the repository.");
+        adopt_repo_config(&mut config, repo_config);
+    }
+
+    for (name, manifest) in find_profiles(path) {
+        import_profile(&mut config, &name, &manifest);
+    }
+    let mut names: Vec<String> = config
+        .profiles
+        .as_ref()
+        .map(|p| p.keys().cloned().collect())
+        .unwrap_or_default();
+    names.sort();
+    if names.is_empty() {
+        save_config(&config)?;
+        return Err("The repository has no profiles to apply.".into());
+    }
+
+    let name = match profile {
+        Some(profile) => resolve_profile(&names, profile)?,
+        None => pick_profile(&config, &names)?,
+    };
+    config.selected_profile = Some(name.clone());
+    save_config(&config)?;
+
+    apply_profile(&config, path, &name)?;
+    println!("Bootstrapped profile '{}' from {}.", name, repo);
+    Ok(())
+}
+
+/// Moves `source` from the home directory of whoever backed it up into
+/// `home`, when it's inside one like `/home/<user>/` or `C:\Users\<user>\`.
+fn rehome(source: &str, home: &Path) -> Option<PathBuf> {
+    let normalized = source.replace('\\', "/");
+    let rest = normalized
+        .strip_prefix("/home/")
+        .or_else(|| normalized.strip_prefix("/Users/"))
+        .or_else(|| {
+            let (drive, rest) = normalized.split_once(":/")?;
+            (drive.len() == 1)
+                .then(|| rest.strip_prefix("Users/"))
+                .flatten()
+        })?;
+    let (_user, rest) = rest.split_once('/')?;
+    let rehomed = home.join(rest);
+    (rehomed != Path::new(source)).then_some(rehomed)
+}
+
+/// Whether `path` is made only of names, with no `..`, root or prefix.
+fn is_inside(path: &Path) -> bool {
+    !path.as_os_str().is_empty() && path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Checks that every entry of a downloaded manifest stays inside its profile
+/// and points the files of the publisher's home directory at `home`. Files
+/// anywhere else are refused, and what the publisher recorded about owners,
+/// sudo, extended attributes and hard links is dropped: nobody should get
+/// those applied from someone else's repository. Encrypted files are left
+/// out, they need the publisher's key.
+fn validate_entries(
+    entries: Vec<ManifestEntry>,
+    home: &Path,
+) -> Result<Vec<ManifestEntry>, Box<dyn std::error::Error>> {
+    let mut valid = Vec::new();
+    let mut encrypted = 0;
+    for mut entry in entries {
+        if !is_inside(Path::new(&entry.repo_path)) {
+            return Err(format!(
+                "The manifest stores {} at '{}', outside the profile.",
+                entry.source, entry.repo_path
+            )
+            .into());
+        }
+        if let Some((stored, original)) = entry.encoded_names.iter().find(|(stored, original)| {
+            !is_inside(Path::new(stored))
+                || original.contains(['/', '\\'])
+                || !is_inside(Path::new(original))
+        }) {
+            return Err(format!(
+                "The manifest restores '{}' of {} as '{}', outside the entry.",
+                stored, entry.source, original
+            )
+            .into());
+        }
+        if entry.encrypted {
+            encrypted += 1;
+            continue;
+        }
+        if let Some(rehomed) = rehome(&entry.source, home) {
+            entry.source = rehomed.to_string_lossy().to_string();
+        }
+        if !Path::new(&entry.source)
+            .strip_prefix(home)
+            .is_ok_and(is_inside)
+        {
+            return Err(format!(
+                "The manifest restores {}, outside your home directory.",
+                entry.source
+            )
+            .into());
+        }
+        entry.owner = None;
+        entry.sudo = false;
+        entry.xattrs = false;
+        entry.hardlinks.clear();
+        valid.push(entry);
+    }
+    if encrypted > 0 {
+        println!(
+            "Skipping {} encrypted file(s), only the key they were backed up with decrypts them.",
+            encrypted
+        );
+    }
+    Ok(valid)
+}
+
+/// Clones a published backup repository to a temporary directory and applies
+/// one of its profiles, without touching the fuxi configuration or the local
+/// backup repository.
+pub fn apply_from(
+    config: &FuxiConfig,
+    repo: &str,
+    profile: Option<&str>,
+    preset: Option<&str>,
+    strategy: ConflictStrategy,
+    dry_run: bool,
+    verify: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let url = remote_url(repo);
+    // private to the user, and removed when it's dropped
+    let clone_dir = tempfile::Builder::new()
+        .prefix(&format!("fuxi-from-{}-", std::process::id()))
+        .tempdir()?;
+    let path = clone_dir.path();
+    let branches = remote_branches(&url).ok_or_else(|| format!("Could not reach {}", url))?;
+    let branch = branches
+        .contains(&config.git_branch)
+        .then_some(config.git_branch.as_str());
+    println!("Cloning {}...", url);
+    let result = clone_repository(&url, path, branch)
+        .and_then(|()| apply_clone(config, path, profile, preset, strategy, dry_run, verify));
+    clone_dir.close()?;
+    result
+}
+
+fn apply_clone(
+    config: &FuxiConfig,
+    path: &Path,
+    profile: Option<&str>,
+    preset: Option<&str>,
+    strategy: ConflictStrategy,
+    dry_run: bool,
+    verify: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let profiles = find_profiles(path);
+    let names: Vec<String> = profiles.iter().map(|(name, _)| name.clone()).collect();
+    if names.is_empty() {
+        return Err("The repository has no profiles with a manifest to apply.".into());
+    }
+    let name = match profile {
+        Some(profile) => resolve_profile(&names, profile)?,
+        None => pick_profile(config, &names)?,
+    };
+    let (_, manifest) = profiles
+        .into_iter()
+        .find(|(n, _)| *n == name)
+        .expect("resolved to a profile of the repository");
+
+    let entries = match preset {
+        Some(preset) => {
+            let entries = manifest.preset_entries(preset);
+            if entries.is_empty() {
+                return Err(
+                    format!("No files belong to preset '{}' in this backup.", preset).into(),
+                );
+            }
+            entries
+        }
+        None => manifest.entries.clone(),
+    };
+    let home = dirs::home_dir().ok_or("Could not determine home directory")?;
+    let entries = validate_entries(entries, &home)?;
+    if !manifest.registry.is_empty() {
+        println!(
+            "Skipping {} registry key(s), they are only imported from your own backups.",
+            manifest.registry.len()
+        );
+    }
+
+    // without a repository path the submodules listed in the clone's
+    // .gitmodules aren't cloned, they could point anywhere
+    let config = FuxiConfig {
+        backup_repo_path: None,
+        ..config.clone()
+    };
+    println!(
+        "Applying profile '{}' ({}) from the cloned repository.",
+        name, manifest.backup_id
+    );
+    let layout = ProfileLayout::new(&path.join(&name), &get_machine_name(&config));
+    apply_backup(
+        &config,
+        &layout,
+        &entries,
+        &manifest.backup_id,
+        strategy,
+        dry_run,
+        verify,
+    )
+}
+
+/// Where `fuxi bootstrap` clones to when no path is given.
+pub fn default_bootstrap_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|d| d.join("fuxi").join("backups"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ownership::Ownership;
+
+    fn entry(source: &str, repo_path: &str) -> ManifestEntry {
+        ManifestEntry {
+            source: source.to_string(),
+            repo_path: repo_path.to_string(),
+            preset: None,
+            template: false,
+            encrypted: false,
+            owner: None,
+            xattrs: false,
+            hardlinks: Vec::new(),
+            sudo: false,
+            encoded_names: Default::default(),
+        }
+    }
+
+    fn rejected(entry: ManifestEntry) -> bool {
+        validate_entries(vec![entry], Path::new("/home/me")).is_err()
+    }
+
+    #[test]
+    fn rehomes_the_publishers_files() {
+        let entries = validate_entries(
+            vec![entry("/home/alice/.config/nvim", ".config/nvim")],
+            Path::new("/home/me"),
+        )
+        .unwrap();
+        assert_eq!(entries[0].source, "/home/me/.config/nvim");
+    }
+
+    #[test]
+    fn rejects_files_outside_the_home_directory() {
+        assert!(rejected(entry("/etc/sudoers", "_root/etc/sudoers")));
+        assert!(rejected(entry("/home/alice", "home")));
+        assert!(rejected(entry("/home/alice/../../etc/passwd", "passwd")));
+        assert!(rejected(entry("/home/me/../other/.bashrc", ".bashrc")));
+    }
+
+    #[test]
+    fn rejects_paths_outside_the_profile() {
+        assert!(rejected(entry("/home/alice/.bashrc", "../.bashrc")));
+        assert!(rejected(entry("/home/alice/.bashrc", "/tmp/.bashrc")));
+        let mut escaping = entry("/home/alice/.config", ".config");
+        escaping
+            .encoded_names
+            .insert("a_".to_string(), "../../.ssh".to_string());
+        assert!(rejected(escaping));
+    }
+
+    #[test]
+    fn drops_what_the_publisher_recorded_about_permissions() {
+        let mut hostile = entry("/home/alice/.local/bin", ".local/bin");
+        hostile.sudo = true;
+        hostile.xattrs = true;
+        hostile.owner = Some(Ownership {
+            user: "root".to_string(),
+            group: "root".to_string(),
+        });
+        hostile.hardlinks = vec![vec![
+            "run".to_string(),
+            "../../.ssh/authorized_keys".to_string(),
+        ]];
+        let entries = validate_entries(vec![hostile], Path::new("/home/me")).unwrap();
+        assert!(!entries[0].sudo);
+        assert!(!entries[0].xattrs);
+        assert_eq!(entries[0].owner, None);
+        assert!(entries[0].hardlinks.is_empty());
+    }
+}
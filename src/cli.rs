@@ -1,7 +1,13 @@
 use std::path::PathBuf;
 
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeDelta, Utc};
 use clap::{Command, arg};
 
+use crate::conflict::STRATEGY_NAMES;
+use crate::exit;
+use crate::migrate::TOOLS;
+use crate::packages::MANAGERS;
+
 pub fn confirm(prompt: &str) -> Result<bool, Box<dyn std::error::Error>> {
     use std::io::{self, Write};
 
@@ -15,11 +21,109 @@ pub fn confirm(prompt: &str) -> Result<bool, Box<dyn std::error::Error>> {
     Ok(input == "y" || input == "yes")
 }
 
+pub fn prompt(prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+    use std::io::{self, Write};
+
+    print!("{}: ", prompt);
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    // prompts that ask again on empty input would otherwise spin forever
+    if io::stdin().read_line(&mut input)? == 0 {
+        return Err("No input, stdin is closed.".into());
+    }
+    Ok(input.trim().to_string())
+}
+
+/// Files `apply` overwrites before it asks, without `confirm_overwrite_threshold`.
+pub const DEFAULT_OVERWRITE_THRESHOLD: usize = 50;
+/// Backups or files `prune` and `clean` delete before they ask, without
+/// `confirm_delete_threshold`.
+pub const DEFAULT_DELETE_THRESHOLD: usize = 10;
+
+/// Asks before an operation that overwrites or deletes `count` things, once
+/// that's more than `threshold`: prints `summary` and has the user type
+/// `name`, the profile's name, which takes more than a reflexive "y".
+/// Smaller operations go ahead without asking.
+pub fn confirm_large(
+    summary: &str,
+    count: usize,
+    threshold: usize,
+    name: &str,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    use std::io::{self, IsTerminal};
+
+    if count <= threshold {
+        return Ok(true);
+    }
+    println!("{}", summary);
+    if !io::stdin().is_terminal() {
+        return Err(exit::config_error(format!(
+            "There's no terminal to confirm this in by typing '{}'. Pass --yes to go ahead.",
+            name
+        )));
+    }
+    Ok(prompt(&format!("Type '{}' to go ahead", name))? == name)
+}
+
+/// Lets the user narrow `items` down by typing and pick one. Returns `None`
+/// when the selection is cancelled with Esc.
+pub fn fuzzy_select(
+    prompt: &str,
+    items: &[String],
+    default: Option<usize>,
+) -> Result<Option<usize>, Box<dyn std::error::Error>> {
+    let mut select = dialoguer::FuzzySelect::new()
+        .with_prompt(prompt)
+        .items(items);
+    if let Some(default) = default {
+        select = select.default(default);
+    }
+    Ok(select.interact_opt()?)
+}
+
+/// Parses `--since`: a date such as `2025-01-31`, or a number of days ago
+/// such as `7d`.
+fn parse_since(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Some(days) = value.strip_suffix('d')
+        && let Ok(days) = days.parse::<i64>()
+    {
+        return Ok(Utc::now() - TimeDelta::days(days));
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| "expected a date like 2025-01-31 or a number of days like 7d".to_string())?;
+    date.and_time(NaiveTime::MIN)
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        .ok_or_else(|| format!("{} has no midnight in the local time zone", value))
+}
+
+/// Parses a backup tag, which can't be empty or contain commas or spaces.
+fn parse_tag(value: &str) -> Result<String, String> {
+    if value.is_empty() || value.contains(|c: char| c == ',' || c.is_whitespace()) {
+        return Err("expected a tag without commas or spaces, like pre-upgrade".to_string());
+    }
+    Ok(value.to_string())
+}
+
 pub fn cli() -> Command {
     Command::new("fuxi")
         .about("fuxi CLI")
         .subcommand_required(true)
         .arg_required_else_help(true)
+        // `fuxi <name>` runs a `fuxi-<name>` plugin from PATH
+        .allow_external_subcommands(true)
+        .arg(arg!(--wait "Wait for another running fuxi to finish instead of failing").global(true))
+        .arg(
+            arg!(--porcelain "Print stable, tab separated output for scripts")
+                .global(true),
+        )
+        .arg(arg!(--"no-color" "Print without colors, like setting NO_COLOR").global(true))
+        .arg(
+            arg!(--profile <NAME> "Use this profile instead of the selected one, without switching to it")
+                .global(true),
+        )
         .subcommand(Command::new("version").about("Show version information"))
         .subcommand(
             Command::new("config")
@@ -29,29 +133,51 @@ pub fn cli() -> Command {
         .subcommand(
             Command::new("init")
                 .about("Initialize Git backup repository")
-                .arg(arg!(<REPO> "GitHub repository (username/repo-name)"))
+                .arg(arg!(<REPO> "Remote repository URL (SSH or HTTPS), or username/repo-name for GitHub"))
                 .arg(
                     arg!(<PATH> "Local backup repository path")
                         .value_parser(clap::value_parser!(PathBuf)),
+                )
+                .arg(arg!(--"public-ok" "Use the repository even if it is public")),
+        )
+        .subcommand(
+            Command::new("setup")
+                .about("Set up the backup repository, a first profile and the first backup step by step"),
+        )
+        .subcommand(
+            Command::new("bootstrap")
+                .about("Set up a new machine from an existing backup repository")
+                .arg(arg!(<REPO> "Remote repository URL, or username/repo-name for GitHub"))
+                .arg(
+                    arg!(--path <PATH> "Where to clone the repository")
+                        .value_parser(clap::value_parser!(PathBuf)),
                 ),
         )
         .subcommand(
             Command::new("profile")
                 .about("Manage profiles")
                 .arg_required_else_help(true)
-                .subcommand(Command::new("list").about("List all profiles"))
+                .subcommand(Command::new("list").visible_alias("ls").about("List all profiles"))
                 .subcommand(
                     Command::new("create")
                         .about("Create a new profile")
-                        .arg(arg!(<NAME> "Profile name")),
+                        .arg(arg!(<NAME> "Profile name"))
+                        .arg(arg!(--branch <BRANCH> "Keep this profile's backups on their own branch"))
+                        .arg(arg!(--remote <URL> "Push this profile to its own remote (needs --path)"))
+                        .arg(
+                            arg!(--path <PATH> "Keep this profile in its own local repository")
+                                .value_parser(clap::value_parser!(PathBuf)),
+                        ),
                 )
                 .subcommand(
                     Command::new("switch")
+                        .visible_alias("select")
                         .about("Switch to a profile")
-                        .arg(arg!(<NAME> "Profile name")),
+                        .arg(arg!([NAME] "Profile name or the start of one, picked from a list when left out")),
                 )
                 .subcommand(
                     Command::new("delete")
+                        .visible_aliases(["rm", "remove"])
                         .about("Delete a profile")
                         .arg(arg!(<NAME> "Profile name")),
                 ),
@@ -60,31 +186,362 @@ pub fn cli() -> Command {
             Command::new("path")
                 .about("Manage paths")
                 .arg_required_else_help(true)
-                .subcommand(Command::new("list").about("List all paths"))
-                .subcommand(Command::new("add").about("Add path(s)").arg(
-                    arg!(<PATH> ... "Paths to add").value_parser(clap::value_parser!(PathBuf)),
-                ))
-                .subcommand(Command::new("remove").about("Remove path(s)").arg(
+                .subcommand(
+                    Command::new("list")
+                        .visible_alias("ls")
+                        .about("List all paths")
+                        .arg(arg!(--check "Show whether each path exists, its size, its last change and whether it differs from the last backup"))
+                        .arg(arg!(-g --group <GROUP> "Only list the paths in a group")),
+                )
+                .subcommand(
+                    Command::new("add")
+                        .about("Add path(s)")
+                        .arg(
+                            arg!(<PATH> ... "Paths to add")
+                                .value_parser(clap::value_parser!(PathBuf)),
+                        )
+                        .arg(arg!(-p --preset <NAME> "Preset to group the paths under"))
+                        .arg(arg!(-g --group <GROUP> "Group to list and back up the paths with"))
+                        .arg(arg!(-n --note <NOTE> "A note to keep with the paths"))
+                        .arg(arg!(-t --template "Render the paths as templates when applying"))
+                        .arg(arg!(-x --xattrs "Keep the paths' extended attributes, capabilities and ACLs"))
+                        .arg(arg!(-f --force "Add paths that look sensitive without encrypting them")),
+                )
+                .subcommand(Command::new("remove").visible_alias("rm").about("Remove path(s)").arg(
                     arg!(<PATH> ... "Paths to remove").value_parser(clap::value_parser!(PathBuf)),
-                )),
+                ))
+                .subcommand(
+                    Command::new("move")
+                        .visible_alias("mv")
+                        .about("Track a path at a new location, keeping its options")
+                        .arg(arg!(<FROM> "Tracked path or its alias").value_parser(clap::value_parser!(PathBuf)))
+                        .arg(arg!(<TO> "Where the path is now").value_parser(clap::value_parser!(PathBuf))),
+                ),
         )
         .subcommand(
             Command::new("backup")
                 .about("Create a backup")
-                .arg(arg!(-m --message <MESSAGE> "Backup commit message"))
-                .arg(arg!(--push "Push to GitHub after backup")),
+                .arg(arg!(-m --message <MESSAGE> "Backup commit message, fills {{ message }} in commit_message_template"))
+                .arg(arg!(--push "Push to the remote after backup"))
+                .arg(arg!(--"auto-push" "Push as push_policy says, or after every backup without one"))
+                .arg(arg!(-y --yes "Don't prompt, rebase onto remote backups when the push diverges"))
+                .arg(arg!(--notify "Show a desktop notification with the result"))
+                .arg(arg!(--"allow-secrets" "Push even if files look like they contain secrets"))
+                .arg(arg!(--"public-ok" "Push even if the repository is public"))
+                .arg(arg!(--prune "Apply the retention policy from the config after pushing"))
+                .arg(arg!(--"include-vcs-dirs" "Also copy .git, .hg and .svn directories inside tracked directories"))
+                .arg(arg!(--"respect-gitignore" "Leave out files ignored by .gitignore files inside tracked directories"))
+                .arg(arg!(--"fail-fast" "Stop at the first file that can't be copied instead of reporting it at the end"))
+                .arg(arg!(--submodules "Record git repositories inside tracked directories as submodules instead of copying them"))
+                .arg(arg!(-g --group <GROUP> "Only back up the paths in a group"))
+                .arg(arg!(-t --tag <TAG> ... "Tag the backup, e.g. pre-upgrade").value_parser(parse_tag))
+                .arg(
+                    arg!(--"dry-run" "Show which files would be copied or skipped without writing to the repository")
+                        .conflicts_with_all(["push", "auto-push", "tag", "prune"]),
+                ),
         )
         .subcommand(
             Command::new("apply")
                 .about("Apply a backup ID")
-                .arg(arg!(<ID> "Backup ID or commit hash"))
-                .arg(arg!(-d --dryrun "Show what would be done without making changes")),
+                .arg(arg!([ID] "Backup ID or commit hash, picked from a list when left out"))
+                .arg(arg!(-d --dryrun "Show what would be done without making changes"))
+                .arg(
+                    arg!(-s --strategy <STRATEGY> "How to handle live files that differ from the backup")
+                        .value_parser(STRATEGY_NAMES),
+                )
+                .arg(arg!(-p --preset <NAME> "Only apply the files belonging to a preset"))
+                .arg(arg!(--"from-machine" <MACHINE> "Apply the files another machine backed up"))
+                .arg(arg!(--verify "Check that every restored file matches the backup"))
+                .arg(arg!(-y --yes "Don't ask before overwriting more files than confirm_overwrite_threshold"))
+                .arg(
+                    arg!(--from <URL> "Apply a profile of another backup repository, without setting it up")
+                        .conflicts_with_all(["ID", "from-machine"]),
+                )
+                .arg(
+                    arg!(--"target-dir" <DIR> "Restore below DIR instead of the live system, e.g. a mounted disk image")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .conflicts_with("from"),
+                ),
         )
         .subcommand(
             Command::new("save")
                 .about("Save current configuration")
-                .arg(arg!(-m --message <MESSAGE> "Commit message"))
-                .arg(arg!(--force "Force save without confirmation")),
+                .arg(arg!(-m --message <MESSAGE> "Commit message, fills {{ message }} in commit_message_template"))
+                .arg(arg!(--force "Force save without confirmation"))
+                .arg(arg!(--diff "Show how many lines change in each file"))
+                .arg(arg!(--"allow-secrets" "Push even if files look like they contain secrets"))
+                .arg(arg!(--"public-ok" "Push even if the repository is public")),
+        )
+        .subcommand(
+            Command::new("list")
+                .visible_alias("ls")
+                .about("List all backups")
+                .arg(arg!(--machine <MACHINE> "Only show backups made by a hostname or machine ID"))
+                .arg(
+                    arg!(--since <DATE> "Only show backups made since a date (2025-01-31) or a number of days (7d)")
+                        .value_parser(parse_since),
+                )
+                .arg(
+                    arg!(-n --limit <COUNT> "Only show the most recent COUNT backups")
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(arg!(--remote "Show the backups on the remote that aren't in the local repository yet"))
+                .arg(arg!(-t --tag <TAG> "Only show backups with a tag").value_parser(parse_tag)),
+        )
+        .subcommand(
+            Command::new("log")
+                .about("Show the history of fuxi operations, newest first")
+                .arg(arg!([COMMAND] "Only show operations of a command, e.g. backup"))
+                .arg(arg!(--failed "Only show operations that failed"))
+                .arg(
+                    arg!(-n --limit <COUNT> "Show at most COUNT operations")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("20"),
+                ),
+        )
+        .subcommand(
+            Command::new("tag")
+                .about("Tag a backup, e.g. to mark an important snapshot")
+                .arg(arg!(<ID> "Backup ID or commit hash"))
+                .arg(arg!(<TAG> ... "Tags to add").value_parser(parse_tag)),
+        )
+        .subcommand(
+            Command::new("daemon")
+                .about("Serve a JSON-RPC API on a local socket for editors, tray apps and scripts"),
+        )
+        .subcommand(
+            Command::new("plugins")
+                .about("List the fuxi-<name> plugins on PATH, the hook script and the configured hooks"),
+        )
+        .subcommand(
+            Command::new("migrate")
+                .about("Track the dotfiles another dotfile manager manages")
+                .arg(
+                    arg!(--from <TOOL> "The dotfile manager to migrate from")
+                        .required(true)
+                        .value_parser(TOOLS),
+                )
+                .arg(
+                    arg!([DIR] "Its source directory, repository, stow directory or dotbot repository")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                )
+                .arg(arg!(--backup "Back up and push the migrated files right away")),
+        )
+        .subcommand(
+            Command::new("search")
+                .about("Search the contents of the backed up files")
+                .arg(arg!(<PATTERN> "Regular expression to look for"))
+                .arg(arg!(-i --"ignore-case" "Match regardless of case"))
+                .arg(arg!(--history "Search every backup, not only the current files")),
+        )
+        .subcommand(
+            Command::new("tui")
+                .about("Browse profiles, paths and backups, and back up or apply them from the keyboard"),
+        )
+        .subcommand(
+            Command::new("sync")
+                .about("Pull, apply newer files, back up local changes and push")
+                .arg(arg!(--"dry-run" "Show what would be synced without making changes"))
+                .arg(arg!(--"allow-secrets" "Push even if files look like they contain secrets"))
+                .arg(arg!(--"public-ok" "Push even if the repository is public")),
+        )
+        .subcommand(
+            Command::new("watch")
+                .about("Back up automatically when tracked files change")
+                .arg(arg!(--push "Push every automatic backup, or as push_policy says")),
+        )
+        .subcommand(
+            Command::new("schedule")
+                .about("Run backups periodically with the system scheduler")
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("install")
+                        .about("Install a systemd timer, launchd agent or scheduled task")
+                        .arg(
+                            arg!(--every <INTERVAL> "How often to back up, e.g. 30m, 6h or 1d")
+                                .default_value("6h"),
+                        ),
+                )
+                .subcommand(Command::new("remove").visible_alias("rm").about("Remove the scheduled backups"))
+                .subcommand(Command::new("status").about("Show the scheduled backups")),
+        )
+        .subcommand(
+            Command::new("packages")
+                .about("Capture and reinstall installed packages")
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("capture")
+                        .about("Export installed package lists into the backup repository")
+                        .arg(
+                            arg!(-m --manager <MANAGER> ... "Only capture these package managers")
+                                .value_parser(MANAGERS.map(|m| m.name)),
+                        ),
+                )
+                .subcommand(
+                    Command::new("apply")
+                        .about("Reinstall packages from the captured lists")
+                        .arg(
+                            arg!(-m --manager <MANAGER> ... "Only reinstall these package managers")
+                                .value_parser(MANAGERS.map(|m| m.name)),
+                        )
+                        .arg(arg!(--"from-machine" <MACHINE> "Use the lists another machine captured"))
+                        .arg(arg!(-d --dryrun "Show the install commands without running them")),
+                ),
+        )
+        .subcommand(
+            Command::new("crypt")
+                .about("Encrypt the whole backup repository with git-crypt")
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("init")
+                        .about("Enable git-crypt for every file in the repository")
+                        .arg(arg!(--"gpg-user" <ID> ... "Share the key with a gpg user")),
+                )
+                .subcommand(
+                    Command::new("unlock")
+                        .about("Decrypt the repository on this machine")
+                        .arg(
+                            arg!([KEY] "Exported key file, gpg is used when omitted")
+                                .value_parser(clap::value_parser!(PathBuf)),
+                        ),
+                )
+                .subcommand(Command::new("lock").about("Encrypt the local working copy again"))
+                .subcommand(
+                    Command::new("export-key")
+                        .about("Export the symmetric key to unlock other machines")
+                        .arg(
+                            arg!(<PATH> "Where to write the key")
+                                .value_parser(clap::value_parser!(PathBuf)),
+                        ),
+                )
+                .subcommand(Command::new("status").about("Show whether git-crypt is enabled and unlocked")),
+        )
+        .subcommand(
+            Command::new("secret")
+                .about("Manage paths that are encrypted with age before backup")
+                .arg_required_else_help(true)
+                .subcommand(Command::new("list").visible_alias("ls").about("List secret paths"))
+                .subcommand(
+                    Command::new("add")
+                        .about("Mark paths as secret, tracking them if needed")
+                        .arg(arg!(<PATH> ... "Paths to encrypt").value_parser(clap::value_parser!(PathBuf))),
+                )
+                .subcommand(
+                    Command::new("remove")
+                        .visible_alias("rm")
+                        .about("Stop encrypting paths")
+                        .arg(arg!(<PATH> ... "Paths to stop encrypting").value_parser(clap::value_parser!(PathBuf))),
+                ),
+        )
+        .subcommand(
+            Command::new("registry")
+                .about("Manage Windows registry keys exported with the selected profile")
+                .arg_required_else_help(true)
+                .subcommand(Command::new("list").visible_alias("ls").about("List tracked registry keys"))
+                .subcommand(
+                    Command::new("add")
+                        .about("Export keys on backup and import them on apply")
+                        .arg(arg!(<KEY> ... "Registry keys, e.g. HKCU\\Software\\Vendor\\App")),
+                )
+                .subcommand(
+                    Command::new("remove")
+                        .visible_alias("rm")
+                        .about("Stop tracking registry keys")
+                        .arg(arg!(<KEY> ... "Registry keys to stop tracking")),
+                ),
+        )
+        .subcommand(
+            Command::new("remote")
+                .about("Manage additional remotes every push is mirrored to")
+                .arg_required_else_help(true)
+                .subcommand(Command::new("list").visible_alias("ls").about("List the remote and its mirrors"))
+                .subcommand(
+                    Command::new("add")
+                        .about("Add a mirror remote")
+                        .arg(arg!(<URL> "Remote URL, or username/repo-name for GitHub")),
+                )
+                .subcommand(
+                    Command::new("remove")
+                        .visible_alias("rm")
+                        .about("Remove a mirror remote")
+                        .arg(arg!(<URL> "Remote URL to remove")),
+                ),
+        )
+        .subcommand(
+            Command::new("clean")
+                .about("Prune cached pre-apply snapshots and delete repository files no tracked path backs up")
+                .arg(arg!(--"dry-run" "Show what would be deleted without deleting anything"))
+                .arg(arg!(-y --yes "Delete orphaned repository files without asking")),
+        )
+        .subcommand(
+            Command::new("gc")
+                .about("Delete stale snapshots, orphaned staging directories, old logs and outdated hash cache entries")
+                .arg(arg!(--"dry-run" "Show what would be deleted and how much space that frees")),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Pack a backup into a self-contained archive")
+                .arg(arg!(-b --backup <ID> "Backup ID to export instead of the local backup directory as it is"))
+                .arg(
+                    arg!(-o --output <FILE> "Archive to write, e.g. snapshot.tar.gz")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .required_unless_present("script"),
+                )
+                .arg(
+                    arg!(--script <FILE> "Write a script restoring the selected profile without fuxi instead, PowerShell for .ps1")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .conflicts_with("output"),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Unpack an archive from 'fuxi export' into the backup directory")
+                .arg(
+                    arg!(<FILE> "Archive to import")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                ),
+        )
+        .subcommand(
+            Command::new("prune")
+                .about("Delete old backups from the storage backend")
+                .arg(
+                    arg!(-k --"keep-last" <COUNT> "Number of recent backups to keep")
+                        .visible_alias("keep")
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(
+                    arg!(--"keep-days" <DAYS> "Keep the backups made in the last DAYS days")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(arg!(--"dry-run" "Show what would be deleted without deleting anything"))
+                .arg(arg!(-y --yes "Rewrite the history of a git repository without asking")),
+        )
+        .subcommand(Command::new("flush").about("Push the backups queued while offline"))
+        .subcommand(
+            Command::new("repair")
+                .about("Fix a backup repository left in a broken state")
+                .arg(arg!(--"dry-run" "Only report the problems found")),
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("Show how big the backup repository is and what takes up the space")
+                .arg(
+                    arg!(-n --backups <COUNT> "Number of recent backups to show the growth of")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("5"),
+                )
+                .arg(
+                    arg!(--top <COUNT> "Number of largest files to list")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("10"),
+                ),
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Check the backed up files against the checksums recorded at backup time"),
+        )
+        .subcommand(
+            Command::new("validate")
+                .about("Check the profiles' paths and the config file for mistakes"),
         )
-        .subcommand(Command::new("list").about("List all backups"))
 }
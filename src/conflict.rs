@@ -0,0 +1,126 @@
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cli::confirm;
+use crate::style;
+
+pub const STRATEGY_NAMES: [&str; 4] = ["overwrite", "skip", "prompt", "keep-newer"];
+
+// max number of changed lines shown before asking in prompt mode
+const DIFF_PREVIEW_LINES: usize = 10;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictStrategy {
+    #[default]
+    Overwrite,
+    Skip,
+    Prompt,
+    KeepNewer,
+}
+
+impl FromStr for ConflictStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "overwrite" => Ok(Self::Overwrite),
+            "skip" => Ok(Self::Skip),
+            "prompt" => Ok(Self::Prompt),
+            "keep-newer" => Ok(Self::KeepNewer),
+            _ => Err(format!("Unknown conflict strategy '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for ConflictStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Overwrite => "overwrite",
+            Self::Skip => "skip",
+            Self::Prompt => "prompt",
+            Self::KeepNewer => "keep-newer",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+pub fn files_differ(a: &Path, b: &Path) -> std::io::Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(true);
+    }
+    Ok(fs::read(a)? != fs::read(b)?)
+}
+
+/// Builds a short line-based diff between the live file and the backup copy.
+pub fn short_diff(live: &Path, backup: &Path) -> String {
+    let (Ok(live_bytes), Ok(backup_bytes)) = (fs::read(live), fs::read(backup)) else {
+        return "  (unable to read files for diff)".to_string();
+    };
+    let (Ok(live_text), Ok(backup_text)) = (
+        String::from_utf8(live_bytes),
+        String::from_utf8(backup_bytes),
+    ) else {
+        return "  (binary files differ)".to_string();
+    };
+
+    let live_lines: Vec<&str> = live_text.lines().collect();
+    let backup_lines: Vec<&str> = backup_text.lines().collect();
+
+    // trim the common prefix and suffix so only the changed region is shown
+    let prefix = live_lines
+        .iter()
+        .zip(&backup_lines)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = live_lines[prefix..]
+        .iter()
+        .rev()
+        .zip(backup_lines[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let removed = &live_lines[prefix..live_lines.len() - suffix];
+    let added = &backup_lines[prefix..backup_lines.len() - suffix];
+
+    let mut out = format!("  @@ line {} @@\n", prefix + 1);
+    let changes = removed
+        .iter()
+        .map(|l| format!("  - {}", l))
+        .chain(added.iter().map(|l| format!("  + {}", l)));
+    let total = removed.len() + added.len();
+    for line in changes.take(DIFF_PREVIEW_LINES) {
+        out.push_str(&line);
+        out.push('\n');
+    }
+    if total > DIFF_PREVIEW_LINES {
+        out.push_str(&format!(
+            "  ... {} more changed lines\n",
+            total - DIFF_PREVIEW_LINES
+        ));
+    }
+    out
+}
+
+/// Decides whether the backup copy should replace a live file that differs from it.
+pub fn should_overwrite(
+    strategy: ConflictStrategy,
+    live: &Path,
+    backup: &Path,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    match strategy {
+        ConflictStrategy::Overwrite => Ok(true),
+        ConflictStrategy::Skip => Ok(false),
+        ConflictStrategy::KeepNewer => {
+            let live_mtime = fs::metadata(live)?.modified()?;
+            let backup_mtime = fs::metadata(backup)?.modified()?;
+            Ok(backup_mtime > live_mtime)
+        }
+        ConflictStrategy::Prompt => {
+            println!("{} differs from the backup:", style::path(live.display()));
+            print!("{}", short_diff(live, backup));
+            confirm(&format!("Overwrite {} with the backup?", live.display()))
+        }
+    }
+}
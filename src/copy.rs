@@ -1,188 +1,813 @@
 use crate::cli::confirm;
-use std::{fs, path::Path, process::Command};
+use crate::hash_cache::HashCache;
+use crate::style;
+use crate::vss;
+use std::{
+    ffi::{OsStr, OsString},
+    fmt::Display,
+    fs,
+    io::{ErrorKind, IsTerminal},
+    path::{Component, Path, PathBuf, Prefix},
+    process::Command,
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread,
+    time::Duration,
+};
 
-pub fn copy_dir_recursive(src: &Path, dst: &Path) -> std::io::Result<()> {
-    fs::create_dir_all(dst)?;
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let src_path = entry.path();
-        let dst_path = dst.join(entry.file_name());
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
 
-        if src_path.is_dir() {
-            copy_dir_recursive(&src_path, &dst_path)?;
-        } else {
-            fs::copy(&src_path, &dst_path)?;
+const VCS_DIRS: [&str; 3] = [".git", ".hg", ".svn"];
+
+/// Whether the `include_vcs_dirs` setting or `backup --include-vcs-dirs` asked
+/// for repositories nested in tracked directories to be copied too. Set once
+/// the command line is parsed.
+static INCLUDE_VCS_DIRS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_include_vcs_dirs(include: bool) {
+    INCLUDE_VCS_DIRS.store(include, Ordering::Relaxed);
+}
+
+/// Whether `name` is a `.git`, `.hg` or `.svn` entry that copies leave out.
+/// Nested repositories would otherwise be committed as broken submodules.
+pub fn is_skipped_vcs_dir(name: &OsStr) -> bool {
+    !INCLUDE_VCS_DIRS.load(Ordering::Relaxed) && VCS_DIRS.iter().any(|dir| name == *dir)
+}
+
+/// Whether the `respect_gitignore` setting or `backup --respect-gitignore`
+/// asked for `.gitignore` files inside tracked directories to be honored.
+/// Set once the command line is parsed.
+static RESPECT_GITIGNORE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_respect_gitignore(respect: bool) {
+    RESPECT_GITIGNORE.store(respect, Ordering::Relaxed);
+}
+
+/// Lists the files below `root` that no `.gitignore` inside `root` ignores.
+/// Ignore files above `root` and the global excludes don't apply.
+fn unignored_files(root: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let walk = WalkBuilder::new(long_path(root))
+        .standard_filters(false)
+        .git_ignore(true)
+        .require_git(false)
+        .parents(false)
+        .follow_links(true)
+        .filter_entry(|entry| !is_skipped_vcs_dir(entry.file_name()))
+        .build();
+    for entry in walk {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) if link_loop(&e).is_some() => {
+                if let Some(child) = link_loop(&e) {
+                    println!(
+                        "{}",
+                        style::warning(format!(
+                            "Warning: Skipped {}, it links back to a directory it's in",
+                            child.display()
+                        ))
+                    );
+                }
+                continue;
+            }
+            Err(ignore::Error::WithDepth { err, .. }) => match *err {
+                ignore::Error::WithPath { path, err } => {
+                    tolerate(&path, err).map_err(std::io::Error::other)?;
+                    continue;
+                }
+                err => return Err(std::io::Error::other(err)),
+            },
+            Err(ignore::Error::WithPath { path, err }) => {
+                tolerate(&path, err).map_err(std::io::Error::other)?;
+                continue;
+            }
+            Err(e) => return Err(std::io::Error::other(e)),
+        };
+        if entry.file_type().is_some_and(|kind| !kind.is_dir()) && !skip_special(entry.path()) {
+            let rel = entry
+                .path()
+                .strip_prefix(long_path(root))
+                .unwrap_or(entry.path());
+            files.push(rel.to_path_buf());
         }
     }
+    files.sort();
+    Ok(files)
+}
+
+/// The hash cache a backup compares each file with its copy in the repo
+/// through, set while it copies. Copies that wouldn't change the repo's file
+/// are skipped, so its mtime stays and git doesn't rehash it either.
+static UNCHANGED_CHECK: Mutex<Option<HashCache>> = Mutex::new(None);
+
+pub fn skip_unchanged(cache: HashCache) {
+    if let Ok(mut check) = UNCHANGED_CHECK.lock() {
+        *check = Some(cache);
+    }
+}
+
+/// Stops skipping unchanged copies, handing back the cache to be saved.
+pub fn take_hash_cache() -> Option<HashCache> {
+    UNCHANGED_CHECK.lock().ok()?.take()
+}
+
+/// Whether `dst` already has the content and permissions of `src`, when a
+/// backup set [`skip_unchanged`].
+fn unchanged(src: &Path, dst: &Path) -> bool {
+    let Ok(mut check) = UNCHANGED_CHECK.lock() else {
+        return false;
+    };
+    let Some(cache) = check.as_mut() else {
+        return false;
+    };
+    let (Ok(src_meta), Ok(dst_meta)) = (fs::metadata(src), fs::metadata(dst)) else {
+        return false;
+    };
+    dst_meta.is_file()
+        && src_meta.permissions() == dst_meta.permissions()
+        && cache.differ(src, dst).is_ok_and(|differ| !differ)
+}
+
+/// Whether `backup --fail-fast` asked for the first file that can't be
+/// copied to abort the copy. Set once the command line is parsed.
+static FAIL_FAST: AtomicBool = AtomicBool::new(false);
+
+pub fn set_fail_fast(fail_fast: bool) {
+    FAIL_FAST.store(fail_fast, Ordering::Relaxed);
+}
+
+/// Files that couldn't be copied since the failures were last taken, with
+/// the reason.
+static FAILURES: Mutex<Vec<(PathBuf, String)>> = Mutex::new(Vec::new());
+
+/// Hands `error` back when failing fast. Otherwise records it against
+/// `path`, with a warning, so the copy can go on with the other files.
+pub fn tolerate<E: Display>(path: &Path, error: E) -> Result<(), E> {
+    if FAIL_FAST.load(Ordering::Relaxed) {
+        return Err(error);
+    }
+    println!(
+        "{}",
+        style::warning(format!(
+            "Warning: Could not copy {}: {}",
+            path.display(),
+            error
+        ))
+    );
+    if let Ok(mut failures) = FAILURES.lock() {
+        failures.push((path.to_path_buf(), error.to_string()));
+    }
     Ok(())
 }
 
-pub fn sudo_copy(src: &Path, dst: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    if let Some(parent) = dst.parent() {
-        let status = Command::new("sudo")
-            .arg("mkdir")
-            .arg("-p")
-            .arg(parent)
-            .status()?;
-        if !status.success() {
-            return Err(format!("sudo mkdir failed for {}", parent.display()).into());
-        }
+/// The failures recorded by [`tolerate`] since the last call.
+pub fn take_failures() -> Vec<(PathBuf, String)> {
+    FAILURES
+        .lock()
+        .map(|mut failures| std::mem::take(&mut *failures))
+        .unwrap_or_default()
+}
+
+/// Special files skipped so far by this process.
+static SKIPPED_SPECIAL_FILES: AtomicUsize = AtomicUsize::new(0);
+
+pub fn skipped_special_files() -> usize {
+    SKIPPED_SPECIAL_FILES.load(Ordering::Relaxed)
+}
+
+#[cfg(unix)]
+fn special_kind(path: &Path) -> Option<&'static str> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let kind = fs::metadata(path).ok()?.file_type();
+    if kind.is_socket() {
+        Some("socket")
+    } else if kind.is_fifo() {
+        Some("FIFO")
+    } else if kind.is_block_device() || kind.is_char_device() {
+        Some("device node")
+    } else {
+        None
     }
-    let status = Command::new("sudo")
-        .arg("cp")
-        .arg("-a")
-        .arg(src.as_os_str())
-        .arg(dst.as_os_str())
-        .status()?;
-    if status.success() {
-        Ok(())
+}
+
+#[cfg(windows)]
+fn special_kind(path: &Path) -> Option<&'static str> {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+    let metadata = fs::symlink_metadata(long_path(path)).ok()?;
+    // links and junctions are followed, but app execution aliases and
+    // dangling links can't be opened at all
+    if metadata.file_attributes() & FILE_ATTRIBUTE_REPARSE_POINT != 0
+        && fs::metadata(long_path(path)).is_err()
+    {
+        Some("reparse point")
     } else {
-        Err(format!(
-            "sudo cp failed copying {} to {}",
-            src.display(),
-            dst.display()
-        )
-        .into())
-    }
-}
-
-pub fn copy_file_or_path(
-    src: &Path,
-    dst: &Path,
-    folder_contents: bool,
-) -> Result<(), Box<dyn std::error::Error>> {
-    if src.is_dir() {
-        if folder_contents {
-            // copy only the contents of `src` into `dst`
-            // ensure destination directory exists
-            if let Err(e) = fs::create_dir_all(dst) {
-                if cfg!(unix) {
-                    let prompt = format!(
-                        "Failed to create destination directory {}: {}. Retry creating it with sudo?",
-                        dst.display(),
-                        e
-                    );
-                    if confirm(&prompt)? {
-                        let status = Command::new("sudo")
-                            .arg("mkdir")
-                            .arg("-p")
-                            .arg(dst)
-                            .status()?;
-                        if !status.success() {
-                            return Err(format!("sudo mkdir failed for {}", dst.display()).into());
-                        }
-                    } else {
-                        return Err(e.into());
-                    }
-                } else {
-                    return Err(e.into());
+        None
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn special_kind(_path: &Path) -> Option<&'static str> {
+    None
+}
+
+/// Warns about and counts sockets, FIFOs and device nodes, which can't be
+/// copied (reading a FIFO would even block until something writes to it).
+fn skip_special(path: &Path) -> bool {
+    let Some(kind) = special_kind(path) else {
+        return false;
+    };
+    println!(
+        "{}",
+        style::warning(format!("Warning: Skipped {} {}", kind, path.display()))
+    );
+    SKIPPED_SPECIAL_FILES.fetch_add(1, Ordering::Relaxed);
+    true
+}
+
+/// `path` as an extended-length `\\?\` path on Windows, which isn't limited
+/// to the 260 characters of MAX_PATH like a regular one, so deeply nested
+/// configs can be copied. Paths elsewhere are returned as they are.
+pub fn long_path(path: &Path) -> PathBuf {
+    if !cfg!(windows) {
+        return path.to_path_buf();
+    }
+    // extended-length paths are used as given, so they must be absolute and normalized
+    let Ok(absolute) = std::path::absolute(path) else {
+        return path.to_path_buf();
+    };
+    let mut components = absolute.components();
+    let Some(Component::Prefix(prefix)) = components.next() else {
+        return absolute;
+    };
+    match prefix.kind() {
+        Prefix::Disk(_) => {
+            let mut long = OsString::from(r"\\?\");
+            long.push(absolute.as_os_str());
+            PathBuf::from(long)
+        }
+        Prefix::UNC(server, share) => {
+            let mut long = OsString::from(r"\\?\UNC\");
+            long.push(server);
+            long.push(r"\");
+            long.push(share);
+            let mut long = PathBuf::from(long);
+            for component in components {
+                if let Component::Normal(part) = component {
+                    long.push(part);
                 }
             }
+            long
+        }
+        _ => absolute,
+    }
+}
+
+/// Whether `path` is a symlink, or a junction on Windows, to one of the
+/// directories it's in. Following it would copy the same files forever, as
+/// with the `Application Data` junction inside `AppData\Local`.
+fn links_to_ancestor(path: &Path) -> bool {
+    let is_link = fs::symlink_metadata(long_path(path)).is_ok_and(|m| m.file_type().is_symlink());
+    if !is_link {
+        return false;
+    }
+    let (Ok(target), Some(parent)) = (fs::canonicalize(long_path(path)), path.parent()) else {
+        return false;
+    };
+    fs::canonicalize(long_path(parent)).is_ok_and(|parent| parent.starts_with(&target))
+}
+
+/// Warns about links that loop back to a directory they're in, which are
+/// left out instead of followed.
+fn skip_link_loop(path: &Path) -> bool {
+    if !links_to_ancestor(path) {
+        return false;
+    }
+    println!(
+        "{}",
+        style::warning(format!(
+            "Warning: Skipped {}, it links back to a directory it's in",
+            path.display()
+        ))
+    );
+    true
+}
+
+/// The link `ignore` found looping back to a directory it's in, if that's
+/// what `error` is about.
+fn link_loop(error: &ignore::Error) -> Option<&Path> {
+    match error {
+        ignore::Error::Loop { child, .. } => Some(child),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithPath { err, .. } => {
+            link_loop(err)
+        }
+        _ => None,
+    }
+}
 
-            for entry in fs::read_dir(src)? {
-                let entry = entry?;
-                let src_entry = entry.path();
-                let dst_entry = dst.join(entry.file_name());
-
-                if src_entry.is_dir() {
-                    if let Err(e) = copy_dir_recursive(&src_entry, &dst_entry) {
-                        if cfg!(unix) {
-                            let prompt = format!(
-                                "Failed to copy directory {} -> {}: {}. Retry with sudo?",
-                                src_entry.display(),
-                                dst_entry.display(),
-                                e
-                            );
-                            if confirm(&prompt)? {
-                                sudo_copy(&src_entry, &dst_entry)?;
-                                continue;
-                            }
-                        }
-                        return Err(Box::new(e));
-                    }
-                } else if let Err(e) = fs::copy(&src_entry, &dst_entry) {
-                    if cfg!(unix) {
-                        let prompt = format!(
-                            "Failed to copy file {} -> {}: {}. Retry with sudo?",
-                            src_entry.display(),
-                            dst_entry.display(),
-                            e
-                        );
-                        if confirm(&prompt)? {
-                            sudo_copy(&src_entry, &dst_entry)?;
-                            continue;
-                        }
-                    }
-                    return Err(e.into());
+/// Lists every file below `root`, relative to `root`.
+pub fn collect_files(root: &Path) -> std::io::Result<Vec<PathBuf>> {
+    if RESPECT_GITIGNORE.load(Ordering::Relaxed) {
+        return unignored_files(root);
+    }
+    let mut files = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(rel_dir) = pending.pop() {
+        for entry in fs::read_dir(long_path(&root.join(&rel_dir)))? {
+            let entry = entry?;
+            if is_skipped_vcs_dir(&entry.file_name()) {
+                continue;
+            }
+            let rel_path = rel_dir.join(entry.file_name());
+            let path = root.join(&rel_path);
+            if long_path(&path).is_dir() {
+                if !skip_link_loop(&path) {
+                    pending.push(rel_path);
                 }
+            } else if !skip_special(&path) {
+                files.push(rel_path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Copies a file, sharing its blocks with `src` on filesystems with
+/// copy-on-write clones (Btrfs, XFS) so even large files copy almost
+/// instantly. Falls back to a regular copy everywhere else, keeping the
+/// holes of sparse files.
+pub fn copy_file(src: &Path, dst: &Path) -> std::io::Result<()> {
+    if unchanged(src, dst) {
+        return Ok(());
+    }
+    #[cfg(target_os = "linux")]
+    if reflink(src, dst).is_ok() {
+        return Ok(());
+    }
+    #[cfg(unix)]
+    if is_sparse(src) {
+        return copy_sparse(src, dst);
+    }
+    let mut attempt = 0;
+    loop {
+        // on macOS and Windows `fs::copy` already clones on APFS and ReFS
+        match fs::copy(long_path(src), long_path(dst)) {
+            Ok(_) => return Ok(()),
+            Err(e) if is_locked(&e) && attempt < LOCKED_RETRIES => {
+                attempt += 1;
+                thread::sleep(LOCKED_RETRY_DELAY * attempt);
+            }
+            Err(e) if is_locked(&e) => {
+                return vss::copy_from_shadow(src, dst).unwrap_or_else(|| {
+                    Err(std::io::Error::new(
+                        e.kind(),
+                        format!("the file is open in another program ({})", e),
+                    ))
+                });
             }
-            Ok(())
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// How often a file another program holds locked is tried again, waiting
+/// a little longer each time.
+const LOCKED_RETRIES: u32 = 3;
+const LOCKED_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Whether copying failed because another program has the file open without
+/// sharing it, which only happens on Windows.
+fn is_locked(error: &std::io::Error) -> bool {
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+    cfg!(windows) && matches!(error.raw_os_error(), Some(32 | 33))
+}
+
+/// Whether `path` takes up less space on disk than its length, i.e. has holes.
+#[cfg(unix)]
+fn is_sparse(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    fs::metadata(path).is_ok_and(|metadata| metadata.blocks() * 512 < metadata.len())
+}
+
+/// Copies `src` seeking over its runs of zeros instead of writing them, so
+/// the copy of a sparse file (disk images, database files) stays sparse.
+#[cfg(unix)]
+fn copy_sparse(src: &Path, dst: &Path) -> std::io::Result<()> {
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    let mut source = fs::File::open(src)?;
+    let mut target = fs::File::create(dst)?;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = source.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        if buffer[..read].iter().all(|&byte| byte == 0) {
+            target.seek(SeekFrom::Current(read as i64))?;
         } else {
-            // copy directory (create dst and copy contents into it)
-            if let Err(e) = copy_dir_recursive(src, dst) {
-                // if it failed, offer to retry with sudo on unix
-                if cfg!(unix) {
-                    let prompt = format!(
-                        "Failed to copy directory {} -> {}: {}. Retry with sudo?",
-                        src.display(),
-                        dst.display(),
-                        e
-                    );
-                    if confirm(&prompt)? {
-                        return sudo_copy(src, dst);
-                    }
-                }
-                return Err(Box::new(e));
+            target.write_all(&buffer[..read])?;
+        }
+    }
+    // a hole at the end is only there once the length is set
+    let length = target.stream_position()?;
+    target.set_len(length)?;
+    target.set_permissions(source.metadata()?.permissions())?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(src: &Path, dst: &Path) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let source = fs::File::open(src)?;
+    let target = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dst)?;
+    // SAFETY: both descriptors are open for as long as the call runs
+    if unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    target.set_permissions(source.metadata()?.permissions())?;
+    Ok(())
+}
+
+/// Copies that failed for lack of permissions, left for [`run_privileged`]
+/// to redo with sudo in one go.
+static PRIVILEGED: Mutex<Vec<(PathBuf, PathBuf)>> = Mutex::new(Vec::new());
+
+/// Restored files whose `uid:gid` owner only root can set, changed by
+/// [`run_privileged`] after the copies.
+static PRIVILEGED_CHOWNS: Mutex<Vec<(PathBuf, String)>> = Mutex::new(Vec::new());
+
+/// Copies each `src dst` argument pair, directories by their contents, then
+/// after a `--` changes each `owner path` pair.
+const PRIVILEGED_COPY_SCRIPT: &str = r#"while [ $# -gt 0 ] && [ "$1" != -- ]; do
+    if [ -d "$1" ]; then
+        mkdir -p "$2" && cp -a "$1/." "$2"
+    else
+        mkdir -p "$(dirname "$2")" && cp -a "$1" "$2"
+    fi || exit 1
+    shift 2
+done
+[ $# -gt 0 ] && shift
+while [ $# -gt 0 ]; do
+    chown -h "$1" "$2" || exit 1
+    shift 2
+done"#;
+
+/// How copies that need root are retried.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum PrivilegeEscalation {
+    #[default]
+    Sudo,
+    /// Polkit's graphical prompt, for runs without a terminal.
+    Pkexec,
+    /// Never escalate, report the paths as failed.
+    None,
+}
+
+/// The `privilege_escalation` setting, recorded when the config is loaded.
+static ESCALATION: OnceLock<PrivilegeEscalation> = OnceLock::new();
+
+pub fn set_privilege_escalation(escalation: Option<PrivilegeEscalation>) {
+    let _ = ESCALATION.set(escalation.unwrap_or_default());
+}
+
+fn escalation() -> PrivilegeEscalation {
+    ESCALATION.get().copied().unwrap_or_default()
+}
+
+/// The name the elevated copy is asked for with, for messages.
+fn elevation() -> &'static str {
+    if cfg!(windows) {
+        "administrator rights"
+    } else if escalation() == PrivilegeEscalation::Pkexec {
+        "pkexec"
+    } else {
+        "sudo"
+    }
+}
+
+/// Leaves copying `src` to `dst` to [`run_privileged`] when `error` means
+/// only root or an administrator may do it. Returns `false` for any other
+/// error.
+fn defer_privileged(src: &Path, dst: &Path, error: &std::io::Error) -> bool {
+    if !(cfg!(unix) || cfg!(windows)) || error.kind() != ErrorKind::PermissionDenied {
+        return false;
+    }
+    let Ok(mut pending) = PRIVILEGED.lock() else {
+        return false;
+    };
+    println!(
+        "Permission denied copying {} -> {}, it will be retried with {}.",
+        style::path(src.display()),
+        style::path(dst.display()),
+        elevation()
+    );
+    pending.push((src.to_path_buf(), dst.to_path_buf()));
+    true
+}
+
+/// Leaves copying `src` to `dst` to [`run_privileged`] without trying it
+/// first, for paths marked with `sudo`.
+pub fn copy_privileged(src: &Path, dst: &Path) {
+    if let Ok(mut pending) = PRIVILEGED.lock() {
+        println!(
+            "Copying {} -> {} with {}.",
+            style::path(src.display()),
+            style::path(dst.display()),
+            elevation()
+        );
+        pending.push((src.to_path_buf(), dst.to_path_buf()));
+    }
+}
+
+/// Leaves giving `path` to `uid:gid` to [`run_privileged`].
+pub fn defer_chown(path: &Path, uid: u32, gid: u32) {
+    if let Ok(mut chowns) = PRIVILEGED_CHOWNS.lock() {
+        chowns.push((path.to_path_buf(), format!("{}:{}", uid, gid)));
+    }
+}
+
+fn privileged_count() -> usize {
+    PRIVILEGED.lock().map(|pending| pending.len()).unwrap_or(0)
+}
+
+/// Asks once whether to redo the copies that were denied and restore the
+/// owners that couldn't be, then runs all of them in a single sudo invocation, or a single elevated PowerShell on
+/// Windows. Declined copies are reported like any other file that couldn't
+/// be copied.
+pub fn run_privileged() -> Result<(), Box<dyn std::error::Error>> {
+    let pending = PRIVILEGED
+        .lock()
+        .map(|mut pending| std::mem::take(&mut *pending))
+        .unwrap_or_default();
+    let chowns = PRIVILEGED_CHOWNS
+        .lock()
+        .map(|mut chowns| std::mem::take(&mut *chowns))
+        .unwrap_or_default();
+    if pending.is_empty() && chowns.is_empty() {
+        return Ok(());
+    }
+
+    if !pending.is_empty() {
+        let admin = if cfg!(windows) {
+            "an administrator"
+        } else {
+            "root"
+        };
+        println!("{} path(s) can only be copied as {}:", pending.len(), admin);
+    }
+    for (src, dst) in &pending {
+        println!(
+            "  {} -> {}",
+            style::path(src.display()),
+            style::path(dst.display())
+        );
+    }
+    if !chowns.is_empty() {
+        println!(
+            "{} path(s) can only be given back to their owner as root:",
+            chowns.len()
+        );
+    }
+    for (path, owner) in &chowns {
+        println!("  {} ({})", style::path(path.display()), owner);
+    }
+    let decline = || -> Result<(), Box<dyn std::error::Error>> {
+        for (src, _) in &pending {
+            tolerate(src, "permission denied")?;
+        }
+        for (path, _) in &chowns {
+            println!(
+                "{}",
+                style::warning(format!(
+                    "Warning: The owner of {} was not restored.",
+                    path.display()
+                ))
+            );
+        }
+        Ok(())
+    };
+    if escalation() == PrivilegeEscalation::None {
+        println!("They were left alone, privilege_escalation is set to \"none\".");
+        return decline();
+    }
+    // without a terminal no one can answer, pkexec's own prompt or a
+    // passwordless sudo stands in for the question
+    let interactive = std::io::stdin().is_terminal();
+    if interactive && !confirm(&format!("Continue with {}?", elevation()))? {
+        return decline();
+    }
+
+    let mut args: Vec<&OsStr> = pending
+        .iter()
+        .flat_map(|(src, dst)| [src.as_os_str(), dst.as_os_str()])
+        .collect();
+    if !chowns.is_empty() {
+        args.push(OsStr::new("--"));
+        args.extend(
+            chowns
+                .iter()
+                .flat_map(|(path, owner)| [OsStr::new(owner), path.as_os_str()]),
+        );
+    }
+    if cfg!(windows) {
+        run_elevated(&pending)?;
+    } else if escalation() == PrivilegeEscalation::Pkexec {
+        let status = Command::new("pkexec")
+            .args(["/bin/sh", "-c", PRIVILEGED_COPY_SCRIPT, "sh"])
+            .args(&args)
+            .status()
+            .map_err(|e| format!("Failed to run pkexec, is polkit installed? {}", e))?;
+        match status.code() {
+            Some(0) => {}
+            Some(126) => {
+                return Err(
+                    "pkexec authorization was dismissed or refused, nothing was copied as root."
+                        .into(),
+                );
+            }
+            Some(127) => {
+                return Err("pkexec could not ask for authorization, no polkit authentication agent is running.".into());
+            }
+            _ => {
+                return Err(
+                    format!("pkexec failed on {} path(s).", pending.len() + chowns.len()).into(),
+                );
             }
-            Ok(())
         }
     } else {
-        // ensure parent exists
-        if let Some(parent) = dst.parent() {
-            if let Err(e) = fs::create_dir_all(parent) {
-                if cfg!(unix) {
-                    let prompt = format!(
-                        "Failed to create parent directory {}: {}. Retry creating it with sudo?",
-                        parent.display(),
-                        e
-                    );
-                    if confirm(&prompt)? {
-                        let status = Command::new("sudo")
-                            .arg("mkdir")
-                            .arg("-p")
-                            .arg(parent)
-                            .status()?;
-                        if !status.success() {
-                            return Err(
-                                format!("sudo mkdir failed for {}", parent.display()).into()
-                            );
-                        }
-                    } else {
-                        return Err(e.into());
-                    }
-                } else {
-                    return Err(e.into());
-                }
+        let mut sudo = Command::new("sudo");
+        if !interactive {
+            sudo.arg("-n");
+        }
+        let status = sudo
+            .args(["sh", "-c", PRIVILEGED_COPY_SCRIPT, "sh"])
+            .args(&args)
+            .status()
+            .map_err(|e| format!("Failed to run sudo: {}", e))?;
+        if !status.success() && !interactive {
+            return Err(format!(
+                "sudo could not handle {} path(s) without a terminal to ask for the password. Set privilege_escalation = \"pkexec\" to be asked graphically, or \"none\" to skip them.",
+                pending.len() + chowns.len()
+            )
+            .into());
+        }
+        if !status.success() {
+            return Err(format!("sudo failed on {} path(s).", pending.len() + chowns.len()).into());
+        }
+    }
+    if !pending.is_empty() {
+        println!("Copied {} path(s) with {}.", pending.len(), elevation());
+    }
+    if !chowns.is_empty() {
+        println!(
+            "Restored the owner of {} path(s) with {}.",
+            chowns.len(),
+            elevation()
+        );
+    }
+    Ok(())
+}
+
+/// Quotes `path` as a PowerShell string literal.
+fn ps_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "''"))
+}
+
+/// Writes the copies to a script and runs it in an elevated PowerShell,
+/// which Windows confirms with a single UAC prompt.
+fn run_elevated(pending: &[(PathBuf, PathBuf)]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut script = String::from("$ErrorActionPreference = 'Stop'\r\n");
+    for (src, dst) in pending {
+        if src.is_dir() {
+            script.push_str(&format!(
+                "New-Item -ItemType Directory -Force -Path {dst} | Out-Null\r\n\
+                 Get-ChildItem -LiteralPath {src} -Force | Copy-Item -Destination {dst} -Recurse -Force\r\n",
+                src = ps_quote(src),
+                dst = ps_quote(dst)
+            ));
+        } else {
+            let parent = dst.parent().unwrap_or(dst);
+            script.push_str(&format!(
+                "New-Item -ItemType Directory -Force -Path {} | Out-Null\r\n\
+                 Copy-Item -LiteralPath {} -Destination {} -Force\r\n",
+                ps_quote(parent),
+                ps_quote(src),
+                ps_quote(dst)
+            ));
+        }
+    }
+
+    let script_path =
+        std::env::temp_dir().join(format!("fuxi-elevated-{}.ps1", std::process::id()));
+    // Windows PowerShell reads scripts without a BOM as the ANSI code page
+    fs::write(&script_path, format!("\u{feff}{}", script))?;
+    let result = vss::powershell(&format!(
+        "$p = Start-Process powershell -Verb RunAs -Wait -PassThru -WindowStyle Hidden \
+         -ArgumentList '-NoProfile','-ExecutionPolicy','Bypass','-File','\"{}\"'; \
+         exit $p.ExitCode",
+        script_path.to_string_lossy().replace('\'', "''")
+    ));
+    let _ = fs::remove_file(&script_path);
+    result.map_err(|e| {
+        format!(
+            "Elevated copy of {} path(s) failed or was declined: {}",
+            pending.len(),
+            e
+        )
+    })?;
+    Ok(())
+}
+
+/// Copies the contents of `src` into `dst`. Files that can't be copied are
+/// handed to [`tolerate`], or to [`run_privileged`] when permission is denied.
+pub fn copy_dir_recursive(src: &Path, dst: &Path) -> std::io::Result<()> {
+    if let Err(e) = fs::create_dir_all(long_path(dst)) {
+        if defer_privileged(src, dst, &e) {
+            return Ok(());
+        }
+        return Err(e);
+    }
+    if RESPECT_GITIGNORE.load(Ordering::Relaxed) {
+        let files = match unignored_files(src) {
+            Ok(files) => files,
+            Err(e) => return tolerate(src, e),
+        };
+        for rel in files {
+            let (src_path, dst_path) = (src.join(&rel), dst.join(&rel));
+            let copied = match dst_path.parent() {
+                Some(parent) => fs::create_dir_all(long_path(parent)),
+                None => Ok(()),
+            }
+            .and_then(|_| copy_file(&src_path, &dst_path));
+            if let Err(e) = copied
+                && !defer_privileged(&src_path, &dst_path, &e)
+            {
+                tolerate(&src_path, e)?;
             }
         }
+        return Ok(());
+    }
+    let entries = match fs::read_dir(long_path(src)) {
+        Ok(entries) => entries,
+        Err(e) if defer_privileged(src, dst, &e) => return Ok(()),
+        Err(e) => return tolerate(src, e),
+    };
+    for entry in entries {
+        let entry = entry?;
+        if is_skipped_vcs_dir(&entry.file_name()) {
+            continue;
+        }
+        let src_path = src.join(entry.file_name());
+        let dst_path = dst.join(entry.file_name());
 
-        match fs::copy(src, dst) {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                // if it failed, offer to retry with sudo on unix
-                if cfg!(unix) {
-                    let prompt = format!(
-                        "Failed to copy file {} -> {}: {}. Retry with sudo?",
-                        src.display(),
-                        dst.display(),
-                        e
-                    );
-                    if confirm(&prompt)? {
-                        return sudo_copy(src, dst);
-                    }
-                }
-                Err(e.into())
+        if long_path(&src_path).is_dir() {
+            if !skip_link_loop(&src_path) {
+                copy_dir_recursive(&src_path, &dst_path)?;
             }
+        } else if !skip_special(&src_path)
+            && let Err(e) = copy_file(&src_path, &dst_path)
+            && !defer_privileged(&src_path, &dst_path, &e)
+        {
+            tolerate(&src_path, e)?;
         }
     }
+    Ok(())
+}
+
+/// Copies a file or a directory's contents to `dst`, creating its parents.
+/// Returns `false` when some of it was left to [`run_privileged`].
+pub fn copy_file_or_path(src: &Path, dst: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+    let deferred = privileged_count();
+    if long_path(src).is_dir() {
+        copy_dir_recursive(src, dst)?;
+        return Ok(privileged_count() == deferred);
+    }
+    if skip_special(src) {
+        return Ok(true);
+    }
+
+    let copied = match dst.parent() {
+        Some(parent) => fs::create_dir_all(long_path(parent)),
+        None => Ok(()),
+    }
+    .and_then(|_| copy_file(src, dst));
+    match copied {
+        Ok(()) => Ok(true),
+        Err(e) if defer_privileged(src, dst, &e) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
 }
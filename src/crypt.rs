@@ -0,0 +1,110 @@
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::git::path_with_git;
+
+const GITATTRIBUTES: &str = ".gitattributes";
+
+// everything is encrypted except the files git-crypt itself needs in plain text
+const GIT_CRYPT_ATTRIBUTES: &str = "\
+* filter=git-crypt diff=git-crypt
+.gitattributes !filter !diff
+.git-crypt/** !filter !diff
+";
+
+fn run_git_crypt(repo_path: &Path, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new("git-crypt")
+        .args(args)
+        .current_dir(repo_path)
+        // git-crypt runs git itself
+        .env("PATH", path_with_git()?)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| format!("Failed to run git-crypt (is it installed?): {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git-crypt {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Whether the repo's `.gitattributes` routes files through git-crypt.
+pub fn is_git_crypt_enabled(repo_path: &Path) -> bool {
+    fs::read_to_string(repo_path.join(GITATTRIBUTES))
+        .is_ok_and(|attributes| attributes.contains("filter=git-crypt"))
+}
+
+/// Whether this clone holds a git-crypt key, i.e. files are decrypted on checkout.
+pub fn is_unlocked(repo_path: &Path) -> bool {
+    repo_path
+        .join(".git")
+        .join("git-crypt")
+        .join("keys")
+        .is_dir()
+}
+
+/// Fails when the repo is encrypted with git-crypt but this clone can't read it.
+pub fn ensure_unlocked(repo_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if is_git_crypt_enabled(repo_path) && !is_unlocked(repo_path) {
+        return Err(
+            "The backup repository is encrypted with git-crypt. Run 'fuxi crypt unlock' first."
+                .into(),
+        );
+    }
+    Ok(())
+}
+
+/// Sets up git-crypt in the repo, optionally sharing the key with gpg users, and
+/// re-stages files that were already committed so they get encrypted.
+pub fn init_git_crypt(
+    repo_path: &Path,
+    gpg_users: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !is_unlocked(repo_path) {
+        run_git_crypt(repo_path, &["init"])?;
+    }
+    for user in gpg_users {
+        run_git_crypt(repo_path, &["add-gpg-user", user])?;
+    }
+
+    let attributes_path = repo_path.join(GITATTRIBUTES);
+    let mut attributes = fs::read_to_string(&attributes_path).unwrap_or_default();
+    if !attributes.contains("filter=git-crypt") {
+        attributes.push_str(GIT_CRYPT_ATTRIBUTES);
+        fs::write(&attributes_path, attributes)?;
+    }
+
+    // `status -f` stages plain text files that should have been encrypted
+    run_git_crypt(repo_path, &["status", "-f"])?;
+    Ok(())
+}
+
+/// Unlocks the repo with a symmetric key file, or with gpg when none is given.
+pub fn unlock(repo_path: &Path, key_file: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    match key_file {
+        Some(key) => run_git_crypt(repo_path, &["unlock", key.to_string_lossy().as_ref()])?,
+        None => run_git_crypt(repo_path, &["unlock"])?,
+    };
+    Ok(())
+}
+
+pub fn lock(repo_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    run_git_crypt(repo_path, &["lock"])?;
+    Ok(())
+}
+
+pub fn export_key(repo_path: &Path, key_file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    run_git_crypt(
+        repo_path,
+        &["export-key", key_file.to_string_lossy().as_ref()],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,354 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        mpsc::{Sender, channel},
+    },
+    thread,
+};
+
+use chrono::Utc;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde_json::{Value, json};
+
+use crate::cfg::{get_selected_profile_paths, load_config};
+use crate::queue::queued_push;
+use crate::storage;
+use crate::style;
+use crate::watch::{backup_and_push, is_ignored};
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+/// What the daemon knows beyond the config, shared by every connection.
+#[derive(Default)]
+struct State {
+    /// One sender per connection subscribed to events.
+    subscribers: Vec<Sender<Value>>,
+    backing_up: bool,
+    last_backup: Option<Value>,
+    watched: usize,
+}
+
+type Shared = Arc<Mutex<State>>;
+
+/// Sends an event notification to every subscribed connection, forgetting
+/// the ones that went away.
+fn broadcast(state: &Shared, event: &str, mut params: Value) {
+    params["event"] = json!(event);
+    let notification = json!({"jsonrpc": "2.0", "method": "event", "params": params});
+    let mut state = state.lock().unwrap();
+    state
+        .subscribers
+        .retain(|subscriber| subscriber.send(notification.clone()).is_ok());
+}
+
+fn status(state: &Shared) -> Result<Value, Box<dyn std::error::Error>> {
+    let config = load_config()?;
+    let state = state.lock().unwrap();
+    Ok(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "profile": config.selected_profile,
+        "repository": config.backup_repo_path,
+        "remote": config.remote_url,
+        "last_backup_id": config.last_backup_id,
+        "queued_push": queued_push(&config).is_some(),
+        "backing_up": state.backing_up,
+        "last_backup": state.last_backup,
+        "watched_paths": state.watched,
+    }))
+}
+
+fn list(params: &Value) -> Result<Value, Box<dyn std::error::Error>> {
+    let config = load_config()?;
+    let limit = params["limit"].as_u64().map_or(usize::MAX, |n| n as usize);
+    let backups = storage::backend(&config)?.list()?;
+    Ok(backups
+        .iter()
+        .take(limit)
+        .map(|b| {
+            json!({
+                "id": b.name(),
+                "summary": b.summary,
+                "created": b.created,
+                "profile": b.profile,
+                "machine": b.hostname,
+                "files_changed": b.files_changed,
+            })
+        })
+        .collect())
+}
+
+fn backup(state: &Shared, params: &Value) -> Result<Value, (i64, String)> {
+    let push = params["push"].as_bool().unwrap_or(false);
+    {
+        let mut state = state.lock().unwrap();
+        if state.backing_up {
+            return Err((SERVER_ERROR, "A backup is already running.".to_string()));
+        }
+        state.backing_up = true;
+    }
+    broadcast(state, "backup_started", json!({"push": push}));
+    let result = backup_and_push("daemon", push).map_err(|e| e.to_string());
+    let outcome = match &result {
+        Ok((id, pushed)) => json!({"id": id, "time": Utc::now(), "pushed": pushed}),
+        Err(e) => json!({"error": e, "time": Utc::now()}),
+    };
+    {
+        let mut state = state.lock().unwrap();
+        state.backing_up = false;
+        state.last_backup = Some(outcome.clone());
+    }
+    match result {
+        Ok((id, _)) => {
+            broadcast(state, "backup_finished", outcome);
+            Ok(json!({"backup_id": id}))
+        }
+        Err(e) => {
+            broadcast(state, "backup_failed", outcome);
+            Err((SERVER_ERROR, e))
+        }
+    }
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> std::io::Result<()> {
+    writeln!(writer, "{}", message)?;
+    writer.flush()
+}
+
+/// Answers the newline separated JSON-RPC requests of one connection. After
+/// `subscribe` the connection only receives event notifications.
+fn serve(reader: impl Read, mut writer: impl Write, state: Shared) -> std::io::Result<()> {
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: Value = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                write_message(
+                    &mut writer,
+                    &json!({"jsonrpc": "2.0", "id": null, "error": {"code": PARSE_ERROR, "message": e.to_string()}}),
+                )?;
+                continue;
+            }
+        };
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        let method = request["method"].as_str().unwrap_or_default();
+        let result = match method {
+            "status" => status(&state).map_err(|e| (SERVER_ERROR, e.to_string())),
+            "list" => list(&params).map_err(|e| (SERVER_ERROR, e.to_string())),
+            "backup" if !params.is_null() && !params.is_object() => Err((
+                INVALID_PARAMS,
+                "backup takes an object like {\"push\": true}.".to_string(),
+            )),
+            "backup" => backup(&state, &params),
+            "subscribe" => Ok(json!({"subscribed": true})),
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method '{}'.", method))),
+        };
+        // requests without an ID are notifications and get no response
+        if let Some(id) = request.get("id") {
+            let response = match result {
+                Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                Err((code, message)) => {
+                    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+                }
+            };
+            write_message(&mut writer, &response)?;
+        }
+
+        if method == "subscribe" {
+            let (tx, rx) = channel();
+            state.lock().unwrap().subscribers.push(tx);
+            for event in rx {
+                write_message(&mut writer, &event)?;
+            }
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// Watches the selected profile's paths and sends their changes to the
+/// subscribers. The watcher stops when it's dropped.
+fn watch_paths(state: &Shared) -> Result<RecommendedWatcher, Box<dyn std::error::Error>> {
+    let config = load_config()?;
+    let events = state.clone();
+    let ignore = config.clone();
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+        let Ok(event) = result else {
+            return;
+        };
+        let kind = match event.kind {
+            EventKind::Create(_) => "created",
+            EventKind::Modify(_) => "modified",
+            EventKind::Remove(_) => "removed",
+            _ => return,
+        };
+        for path in event.paths {
+            if !is_ignored(&ignore, &path) {
+                broadcast(&events, "change", json!({"path": path, "kind": kind}));
+            }
+        }
+    })?;
+    let mut watched = 0;
+    for path in get_selected_profile_paths(&config) {
+        let path = PathBuf::from(path);
+        if path.exists() {
+            watcher.watch(&path, RecursiveMode::Recursive)?;
+            watched += 1;
+        }
+    }
+    state.lock().unwrap().watched = watched;
+    Ok(watcher)
+}
+
+/// Where the daemon listens: a socket in the runtime directory, or in the
+/// data directory where there's none.
+#[cfg(unix)]
+pub fn socket_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = dirs::runtime_dir()
+        .or_else(|| dirs::data_dir().map(|dir| dir.join("fuxi")))
+        .ok_or("Could not determine data directory")?;
+    Ok(dir.join("fuxi.sock"))
+}
+
+/// Where the daemon listens: a named pipe of the current user.
+#[cfg(windows)]
+pub fn socket_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let user = std::env::var("USERNAME").unwrap_or_default();
+    Ok(PathBuf::from(format!(r"\\.\pipe\fuxi-{}", user)))
+}
+
+#[cfg(unix)]
+fn listen(path: &std::path::Path, state: Shared) -> Result<(), Box<dyn std::error::Error>> {
+    use std::{
+        fs,
+        os::unix::{
+            fs::PermissionsExt,
+            net::{UnixListener, UnixStream},
+        },
+    };
+
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(format!("fuxi daemon is already running on {}.", path.display()).into());
+        }
+        // left behind by a daemon that was killed
+        fs::remove_file(path)?;
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    // other users must not trigger backups
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    println!(
+        "Listening on {}. Press Ctrl+C to stop.",
+        style::path(path.display())
+    );
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let writer = stream.try_clone()?;
+        let state = state.clone();
+        thread::spawn(move || serve(stream, writer, state));
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn listen(path: &std::path::Path, state: Shared) -> Result<(), Box<dyn std::error::Error>> {
+    use std::{
+        ffi::c_void,
+        fs::File,
+        io,
+        os::windows::{ffi::OsStrExt, io::FromRawHandle},
+        ptr,
+    };
+
+    const PIPE_ACCESS_DUPLEX: u32 = 0x3;
+    const FILE_FLAG_FIRST_PIPE_INSTANCE: u32 = 0x0008_0000;
+    const PIPE_REJECT_REMOTE_CLIENTS: u32 = 0x8;
+    const PIPE_UNLIMITED_INSTANCES: u32 = 255;
+    const ERROR_PIPE_CONNECTED: i32 = 535;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn CreateNamedPipeW(
+            name: *const u16,
+            open_mode: u32,
+            pipe_mode: u32,
+            max_instances: u32,
+            out_buffer_size: u32,
+            in_buffer_size: u32,
+            default_timeout: u32,
+            security_attributes: *mut c_void,
+        ) -> *mut c_void;
+        fn ConnectNamedPipe(pipe: *mut c_void, overlapped: *mut c_void) -> i32;
+    }
+
+    let name: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+    let mut first = true;
+    println!(
+        "Listening on {}. Press Ctrl+C to stop.",
+        style::path(path.display())
+    );
+    loop {
+        let open_mode = if first {
+            PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE
+        } else {
+            PIPE_ACCESS_DUPLEX
+        };
+        // SAFETY: `name` is a NUL terminated UTF-16 string that outlives the call
+        let pipe = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                open_mode,
+                PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                4096,
+                4096,
+                0,
+                ptr::null_mut(),
+            )
+        };
+        if pipe as isize == -1 {
+            let error = io::Error::last_os_error();
+            if first {
+                return Err(format!(
+                    "Could not listen on {}, is fuxi daemon already running? {}",
+                    path.display(),
+                    error
+                )
+                .into());
+            }
+            return Err(error.into());
+        }
+        first = false;
+        // SAFETY: `pipe` is a valid pipe handle owned by nothing else yet
+        let connected = unsafe { ConnectNamedPipe(pipe, ptr::null_mut()) } != 0
+            || io::Error::last_os_error().raw_os_error() == Some(ERROR_PIPE_CONNECTED);
+        // SAFETY: the handle is valid and the File takes ownership of it
+        let stream = unsafe { File::from_raw_handle(pipe) };
+        if !connected {
+            continue;
+        }
+        let writer = stream.try_clone()?;
+        let state = state.clone();
+        thread::spawn(move || serve(stream, writer, state));
+    }
+}
+
+/// Serves the JSON-RPC API on a local socket, or a named pipe on Windows,
+/// until stopped, while watching the tracked paths for change events.
+pub fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let state = Shared::default();
+    let _watcher = watch_paths(&state)?;
+    let path = socket_path()?;
+    listen(&path, state)
+}
@@ -0,0 +1,276 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use crate::git::DivergenceStrategy;
+use crate::object_store::{IndexEntry, STATE_DIR, check_secrets, hash_tree};
+use crate::storage::{Backend, BackupRef, Retention};
+use crate::style;
+
+/// Stores every backup as a plain, timestamped copy of the backup directory
+/// under `root`, e.g. on an external drive or a mounted NAS share.
+pub struct DirectoryBackend {
+    local: PathBuf,
+    root: PathBuf,
+}
+
+impl DirectoryBackend {
+    pub fn new(local: &Path, root: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        if root.is_empty() {
+            return Err(
+                "The dir backend needs a target, e.g. backend = \"dir:/mnt/nas/dotfiles\".".into(),
+            );
+        }
+        Ok(Self {
+            local: local.to_path_buf(),
+            root: PathBuf::from(root),
+        })
+    }
+
+    /// Refuses to write into a mount point that isn't mounted.
+    fn ensure_root(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.root.is_dir() {
+            return Err(format!(
+                "{} does not exist. Is the drive mounted?",
+                self.root.display()
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Snapshot IDs in `root`, oldest first.
+    fn snapshot_ids(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        self.ensure_root()?;
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(&self.root)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with("snap_") && entry.path().is_dir() {
+                ids.push(name);
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn message_path(&self, id: &str) -> PathBuf {
+        self.root.join(format!("{}.toml", id))
+    }
+
+    fn head_path(&self) -> PathBuf {
+        self.local.join(STATE_DIR).join("HEAD")
+    }
+
+    /// The snapshot the local directory was last brought in line with.
+    fn head(&self) -> Option<String> {
+        fs::read_to_string(self.head_path())
+            .ok()
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+    }
+
+    fn set_head(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(self.local.join(STATE_DIR))?;
+        fs::write(self.head_path(), id)?;
+        Ok(())
+    }
+
+    fn head_files(&self) -> Result<BTreeMap<String, String>, Box<dyn std::error::Error>> {
+        match self.head() {
+            Some(id) => hash_tree(&self.root.join(id)),
+            None => Ok(BTreeMap::new()),
+        }
+    }
+
+    /// Copies snapshot `id` into the local directory. With `keep_local`, files
+    /// changed locally since `base` are left alone.
+    fn checkout(
+        &self,
+        id: &str,
+        base: &BTreeMap<String, String>,
+        keep_local: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let source = self.root.join(id);
+        let snapshot = hash_tree(&source)?;
+        let current = hash_tree(&self.local)?;
+        let unchanged = |rel: &str| !keep_local || current.get(rel) == base.get(rel);
+
+        for (rel, hash) in &snapshot {
+            if current.get(rel) != Some(hash) && unchanged(rel) {
+                let target = self.local.join(rel);
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(source.join(rel), target)?;
+            }
+        }
+        for rel in current.keys() {
+            if !snapshot.contains_key(rel) && unchanged(rel) {
+                fs::remove_file(self.local.join(rel))?;
+            }
+        }
+        self.set_head(id)
+    }
+}
+
+impl Backend for DirectoryBackend {
+    fn name(&self) -> &'static str {
+        "dir"
+    }
+
+    fn prepare(&self) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(&self.local)?;
+        Ok(())
+    }
+
+    fn snapshot(
+        &self,
+        message: Option<String>,
+        allow_secrets: bool,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let latest = self.snapshot_ids()?.pop();
+        let files = hash_tree(&self.local)?;
+        let previous = match &latest {
+            Some(id) => hash_tree(&self.root.join(id))?,
+            None => BTreeMap::new(),
+        };
+        if files == previous {
+            println!("No changes to snapshot.");
+            return Ok(false);
+        }
+        if !allow_secrets {
+            check_secrets(&self.local, &files, &previous)?;
+        }
+
+        let timestamp = chrono::Utc::now();
+        let mut id = format!("snap_{}", timestamp.format("%Y%m%d_%H%M%S"));
+        // two backups within the same second still need their own directory
+        let mut n = 1;
+        while self.root.join(&id).exists() {
+            n += 1;
+            id = format!("snap_{}_{}", timestamp.format("%Y%m%d_%H%M%S"), n);
+        }
+        let entry = IndexEntry {
+            id,
+            message: message.unwrap_or_else(|| "Automated backup".to_string()),
+            timestamp,
+        };
+        // copy under a temporary name so an interrupted copy never looks like a snapshot
+        let partial = self.root.join(format!(".{}.partial", entry.id));
+        if partial.exists() {
+            fs::remove_dir_all(&partial)?;
+        }
+        for rel in files.keys() {
+            let target = partial.join(rel);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(self.local.join(rel), target)?;
+        }
+        fs::create_dir_all(&partial)?;
+        fs::rename(&partial, self.root.join(&entry.id))?;
+        fs::write(
+            self.message_path(&entry.id),
+            toml::to_string_pretty(&entry)?,
+        )?;
+        self.set_head(&entry.id)?;
+        println!(
+            "Stored snapshot {} in {}",
+            entry.id,
+            style::path(self.root.display())
+        );
+        Ok(true)
+    }
+
+    fn push(&self, _divergence: DivergenceStrategy) -> Result<(), Box<dyn std::error::Error>> {
+        // snapshots are written straight to the target directory
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<BackupRef>, Box<dyn std::error::Error>> {
+        let mut backups = Vec::new();
+        for id in self.snapshot_ids()?.into_iter().rev() {
+            let entry = fs::read_to_string(self.message_path(&id))
+                .ok()
+                .and_then(|content| toml::from_str::<IndexEntry>(&content).ok());
+            let summary = entry
+                .as_ref()
+                .and_then(|entry| entry.message.lines().next().map(str::to_string))
+                .unwrap_or_default();
+            backups.push(BackupRef {
+                id,
+                summary,
+                tag: None,
+                created: entry.map(|entry| entry.timestamp),
+                files_changed: None,
+                profile: None,
+                hostname: None,
+            });
+        }
+        Ok(backups)
+    }
+
+    fn fetch(&self, id: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        let backups = self.list()?;
+        let backup = match id {
+            Some(id) => backups.iter().find(|b| b.matches(id)),
+            None => backups.first(),
+        }
+        .ok_or("No matching snapshot found.")?;
+        println!("Copying snapshot {}...", backup.id);
+        self.checkout(&backup.id, &BTreeMap::new(), false)
+    }
+
+    fn update(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(latest) = self.snapshot_ids()?.pop() else {
+            return Ok(());
+        };
+        if self.head().as_deref() >= Some(latest.as_str()) {
+            println!(
+                "Already up to date with {}",
+                style::path(self.root.display())
+            );
+            return Ok(());
+        }
+        println!("Copying snapshot {}...", latest);
+        let base = self.head_files()?;
+        self.checkout(&latest, &base, true)
+    }
+
+    fn prune(
+        &self,
+        retention: &Retention,
+        dry_run: bool,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        // IDs are creation times, one that doesn't parse is kept to be safe
+        let dated: Vec<(String, DateTime<Utc>)> = self
+            .snapshot_ids()?
+            .into_iter()
+            .rev()
+            .filter_map(|id| {
+                let time = id.strip_prefix("snap_")?;
+                let created = NaiveDateTime::parse_from_str(time, "%Y%m%d_%H%M%S").ok()?;
+                Some((id, created.and_utc()))
+            })
+            .collect();
+        let created: Vec<DateTime<Utc>> = dated.iter().map(|(_, created)| *created).collect();
+        let evicted: Vec<String> = dated
+            .into_iter()
+            .zip(retention.reasons(&created))
+            .filter(|(_, reasons)| reasons.is_empty())
+            .map(|((id, _), _)| id)
+            .collect();
+        if !dry_run {
+            for id in &evicted {
+                fs::remove_dir_all(self.root.join(id))?;
+                let _ = fs::remove_file(self.message_path(id));
+            }
+        }
+        Ok(evicted)
+    }
+}
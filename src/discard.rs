@@ -0,0 +1,29 @@
+use std::{fs, path::Path};
+
+use crate::cfg::FuxiConfig;
+
+/// Whether deleted files go to the system trash, the `use_trash` setting.
+pub fn uses_trash(config: &FuxiConfig) -> bool {
+    config.use_trash.unwrap_or(true)
+}
+
+/// Deletes the file or directory at `path`, by moving it to the system trash
+/// unless `use_trash` is off, so a mistake can be undone without fuxi.
+pub fn discard(config: &FuxiConfig, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if uses_trash(config) {
+        return trash::delete(path).map_err(|e| {
+            format!(
+                "Could not move {} to the trash: {}. Set use_trash = false to delete it instead.",
+                path.display(),
+                e
+            )
+            .into()
+        });
+    }
+    if path.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::git::NetworkError;
+
+// Exit codes are part of fuxi's interface: new ones may be added, but these
+// keep their meaning.
+/// Anything without a more specific code.
+pub const FAILURE: u8 = 1;
+/// Invalid arguments or configuration, or fuxi isn't set up yet. clap exits
+/// with 2 for usage errors too.
+pub const CONFIG_ERROR: u8 = 2;
+/// A git operation on the backup repository failed.
+pub const GIT_ERROR: u8 = 3;
+/// The command finished, but some files couldn't be backed up or applied.
+pub const PARTIAL_FAILURE: u8 = 4;
+/// The remote couldn't be reached.
+pub const NETWORK_ERROR: u8 = 5;
+/// Another fuxi is changing the backup repository.
+pub const LOCKED: u8 = 6;
+
+/// An error that makes fuxi exit with `code`.
+pub struct Failure {
+    pub code: u8,
+    pub message: String,
+}
+
+// reads like the string errors used everywhere else when main prints it
+impl std::fmt::Debug for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.message)
+    }
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+pub fn failure(code: u8, message: impl Into<String>) -> Box<dyn std::error::Error> {
+    Box::new(Failure {
+        code,
+        message: message.into(),
+    })
+}
+
+pub fn config_error(message: impl Into<String>) -> Box<dyn std::error::Error> {
+    failure(CONFIG_ERROR, message)
+}
+
+/// Puts `context` in front of `error`'s message, keeping its exit code.
+pub fn context(context: &str, error: Box<dyn std::error::Error>) -> Box<dyn std::error::Error> {
+    failure(exit_code(error.as_ref()), format!("{}: {}", context, error))
+}
+
+/// The exit code `error` ends fuxi with.
+pub fn exit_code(error: &(dyn std::error::Error + 'static)) -> u8 {
+    if let Some(failure) = error.downcast_ref::<Failure>() {
+        failure.code
+    } else if error.is::<NetworkError>() {
+        NETWORK_ERROR
+    } else if error.is::<git2::Error>() {
+        GIT_ERROR
+    } else {
+        FAILURE
+    }
+}
+
+/// Whether a command left some files behind while otherwise succeeding.
+static PARTIAL: AtomicBool = AtomicBool::new(false);
+
+pub fn set_partial() {
+    PARTIAL.store(true, Ordering::Relaxed);
+}
+
+pub fn is_partial() -> bool {
+    PARTIAL.load(Ordering::Relaxed)
+}
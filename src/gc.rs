@@ -0,0 +1,165 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::cfg::FuxiConfig;
+use crate::hash_cache::HashCache;
+use crate::journal;
+use crate::lock::process_alive;
+use crate::snapshot::{dir_size, prune_snapshots};
+use crate::stats::format_size;
+use crate::style;
+
+/// Logs nothing has written to for this long belong to a schedule that's gone.
+const LOG_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Temporary directories apply and export stage files in, named
+/// `<prefix><pid>...`. They're removed when the command finishes.
+const STAGING_PREFIXES: [&str; 3] = ["fuxi-apply-", "fuxi-export-", "fuxi-from-"];
+
+/// Something fuxi left behind that can be deleted.
+struct Leftover {
+    path: PathBuf,
+    size: u64,
+    what: &'static str,
+}
+
+/// Snapshots over the configured limits, except the one an interrupted apply
+/// still needs to be cleaned up.
+fn stale_snapshots(config: &FuxiConfig) -> Result<Vec<Leftover>, Box<dyn std::error::Error>> {
+    let in_use = journal::interrupted().and_then(|operation| operation.snapshot);
+    Ok(prune_snapshots(config, true)?
+        .into_iter()
+        .filter(|snapshot| in_use.as_ref() != Some(&snapshot.path))
+        .map(|snapshot| Leftover {
+            path: snapshot.path,
+            size: snapshot.size,
+            what: "snapshot",
+        })
+        .collect())
+}
+
+/// Staging directories of fuxi processes that no longer run.
+fn orphaned_staging() -> Vec<Leftover> {
+    let Ok(entries) = fs::read_dir(env::temp_dir()) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let pid: u32 = STAGING_PREFIXES
+                .iter()
+                .find_map(|prefix| name.strip_prefix(prefix))?
+                .split('-')
+                .next()?
+                .parse()
+                .ok()?;
+            if process_alive(pid) {
+                return None;
+            }
+            Some(Leftover {
+                size: dir_size(&entry.path()),
+                path: entry.path(),
+                what: "staging directory",
+            })
+        })
+        .collect()
+}
+
+/// Log files in the fuxi cache directory that haven't been written to lately.
+fn old_logs() -> Vec<Leftover> {
+    let Some(dir) = dirs::cache_dir().map(|dir| dir.join("fuxi")) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "log"))
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok()?;
+            let age = meta.modified().ok()?.elapsed().ok()?;
+            (age > LOG_MAX_AGE).then(|| Leftover {
+                path: entry.path(),
+                size: meta.len(),
+                what: "log",
+            })
+        })
+        .collect()
+}
+
+fn remove(path: &Path) -> std::io::Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Deletes stale snapshots, orphaned staging directories and old logs, and
+/// drops hash cache entries of deleted files. With `dry_run`, only reports
+/// what would go and how much space that frees.
+pub fn gc(config: &FuxiConfig, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut leftovers = stale_snapshots(config)?;
+    leftovers.extend(orphaned_staging());
+    leftovers.extend(old_logs());
+
+    let mut reclaimed = 0;
+    for leftover in &leftovers {
+        if dry_run {
+            println!(
+                "[Dry Run] Would remove {} {} ({})",
+                leftover.what,
+                style::path(leftover.path.display()),
+                format_size(leftover.size)
+            );
+        } else {
+            remove(&leftover.path)?;
+            println!(
+                "Removed {} {} ({})",
+                leftover.what,
+                style::path(leftover.path.display()),
+                format_size(leftover.size)
+            );
+        }
+        reclaimed += leftover.size;
+    }
+
+    let mut cache = HashCache::load();
+    let before = cache.stored_size();
+    let forgotten = cache.forget_deleted();
+    if forgotten > 0 {
+        let saved = before.saturating_sub(cache.stored_size());
+        if dry_run {
+            println!(
+                "[Dry Run] Would drop {} hash cache entr{} of deleted files ({})",
+                forgotten,
+                if forgotten == 1 { "y" } else { "ies" },
+                format_size(saved)
+            );
+        } else {
+            cache.save()?;
+            println!(
+                "Dropped {} hash cache entr{} of deleted files ({})",
+                forgotten,
+                if forgotten == 1 { "y" } else { "ies" },
+                format_size(saved)
+            );
+        }
+        reclaimed += saved;
+    }
+
+    if leftovers.is_empty() && forgotten == 0 {
+        println!("Nothing to clean up.");
+    } else if dry_run {
+        println!("{} would be reclaimed.", format_size(reclaimed));
+    } else {
+        println!("{} reclaimed.", format_size(reclaimed));
+    }
+    Ok(())
+}
@@ -1,17 +1,209 @@
-use std::path::Path;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
 
-pub fn run_git_command(
-    repo_path: &Path,
-    args: &[&str],
-) -> Result<String, Box<dyn std::error::Error>> {
-    let output = Command::new("git")
+use chrono::{DateTime, Utc};
+use git2::build::{CheckoutBuilder, RepoBuilder};
+use git2::{
+    Commit, Cred, CredentialType, Delta, DiffOptions, Direction, ErrorClass, ErrorCode,
+    FetchOptions, Index, IndexAddOption, Oid, Patch, PushOptions, Remote, RemoteCallbacks,
+    Repository, ResetType, Signature, Sort, StashFlags,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::cli::prompt;
+use crate::crypt::is_git_crypt_enabled;
+use crate::exit::{GIT_ERROR, config_error, failure};
+use crate::lfs::{self, uses_lfs};
+use crate::machine::hostname;
+use crate::metadata::{BackupMetadata, METADATA_DIR};
+use crate::scan::{format_findings, scan_staged};
+use crate::storage::Retention;
+use crate::style;
+
+/// How to reconcile local backups with commits another machine already pushed.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum DivergenceStrategy {
+    #[default]
+    Prompt,
+    Rebase,
+    Merge,
+    Abort,
+}
+
+impl DivergenceStrategy {
+    /// The strategy to use when nobody is around to answer a prompt.
+    pub fn unattended(self) -> Self {
+        match self {
+            Self::Prompt => Self::Rebase,
+            other => other,
+        }
+    }
+}
+
+/// What to do with uncommitted edits in the backup repository before an
+/// operation that would overwrite them.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum DirtyRepoPolicy {
+    #[default]
+    Abort,
+    Stash,
+}
+
+/// The remote couldn't be reached at all, as opposed to refusing the push.
+pub struct NetworkError(pub String);
+
+// reads like the string errors used everywhere else when main returns it
+impl std::fmt::Debug for NetworkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl std::fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+/// Whether `error` means the remote couldn't be reached. Rejected
+/// credentials don't count, trying again won't fix them.
+fn is_network_error(error: &git2::Error) -> bool {
+    matches!(
+        error.class(),
+        ErrorClass::Net | ErrorClass::Http | ErrorClass::Ssh | ErrorClass::Ssl | ErrorClass::Os
+    ) && error.code() != ErrorCode::Auth
+}
+
+/// `message` about `error`, as a network error when the remote couldn't be
+/// reached.
+fn remote_error(message: String, error: &git2::Error) -> Box<dyn std::error::Error> {
+    if is_network_error(error) {
+        Box::new(NetworkError(message))
+    } else {
+        failure(GIT_ERROR, message)
+    }
+}
+
+fn push_error(error: git2::Error) -> Box<dyn std::error::Error> {
+    remote_error(format!("Failed to push: {}", error.message()), &error)
+}
+
+const DEFAULT_NETWORK_RETRIES: u32 = 3;
+
+/// The `network_retries` setting, recorded when the config is loaded.
+static NETWORK_RETRIES: OnceLock<u32> = OnceLock::new();
+
+pub fn set_network_retries(retries: Option<u32>) {
+    let _ = NETWORK_RETRIES.set(retries.unwrap_or(DEFAULT_NETWORK_RETRIES));
+}
+
+/// Runs `operation` again after 1, 2, 4... seconds while it fails with a
+/// network error, up to `network_retries` times.
+fn with_retries<T>(
+    what: &str,
+    mut operation: impl FnMut() -> Result<T, git2::Error>,
+) -> Result<T, git2::Error> {
+    let retries = *NETWORK_RETRIES.get().unwrap_or(&DEFAULT_NETWORK_RETRIES);
+    let mut attempt = 0;
+    loop {
+        match operation() {
+            Err(e) if attempt < retries && is_network_error(&e) => {
+                let delay = Duration::from_secs(1 << attempt);
+                attempt += 1;
+                println!(
+                    "{} failed ({}), retrying in {}s ({}/{})...",
+                    what,
+                    e.message(),
+                    delay.as_secs(),
+                    attempt,
+                    retries
+                );
+                thread::sleep(delay);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Backup tags live under `refs/tags/fuxi/`.
+const TAG_PREFIX: &str = "fuxi/";
+
+/// The `git_binary` setting, recorded when the config is loaded.
+static GIT_BINARY: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+pub fn set_git_binary(path: Option<&str>) {
+    let _ = GIT_BINARY.set(path.map(PathBuf::from));
+}
+
+/// Finds the git executable: the `git_binary` setting, then PATH, then where
+/// Git for Windows and portable Git usually live.
+pub fn git_binary() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if let Some(Some(path)) = GIT_BINARY.get() {
+        if path.is_file() {
+            return Ok(path.clone());
+        }
+        return Err(format!(
+            "git_binary is set to {}, which does not exist.",
+            path.display()
+        )
+        .into());
+    }
+
+    let name = if cfg!(windows) { "git.exe" } else { "git" };
+    let mut candidates: Vec<PathBuf> = env::var_os("PATH")
+        .map(|path| env::split_paths(&path).map(|dir| dir.join(name)).collect())
+        .unwrap_or_default();
+    if cfg!(windows) {
+        for (var, dir) in [
+            ("ProgramFiles", "Git"),
+            ("LOCALAPPDATA", "Programs\\Git"),
+            ("USERPROFILE", "PortableGit"),
+            ("USERPROFILE", "Downloads\\PortableGit"),
+        ] {
+            if let Some(base) = env::var_os(var) {
+                candidates.push(Path::new(&base).join(dir).join("cmd").join(name));
+            }
+        }
+    }
+    candidates.into_iter().find(|c| c.is_file()).ok_or_else(|| {
+        "git was not found. Repositories encrypted with git-crypt need it, install git or set git_binary in the config."
+            .into()
+    })
+}
+
+/// PATH with the git binary's directory in front, for tools such as git-crypt
+/// that run git themselves.
+pub fn path_with_git() -> Result<OsString, Box<dyn std::error::Error>> {
+    let git = git_binary()?;
+    let mut dirs: Vec<PathBuf> = git.parent().map(Path::to_path_buf).into_iter().collect();
+    if let Some(path) = env::var_os("PATH") {
+        dirs.extend(env::split_paths(&path));
+    }
+    Ok(env::join_paths(dirs)?)
+}
+
+fn run_git_command(repo_path: &Path, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new(git_binary()?)
         .args(args)
         .current_dir(repo_path)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .envs(std::env::vars())
-        .output()?;
+        .env("PATH", path_with_git()?)
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -28,80 +220,1377 @@ pub fn run_git_command(
     Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
-pub fn push_to_github(
+/// Runs `args` through the git CLI when the repo is encrypted with git-crypt
+/// or stores files with Git LFS, whose clean and smudge filters libgit2 can't
+/// run. Returns `false` when the repo doesn't need it.
+fn via_cli(repo_path: &Path, args: &[&str]) -> Result<bool, Box<dyn std::error::Error>> {
+    if !is_git_crypt_enabled(repo_path) && !uses_lfs(repo_path) {
+        return Ok(false);
+    }
+    run_git_command(repo_path, args)?;
+    Ok(true)
+}
+
+pub fn open_repo(repo_path: &Path) -> Result<Repository, Box<dyn std::error::Error>> {
+    Repository::open(repo_path).map_err(|e| {
+        failure(
+            GIT_ERROR,
+            format!(
+                "{} is not a git repository: {}",
+                repo_path.display(),
+                e.message()
+            ),
+        )
+    })
+}
+
+/// Answers credential requests like the git CLI would: ssh-agent or the
+/// default keys for SSH remotes, the configured credential helper for HTTPS.
+fn callbacks<'a>() -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    let attempts = Cell::new(0);
+    callbacks.credentials(move |url, username, allowed| {
+        let attempt = attempts.get();
+        attempts.set(attempt + 1);
+        if allowed.contains(CredentialType::SSH_KEY) {
+            let user = username.unwrap_or("git");
+            if attempt == 0 {
+                return Cred::ssh_key_from_agent(user);
+            }
+            let keys: Vec<_> = ["id_ed25519", "id_ecdsa", "id_rsa"]
+                .iter()
+                .filter_map(|name| dirs::home_dir().map(|h| h.join(".ssh").join(name)))
+                .filter(|key| key.exists())
+                .collect();
+            if let Some(key) = keys.get(attempt - 1) {
+                return Cred::ssh_key(user, None, key, None);
+            }
+        } else if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) && attempt == 0 {
+            return Cred::credential_helper(&git2::Config::open_default()?, url, username);
+        } else if allowed.contains(CredentialType::USERNAME) && attempt == 0 {
+            return Cred::username(username.unwrap_or("git"));
+        }
+        // libgit2 keeps asking for as long as credentials are returned
+        Err(git2::Error::from_str(&format!(
+            "Authentication to {} failed",
+            url
+        )))
+    });
+    callbacks
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SigningFormat {
+    Gpg,
+    Ssh,
+}
+
+/// Who backup commits are attributed to and how they are signed, independent
+/// of the global git config.
+#[derive(Debug, Clone, Default)]
+pub struct CommitIdentity {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub signing: Option<SigningFormat>,
+    pub signing_key: Option<String>,
+}
+
+impl CommitIdentity {
+    /// The configured name and email, falling back to the repo's git config
+    /// and then to a fuxi identity on machines without one.
+    fn signature(&self, repo: &Repository) -> Result<Signature<'static>, git2::Error> {
+        let config = repo.config()?;
+        let name = self
+            .name
+            .clone()
+            .or_else(|| config.get_string("user.name").ok())
+            .unwrap_or_else(|| "fuxi".to_string());
+        let email = self
+            .email
+            .clone()
+            .or_else(|| config.get_string("user.email").ok())
+            .unwrap_or_else(|| format!("fuxi@{}", hostname()));
+        Signature::now(&name, &email)
+    }
+
+    /// Signs a commit buffer the way git does for `gpg.format`.
+    fn sign(
+        &self,
+        format: SigningFormat,
+        content: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let mut command = match format {
+            SigningFormat::Gpg => {
+                let mut command = Command::new("gpg");
+                command.args(["--detach-sign", "--armor"]);
+                if let Some(key) = &self.signing_key {
+                    command.args(["--local-user", key]);
+                }
+                command
+            }
+            SigningFormat::Ssh => {
+                let key = self
+                    .signing_key
+                    .as_ref()
+                    .ok_or("SSH commit signing needs commit_signing_key, the path to your key.")?;
+                let mut command = Command::new("ssh-keygen");
+                command.args(["-Y", "sign", "-n", "git", "-f", key]);
+                command
+            }
+        };
+        let program = command.get_program().to_string_lossy().to_string();
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to run {} (is it installed?): {}", program, e))?;
+        child
+            .stdin
+            .take()
+            .ok_or("Failed to open the signer's stdin")?
+            .write_all(content.as_bytes())?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "Signing the commit with {} failed: {}",
+                program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    }
+
+    /// Creates a commit, signed if configured, without moving any reference.
+    fn commit(
+        &self,
+        repo: &Repository,
+        author: Option<&Signature>,
+        message: &str,
+        tree: &git2::Tree,
+        parents: &[&Commit],
+    ) -> Result<Oid, Box<dyn std::error::Error>> {
+        let committer = self.signature(repo)?;
+        let author = author.unwrap_or(&committer);
+        let Some(format) = self.signing else {
+            return Ok(repo.commit(None, author, &committer, message, tree, parents)?);
+        };
+        let buffer = repo.commit_create_buffer(author, &committer, message, tree, parents)?;
+        let content = buffer.as_str()?;
+        let signature = self.sign(format, content)?;
+        Ok(repo.commit_signed(content, &signature, None)?)
+    }
+}
+
+/// Points the current branch, or a detached HEAD, at `id`.
+fn advance_head(repo: &Repository, id: Oid, message: &str) -> Result<(), git2::Error> {
+    let head = repo.find_reference("HEAD")?;
+    match head.symbolic_target()? {
+        Some(branch) => repo.reference(branch, id, true, message).map(|_| ()),
+        None => repo.set_head_detached(id),
+    }
+}
+
+/// Updates `refs/remotes/origin/<branch>` from the remote.
+fn fetch_branch(repo: &Repository, branch: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut remote = repo.find_remote("origin").map_err(|_| {
+        config_error("No remote repository configured. Please run 'fuxi init' first.")
+    })?;
+    let refspec = format!("+refs/heads/{0}:refs/remotes/origin/{0}", branch);
+    let tags = format!("+refs/tags/{0}*:refs/tags/{0}*", TAG_PREFIX);
+    with_retries("Fetching", || {
+        let mut options = FetchOptions::new();
+        options.remote_callbacks(callbacks());
+        remote.fetch(&[refspec.as_str(), tags.as_str()], Some(&mut options), None)
+    })
+    .map_err(|e| {
+        remote_error(
+            format!(
+                "Failed to fetch from {}: {}",
+                remote_name(&remote),
+                e.message()
+            ),
+            &e,
+        )
+    })?;
+    Ok(())
+}
+
+fn remote_name(remote: &Remote) -> String {
+    remote.url().unwrap_or("the remote").to_string()
+}
+
+/// Pushes `branch` along with the backup tags. A push the remote rejects
+/// because it has commits this repo lacks fails with [`ErrorCode::NotFastForward`].
+fn push_to(repo: &Repository, remote: &mut Remote, branch: &str) -> Result<(), git2::Error> {
+    if let Some(workdir) = repo.workdir()
+        && uses_lfs(workdir)
+    {
+        // mirrors are anonymous remotes, known only by their URL
+        let target = match remote.name() {
+            Ok(Some(name)) => name.to_string(),
+            _ => remote.url()?.to_string(),
+        };
+        lfs::push_objects(workdir, &target, branch)
+            .map_err(|e| git2::Error::from_str(&e.to_string()))?;
+    }
+    with_retries("Pushing", || push_once(repo, remote, branch))
+}
+
+fn push_once(repo: &Repository, remote: &mut Remote, branch: &str) -> Result<(), git2::Error> {
+    let branch_ref = format!("refs/heads/{}", branch);
+    let rejected = RefCell::new(None);
+    let mut callbacks = callbacks();
+    callbacks.push_update_reference(|name, status| {
+        match status {
+            Some(status) if name == branch_ref => *rejected.borrow_mut() = Some(status.to_string()),
+            // a clashing tag shouldn't keep the backup itself from being pushed
+            Some(status) => println!(
+                "{}",
+                style::warning(format!("Warning: the remote rejected {}: {}", name, status))
+            ),
+            None => {}
+        }
+        Ok(())
+    });
+    let mut options = PushOptions::new();
+    options.remote_callbacks(callbacks);
+    let mut refspecs = vec![format!("{0}:{0}", branch_ref)];
+    for tag in repo
+        .tag_names(Some(&format!("{}*", TAG_PREFIX)))?
+        .iter()
+        .flatten()
+        .flatten()
+    {
+        refspecs.push(format!("refs/tags/{0}:refs/tags/{0}", tag));
+    }
+    remote.push(&refspecs, Some(&mut options))?;
+    drop(options);
+
+    match rejected.into_inner() {
+        None => Ok(()),
+        Some(status) => {
+            let code = if status.contains("non-fast-forward") || status.contains("fetch first") {
+                ErrorCode::NotFastForward
+            } else {
+                ErrorCode::GenericError
+            };
+            Err(git2::Error::new(
+                code,
+                ErrorClass::Reference,
+                format!("the remote rejected {}: {}", branch, status),
+            ))
+        }
+    }
+}
+
+/// Checks out `commit` and points HEAD at `branch`, or detaches HEAD at the
+/// commit when there is no branch.
+fn switch_to(
+    repo_path: &Path,
+    repo: &Repository,
+    commit: &Commit,
+    branch: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let id = commit.id().to_string();
+    if via_cli(repo_path, &["checkout", branch.unwrap_or(&id)])? {
+        return Ok(());
+    }
+    repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().safe()))?;
+    match branch {
+        Some(branch) => repo.set_head(&format!("refs/heads/{}", branch))?,
+        None => repo.set_head_detached(commit.id())?,
+    }
+    Ok(())
+}
+
+/// Moves the current branch to `commit` and updates the working tree. Local
+/// changes are discarded with `hard`, otherwise they are kept or the move fails.
+fn move_head(
+    repo_path: &Path,
+    repo: &Repository,
+    commit: &Commit,
+    hard: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let id = commit.id().to_string();
+    let mode = if hard { "--hard" } else { "--keep" };
+    if via_cli(repo_path, &["reset", mode, &id])? {
+        return Ok(());
+    }
+    if hard {
+        repo.reset(commit.as_object(), ResetType::Hard, None)?;
+    } else {
+        repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().safe()))?;
+        repo.reset(commit.as_object(), ResetType::Soft, None)?;
+    }
+    Ok(())
+}
+
+/// Groups conflicting repo paths by their profile (first path component).
+fn format_conflicts(index: &Index) -> String {
+    let mut by_profile: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for conflict in index.conflicts().into_iter().flatten().flatten() {
+        let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) else {
+            continue;
+        };
+        let file = String::from_utf8_lossy(&entry.path).to_string();
+        let (profile, rest) = file
+            .split_once('/')
+            .map(|(p, r)| (p.to_string(), r.to_string()))
+            .unwrap_or(("(repository root)".to_string(), file.clone()));
+        by_profile.entry(profile).or_default().push(rest);
+    }
+
+    let mut out = String::from("Conflicting files:");
+    for (profile, files) in by_profile {
+        out.push_str(&format!("\n  profile '{}':", profile));
+        for file in files {
+            out.push_str(&format!("\n    - {}", file));
+        }
+    }
+    out
+}
+
+fn conflict_error(action: &str, index: &Index) -> Box<dyn std::error::Error> {
+    format!(
+        "Could not {} automatically, the repository was left unchanged.\n{}",
+        action,
+        format_conflicts(index)
+    )
+    .into()
+}
+
+/// Replays the commits of `local` onto `upstream` without touching the
+/// working tree and returns the new tip. Merges are flattened, like `git rebase`.
+fn rebase_onto(
+    repo: &Repository,
+    identity: &CommitIdentity,
+    local: &Commit,
+    upstream: &Commit,
+) -> Result<Oid, Box<dyn std::error::Error>> {
+    let mut walk = repo.revwalk()?;
+    walk.push(local.id())?;
+    walk.hide(repo.merge_base(local.id(), upstream.id())?)?;
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+
+    let mut tip = upstream.clone();
+    for id in walk {
+        let commit = repo.find_commit(id?)?;
+        if commit.parent_count() > 1 {
+            continue;
+        }
+        let mut index = repo.cherrypick_commit(&commit, &tip, 0, None)?;
+        if index.has_conflicts() {
+            return Err(conflict_error("rebase", &index));
+        }
+        let tree = repo.find_tree(index.write_tree_to(repo)?)?;
+        // the change is already upstream
+        if tree.id() == tip.tree_id() {
+            continue;
+        }
+        let message = commit.message().unwrap_or_default();
+        let id = identity.commit(repo, Some(&commit.author()), message, &tree, &[&tip])?;
+        tip = repo.find_commit(id)?;
+    }
+    Ok(tip.id())
+}
+
+/// Merges `theirs` into `ours` without touching the working tree and
+/// returns the merge commit.
+fn merge_commits(
+    repo: &Repository,
+    identity: &CommitIdentity,
+    ours: &Commit,
+    theirs: &Commit,
+    message: &str,
+) -> Result<Oid, Box<dyn std::error::Error>> {
+    let mut index = repo.merge_commits(ours, theirs, None)?;
+    if index.has_conflicts() {
+        return Err(conflict_error("merge", &index));
+    }
+    let tree = repo.find_tree(index.write_tree_to(repo)?)?;
+    identity.commit(repo, None, message, &tree, &[ours, theirs])
+}
+
+fn remote_commit<'r>(repo: &'r Repository, branch: &str) -> Result<Commit<'r>, git2::Error> {
+    repo.find_reference(&format!("refs/remotes/origin/{}", branch))?
+        .peel_to_commit()
+}
+
+fn integrate_remote_changes(
     repo_path: &Path,
+    repo: &Repository,
     branch: &str,
-    message: Option<String>,
+    identity: &CommitIdentity,
+    strategy: DivergenceStrategy,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    println!("Pushing to GitHub...");
+    let strategy = match strategy {
+        DivergenceStrategy::Prompt => {
+            let answer = prompt("Rebase onto the remote (r), merge it (m) or abort (a)? [a]")?;
+            match answer.to_lowercase().as_str() {
+                "r" | "rebase" => DivergenceStrategy::Rebase,
+                "m" | "merge" => DivergenceStrategy::Merge,
+                _ => DivergenceStrategy::Abort,
+            }
+        }
+        other => other,
+    };
+    if !matches!(
+        strategy,
+        DivergenceStrategy::Rebase | DivergenceStrategy::Merge
+    ) {
+        return Err(
+            "Push rejected: the remote has backups from another machine. Run 'fuxi sync' or set 'divergence_strategy' to 'rebase' or 'merge'."
+                .into(),
+        );
+    }
+
+    println!("Fetching remote changes...");
+    fetch_branch(repo, branch)?;
+    let local = repo.head()?.peel_to_commit()?;
+    let remote = remote_commit(repo, branch)?;
+    let tip = if strategy == DivergenceStrategy::Rebase {
+        rebase_onto(repo, identity, &local, &remote)?
+    } else {
+        let message = format!("Merge remote-tracking branch 'origin/{}'", branch);
+        merge_commits(repo, identity, &local, &remote, &message)?
+    };
+    move_head(repo_path, repo, &repo.find_commit(tip)?, false)
+}
+
+/// Switches the working tree to `branch`, creating it (tracking the remote
+/// branch if there is one) when it doesn't exist yet.
+pub fn ensure_branch(repo_path: &Path, branch: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = open_repo(repo_path)?;
+    let local = format!("refs/heads/{}", branch);
+    match repo.head() {
+        Ok(head) if head.is_branch() && head.shorthand() == Ok(branch) => return Ok(()),
+        Ok(_) => {}
+        Err(e) if e.code() == ErrorCode::UnbornBranch => {
+            // nothing committed yet, just rename the unborn branch
+            if repo.find_reference("HEAD")?.symbolic_target()? != Some(local.as_str()) {
+                repo.set_head(&local)?;
+                println!("Switched the backup repository to branch '{}'.", branch);
+            }
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    let commit = match repo.find_branch(branch, git2::BranchType::Local) {
+        Ok(existing) => existing.get().peel_to_commit()?,
+        Err(_) => match remote_commit(&repo, branch) {
+            Ok(remote) => {
+                let mut created = repo.branch(branch, &remote, false)?;
+                created.set_upstream(Some(&format!("origin/{}", branch)))?;
+                remote
+            }
+            Err(_) => {
+                let head = repo.head()?.peel_to_commit()?;
+                repo.branch(branch, &head, false)?;
+                head
+            }
+        },
+    };
+    switch_to(repo_path, &repo, &commit, Some(branch))?;
+    println!("Switched the backup repository to branch '{}'.", branch);
+    Ok(())
+}
+
+/// Stages everything in the repo and commits it, refusing when the staged
+/// files look like they contain secrets. Returns `false` if nothing changed.
+pub fn commit_changes(
+    repo_path: &Path,
+    branch: &str,
+    identity: &CommitIdentity,
+    message: Option<String>,
+    allow_secrets: bool,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    ensure_branch(repo_path, branch)?;
+    let repo = open_repo(repo_path)?;
 
     println!("Adding files...");
-    if let Err(e) = run_git_command(repo_path, &["add", "."]) {
+    let mut index = repo.index()?;
+    let staged = via_cli(repo_path, &["add", "."]).and_then(|cli| {
+        if cli {
+            index.read(true)?;
+        } else {
+            index.add_all(["*"], IndexAddOption::DEFAULT, None)?;
+            // add_all doesn't notice deleted files
+            index.update_all(["*"], None)?;
+            index.write()?;
+        }
+        Ok(())
+    });
+    if let Err(e) = staged {
         return Err(format!("Failed to add files: {}", e).into());
     }
 
     println!("Checking status...");
-    let status = match run_git_command(repo_path, &["status", "--porcelain"]) {
-        Ok(status) => status,
-        Err(e) => return Err(format!("Failed to check status: {}", e).into()),
-    };
-
-    if status.trim().is_empty() {
+    let head = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+    let head_tree = head.as_ref().map(|c| c.tree()).transpose()?;
+    let changes = repo
+        .diff_tree_to_index(head_tree.as_ref(), Some(&index), None)?
+        .deltas()
+        .len();
+    if changes == 0 {
         println!("No changes to commit.");
-        return Ok(());
+        return Ok(false);
+    }
+
+    if !allow_secrets {
+        println!("Scanning for secrets...");
+        let findings = scan_staged(repo_path)?;
+        if !findings.is_empty() {
+            return Err(format!(
+                "{}\nNothing was committed. Mark these paths with 'fuxi secret add' or rerun with --allow-secrets.",
+                format_findings(&findings)
+            )
+            .into());
+        }
     }
 
     let commit_msg = message.unwrap_or_else(|| "Automated backup commit".to_string());
     println!("Committing with message: '{}'", commit_msg);
-    if let Err(e) = run_git_command(repo_path, &["commit", "-m", commit_msg.as_str()]) {
-        return Err(format!("Failed to commit: {}", e).into());
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let parents: Vec<&Commit> = head.iter().collect();
+    let id = identity
+        .commit(&repo, None, &commit_msg, &tree, &parents)
+        .map_err(|e| format!("Failed to commit: {}", e))?;
+    advance_head(&repo, id, &format!("commit: {}", commit_msg))?;
+    Ok(true)
+}
+
+/// A file that differs between HEAD and the working tree.
+pub struct FileChange {
+    /// `A`, `M`, `D`, `R` or `T`, as in `git status --short`.
+    pub status: char,
+    pub path: String,
+    /// Lines added and removed, `None` for binary files.
+    pub lines: Option<(usize, usize)>,
+}
+
+/// Everything the next commit would contain, untracked files included.
+pub fn pending_changes(repo_path: &Path) -> Result<Vec<FileChange>, Box<dyn std::error::Error>> {
+    let repo = open_repo(repo_path)?;
+    let head_tree = repo.head().ok().and_then(|h| h.peel_to_tree().ok());
+    let mut options = DiffOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .show_untracked_content(true);
+    let diff = repo.diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut options))?;
+
+    let mut changes = Vec::new();
+    for (i, delta) in diff.deltas().enumerate() {
+        let status = match delta.status() {
+            Delta::Added | Delta::Untracked => 'A',
+            Delta::Deleted => 'D',
+            Delta::Renamed => 'R',
+            Delta::Typechange => 'T',
+            _ => 'M',
+        };
+        let Some(path) = delta.new_file().path().or(delta.old_file().path()) else {
+            continue;
+        };
+        let lines = match Patch::from_diff(&diff, i)? {
+            Some(patch) if !delta.flags().is_binary() => {
+                let (_, added, removed) = patch.line_stats()?;
+                Some((added, removed))
+            }
+            _ => None,
+        };
+        changes.push(FileChange {
+            status,
+            path: path.to_string_lossy().to_string(),
+            lines,
+        });
     }
+    Ok(changes)
+}
 
-    println!("Checking remote configuration...");
-    match run_git_command(repo_path, &["remote", "-v"]) {
-        Ok(remotes) => {
-            if remotes.trim().is_empty() {
-                return Err("No remote repository configured. Please add a remote with 'git remote add origin <url>'".into());
+/// Checks the working tree before `operation` replaces it. Uncommitted changes
+/// are stashed or refused per `policy`. Returns whether a stash was made.
+pub fn guard_dirty(
+    repo_path: &Path,
+    policy: DirtyRepoPolicy,
+    identity: &CommitIdentity,
+    operation: &str,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let changes = pending_changes(repo_path)?;
+    if changes.is_empty() {
+        return Ok(false);
+    }
+    if policy == DirtyRepoPolicy::Abort {
+        let paths: Vec<&str> = changes.iter().map(|c| c.path.as_str()).collect();
+        return Err(format!(
+            "The backup repository has uncommitted changes that {} would overwrite: {}\nCommit them with 'fuxi save', or set dirty_repo_policy = \"stash\" to set them aside automatically.",
+            operation,
+            paths.join(", ")
+        )
+        .into());
+    }
+
+    println!(
+        "Stashing {} uncommitted change(s) in the backup repository...",
+        changes.len()
+    );
+    let message = format!("fuxi: before {}", operation);
+    if !via_cli(
+        repo_path,
+        &["stash", "push", "--include-untracked", "-m", &message],
+    )? {
+        let mut repo = open_repo(repo_path)?;
+        let signature = identity.signature(&repo)?;
+        repo.stash_save(&signature, &message, Some(StashFlags::INCLUDE_UNTRACKED))?;
+    }
+    Ok(true)
+}
+
+/// Reapplies the changes stashed by [`guard_dirty`]. If they conflict with
+/// the new state they stay in the stash, like `git stash pop` does.
+pub fn restore_stash(repo_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let kept = |reason: String| {
+        format!(
+            "Your stashed changes could not be restored cleanly. {}\nThey are kept in the stash, resolve the conflicts in {} and run 'git stash drop'.",
+            reason,
+            repo_path.display()
+        )
+    };
+    match via_cli(repo_path, &["stash", "pop"]) {
+        Ok(true) => {}
+        Ok(false) => {
+            let mut repo = open_repo(repo_path)?;
+            repo.stash_apply(0, None)
+                .map_err(|e| kept(e.message().to_string()))?;
+            let index = repo.index()?;
+            if index.has_conflicts() {
+                return Err(kept(format_conflicts(&index)).into());
             }
-            println!("Remotes configured:\n{}", remotes);
+            repo.stash_drop(0)?;
+        }
+        Err(e) => return Err(kept(e.to_string()).into()),
+    }
+    println!("Restored the stashed changes in the backup repository.");
+    Ok(())
+}
+
+/// Paths and contents of the files staged for the next commit.
+pub fn staged_files(repo_path: &Path) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    let repo = open_repo(repo_path)?;
+    let index = repo.index()?;
+    let head_tree = repo.head().ok().and_then(|h| h.peel_to_tree().ok());
+    let diff = repo.diff_tree_to_index(head_tree.as_ref(), Some(&index), None)?;
+
+    let mut files = Vec::new();
+    for delta in diff.deltas() {
+        if matches!(delta.status(), Delta::Deleted) {
+            continue;
+        }
+        let file = delta.new_file();
+        // submodules point at commits of other repositories
+        if file.mode() == git2::FileMode::Commit {
+            continue;
         }
-        Err(e) => return Err(format!("Failed to check remotes: {}", e).into()),
+        let Some(path) = file.path() else {
+            continue;
+        };
+        let blob = repo.find_blob(file.id())?;
+        files.push((
+            path.to_string_lossy().to_string(),
+            String::from_utf8_lossy(blob.content()).to_string(),
+        ));
+    }
+    Ok(files)
+}
+
+pub fn push_branch(
+    repo_path: &Path,
+    branch: &str,
+    identity: &CommitIdentity,
+    divergence: DivergenceStrategy,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("Pushing to the remote...");
+    ensure_branch(repo_path, branch)?;
+    let repo = open_repo(repo_path)?;
+
+    println!("Checking remote configuration...");
+    let Ok(mut remote) = repo.find_remote("origin") else {
+        return Err("No remote repository configured. Please add a remote with 'git remote add origin <url>'".into());
     };
+    println!("Remote configured: {}", remote_name(&remote));
+
+    if let Err(e) = push_to(&repo, &mut remote, branch) {
+        if e.code() != ErrorCode::NotFastForward {
+            return Err(push_error(e));
+        }
 
-    println!("Pushing to remote...");
-    if let Err(e) = run_git_command(repo_path, &["push", "origin", branch]) {
-        return Err(format!("Failed to push: {}", e).into());
+        println!("The remote has backups this machine doesn't have yet.");
+        integrate_remote_changes(repo_path, &repo, branch, identity, divergence)?;
+        if let Err(e) = push_to(&repo, &mut remote, branch) {
+            return Err(push_error(e));
+        }
     }
 
-    println!("Successfully pushed to GitHub!");
+    println!("{}", style::success("Successfully pushed to the remote!"));
     Ok(())
 }
 
-pub fn fetch_from_github(
+/// Pushes `branch` to each mirror URL, reporting every remote separately.
+/// Mirrors are only ever fast-forwarded, a diverged mirror counts as failed.
+pub fn push_to_mirrors(
+    repo_path: &Path,
+    branch: &str,
+    mirrors: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if mirrors.is_empty() {
+        return Ok(());
+    }
+    let repo = open_repo(repo_path)?;
+    let mut failed = 0;
+    for url in mirrors {
+        println!("Pushing to mirror {}...", url);
+        match repo
+            .remote_anonymous(url)
+            .and_then(|mut remote| push_to(&repo, &mut remote, branch))
+        {
+            Ok(_) => println!("  {}: pushed", url),
+            Err(e) => {
+                failed += 1;
+                println!("  {}: failed: {}", url, e.message());
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!(
+            "Pushing to {} of {} mirror(s) failed.",
+            failed,
+            mirrors.len()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+pub fn fetch_from_remote(
     repo_path: &Path,
     branch: &str,
     commit_hash: Option<&str>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    println!("Fetching from GitHub...");
-    // fetch the commit hash if provided, else fetch the branch
+    println!("Fetching from the remote...");
+    let repo = open_repo(repo_path)?;
+    fetch_branch(&repo, branch)?;
+    // check out the commit if provided, else the latest state of the branch
     if let Some(hash) = commit_hash {
-        run_git_command(repo_path, &["fetch", "origin", hash])?;
-        run_git_command(repo_path, &["checkout", hash])?;
+        let commit = repo
+            .revparse_single(hash)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|_| format!("Backup ID or commit hash '{}' not found.", hash))?;
+        switch_to(repo_path, &repo, &commit, None)?;
     } else {
-        run_git_command(repo_path, &["fetch", "origin", branch])?;
-        run_git_command(repo_path, &["checkout", branch])?;
-        run_git_command(
-            repo_path,
-            &["reset", "--hard", &format!("origin/{}", branch)],
-        )?;
-    }
-    println!("Successfully fetched from GitHub!");
+        ensure_branch(repo_path, branch)?;
+        move_head(repo_path, &repo, &remote_commit(&repo, branch)?, true)?;
+    }
+    println!(
+        "{}",
+        style::success("Successfully fetched from the remote!")
+    );
+    Ok(())
+}
+
+/// Fetches `branch` and fast-forwards or merges it into the current branch.
+pub fn pull_from_remote(
+    repo_path: &Path,
+    branch: &str,
+    identity: &CommitIdentity,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("Pulling from the remote...");
+    let repo = open_repo(repo_path)?;
+    fetch_branch(&repo, branch)?;
+    let remote = remote_commit(&repo, branch)?;
+    let (analysis, _) = repo.merge_analysis(&[&repo.find_annotated_commit(remote.id())?])?;
+
+    if analysis.is_unborn() {
+        let head = repo.find_reference("HEAD")?;
+        let target = head.symbolic_target()?.ok_or("HEAD is not a branch")?;
+        repo.reference(target, remote.id(), false, "pull")?;
+        if !via_cli(repo_path, &["checkout", "--", "."])? {
+            repo.checkout_head(Some(CheckoutBuilder::new().safe()))?;
+        }
+    } else if analysis.is_fast_forward() {
+        move_head(repo_path, &repo, &remote, false)?;
+    } else if !analysis.is_up_to_date() {
+        let local = repo.head()?.peel_to_commit()?;
+        let message = format!("Merge branch '{}' of the remote", branch);
+        let merged = merge_commits(&repo, identity, &local, &remote, &message)?;
+        move_head(repo_path, &repo, &repo.find_commit(merged)?, false)?;
+    }
+    println!("{}", style::success("Successfully pulled from the remote!"));
     Ok(())
 }
 
-pub fn pull_from_github(repo_path: &Path, branch: &str) -> Result<(), Box<dyn std::error::Error>> {
-    println!("Pulling from GitHub...");
-    run_git_command(repo_path, &["pull", "origin", branch])?;
-    println!("Successfully pulled from GitHub!");
+/// Unix timestamp of the last commit touching `path`, if it has been committed.
+pub fn last_commit_time(repo_path: &Path, path: &Path) -> Option<i64> {
+    let repo = Repository::open(repo_path).ok()?;
+    let entry = |commit: &Commit| {
+        commit
+            .tree()
+            .ok()
+            .and_then(|tree| tree.get_path(path).ok())
+            .map(|entry| entry.id())
+    };
+    let mut walk = repo.revwalk().ok()?;
+    walk.push_head().ok()?;
+    for id in walk {
+        let commit = repo.find_commit(id.ok()?).ok()?;
+        let current = entry(&commit);
+        // like `git log -- <path>`, a merge only counts if it differs from every parent
+        let changed = if commit.parent_count() == 0 {
+            current.is_some()
+        } else {
+            commit.parents().all(|parent| entry(&parent) != current)
+        };
+        if changed {
+            return Some(commit.time().seconds());
+        }
+    }
+    None
+}
+
+pub struct LogEntry {
+    /// The abbreviated hash.
+    pub hash: String,
+    pub summary: String,
+    /// The backup tag pointing at the commit, if any.
+    pub tag: Option<String>,
+    pub time: Option<DateTime<Utc>>,
+    /// How many files the commit changed compared to its first parent.
+    pub files_changed: usize,
+    /// Taken from the `Fuxi-Profile` and `Fuxi-Hostname` trailers.
+    pub profile: Option<String>,
+    pub hostname: Option<String>,
+}
+
+/// The value of the `Fuxi-<key>` trailer in a commit message.
+fn trailer(message: &str, key: &str) -> Option<String> {
+    message
+        .lines()
+        .find_map(|line| line.strip_prefix(&format!("Fuxi-{}: ", key)))
+        .map(|value| value.trim().to_string())
+}
+
+/// The commits `walk` goes through, with their backup tags.
+fn log_entries(
+    repo: &Repository,
+    walk: git2::Revwalk,
+) -> Result<Vec<LogEntry>, Box<dyn std::error::Error>> {
+    let mut tags = HashMap::new();
+    for name in repo
+        .tag_names(Some(&format!("{}*", TAG_PREFIX)))?
+        .iter()
+        .flatten()
+        .flatten()
+    {
+        if let Ok(object) = repo.revparse_single(&format!("refs/tags/{}", name))
+            && let Ok(commit) = object.peel_to_commit()
+        {
+            tags.insert(commit.id(), name.to_string());
+        }
+    }
+    let mut commits = Vec::new();
+    for id in walk {
+        let commit = repo.find_commit(id?)?;
+        let short = commit.as_object().short_id()?;
+        let parent = commit.parents().next().map(|p| p.tree()).transpose()?;
+        let files_changed = repo
+            .diff_tree_to_tree(parent.as_ref(), Some(&commit.tree()?), None)?
+            .deltas()
+            .len();
+        let message = commit.message().unwrap_or_default();
+        commits.push(LogEntry {
+            hash: short.as_str().unwrap_or_default().to_string(),
+            summary: commit
+                .summary()
+                .ok()
+                .flatten()
+                .unwrap_or_default()
+                .to_string(),
+            tag: tags.remove(&commit.id()),
+            time: DateTime::from_timestamp(commit.time().seconds(), 0),
+            files_changed,
+            profile: trailer(message, "Profile"),
+            hostname: trailer(message, "Hostname"),
+        });
+    }
+    Ok(commits)
+}
+
+/// Every commit on HEAD, newest first.
+pub fn log(repo_path: &Path) -> Result<Vec<LogEntry>, Box<dyn std::error::Error>> {
+    let repo = open_repo(repo_path)?;
+    let mut walk = repo.revwalk()?;
+    if walk.push_head().is_err() {
+        // nothing committed yet
+        return Ok(Vec::new());
+    }
+    log_entries(&repo, walk)
+}
+
+/// The commits on the remote's `branch` that the local one doesn't have yet,
+/// newest first. Only the remote-tracking branch is updated, the working
+/// tree is left alone.
+pub fn remote_log(
+    repo_path: &Path,
+    branch: &str,
+) -> Result<Vec<LogEntry>, Box<dyn std::error::Error>> {
+    let repo = open_repo(repo_path)?;
+    fetch_branch(&repo, branch)?;
+    let Ok(remote) = remote_commit(&repo, branch) else {
+        // the remote has no backups on this branch yet
+        return Ok(Vec::new());
+    };
+    let mut walk = repo.revwalk()?;
+    walk.push(remote.id())?;
+    if let Ok(local) = repo
+        .find_reference(&format!("refs/heads/{}", branch))
+        .and_then(|reference| reference.peel_to_commit())
+    {
+        walk.hide(local.id())?;
+    }
+    log_entries(&repo, walk)
+}
+
+/// Tags HEAD as `fuxi/<profile>/<backup id>`, taken from the commit's
+/// `Fuxi-*` trailers. Commits without a backup ID are left untagged.
+pub fn tag_backup(
+    repo_path: &Path,
+    identity: &CommitIdentity,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = open_repo(repo_path)?;
+    let commit = repo.head()?.peel_to_commit()?;
+    let message = commit.message().unwrap_or_default();
+    let tag_part = |key: &str| {
+        trailer(message, key).map(|value| {
+            value
+                .chars()
+                .map(|c| {
+                    if c.is_alphanumeric() || "-_.".contains(c) {
+                        c
+                    } else {
+                        '-'
+                    }
+                })
+                .collect::<String>()
+        })
+    };
+    let Some(backup_id) = tag_part("Backup-Id") else {
+        return Ok(());
+    };
+    let profile = tag_part("Profile").unwrap_or_else(|| "default".to_string());
+    let name = format!("{}{}/{}", TAG_PREFIX, profile, backup_id);
+    match repo.tag(
+        &name,
+        commit.as_object(),
+        &identity.signature(&repo)?,
+        commit.summary().ok().flatten().unwrap_or_default(),
+        false,
+    ) {
+        Ok(_) => println!("Tagged the backup as {}", name),
+        // 'fuxi save' commits again under the backup it saves, keep the first tag
+        Err(e) if e.code() == ErrorCode::Exists => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+/// Builds `tree` without the files in `paths`.
+fn tree_without<'r>(
+    repo: &'r Repository,
+    tree: &git2::Tree,
+    paths: &[PathBuf],
+) -> Result<git2::Tree<'r>, git2::Error> {
+    let mut index = Index::new()?;
+    index.read_tree(tree)?;
+    for path in paths {
+        // older commits may not have it yet
+        let _ = index.remove_path(path);
+    }
+    repo.find_tree(index.write_tree_to(repo)?)
+}
+
+/// Points the `fuxi/` tags of rewritten commits at their new versions and
+/// deletes the ones whose commit was dropped. Returns the refspecs that
+/// bring the remote's tags in line.
+fn retag(
+    repo: &Repository,
+    rewritten: &HashMap<Oid, Oid>,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut refspecs = Vec::new();
+    let names: Vec<String> = repo
+        .tag_names(Some(&format!("{}*", TAG_PREFIX)))?
+        .iter()
+        .flatten()
+        .flatten()
+        .map(str::to_string)
+        .collect();
+    for name in names {
+        let reference = repo.find_reference(&format!("refs/tags/{}", name))?;
+        let target = reference.peel_to_commit()?.id();
+        let annotation = reference.peel_to_tag().ok();
+        repo.tag_delete(&name)?;
+        match rewritten.get(&target) {
+            Some(id) => {
+                let commit = repo.find_object(*id, None)?;
+                match annotation
+                    .as_ref()
+                    .and_then(|tag| tag.tagger().map(|t| (tag, t)))
+                {
+                    Some((tag, tagger)) => {
+                        repo.tag(
+                            &name,
+                            &commit,
+                            &tagger,
+                            tag.message().ok().flatten().unwrap_or_default(),
+                            true,
+                        )?;
+                    }
+                    None => {
+                        repo.tag_lightweight(&name, &commit, true)?;
+                    }
+                }
+                refspecs.push(format!("+refs/tags/{0}:refs/tags/{0}", name));
+            }
+            None => refspecs.push(format!(":refs/tags/{}", name)),
+        }
+    }
+    Ok(refspecs)
+}
+
+/// Pushes `refspecs` as given, forced updates and deletions included.
+fn force_push(remote: &mut Remote, refspecs: &[String]) -> Result<(), git2::Error> {
+    let rejected = RefCell::new(Vec::new());
+    let mut callbacks = callbacks();
+    callbacks.push_update_reference(|name, status| {
+        if let Some(status) = status {
+            rejected.borrow_mut().push(format!("{}: {}", name, status));
+        }
+        Ok(())
+    });
+    let mut options = PushOptions::new();
+    options.remote_callbacks(callbacks);
+    remote.push(refspecs, Some(&mut options))?;
+    drop(options);
+    let rejected = rejected.into_inner();
+    if rejected.is_empty() {
+        Ok(())
+    } else {
+        Err(git2::Error::from_str(&format!(
+            "the remote rejected {}",
+            rejected.join(", ")
+        )))
+    }
+}
+
+/// Metadata files `commit` added compared to its first parent.
+fn metadata_added(
+    repo: &Repository,
+    commit: &Commit,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let parent = commit.parents().next().map(|p| p.tree()).transpose()?;
+    let mut options = DiffOptions::new();
+    options.pathspec(METADATA_DIR);
+    let diff =
+        repo.diff_tree_to_tree(parent.as_ref(), Some(&commit.tree()?), Some(&mut options))?;
+    Ok(diff
+        .deltas()
+        .filter(|d| d.status() == Delta::Added)
+        .filter_map(|d| d.new_file().path().map(Path::to_path_buf))
+        .collect())
+}
+
+/// The tip's tree without the metadata of dropped backups, and with the
+/// metadata of kept ones recording why they were kept.
+fn mark_retained<'r>(
+    repo: &'r Repository,
+    tree: &git2::Tree,
+    stale: &[PathBuf],
+    retained: &[(PathBuf, Vec<&str>)],
+) -> Result<git2::Tree<'r>, Box<dyn std::error::Error>> {
+    let mut index = Index::new()?;
+    index.read_tree(tree)?;
+    for path in stale {
+        let _ = index.remove_path(path);
+    }
+    for (path, reasons) in retained {
+        let Some(mut entry) = index.get_path(path, 0) else {
+            continue;
+        };
+        let blob = repo.find_blob(entry.id)?;
+        let Ok(mut metadata) =
+            toml::from_str::<BackupMetadata>(&String::from_utf8_lossy(blob.content()))
+        else {
+            continue;
+        };
+        metadata.retained_by = reasons.iter().map(|r| r.to_string()).collect();
+        let content = toml::to_string_pretty(&metadata)?;
+        entry.id = repo.blob(content.as_bytes())?;
+        entry.file_size = content.len() as u32;
+        index.add(&entry)?;
+    }
+    Ok(repo.find_tree(index.write_tree_to(repo)?)?)
+}
+
+/// Rewrites `branch` so it only holds the backups `retention` keeps, each
+/// with its full content, drops the metadata and `fuxi/` tags of the others,
+/// force pushes the result and compacts the repository. Returns the
+/// abbreviated hashes of the dropped commits. With `dry_run`, only returns them.
+pub fn prune_history(
+    repo_path: &Path,
+    branch: &str,
+    identity: &CommitIdentity,
+    retention: &Retention,
+    dry_run: bool,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    ensure_branch(repo_path, branch)?;
+    let repo = open_repo(repo_path)?;
+    let Ok(tip) = repo.head().and_then(|head| head.peel_to_commit()) else {
+        return Ok(Vec::new());
+    };
+
+    // the first-parent chain, newest first, is what `fuxi list` shows
+    let mut chain = Vec::new();
+    let mut next = Some(tip);
+    while let Some(commit) = next {
+        next = commit.parents().next();
+        chain.push(commit);
+    }
+    // the author time survives earlier prunes, the commit time doesn't
+    let created: Vec<DateTime<Utc>> = chain
+        .iter()
+        .map(|commit| {
+            DateTime::from_timestamp(commit.author().when().seconds(), 0).unwrap_or_default()
+        })
+        .collect();
+    let mut reasons = retention.reasons(&created);
+    // the latest backup always stays, it's what the working tree holds
+    if reasons[0].is_empty() {
+        reasons[0].push("latest");
+    }
+    if reasons.iter().all(|r| !r.is_empty()) {
+        return Ok(Vec::new());
+    }
+
+    let mut kept = Vec::new();
+    let mut dropped = Vec::new();
+    for (commit, reasons) in chain.into_iter().zip(reasons) {
+        if reasons.is_empty() {
+            dropped.push(commit);
+        } else {
+            kept.push((commit, reasons));
+        }
+    }
+    let dropped_ids = dropped
+        .iter()
+        .map(|commit| {
+            Ok(commit
+                .as_object()
+                .short_id()?
+                .as_str()
+                .unwrap_or_default()
+                .to_string())
+        })
+        .collect::<Result<Vec<_>, git2::Error>>()?;
+    if dry_run {
+        return Ok(dropped_ids);
+    }
+
+    let mut stale = Vec::new();
+    for commit in &dropped {
+        stale.extend(metadata_added(&repo, commit)?);
+    }
+    let mut retained = Vec::new();
+    for (commit, reasons) in &kept {
+        for path in metadata_added(&repo, commit)? {
+            retained.push((path, reasons.clone()));
+        }
+    }
+
+    println!("Rewriting {} backup(s)...", kept.len());
+    let mut rewritten = HashMap::new();
+    let mut parent: Option<Commit> = None;
+    for (position, (commit, _)) in kept.iter().enumerate().rev() {
+        let tree = if position == 0 {
+            mark_retained(&repo, &commit.tree()?, &stale, &retained)?
+        } else {
+            tree_without(&repo, &commit.tree()?, &stale)?
+        };
+        let parents: Vec<&Commit> = parent.iter().collect();
+        let message = commit.message().unwrap_or_default();
+        let id = identity.commit(&repo, Some(&commit.author()), message, &tree, &parents)?;
+        rewritten.insert(commit.id(), id);
+        parent = Some(repo.find_commit(id)?);
+    }
+    let new_tip = parent.expect("the latest backup is always kept");
+    move_head(repo_path, &repo, &new_tip, false)?;
+    let mut refspecs = vec![format!("+refs/heads/{0}:refs/heads/{0}", branch)];
+    refspecs.extend(retag(&repo, &rewritten)?);
+
+    match repo.find_remote("origin") {
+        Ok(mut remote) => {
+            println!(
+                "Force pushing the rewritten history to {}...",
+                remote_name(&remote)
+            );
+            with_retries("Pushing", || force_push(&mut remote, &refspecs)).map_err(push_error)?;
+        }
+        Err(_) => println!("No remote is configured, only the local history was rewritten."),
+    }
+
+    println!("Compacting the repository...");
+    let compacted = run_git_command(repo_path, &["reflog", "expire", "--expire=now", "--all"])
+        .and_then(|_| run_git_command(repo_path, &["gc", "--aggressive", "--prune=now"]));
+    if let Err(e) = compacted {
+        println!(
+            "{}",
+            style::warning(format!(
+                "Warning: could not compact the repository, run 'git gc --aggressive --prune=now' in {}: {}",
+                repo_path.display(),
+                e
+            ))
+        );
+    }
+    Ok(dropped_ids)
+}
+
+/// Files added below `dir`, each with the abbreviated hash of the commit
+/// that added it.
+pub fn commits_adding(
+    repo_path: &Path,
+    dir: &str,
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    let repo = open_repo(repo_path)?;
+    let mut walk = repo.revwalk()?;
+    if walk.push_head().is_err() {
+        return Ok(Vec::new());
+    }
+    let mut options = git2::DiffOptions::new();
+    options.pathspec(dir);
+    let mut added = Vec::new();
+    for id in walk {
+        let commit = repo.find_commit(id?)?;
+        // like `git log`, merges show no changes of their own
+        if commit.parent_count() > 1 {
+            continue;
+        }
+        let parent = commit.parents().next().map(|p| p.tree()).transpose()?;
+        let diff =
+            repo.diff_tree_to_tree(parent.as_ref(), Some(&commit.tree()?), Some(&mut options))?;
+        let short = commit.as_object().short_id()?;
+        let hash = short.as_str().unwrap_or_default();
+        for delta in diff.deltas().filter(|d| d.status() == Delta::Added) {
+            if let Some(path) = delta.new_file().path() {
+                added.push((hash.to_string(), path.to_string_lossy().to_string()));
+            }
+        }
+    }
+    Ok(added)
+}
+
+/// The full hash of HEAD and how many files its commit changed.
+pub fn head_commit(repo_path: &Path) -> Option<(String, usize)> {
+    let repo = Repository::open(repo_path).ok()?;
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    let parent = commit.parents().next().and_then(|p| p.tree().ok());
+    let files = repo
+        .diff_tree_to_tree(parent.as_ref(), commit.tree().ok().as_ref(), None)
+        .map(|diff| diff.deltas().len())
+        .ok()?;
+    Some((commit.id().to_string(), files))
+}
+
+/// Whether `origin/<branch>` is known locally, i.e. the branch was pushed or fetched.
+pub fn has_remote_branch(repo_path: &Path, branch: &str) -> bool {
+    Repository::open(repo_path).is_ok_and(|repo| remote_commit(&repo, branch).is_ok())
+}
+
+/// The URL of the `origin` remote.
+pub fn origin_url(repo_path: &Path) -> Option<String> {
+    let repo = Repository::open(repo_path).ok()?;
+    let remote = repo.find_remote("origin").ok()?;
+    remote.url().ok().map(str::to_string)
+}
+
+/// Turns `username/repo` into a GitHub URL, leaving full git URLs and local paths as they are.
+pub fn remote_url(repo: &str) -> String {
+    if repo.contains(':')
+        || repo.starts_with('/')
+        || repo.starts_with('.')
+        || repo.ends_with(".git")
+    {
+        repo.to_string()
+    } else {
+        format!("https://github.com/{}.git", repo)
+    }
+}
+
+/// Lists the branches of a remote, or `None` if it can't be reached.
+pub fn remote_branches(url: &str) -> Option<Vec<String>> {
+    let mut remote = Remote::create_detached(url).ok()?;
+    let connection = remote
+        .connect_auth(Direction::Fetch, Some(callbacks()), None)
+        .ok()?;
+    Some(
+        connection
+            .list()
+            .ok()?
+            .iter()
+            .filter_map(|head| head.name().strip_prefix("refs/heads/").map(str::to_string))
+            .collect(),
+    )
+}
+
+/// Clones `url` into `path`, or initializes an empty repository with it as
+/// `origin` when it can't be reached. Returns whether it cloned.
+pub fn clone_or_init(
+    url: &str,
+    path: &Path,
+    branch: &str,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    if let Some(branches) = remote_branches(url) {
+        let branch = branches.iter().any(|b| b == branch).then_some(branch);
+        println!("Cloning {} into {}...", url, style::path(path.display()));
+        clone_repository(url, path, branch)?;
+        return Ok(true);
+    }
+
+    println!(
+        "Could not reach {}, creating an empty repository instead.",
+        url
+    );
+    std::fs::create_dir_all(path)?;
+    let repo = Repository::init(path)?;
+    repo.remote("origin", url)?;
+    ensure_branch(path, branch)?;
+    Ok(false)
+}
+
+pub fn clone_repository(
+    url: &str,
+    path: &Path,
+    branch: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    with_retries("Cloning", || {
+        let mut options = FetchOptions::new();
+        options.remote_callbacks(callbacks());
+        let mut builder = RepoBuilder::new();
+        builder.fetch_options(options);
+        if let Some(branch) = branch {
+            builder.branch(branch);
+        }
+        builder.clone(url, path)
+    })
+    .map_err(|e| remote_error(format!("Failed to clone {}: {}", url, e.message()), &e))?;
     Ok(())
 }
@@ -0,0 +1,134 @@
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use serde_json::Value;
+
+use crate::cfg::{FuxiConfig, save_config};
+use crate::cli::confirm;
+use crate::git::{has_remote_branch, origin_url};
+use crate::style;
+
+/// Extracts `owner/repo` from a GitHub remote URL.
+pub fn github_slug(url: &str) -> Option<String> {
+    let rest = [
+        "https://github.com/",
+        "ssh://git@github.com/",
+        "git@github.com:",
+    ]
+    .iter()
+    .find_map(|prefix| url.strip_prefix(prefix))?;
+    let slug = rest.trim_end_matches('/').trim_end_matches(".git");
+    (slug.split('/').count() == 2).then(|| slug.to_string())
+}
+
+/// Asks the GitHub API whether a repo is public. Private and missing repos both
+/// come back as 404 without a token, so only a definite answer is `Some`.
+fn is_public(slug: &str) -> Option<bool> {
+    let output = Command::new("curl")
+        .args([
+            "--silent",
+            "--max-time",
+            "10",
+            "-H",
+            "Accept: application/vnd.github+json",
+            &format!("https://api.github.com/repos/{}", slug),
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let body: Value = serde_json::from_slice(&output.stdout).ok()?;
+    body.get("private")?.as_bool().map(|private| !private)
+}
+
+/// Refuses to continue with a public GitHub repo unless `public_ok` is set or
+/// the user confirms. The answer is remembered in the config.
+pub fn ensure_private(
+    config: &mut FuxiConfig,
+    url: &str,
+    public_ok: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if config.public_repo_ok == Some(true) {
+        return Ok(());
+    }
+    let Some(slug) = github_slug(url) else {
+        return Ok(());
+    };
+    if is_public(&slug) != Some(true) {
+        return Ok(());
+    }
+
+    println!(
+        "{}",
+        style::warning(format!(
+            "Warning: github.com/{} is public. Dotfiles often contain tokens, hostnames and other details you may not want to publish.",
+            slug
+        ))
+    );
+    if !public_ok && !confirm("Push to this public repository anyway?")? {
+        return Err(
+            "Refusing to use a public repository. Make it private or pass --public-ok.".into(),
+        );
+    }
+    config.public_repo_ok = Some(true);
+    save_config(config)?;
+    Ok(())
+}
+
+/// Runs [`ensure_private`] against `origin` if this branch has never been pushed.
+pub fn check_before_first_push(
+    config: &mut FuxiConfig,
+    repo_path: &Path,
+    public_ok: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if has_remote_branch(repo_path, &config.git_branch) {
+        return Ok(());
+    }
+    let Some(url) = origin_url(repo_path) else {
+        return Ok(());
+    };
+    ensure_private(config, &url, public_ok)
+}
+
+/// Whether the GitHub CLI is installed.
+pub fn gh_available() -> bool {
+    Command::new("gh")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Logs in with `gh auth login` unless already logged in, and lets git use
+/// gh for HTTPS credentials. Returns whether it worked.
+pub fn gh_login() -> Result<bool, Box<dyn std::error::Error>> {
+    let logged_in = Command::new("gh")
+        .args(["auth", "status"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?
+        .success();
+    if !logged_in
+        && !Command::new("gh")
+            .args(["auth", "login"])
+            .status()?
+            .success()
+    {
+        return Ok(false);
+    }
+    Ok(Command::new("gh")
+        .args(["auth", "setup-git"])
+        .status()?
+        .success())
+}
+
+/// Creates `slug` as a private repository on GitHub with `gh`.
+pub fn gh_create_private(slug: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    Ok(Command::new("gh")
+        .args(["repo", "create", slug, "--private"])
+        .status()?
+        .success())
+}
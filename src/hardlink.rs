@@ -0,0 +1,87 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::conflict::files_differ;
+use crate::copy::collect_files;
+use crate::manifest::ManifestEntry;
+use crate::overlay::join_rel;
+
+/// The `(device, inode)` of `path`, when it has other hard links.
+#[cfg(unix)]
+fn linked_inode(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::symlink_metadata(path).ok()?;
+    (metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn linked_inode(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    matches!((linked_inode(a), linked_inode(b)), (Some(a), Some(b)) if a == b)
+}
+
+/// Groups of files below `source` that are hard links of each other, by
+/// path relative to `source`. Links to files outside it aren't counted.
+pub fn hardlink_groups(source: &Path) -> Vec<Vec<String>> {
+    if !source.is_dir() {
+        return Vec::new();
+    }
+    let Ok(files) = collect_files(source) else {
+        return Vec::new();
+    };
+    let mut inodes: BTreeMap<(u64, u64), Vec<String>> = BTreeMap::new();
+    for rel in files {
+        if let Some(inode) = linked_inode(&source.join(&rel)) {
+            inodes
+                .entry(inode)
+                .or_default()
+                .push(rel.to_string_lossy().replace('\\', "/"));
+        }
+    }
+    inodes
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect()
+}
+
+/// Turns the restored copies of each hard link group of `entry` back into
+/// links of its first file. Members that differ from it, e.g. because the
+/// conflict strategy skipped them, are left alone. Returns how many files
+/// were relinked.
+pub fn relink(entry: &ManifestEntry) -> Result<usize, Box<dyn std::error::Error>> {
+    let source = Path::new(&entry.source);
+    let mut relinked = 0;
+    for group in &entry.hardlinks {
+        let Some((first, rest)) = group.split_first() else {
+            continue;
+        };
+        let first = join_rel(source, Path::new(first));
+        if !first.is_file() {
+            continue;
+        }
+        for member in rest {
+            let member = join_rel(source, Path::new(member));
+            if !member.is_file() || same_file(&first, &member) || files_differ(&first, &member)? {
+                continue;
+            }
+            // linked beside it and renamed over it, so it's never missing
+            let mut temporary = member.clone().into_os_string();
+            temporary.push(".fuxi-link");
+            let temporary = PathBuf::from(temporary);
+            fs::hard_link(&first, &temporary)?;
+            if let Err(e) = fs::rename(&temporary, &member) {
+                let _ = fs::remove_file(&temporary);
+                return Err(e.into());
+            }
+            relinked += 1;
+        }
+    }
+    Ok(relinked)
+}
@@ -0,0 +1,173 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::object_store::hash_file;
+
+/// Files modified this recently aren't cached, a write landing in the same
+/// timestamp tick as the hashing would otherwise go unnoticed.
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct CachedHash {
+    size: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+    hash: String,
+}
+
+/// Content hashes keyed by absolute path, reused for as long as the size and
+/// mtime of the file stay the same.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct HashCache {
+    files: HashMap<String, CachedHash>,
+    #[serde(skip)]
+    changed: bool,
+}
+
+fn cache_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let data_dir = dirs::data_dir().ok_or("Could not determine data directory")?;
+    Ok(data_dir.join("fuxi").join("hash-cache.json"))
+}
+
+impl HashCache {
+    /// Starts empty when the cache is missing or unreadable.
+    pub fn load() -> Self {
+        cache_path()
+            .ok()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.changed {
+            return Ok(());
+        }
+        let path = cache_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// The hash of `path`, read from the cache unless the file changed since.
+    pub fn hash(&mut self, path: &Path) -> std::io::Result<String> {
+        let key = path.to_string_lossy().to_string();
+        let meta = fs::metadata(path)?;
+        let modified = meta.modified()?;
+        let since_epoch = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        if let Some(cached) = self.files.get(&key)
+            && cached.size == meta.len()
+            && cached.modified_secs == since_epoch.as_secs()
+            && cached.modified_nanos == since_epoch.subsec_nanos()
+        {
+            return Ok(cached.hash.clone());
+        }
+
+        let hash = hash_file(path)?;
+        let settled = SystemTime::now()
+            .duration_since(modified)
+            .is_ok_and(|age| age >= RACY_WINDOW);
+        if settled {
+            self.files.insert(
+                key,
+                CachedHash {
+                    size: meta.len(),
+                    modified_secs: since_epoch.as_secs(),
+                    modified_nanos: since_epoch.subsec_nanos(),
+                    hash: hash.clone(),
+                },
+            );
+            self.changed = true;
+        } else if self.files.remove(&key).is_some() {
+            self.changed = true;
+        }
+        Ok(hash)
+    }
+
+    /// Whether `a` and `b` hold different contents, comparing their hashes
+    /// instead of reading both files when the cache knows them.
+    pub fn differ(&mut self, a: &Path, b: &Path) -> std::io::Result<bool> {
+        if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+            return Ok(true);
+        }
+        Ok(self.hash(a)? != self.hash(b)?)
+    }
+
+    /// Drops the entries of files that no longer exist, returning how many.
+    pub fn forget_deleted(&mut self) -> usize {
+        let before = self.files.len();
+        self.files.retain(|key, _| Path::new(key).exists());
+        let forgotten = before - self.files.len();
+        self.changed |= forgotten > 0;
+        forgotten
+    }
+
+    /// Bytes the cache takes up once saved.
+    pub fn stored_size(&self) -> u64 {
+        serde_json::to_vec(self).map_or(0, |json| json.len() as u64)
+    }
+
+    /// Drops the entries under `root` that weren't hashed in the last walk,
+    /// their files are gone.
+    pub fn forget_missing(&mut self, root: &Path, seen: &HashSet<PathBuf>) {
+        let before = self.files.len();
+        self.files.retain(|key, _| {
+            let path = Path::new(key);
+            !path.starts_with(root) || seen.contains(path)
+        });
+        self.changed |= self.files.len() != before;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `content` to `path`, dated `secs_ago` seconds back so its hash
+    /// gets cached.
+    fn write_settled(path: &Path, content: &str, secs_ago: u64) {
+        fs::write(path, content).unwrap();
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(secs_ago))
+            .unwrap();
+    }
+
+    #[test]
+    fn compares_files_by_cached_hash() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (live, copy) = (tmp.path().join("live"), tmp.path().join("copy"));
+        write_settled(&live, "same", 60);
+        write_settled(&copy, "same", 60);
+        let mut cache = HashCache::default();
+
+        assert!(!cache.differ(&live, &copy).unwrap());
+        assert_eq!(cache.files.len(), 2);
+        assert!(cache.changed);
+
+        // same size, but a new mtime invalidates the cached hash
+        write_settled(&live, "diff", 30);
+        assert!(cache.differ(&live, &copy).unwrap());
+        write_settled(&live, "longer", 30);
+        assert!(cache.differ(&live, &copy).unwrap());
+    }
+
+    #[test]
+    fn leaves_recently_modified_files_uncached() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("fresh");
+        fs::write(&path, "new").unwrap();
+        let mut cache = HashCache::default();
+
+        assert_eq!(cache.hash(&path).unwrap(), hash_file(&path).unwrap());
+        assert!(cache.files.is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::style;
+
+/// How many operations the history keeps, older ones are dropped.
+const MAX_OPERATIONS: usize = 1000;
+
+/// Commands that only show things, which the history leaves out. `watch` and
+/// the daemon record each backup they make instead of themselves.
+const READ_ONLY: [&str; 11] = [
+    "log", "version", "config", "list", "search", "stats", "validate", "plugins", "tui", "watch",
+    "daemon",
+];
+
+/// One fuxi command, or one automatic backup, as the history records it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Operation {
+    pub started: DateTime<Utc>,
+    /// The command and the flags it was given, without their values.
+    pub command: String,
+    pub profile: Option<String>,
+    pub backup_id: Option<String>,
+    /// `ok`, `partial` when some files failed, or `failed`.
+    pub result: String,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// The operation running in this process, recorded once it finishes.
+static CURRENT: Mutex<Option<Operation>> = Mutex::new(None);
+
+fn history_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let data_dir = dirs::data_dir().ok_or("Could not determine data directory")?;
+    Ok(data_dir.join("fuxi").join("history.jsonl"))
+}
+
+/// Whether `command`, with its `subcommand` if it has one, changes anything
+/// worth recording.
+pub fn is_recorded(command: &str, subcommand: Option<&str>) -> bool {
+    !READ_ONLY.contains(&command) && !matches!(subcommand, Some("list" | "status"))
+}
+
+/// Starts recording `command` run on `profile`.
+pub fn start(command: String, profile: Option<String>) {
+    *CURRENT.lock().unwrap() = Some(Operation {
+        started: Utc::now(),
+        command,
+        profile,
+        backup_id: None,
+        result: "ok".to_string(),
+        error: None,
+        duration_ms: 0,
+    });
+}
+
+/// Notes the backup the running operation created or applied.
+pub fn note_backup(id: &str) {
+    if let Some(operation) = CURRENT.lock().unwrap().as_mut() {
+        operation.backup_id = Some(id.to_string());
+    }
+}
+
+/// Records the running operation, if one was started, as having taken
+/// `duration` and ended with `result`: `ok`, `partial` or `failed`.
+pub fn finish(duration: Duration, result: &str, error: Option<String>) {
+    let Some(mut operation) = CURRENT.lock().unwrap().take() else {
+        return;
+    };
+    operation.duration_ms = duration.as_millis() as u64;
+    operation.result = result.to_string();
+    operation.error = error;
+    // the history must never be why a command fails
+    if let Err(e) = append(&operation) {
+        eprintln!(
+            "{}",
+            style::warning(format!(
+                "Warning: Could not record the operation in the history: {}",
+                e
+            ))
+        );
+    }
+}
+
+fn append(operation: &Operation) -> Result<(), Box<dyn std::error::Error>> {
+    let path = history_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{}", serde_json::to_string(operation)?)?;
+    drop(file);
+
+    // trimmed in bulk, not on every append
+    let operations = load();
+    if operations.len() > MAX_OPERATIONS * 2 {
+        let mut content = String::new();
+        for operation in &operations[operations.len() - MAX_OPERATIONS..] {
+            content.push_str(&serde_json::to_string(operation)?);
+            content.push('\n');
+        }
+        fs::write(&path, content)?;
+    }
+    Ok(())
+}
+
+/// The recorded operations, oldest first. Lines that can't be read, like one
+/// cut short by a crash, are skipped.
+pub fn load() -> Vec<Operation> {
+    history_path()
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|content| {
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// A duration as `850ms`, `12.4s` or `3m 05s`.
+pub fn format_duration(ms: u64) -> String {
+    if ms < 1000 {
+        format!("{}ms", ms)
+    } else if ms < 60_000 {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    } else {
+        format!("{}m {:02}s", ms / 60_000, ms / 1000 % 60)
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    fs,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use git2::{Repository, build::CheckoutBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::apply::apply_backup;
+use crate::backup::create_backup;
+use crate::cfg::FuxiConfig;
+use crate::cli::prompt;
+use crate::conflict::ConflictStrategy;
+use crate::discard::{discard, uses_trash};
+use crate::manifest::ManifestEntry;
+use crate::overlay::ProfileLayout;
+use crate::snapshot::Snapshot;
+use crate::style;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OperationKind {
+    Backup,
+    Apply,
+}
+
+/// A backup or apply in progress, with what's needed to redo or undo it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Operation {
+    pub kind: OperationKind,
+    pub backup_id: String,
+    pub started: DateTime<Utc>,
+    /// Message the backup was created with.
+    pub message: Option<String>,
+    /// Where the applied files come from.
+    pub layout_root: Option<PathBuf>,
+    pub layout_overlay: Option<PathBuf>,
+    pub strategy: Option<ConflictStrategy>,
+    /// Where apply keeps the live files it overwrites.
+    pub snapshot: Option<PathBuf>,
+    #[serde(default)]
+    pub steps: Vec<String>,
+    /// Live files apply has written to so far.
+    #[serde(default)]
+    pub written: Vec<PathBuf>,
+    #[serde(default)]
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// Records the progress of an operation in the data directory, so a run that
+/// gets killed halfway can be finished or undone by the next one.
+pub struct Journal {
+    operation: Operation,
+}
+
+fn journal_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let data_dir = dirs::data_dir().ok_or("Could not determine data directory")?;
+    Ok(data_dir.join("fuxi").join("journal.toml"))
+}
+
+impl Journal {
+    fn new(kind: OperationKind, backup_id: &str) -> Self {
+        Self {
+            operation: Operation {
+                kind,
+                backup_id: backup_id.to_string(),
+                started: Utc::now(),
+                message: None,
+                layout_root: None,
+                layout_overlay: None,
+                strategy: None,
+                snapshot: None,
+                steps: Vec::new(),
+                written: Vec::new(),
+                entries: Vec::new(),
+            },
+        }
+    }
+
+    pub fn begin_backup(
+        backup_id: &str,
+        message: Option<String>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut journal = Self::new(OperationKind::Backup, backup_id);
+        journal.operation.message = message;
+        journal.save()?;
+        Ok(journal)
+    }
+
+    pub fn begin_apply(
+        backup_id: &str,
+        layout: &ProfileLayout,
+        entries: &[ManifestEntry],
+        strategy: ConflictStrategy,
+        snapshot: &Snapshot,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut journal = Self::new(OperationKind::Apply, backup_id);
+        journal.operation.layout_root = Some(layout.root.clone());
+        journal.operation.layout_overlay = layout.overlay.clone();
+        journal.operation.strategy = Some(strategy);
+        journal.operation.snapshot = Some(snapshot.dir.clone());
+        journal.operation.entries = entries.to_vec();
+        journal.save()?;
+        Ok(journal)
+    }
+
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let path = journal_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, toml::to_string_pretty(&self.operation)?)?;
+        Ok(())
+    }
+
+    pub fn step(&mut self, step: String) -> Result<(), Box<dyn std::error::Error>> {
+        self.operation.steps.push(step);
+        self.save()
+    }
+
+    /// Must be called right before `path` is overwritten.
+    pub fn writing(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        self.operation.written.push(path.to_path_buf());
+        self.save()
+    }
+
+    pub fn complete(self) -> Result<(), Box<dyn std::error::Error>> {
+        clear()
+    }
+}
+
+fn clear() -> Result<(), Box<dyn std::error::Error>> {
+    let path = journal_path()?;
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// The operation a previous run didn't get to finish, if any.
+pub fn interrupted() -> Option<Operation> {
+    journal_path()
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| toml::from_str(&content).ok())
+}
+
+/// Discards what an interrupted backup copied into the repository.
+fn clean_up_backup(config: &FuxiConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(repo_path) = config.backup_repo_path.as_deref() else {
+        return Ok(());
+    };
+    if config.backend.as_deref().unwrap_or("git") != "git" {
+        println!(
+            "The next backup will overwrite the partial copy in {}.",
+            repo_path
+        );
+        return Ok(());
+    }
+    let repo = Repository::open(repo_path)?;
+    repo.checkout_head(Some(CheckoutBuilder::new().force().remove_untracked(true)))?;
+    println!("Discarded the partial backup in {}.", repo_path);
+    Ok(())
+}
+
+/// Puts back the live files an interrupted apply overwrote, and removes the
+/// ones it created.
+fn clean_up_apply(
+    config: &FuxiConfig,
+    operation: &Operation,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot = operation
+        .snapshot
+        .clone()
+        .map(|dir| Snapshot { dir, saved: 0 });
+    for path in operation.written.iter().rev() {
+        let saved = snapshot.as_ref().map(|snapshot| snapshot.path_for(path));
+        match saved {
+            Some(saved) if saved.is_file() => {
+                fs::copy(&saved, path)?;
+                println!("Restored {}", style::path(path.display()));
+            }
+            _ if path.is_file() => {
+                discard(config, path)?;
+                if uses_trash(config) {
+                    println!("Moved {} to the trash", style::path(path.display()));
+                } else {
+                    println!("Removed {}", style::path(path.display()));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn resume(config: &FuxiConfig, operation: Operation) -> Result<(), Box<dyn std::error::Error>> {
+    match operation.kind {
+        OperationKind::Backup => {
+            create_backup(config, operation.message)?;
+        }
+        OperationKind::Apply => {
+            let layout = ProfileLayout {
+                root: operation
+                    .layout_root
+                    .ok_or("The journal doesn't record what was being applied.")?,
+                overlay: operation.layout_overlay,
+            };
+            apply_backup(
+                config,
+                &layout,
+                &operation.entries,
+                &operation.backup_id,
+                operation.strategy.unwrap_or_default(),
+                false,
+                false,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Offers to resume or clean up an operation a previous run left unfinished.
+/// Left alone when nobody is there to answer.
+pub fn recover(config: &FuxiConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(operation) = interrupted() else {
+        return Ok(());
+    };
+    let what = match operation.kind {
+        OperationKind::Backup => "backup",
+        OperationKind::Apply => "apply",
+    };
+    println!(
+        "The {} of '{}' started {} was interrupted after {} step(s).",
+        what,
+        operation.backup_id,
+        operation.started.format("%Y-%m-%d %H:%M:%S"),
+        operation.steps.len() + operation.written.len()
+    );
+    if !std::io::stdin().is_terminal() {
+        println!("Run fuxi interactively to resume or clean it up.");
+        return Ok(());
+    }
+
+    let cleanup = match operation.kind {
+        OperationKind::Backup => "discard uncommitted changes in the backup repository",
+        OperationKind::Apply => "put back the files it overwrote",
+    };
+    let answer = prompt(&format!(
+        "[r]esume it, [c]lean up ({}) or leave it for now? (r/c/N)",
+        cleanup
+    ))?
+    .to_lowercase();
+    match answer.as_str() {
+        "r" | "resume" => {
+            // a fresh journal is recorded by the resumed operation
+            clear()?;
+            resume(config, operation)?;
+            println!(
+                "{}",
+                style::success(format!("Interrupted {} finished.", what))
+            );
+        }
+        "c" | "clean" | "clean up" => {
+            match operation.kind {
+                OperationKind::Backup => clean_up_backup(config)?,
+                OperationKind::Apply => clean_up_apply(config, &operation)?,
+            }
+            clear()?;
+            println!(
+                "{}",
+                style::success(format!("Interrupted {} cleaned up.", what))
+            );
+        }
+        _ => println!("Leaving it, you'll be asked again next time."),
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use git2::{AttrCheckFlags, Repository, Status, StatusOptions};
+
+use crate::git::{git_binary, path_with_git};
+use crate::style;
+
+const GITATTRIBUTES: &str = ".gitattributes";
+
+/// GitHub warns about files over 50 MB and rejects files over 100 MB.
+pub const DEFAULT_LARGE_FILE_THRESHOLD_MB: u64 = 50;
+
+const MB: u64 = 1024 * 1024;
+
+fn run_git_lfs(repo_path: &Path, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new(git_binary()?)
+        .arg("lfs")
+        .args(args)
+        .current_dir(repo_path)
+        .env("PATH", path_with_git()?)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| format!("Failed to run git lfs: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git lfs {} failed (is Git LFS installed?): {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Whether the repo's `.gitattributes` routes files through Git LFS.
+pub fn uses_lfs(repo_path: &Path) -> bool {
+    fs::read_to_string(repo_path.join(GITATTRIBUTES))
+        .is_ok_and(|attributes| attributes.contains("filter=lfs"))
+}
+
+/// New or modified files bigger than `threshold_mb` that aren't stored with
+/// Git LFS, with their size in bytes.
+pub fn large_changes(
+    repo_path: &Path,
+    threshold_mb: u64,
+) -> Result<Vec<(String, u64)>, Box<dyn std::error::Error>> {
+    let repo = Repository::open(repo_path)?;
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true);
+    let mut large = Vec::new();
+    for entry in repo.statuses(Some(&mut options))?.iter() {
+        if entry
+            .status()
+            .intersects(Status::WT_DELETED | Status::INDEX_DELETED)
+        {
+            continue;
+        }
+        let Ok(path) = entry.path() else {
+            continue;
+        };
+        let Ok(meta) = fs::metadata(repo_path.join(path)) else {
+            continue;
+        };
+        let in_lfs = repo.get_attr(Path::new(path), "filter", AttrCheckFlags::FILE_THEN_INDEX)?
+            == Some("lfs");
+        if meta.len() > threshold_mb * MB && !in_lfs {
+            large.push((path.to_string(), meta.len()));
+        }
+    }
+    Ok(large)
+}
+
+/// Warns about changed files over the threshold, or with `track` hands them
+/// to Git LFS so they're committed as pointers.
+pub fn guard_large_files(
+    repo_path: &Path,
+    threshold_mb: u64,
+    track: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if threshold_mb == 0 {
+        return Ok(());
+    }
+    let large = large_changes(repo_path, threshold_mb)?;
+    if large.is_empty() {
+        return Ok(());
+    }
+
+    if !track {
+        for (path, size) in &large {
+            println!(
+                "{}",
+                style::warning(format!(
+                    "Warning: {} is {} MB, over the {} MB large file threshold.",
+                    path,
+                    size / MB,
+                    threshold_mb
+                ))
+            );
+        }
+        println!(
+            "GitHub rejects files over 100 MB. Set git_lfs = true to store large files with Git LFS, or stop tracking them."
+        );
+        return Ok(());
+    }
+
+    run_git_lfs(repo_path, &["install", "--local"])?;
+    for (path, size) in &large {
+        run_git_lfs(repo_path, &["track", "--filename", path])?;
+        println!("Storing {} ({} MB) with Git LFS.", path, size / MB);
+    }
+    Ok(())
+}
+
+/// Uploads the LFS objects `branch` refers to. libgit2 doesn't run the
+/// pre-push hook that would do it.
+pub fn push_objects(
+    repo_path: &Path,
+    remote: &str,
+    branch: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    run_git_lfs(repo_path, &["push", remote, branch])?;
+    Ok(())
+}
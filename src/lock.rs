@@ -0,0 +1,136 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::exit::{LOCKED, failure};
+
+/// How long a lock file may stay unreadable before it's considered abandoned.
+const UNREADABLE_LOCK_AGE: Duration = Duration::from_secs(10);
+
+/// Who holds the lock, written into the lock file.
+#[derive(Debug, Serialize, Deserialize)]
+struct LockInfo {
+    pid: u32,
+    command: String,
+    started: DateTime<Utc>,
+}
+
+/// Keeps other fuxi processes from changing the backup repository at the
+/// same time. Released when dropped.
+pub struct RunLock {
+    path: PathBuf,
+}
+
+fn lock_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let data_dir = dirs::data_dir().ok_or("Could not determine data directory")?;
+    Ok(data_dir.join("fuxi").join("fuxi.lock"))
+}
+
+#[cfg(unix)]
+pub fn process_alive(pid: u32) -> bool {
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(true)
+}
+
+#[cfg(windows)]
+pub fn process_alive(pid: u32) -> bool {
+    Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+        .stderr(Stdio::null())
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()))
+        .unwrap_or(true)
+}
+
+impl RunLock {
+    /// Takes the lock for `command`. If another process holds it, fails or,
+    /// with `wait`, blocks until it's released. Locks left behind by
+    /// processes that no longer exist are taken over.
+    pub fn acquire(command: &str, wait: bool) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = lock_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let info = LockInfo {
+            pid: std::process::id(),
+            command: command.to_string(),
+            started: Utc::now(),
+        };
+        let mut waiting = false;
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(toml::to_string(&info)?.as_bytes())?;
+                    return Ok(Self { path });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.into()),
+            }
+
+            let holder = fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| toml::from_str::<LockInfo>(&content).ok());
+            let Some(holder) = holder else {
+                // most likely being written right now, unless it has been empty for a while
+                let age = fs::metadata(&path)
+                    .and_then(|meta| meta.modified())
+                    .ok()
+                    .and_then(|modified| modified.elapsed().ok())
+                    .unwrap_or_default();
+                if age > UNREADABLE_LOCK_AGE {
+                    // another process may have cleaned it up first
+                    let _ = fs::remove_file(&path);
+                } else {
+                    thread::sleep(Duration::from_millis(100));
+                }
+                continue;
+            };
+            if !process_alive(holder.pid) {
+                println!(
+                    "Removing a stale lock left by fuxi {} (pid {}).",
+                    holder.command, holder.pid
+                );
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            if !wait {
+                return Err(failure(
+                    LOCKED,
+                    format!(
+                        "Another fuxi is running '{}' (pid {}, since {}). Try again once it's done, or pass --wait.",
+                        holder.command,
+                        holder.pid,
+                        holder.started.format("%H:%M:%S")
+                    ),
+                ));
+            }
+            if !waiting {
+                println!(
+                    "Waiting for fuxi {} (pid {}) to finish...",
+                    holder.command, holder.pid
+                );
+                waiting = true;
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,61 @@
+use std::{env, fs, process::Command};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MachineIdentity {
+    pub hostname: String,
+    pub os: String,
+    pub id: String,
+}
+
+impl MachineIdentity {
+    /// Matches a `--machine` filter against the hostname or a prefix of the id.
+    pub fn matches(&self, filter: &str) -> bool {
+        self.hostname.eq_ignore_ascii_case(filter) || self.id.starts_with(filter)
+    }
+}
+
+pub fn hostname() -> String {
+    if let Ok(name) = env::var("HOSTNAME").or_else(|_| env::var("COMPUTERNAME"))
+        && !name.trim().is_empty()
+    {
+        return name.trim().to_string();
+    }
+    if let Ok(name) = fs::read_to_string("/etc/hostname")
+        && !name.trim().is_empty()
+    {
+        return name.trim().to_string();
+    }
+    Command::new("hostname")
+        .output()
+        .ok()
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Returns this machine's identity, generating its UUID on first use and
+/// keeping it in the data dir so it stays stable across runs.
+pub fn machine_identity() -> Result<MachineIdentity, Box<dyn std::error::Error>> {
+    let data_dir = dirs::data_dir()
+        .ok_or("Could not determine data directory")?
+        .join("fuxi");
+    let id_path = data_dir.join("machine-id");
+
+    let id = match fs::read_to_string(&id_path) {
+        Ok(id) if !id.trim().is_empty() => id.trim().to_string(),
+        _ => {
+            let id = uuid::Uuid::new_v4().to_string();
+            fs::create_dir_all(&data_dir)?;
+            fs::write(&id_path, &id)?;
+            id
+        }
+    };
+
+    Ok(MachineIdentity {
+        hostname: hostname(),
+        os: env::consts::OS.to_string(),
+        id,
+    })
+}
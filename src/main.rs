@@ -1,19 +1,116 @@
+mod apply;
+mod archive;
+mod backup;
+mod bootstrap;
 mod cfg;
 mod cli;
+mod conflict;
 mod copy;
+mod crypt;
+mod daemon;
+mod directory;
+mod discard;
+mod exit;
+mod gc;
 mod git;
+mod github;
+mod hardlink;
+mod hash_cache;
+mod history;
+mod journal;
+mod lfs;
+mod lock;
+mod machine;
+mod manifest;
+mod metadata;
+mod migrate;
+mod moved;
+mod notification;
+mod object_store;
+mod orphans;
+mod overlay;
+mod ownership;
+mod packages;
+mod plugin;
+mod porcelain;
+mod portable;
+mod push_policy;
+mod queue;
+mod rclone;
+mod registry;
+mod repair;
+mod s3;
+mod scan;
+mod schedule;
+mod script;
+mod search;
+mod secret;
+mod sensitive;
+mod shared;
+mod snapshot;
+mod stats;
+mod storage;
+mod style;
+mod submodule;
+mod sync;
+mod template;
+mod tui;
+mod validate;
+mod verify;
+mod vss;
+mod watch;
+mod webhook;
+mod wizard;
+mod xattrs;
 
 use std::collections::HashMap;
 use std::env;
+use std::ffi::OsString;
 use std::fs;
+use std::io::{self, IsTerminal};
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Instant;
 
-use cfg::{FuxiConfig, get_config_path, load_config, save_config};
-use cli::{cli, confirm};
-use copy::copy_file_or_path;
-use git::{fetch_from_github, pull_from_github, push_to_github, run_git_command};
+use apply::{apply_backup, retarget};
+use backup::{create_backup, preview_backup};
+use cfg::{
+    FuxiConfig, PathEntry, ProfileTarget, adopt_repo_config, get_config_path, get_machine_name,
+    get_path_preset, get_profile_dir, get_profile_entries, get_selected_profile_paths,
+    is_template_path, is_xattr_path, load_config, load_repo_config, profile_names, repo_path,
+    resolve_profile, save_config, save_repo_config, update_last_backup_id,
+};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use cli::{DEFAULT_DELETE_THRESHOLD, cli, confirm, confirm_large, fuzzy_select, prompt};
+use conflict::ConflictStrategy;
+use copy::collect_files;
+use crypt::ensure_unlocked;
+use git::{clone_or_init, pending_changes, remote_url};
+use hash_cache::HashCache;
+use lock::RunLock;
+use manifest::{Manifest, load_manifest, manifest_entry, save_manifest};
+use metadata::{BackupMetadata, add_tags, load_all_metadata, metadata_by_commit, save_metadata};
+use notification::{notify_failure, notify_success};
+use overlay::ProfileLayout;
+use packages::{PACKAGES_DIR, capture_packages, install_packages};
+use push_policy::push_due;
+use secret::{encryption_backend, is_secret_path};
+use sensitive::sensitive_match;
+use snapshot::{Snapshot, prune_snapshots};
+use stats::format_size;
+use storage::{Retention, snapshot_and_push};
+use sync::{PathStatus, path_status, sync_entries};
+use template::commit_message;
+use webhook::report_push;
 
-fn add_paths(new_paths: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+fn add_paths(
+    new_paths: &[PathBuf],
+    preset: Option<&str>,
+    template: bool,
+    xattrs: bool,
+    group: Option<&str>,
+    note: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
     let mut config = load_config()?;
 
     let selected = config
@@ -23,6 +120,7 @@ fn add_paths(new_paths: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
     if selected.is_empty() {
         return Err("Please select a profile before adding paths.".into());
     }
+    ensure_profile_writable(&config, &selected)?;
 
     if config.profiles.is_none() {
         config.profiles = Some(HashMap::new());
@@ -34,16 +132,67 @@ fn add_paths(new_paths: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
     for path in new_paths {
         let path_str = path.to_string_lossy().to_string();
 
-        if !paths_vec.contains(&path_str) {
-            paths_vec.push(path_str);
-            println!("Added: {}", path.display());
-        } else {
-            println!("Path already exists: {}", path.display());
+        match paths_vec.iter_mut().find(|entry| entry.source == path_str) {
+            None => {
+                paths_vec.push(PathEntry {
+                    group: group.map(str::to_string),
+                    note: note.map(str::to_string),
+                    ..PathEntry::new(path_str)
+                });
+                println!("Added: {}", style::path(path.display()));
+            }
+            Some(entry) if group.is_some() || note.is_some() => {
+                if let Some(group) = group {
+                    entry.group = Some(group.to_string());
+                }
+                if let Some(note) = note {
+                    entry.note = Some(note.to_string());
+                }
+                println!("Updated: {}", style::path(path.display()));
+            }
+            Some(_) => println!("Path already exists: {}", style::path(path.display())),
         }
     }
 
+    if let Some(preset) = preset {
+        let members = config
+            .presets
+            .get_or_insert_with(HashMap::new)
+            .entry(preset.to_string())
+            .or_insert_with(Vec::new);
+        for path in new_paths {
+            let path_str = path.to_string_lossy().to_string();
+            if !members.contains(&path_str) {
+                members.push(path_str);
+            }
+        }
+        println!("Paths added to preset '{}'.", preset);
+    }
+
+    if template {
+        let templates = config.templates.get_or_insert_with(Vec::new);
+        for path in new_paths {
+            let path_str = path.to_string_lossy().to_string();
+            if !templates.contains(&path_str) {
+                templates.push(path_str);
+            }
+        }
+        println!("Paths marked as templates, edit them inside the backup repository.");
+    }
+
+    if xattrs {
+        let xattr_paths = config.xattr_paths.get_or_insert_with(Vec::new);
+        for path in new_paths {
+            let path_str = path.to_string_lossy().to_string();
+            if !xattr_paths.contains(&path_str) {
+                xattr_paths.push(path_str);
+            }
+        }
+        println!("Extended attributes and ACLs of the paths will be kept.");
+    }
+
     save_config(&config)?;
-    println!("Configuration updated successfully!");
+    println!("{}", style::success("Configuration updated successfully!"));
     Ok(())
 }
 
@@ -57,6 +206,7 @@ fn remove_paths(paths_to_remove: &[PathBuf]) -> Result<(), Box<dyn std::error::E
     if selected.is_empty() {
         return Err("Please select a profile before trying to remove paths.".into());
     }
+    ensure_profile_writable(&config, &selected)?;
 
     if config.profiles.is_none() {
         config.profiles = Some(HashMap::new());
@@ -67,66 +217,740 @@ fn remove_paths(paths_to_remove: &[PathBuf]) -> Result<(), Box<dyn std::error::E
 
     for path in paths_to_remove {
         let path_str = path.to_string_lossy().to_string();
-        if let Some(pos) = paths_vec.iter().position(|x| x == &path_str) {
+        if let Some(pos) = paths_vec
+            .iter()
+            .position(|entry| entry.source == path_str || entry.alias.as_ref() == Some(&path_str))
+        {
             paths_vec.remove(pos);
-            println!("Removed: {}", path.display());
+            println!("Removed: {}", style::path(path.display()));
         } else {
-            println!("Path not found: {}", path.display());
+            println!("Path not found: {}", style::path(path.display()));
         }
     }
 
     save_config(&config)?;
-    println!("Configuration updated successfully!");
+    println!("{}", style::success("Configuration updated successfully!"));
     Ok(())
 }
 
-fn list_paths() -> Result<(), Box<dyn std::error::Error>> {
+/// Tracks the path `from`, or the one aliased `from`, at `to` instead,
+/// keeping its options and the lists it's in.
+fn move_path(from: &str, to: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = load_config()?;
+    let selected = config
+        .selected_profile
+        .clone()
+        .ok_or("No profile selected")?;
+    ensure_profile_writable(&config, &selected)?;
+
+    let entries = config
+        .profiles
+        .as_mut()
+        .and_then(|profiles| profiles.get_mut(&selected))
+        .ok_or_else(|| format!("Path not found: {}", from))?;
+    if entries.iter().any(|entry| entry.source == to) {
+        return Err(format!("{} is already tracked.", to).into());
+    }
+    let entry = entries
+        .iter_mut()
+        .find(|entry| entry.source == from || entry.alias.as_deref() == Some(from))
+        .ok_or_else(|| format!("Path not found: {}", from))?;
+    let old = std::mem::replace(&mut entry.source, to.to_string());
+
+    let lists = [
+        config.templates.as_mut(),
+        config.secrets.as_mut(),
+        config.xattr_paths.as_mut(),
+    ];
+    let presets = config
+        .presets
+        .iter_mut()
+        .flat_map(|presets| presets.values_mut());
+    for list in lists.into_iter().flatten().chain(presets) {
+        for path in list.iter_mut().filter(|path| **path == old) {
+            *path = to.to_string();
+        }
+    }
+
+    save_config(&config)?;
+    println!("Moved: {} -> {}", old, to);
+    println!("{}", style::success("Configuration updated successfully!"));
+    Ok(())
+}
+
+/// The flags of a path for `path list`: template, secret, xattrs, sudo,
+/// disabled and inactive, when it's meant for other platforms.
+fn path_flags(config: &FuxiConfig, entry: &PathEntry) -> Vec<&'static str> {
+    let path = entry.source.as_str();
+    [
+        ("template", is_template_path(config, path)),
+        (
+            "secret",
+            is_secret_path(config, path) || entry.encrypt.unwrap_or(false),
+        ),
+        ("xattrs", is_xattr_path(config, path)),
+        ("sudo", entry.sudo.unwrap_or(false)),
+        ("disabled", entry.disabled.unwrap_or(false)),
+        (
+            "inactive",
+            !entry.disabled.unwrap_or(false) && !entry.is_active(),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(flag, set)| set.then_some(flag))
+    .collect()
+}
+
+/// What `fuxi path list --check` found out about a tracked path.
+struct PathCheck {
+    size: u64,
+    files: usize,
+    modified: Option<DateTime<Utc>>,
+    status: PathStatus,
+}
+
+/// Measures the tracked path `source` and compares it with its copy in the
+/// last backup, when there is a backup repository.
+fn check_path(
+    config: &FuxiConfig,
+    layout: Option<&ProfileLayout>,
+    manifest: Option<&Manifest>,
+    source: &str,
+    cache: &mut HashCache,
+) -> Result<PathCheck, Box<dyn std::error::Error>> {
+    let path = Path::new(source);
+    let files: Vec<PathBuf> = if path.is_dir() {
+        collect_files(path)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|rel| path.join(rel))
+            .collect()
+    } else if path.exists() {
+        vec![path.to_path_buf()]
+    } else {
+        Vec::new()
+    };
+    let metadata: Vec<fs::Metadata> = files.iter().filter_map(|f| fs::metadata(f).ok()).collect();
+
+    // the manifest knows the names the backup had to encode
+    let entry = manifest
+        .and_then(|manifest| manifest.entries.iter().find(|e| e.source == source))
+        .cloned()
+        .unwrap_or_else(|| manifest_entry(config, source));
+    let status = match layout {
+        Some(layout) => path_status(layout, &entry, cache)?,
+        None if path.exists() => PathStatus::NotBackedUp,
+        None => PathStatus::Missing,
+    };
+    Ok(PathCheck {
+        size: metadata.iter().map(|m| m.len()).sum(),
+        files: metadata.len(),
+        modified: metadata
+            .iter()
+            .filter_map(|m| m.modified().ok())
+            .max()
+            .map(DateTime::<Utc>::from),
+        status,
+    })
+}
+
+/// Prints a `path` record: the profile, the path, its preset, its flags, its
+/// restore target, its alias, what `--check` found: its size, number of
+/// files, last change and status, then its group and note.
+fn path_record(config: &FuxiConfig, profile: &str, entry: &PathEntry, check: Option<&PathCheck>) {
+    porcelain::record(
+        "path",
+        &[
+            profile,
+            &entry.source,
+            &get_path_preset(config, &entry.source).unwrap_or_default(),
+            &path_flags(config, entry).join(","),
+            entry.target.as_deref().unwrap_or_default(),
+            entry.alias.as_deref().unwrap_or_default(),
+            &check.map(|c| c.size.to_string()).unwrap_or_default(),
+            &check.map(|c| c.files.to_string()).unwrap_or_default(),
+            &check
+                .and_then(|c| c.modified)
+                .map(|m| m.to_rfc3339_opts(SecondsFormat::Secs, true))
+                .unwrap_or_default(),
+            check.map(|c| c.status.name()).unwrap_or_default(),
+            entry.group.as_deref().unwrap_or_default(),
+            entry.note.as_deref().unwrap_or_default(),
+        ],
+    );
+}
+
+fn list_paths(check: bool, group: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
     let config = load_config()?;
-    let paths = get_selected_profile_paths(&config);
+    let profile = config.selected_profile.clone().unwrap_or_default();
+    let entries: Vec<&PathEntry> = get_profile_entries(&config, &profile)
+        .iter()
+        .filter(|entry| group.is_none_or(|group| entry.group.as_deref() == Some(group)))
+        .collect();
+
+    let profile_dir = config
+        .backup_repo_path
+        .as_ref()
+        .map(|repo| get_profile_dir(&config, Path::new(repo), &profile, None));
+    let layout = profile_dir
+        .as_ref()
+        .map(|dir| ProfileLayout::new(dir, &get_machine_name(&config)));
+    let manifest = match &profile_dir {
+        Some(dir) if check => load_manifest(dir)?,
+        _ => None,
+    };
+    let mut cache = HashCache::load();
+    let mut check_entry =
+        |entry: &PathEntry| -> Result<Option<PathCheck>, Box<dyn std::error::Error>> {
+            if !check {
+                return Ok(None);
+            }
+            check_path(
+                &config,
+                layout.as_ref(),
+                manifest.as_ref(),
+                &entry.source,
+                &mut cache,
+            )
+            .map(Some)
+        };
 
-    if paths.is_empty() {
-        println!("No paths configured.");
+    if porcelain::enabled() {
+        for entry in entries {
+            path_record(&config, &profile, entry, check_entry(entry)?.as_ref());
+        }
+    } else if entries.is_empty() {
+        match group {
+            Some(group) => println!("No paths in group '{}'.", group),
+            None => println!("No paths configured."),
+        }
     } else {
         println!("Configured paths:");
-        for (i, path) in paths.iter().enumerate() {
-            println!("  {}: {}", i + 1, path);
+        for (i, entry) in entries.iter().enumerate() {
+            let mut labels: Vec<String> = entry
+                .group
+                .as_ref()
+                .map(|group| format!("group {}", group))
+                .into_iter()
+                .chain(get_path_preset(&config, &entry.source))
+                .chain(entry.alias.as_ref().map(|alias| format!("alias {}", alias)))
+                .chain(path_flags(&config, entry).into_iter().map(str::to_string))
+                .collect();
+            if let Some(target) = &entry.target {
+                labels.push(format!("restored to {}", target));
+            }
+            if labels.is_empty() {
+                println!("  {}: {}", i + 1, entry.source);
+            } else {
+                println!("  {}: {} [{}]", i + 1, entry.source, labels.join(", "));
+            }
+            if let Some(note) = &entry.note {
+                println!("     Note: {}", note);
+            }
+
+            let Some(check) = check_entry(entry)? else {
+                continue;
+            };
+            if matches!(check.status, PathStatus::Missing) {
+                println!("     missing");
+                continue;
+            }
+            let size = if Path::new(&entry.source).is_dir() {
+                format!("{} in {} file(s)", format_size(check.size), check.files)
+            } else {
+                format_size(check.size)
+            };
+            let modified = check
+                .modified
+                .map(|m| {
+                    DateTime::<chrono::Local>::from(m)
+                        .format(", last changed %Y-%m-%d %H:%M")
+                        .to_string()
+                })
+                .unwrap_or_default();
+            println!("     {}{}, {}", size, modified, check.status.describe());
         }
     }
+    cache.save()?;
     Ok(())
 }
 
-fn update_last_backup_id(backup_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let mut config = load_config()?;
-    config.last_backup_id = Some(backup_id.to_string());
-    save_config(&config)?;
+fn is_shared_profile(config: &FuxiConfig, name: &str) -> bool {
+    let is_user_profile = config
+        .profiles
+        .as_ref()
+        .is_some_and(|profiles| profiles.contains_key(name));
+    !is_user_profile && config.shared_profiles.contains_key(name)
+}
+
+fn ensure_profile_writable(
+    config: &FuxiConfig,
+    name: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if is_shared_profile(config, name) {
+        return Err(format!(
+            "Profile '{}' is a shared system profile and is read-only.",
+            name
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Lists what `fuxi save` is about to commit, like `git status --short`.
+fn print_pending_changes(repo_path: &Path, diff: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let changes = pending_changes(repo_path)?;
+    if changes.is_empty() {
+        println!("No changes to commit, only unpushed commits will be pushed.");
+        return Ok(());
+    }
+    println!("Changes to be saved:");
+    let (mut added, mut removed) = (0, 0);
+    for change in &changes {
+        match (diff, change.lines) {
+            (true, Some((plus, minus))) => {
+                added += plus;
+                removed += minus;
+                println!("  {} {} (+{} -{})", change.status, change.path, plus, minus);
+            }
+            (true, None) => println!("  {} {} (binary)", change.status, change.path),
+            (false, _) => println!("  {} {}", change.status, change.path),
+        }
+    }
+    if diff {
+        println!(
+            "{} file(s) changed, {} insertion(s), {} deletion(s)",
+            changes.len(),
+            added,
+            removed
+        );
+    }
     Ok(())
 }
 
-fn get_selected_profile_paths(config: &FuxiConfig) -> Vec<String> {
-    if let Some(selected) = &config.selected_profile {
-        if let Some(profiles) = &config.profiles {
-            if let Some(paths) = profiles.get(selected) {
-                return paths.clone();
+/// Prints `rows` in columns under `headers`, leaving the last column unpadded.
+fn print_table<const N: usize>(headers: [&str; N], rows: &[[String; N]]) {
+    let mut widths = headers.map(str::len);
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let line = |cells: Vec<&str>| {
+        let mut line = String::new();
+        for (i, cell) in cells.iter().enumerate() {
+            if i + 1 == N {
+                line.push_str(cell);
+            } else {
+                line.push_str(&format!("{:<width$}  ", cell, width = widths[i]));
             }
         }
+        line.trim_end().to_string()
+    };
+    println!("{}", style::heading(line(headers.to_vec())));
+    for row in rows {
+        println!("{}", line(row.iter().map(String::as_str).collect()));
+    }
+}
+
+/// Lists the backups with their date, message and machine and asks which
+/// one to apply. Returns `None` when the user picks none.
+fn pick_backup(config: &FuxiConfig) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    if !io::stdin().is_terminal() {
+        return Err(
+            "Please provide a backup ID, or run 'fuxi apply' in a terminal to pick one.".into(),
+        );
+    }
+    let repo_path = repo_path(config)?;
+    let backups = storage::backend(config)?.list()?;
+    if backups.is_empty() {
+        return Err("No backups found in the repository.".into());
+    }
+    let metadata = metadata_by_commit(Path::new(repo_path)).unwrap_or_default();
+
+    println!("Backups:");
+    for (i, backup) in backups.iter().enumerate() {
+        let metadata = metadata.get(&backup.id);
+        let mut line = format!("  {}: {}", i + 1, backup.name());
+        if let Some(metadata) = metadata {
+            line.push_str(&format!(
+                "  {}",
+                metadata.timestamp.format("%Y-%m-%d %H:%M")
+            ));
+        }
+        line.push_str(&format!("  {}", backup.summary));
+        if let Some(machine) = metadata.and_then(|m| m.machine.as_ref()) {
+            line.push_str(&format!("  [{}]", machine.hostname));
+        }
+        println!("{}", line);
+    }
+    loop {
+        let answer = prompt("Backup to apply (number, empty to cancel)")?;
+        if answer.is_empty() {
+            return Ok(None);
+        }
+        match answer
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| backups.get(n.wrapping_sub(1)))
+        {
+            Some(backup) => return Ok(Some(backup.name().to_string())),
+            None => println!("'{}' is not one of the numbers listed.", answer),
+        }
+    }
+}
+
+/// Adds `tags` to the backup `id`, given by its backup ID or, for git
+/// backups, anything `fuxi apply` accepts.
+fn tag_backup(
+    config: &FuxiConfig,
+    id: &str,
+    tags: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo_path = Path::new(repo_path(config)?);
+    let backup_id = if load_all_metadata(repo_path)?.iter().any(|m| m.id == id) {
+        id.to_string()
+    } else {
+        let metadata = metadata_by_commit(repo_path).unwrap_or_default();
+        storage::backend(config)?
+            .list()?
+            .iter()
+            .filter(|backup| backup.matches(id))
+            .find_map(|backup| metadata.get(&backup.id))
+            .map(|metadata| metadata.id.clone())
+            .ok_or_else(|| format!("Backup ID or commit hash '{}' not found.", id))?
+    };
+
+    let added = add_tags(repo_path, &backup_id, tags)?;
+    if added.is_empty() {
+        println!("{} already has these tags.", backup_id);
+    } else {
+        println!("Tagged {}: {}", backup_id, added.join(", "));
+        println!("Save the tags using the 'fuxi save' command.");
+    }
+    Ok(())
+}
+
+/// Prints the recorded operations, newest first, at most `limit` of them.
+/// `command` only shows the operations of a command, and `failed` only
+/// those that failed.
+fn show_log(command: Option<&str>, failed: bool, limit: usize) {
+    let operations: Vec<_> = history::load()
+        .into_iter()
+        .rev()
+        .filter(|operation| {
+            // `backup` also finds the backups `watch` and the daemon made
+            command.is_none_or(|command| {
+                operation.command.starts_with(&format!("{} ", command))
+                    || operation.command.split(' ').any(|word| word == command)
+            })
+        })
+        .filter(|operation| !failed || operation.result == "failed")
+        .take(limit)
+        .collect();
+
+    if porcelain::enabled() {
+        for operation in &operations {
+            porcelain::record(
+                "operation",
+                &[
+                    &operation.started.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    &operation.command,
+                    operation.profile.as_deref().unwrap_or_default(),
+                    operation.backup_id.as_deref().unwrap_or_default(),
+                    &operation.result,
+                    &operation.duration_ms.to_string(),
+                    operation.error.as_deref().unwrap_or_default(),
+                ],
+            );
+        }
+        return;
     }
-    Vec::new()
+    if operations.is_empty() {
+        println!("No operations recorded yet.");
+        return;
+    }
+    let rows: Vec<[String; 6]> = operations
+        .iter()
+        .map(|operation| {
+            let result = match &operation.error {
+                Some(error) => format!(
+                    "{}: {}",
+                    operation.result,
+                    error.lines().next().unwrap_or_default()
+                ),
+                None => operation.result.clone(),
+            };
+            [
+                operation
+                    .started
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string(),
+                operation.command.clone(),
+                operation.profile.clone().unwrap_or_else(|| "-".to_string()),
+                operation
+                    .backup_id
+                    .clone()
+                    .unwrap_or_else(|| "-".to_string()),
+                history::format_duration(operation.duration_ms),
+                result,
+            ]
+        })
+        .collect();
+    print_table(
+        ["DATE", "COMMAND", "PROFILE", "BACKUP", "DURATION", "RESULT"],
+        &rows,
+    );
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// Who agreed to the backups a prune drops.
+#[derive(Clone, Copy, PartialEq)]
+enum PruneConsent {
+    /// Ask before rewriting the history of a git repository, and before
+    /// dropping more backups than `confirm_delete_threshold`.
+    Ask,
+    /// `fuxi prune --yes`.
+    Given,
+    /// Nobody is there to ask, like after a scheduled backup: more backups
+    /// than `confirm_delete_threshold` are refused, the retention policy or
+    /// the clock may be wrong.
+    Unattended,
+}
+
+/// Deletes the backups `retention` doesn't keep, once `consent` allows it.
+fn prune_backups(
+    config: &FuxiConfig,
+    retention: &Retention,
+    dry_run: bool,
+    consent: PruneConsent,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let backend = storage::backend(config)?;
+    if !dry_run && consent != PruneConsent::Given {
+        let dropped = backend.prune(retention, true)?;
+        if dropped.is_empty() {
+            println!("Nothing to prune.");
+            return Ok(());
+        }
+        let threshold = config
+            .confirm_delete_threshold
+            .unwrap_or(DEFAULT_DELETE_THRESHOLD);
+        if consent == PruneConsent::Unattended {
+            if dropped.len() > threshold {
+                return Err(format!(
+                    "The retention policy would delete {} backup(s), more than confirm_delete_threshold ({}). Nothing was pruned, run 'fuxi prune' to check and confirm it.",
+                    dropped.len(),
+                    threshold
+                )
+                .into());
+            }
+        } else {
+            let git = backend.name() == "git";
+            if git {
+                println!(
+                    "{}",
+                    style::warning(format!(
+                        "WARNING: Pruning drops {} backup(s) by rewriting the history of the backup branch and force pushing it.",
+                        dropped.len()
+                    ))
+                );
+                println!(
+                    "The dropped backups are gone for good, from the remote too. Every other machine has to clone the repository again with 'fuxi bootstrap', or its next push brings the old history back."
+                );
+            }
+            let summary = format!("Pruning will delete {} backup(s).", dropped.len());
+            let profile = config.selected_profile.as_deref().unwrap_or("yes");
+            let confirmed = if dropped.len() > threshold {
+                confirm_large(&summary, dropped.len(), threshold, profile)?
+            } else {
+                !git || confirm("Rewrite the history?")?
+            };
+            if !confirmed {
+                println!("Nothing was pruned.");
+                return Ok(());
+            }
+        }
+    }
+    let evicted = backend.prune(retention, dry_run)?;
+    if evicted.is_empty() {
+        println!("Nothing to prune.");
+    }
+    for id in &evicted {
+        if dry_run {
+            println!("[Dry Run] Would delete backup {}", id);
+        } else {
+            println!("Deleted backup {}", id);
+        }
+    }
+    Ok(())
+}
+
+/// The command and subcommand `matches` ran, followed by the flags given,
+/// without their values, which may be paths or URLs with credentials.
+fn command_line(matches: &clap::ArgMatches) -> String {
+    let mut words = Vec::new();
+    if let Some((name, sub_matches)) = matches.subcommand() {
+        words.push(name.to_string());
+        words.extend(sub_matches.subcommand_name().map(str::to_string));
+    }
+    words.extend(
+        env::args()
+            .skip(1)
+            .filter(|arg| arg.starts_with('-'))
+            .map(|arg| arg.split('=').next().unwrap_or_default().to_string()),
+    );
+    words.join(" ")
+}
+
+fn main() -> ExitCode {
+    let started = Instant::now();
+    let result = run();
+    match &result {
+        Ok(()) if exit::is_partial() => history::finish(started.elapsed(), "partial", None),
+        Ok(()) => history::finish(started.elapsed(), "ok", None),
+        Err(e) => history::finish(started.elapsed(), "failed", Some(e.to_string())),
+    }
+    match result {
+        Ok(()) if exit::is_partial() => ExitCode::from(exit::PARTIAL_FAILURE),
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            let code = exit::exit_code(e.as_ref());
+            if porcelain::enabled() {
+                eprintln!("error\t{}\t{}", code, porcelain::escape(&e.to_string()));
+            } else {
+                eprintln!("{}", style::error(format!("Error: {:?}", e)));
+            }
+            ExitCode::from(code)
+        }
+    }
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
     let config_path = get_config_path()?;
     // let _data_dir = dirs::data_dir().unwrap().join("fuxi");
     // let _cache_dir = dirs::cache_dir().unwrap().join("fuxi");
 
-    let mut config = load_config()?;
-
-    let matches = cli().get_matches();
+    // a bare `fuxi` before anything is configured starts the setup wizard
+    let first_run = !config_path.exists()
+        && env::args_os().len() == 1
+        && io::stdin().is_terminal()
+        && confirm("fuxi isn't set up yet. Run the setup wizard?")?;
+    let matches = if first_run {
+        cli().get_matches_from(["fuxi", "setup"])
+    } else {
+        cli().get_matches()
+    };
+    let mut config = match load_config() {
+        // `fuxi validate` is how to find out what's wrong with the file
+        Err(_) if matches.subcommand_name() == Some("validate") => {
+            let problems = validate::config_problems(&config_path);
+            for problem in &problems {
+                println!("Config: {}", problem);
+            }
+            return Err(exit::config_error(format!(
+                "Found {} problem(s) in the config file.",
+                problems.len()
+            )));
+        }
+        config => config?,
+    };
+    porcelain::set_enabled(matches.get_flag("porcelain"));
+    if matches.get_flag("no-color") || matches.get_flag("porcelain") {
+        style::disable();
+    }
+    if porcelain::enabled() {
+        let command = match matches.subcommand() {
+            Some((name, sub_matches)) => match sub_matches.subcommand_name() {
+                Some(subcommand) => format!("{} {}", name, subcommand),
+                None => name.to_string(),
+            },
+            None => String::new(),
+        };
+        if !porcelain::COMMANDS.contains(&command.as_str()) {
+            return Err(exit::config_error(format!(
+                "'fuxi {}' has no porcelain output.",
+                command
+            )));
+        }
+    }
+    // bootstrap and `apply --from` look `--profile` up in the repository they
+    // clone instead
+    let applies_from = matches
+        .subcommand_matches("apply")
+        .is_some_and(|apply| apply.contains_id("from"));
+    if let Some(profile) = matches.get_one::<String>("profile")
+        && matches.subcommand_name() != Some("bootstrap")
+        && !applies_from
+    {
+        cfg::set_profile_override(resolve_profile(&profile_names(&config), profile)?);
+        config = load_config()?;
+    }
+    if let Some((name, sub_matches)) = matches.subcommand()
+        && history::is_recorded(name, sub_matches.subcommand_name())
+    {
+        history::start(command_line(&matches), config.selected_profile.clone());
+    }
+    copy::set_include_vcs_dirs(
+        config.include_vcs_dirs.unwrap_or(false)
+            || matches
+                .subcommand_matches("backup")
+                .is_some_and(|backup| backup.get_flag("include-vcs-dirs")),
+    );
+    copy::set_fail_fast(
+        matches
+            .subcommand_matches("backup")
+            .is_some_and(|backup| backup.get_flag("fail-fast")),
+    );
+    apply::set_assume_yes(
+        matches
+            .subcommand_matches("apply")
+            .is_some_and(|apply| apply.get_flag("yes")),
+    );
+    copy::set_respect_gitignore(
+        config.respect_gitignore.unwrap_or(false)
+            || matches
+                .subcommand_matches("backup")
+                .is_some_and(|backup| backup.get_flag("respect-gitignore")),
+    );
+    submodule::set_reference_nested_repos(
+        config.nested_repo_submodules.unwrap_or(false)
+            || matches
+                .subcommand_matches("backup")
+                .is_some_and(|backup| backup.get_flag("submodules")),
+    );
+    // commands that change the backup repository must not run concurrently
+    let _lock = match matches.subcommand_name() {
+        Some(
+            command @ ("init" | "setup" | "bootstrap" | "backup" | "save" | "apply" | "sync"
+            | "crypt" | "import" | "prune" | "flush" | "repair" | "gc" | "clean"),
+        ) => Some(RunLock::acquire(command, matches.get_flag("wait"))?),
+        _ => None,
+    };
+    if _lock.is_some() {
+        journal::recover(&config)?;
+    }
+    // pushes queued while offline go out with the next command that pushes anyway
+    if matches!(matches.subcommand_name(), Some("backup" | "save" | "sync"))
+        && !matches
+            .subcommand_matches("backup")
+            .is_some_and(|backup| backup.get_flag("dry-run"))
+        && queue::queued_push(&config).is_some()
+        && let Err(e) = queue::flush(&config, config.divergence_strategy.unwrap_or_default())
+    {
+        println!("Queued backups are still waiting to be pushed: {}", e);
+    }
     match matches.subcommand() {
         Some(("version", _)) => {
-            println!("fuxi version {}", env!("CARGO_PKG_VERSION"));
+            if porcelain::enabled() {
+                porcelain::record("version", &[env!("CARGO_PKG_VERSION")]);
+            } else {
+                println!("fuxi version {}", env!("CARGO_PKG_VERSION"));
+            }
         }
         Some(("config", sub_matches)) => {
-            if sub_matches.get_flag("raw") {
+            if porcelain::enabled() {
+                porcelain::record("config", &[&config_path.to_string_lossy()]);
+            } else if sub_matches.get_flag("raw") {
                 println!("{}", config_path.display());
             } else {
                 println!("Configuration file: {:?}", config_path);
@@ -145,8 +969,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 return Err("Please provide a valid path for the backup repository.".into());
             } else if repo.is_empty() {
                 return Err(
-                    "Please provide a valid GitHub repository in the format username/repo-name."
-                        .into(),
+                    "Please provide a remote repository URL or a GitHub username/repo-name.".into(),
                 );
             }
 
@@ -157,30 +980,102 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 return Ok(());
             }
 
+            let url = remote_url(repo);
+            config.public_repo_ok = None;
+            github::ensure_private(&mut config, &url, sub_matches.get_flag("public-ok"))?;
+
             config.backup_repo_path = Some(path.to_string_lossy().to_string());
-            config.github_repo = Some(repo.to_string());
+            config.remote_url = Some(url.clone());
             save_config(&config)?;
             println!(
-                "Backups will use the {} repository at {}",
-                repo,
-                path.display()
+                "Backups will use {} with the local repository at {}",
+                url,
+                style::path(path.display())
             );
-            if !path.exists() {
-                fs::create_dir_all(path)?;
-                run_git_command(path, &["init"])?;
+
+            let is_empty = fs::read_dir(path).map_or(true, |mut dir| dir.next().is_none());
+            if !is_empty {
+                return Ok(());
             }
+
+            if clone_or_init(&url, path, &config.git_branch)? {
+                if let Some(repo_config) = load_repo_config(path)? {
+                    adopt_repo_config(&mut config, repo_config);
+                    save_config(&config)?;
+                    println!("Imported the fuxi configuration stored in the repository.");
+                }
+                bootstrap::restore_from_clone(&mut config, path)?;
+            }
+        }
+        Some(("tui", _)) => tui::run()?,
+        Some(("setup", _)) => {
+            wizard::run(&mut config)?;
+        }
+        Some(("bootstrap", sub_matches)) => {
+            let repo = sub_matches
+                .get_one::<String>("REPO")
+                .expect("REPO is required");
+            let path = match sub_matches.get_one::<PathBuf>("path") {
+                Some(path) => path.clone(),
+                None => bootstrap::default_bootstrap_path()
+                    .ok_or("Could not determine data directory, pass --path instead.")?,
+            };
+            let profile = sub_matches.get_one::<String>("profile").map(|s| s.as_str());
+            bootstrap::bootstrap(repo, &path, profile)?;
         }
         Some(("profile", sub_matches)) => match sub_matches.subcommand() {
+            Some(("list", _)) if porcelain::enabled() => {
+                let mut profiles: Vec<(&String, &Vec<PathEntry>, &str)> = config
+                    .profiles
+                    .iter()
+                    .flatten()
+                    .map(|(name, paths)| (name, paths, "local"))
+                    .chain(
+                        config
+                            .shared_profiles
+                            .iter()
+                            .filter(|(name, _)| is_shared_profile(&config, name))
+                            .map(|(name, paths)| (name, paths, "shared")),
+                    )
+                    .collect();
+                profiles.sort_by_key(|(name, _, _)| *name);
+                for (name, paths, kind) in profiles {
+                    let selected = config.selected_profile.as_ref() == Some(name);
+                    porcelain::record("profile", &[name, if selected { "1" } else { "0" }, kind]);
+                    for entry in paths {
+                        path_record(&config, name, entry, None);
+                    }
+                }
+            }
             Some(("list", _)) => {
+                if config.profiles.is_none() && config.shared_profiles.is_empty() {
+                    println!("No profiles found.");
+                }
                 if let Some(profiles) = &config.profiles {
                     for (name, paths) in profiles {
-                        println!("Profile: {}", name);
-                        for path in paths {
-                            println!("  - {}", path);
+                        match config.profile_targets.as_ref().and_then(|t| t.get(name)) {
+                            Some(target) => println!(
+                                "Profile: {} (branch {}, repository {}, remote {})",
+                                name,
+                                target.branch.as_deref().unwrap_or("default"),
+                                target.repo_path.as_deref().unwrap_or("default"),
+                                target.remote_url.as_deref().unwrap_or("default")
+                            ),
+                            None => println!("Profile: {}", name),
+                        }
+                        for entry in paths {
+                            println!("  - {}", entry.source);
                         }
                     }
-                } else {
-                    println!("No profiles found.");
+                }
+                for (name, paths) in &config.shared_profiles {
+                    if !is_shared_profile(&config, name) {
+                        continue;
+                    }
+                    println!("Profile: {} (shared, read-only)", name);
+                    for entry in paths {
+                        println!("  - {}", entry.source);
+                    }
                 }
             }
             Some(("create", profile_matches)) => {
@@ -188,18 +1083,42 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .get_one::<String>("NAME")
                     .map(|s| s.as_str())
                     .unwrap_or("");
-                if config.profiles.is_none() {
-                    config.profiles = Some(HashMap::new());
+                let target = ProfileTarget {
+                    branch: profile_matches.get_one::<String>("branch").cloned(),
+                    repo_path: profile_matches
+                        .get_one::<PathBuf>("path")
+                        .map(|p| p.to_string_lossy().to_string()),
+                    remote_url: profile_matches
+                        .get_one::<String>("remote")
+                        .map(|r| remote_url(r)),
+                };
+                if target.remote_url.is_some() && target.repo_path.is_none() {
+                    return Err("A profile with its own --remote also needs its own --path.".into());
                 }
 
-                if let Some(profiles) = &mut config.profiles {
-                    if profiles.contains_key(name) {
-                        println!("Profile '{}' already exists.", name);
-                    } else {
-                        profiles.insert(name.to_string(), Vec::new());
-                        save_config(&config)?;
-                        println!("Profile '{}' created.", name);
+                let profiles = config.profiles.get_or_insert_with(HashMap::new);
+                if profiles.contains_key(name) {
+                    println!("Profile '{}' already exists.", name);
+                } else {
+                    profiles.insert(name.to_string(), Vec::new());
+                    if target != ProfileTarget::default() {
+                        if let Some(path) = &target.repo_path {
+                            let path = Path::new(path);
+                            let is_empty =
+                                fs::read_dir(path).map_or(true, |mut dir| dir.next().is_none());
+                            let url = target.remote_url.as_ref().or(config.remote_url.as_ref());
+                            if let (true, Some(url)) = (is_empty, url) {
+                                let branch = target.branch.as_ref().unwrap_or(&config.git_branch);
+                                clone_or_init(url, path, branch)?;
+                            }
+                        }
+                        config
+                            .profile_targets
+                            .get_or_insert_with(HashMap::new)
+                            .insert(name.to_string(), target);
                     }
+                    save_config(&config)?;
+                    println!("Profile '{}' created.", name);
                 }
 
                 if config.profiles.as_ref().unwrap().len() == 1 {
@@ -208,18 +1127,36 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     println!("Profile '{}' is now the selected profile.", name);
                 }
             }
-            Some(("select", profile_matches)) => {
-                let name = profile_matches
-                    .get_one::<String>("NAME")
-                    .map(|s| s.as_str())
-                    .unwrap_or("");
-
-                if config.profiles.is_none() {
+            Some(("switch", profile_matches)) => {
+                let names = profile_names(&config);
+                if names.is_empty() {
                     println!("No profiles available. Please create a profile first.");
                     return Ok(());
                 }
+                let name = match profile_matches.get_one::<String>("NAME") {
+                    Some(name) => resolve_profile(&names, name)?,
+                    None if io::stdin().is_terminal() => {
+                        let current = config
+                            .selected_profile
+                            .as_ref()
+                            .and_then(|selected| names.iter().position(|n| n == selected));
+                        match fuzzy_select("Profile to switch to", &names, current)? {
+                            Some(i) => names[i].clone(),
+                            None => {
+                                println!("Switch cancelled.");
+                                return Ok(());
+                            }
+                        }
+                    }
+                    None => return Err("Please provide the name of the profile.".into()),
+                };
+                let name = name.as_str();
 
-                if let Some(profiles) = &config.profiles {
+                if is_shared_profile(&config, name) {
+                    config.selected_profile = Some(name.to_string());
+                    save_config(&config)?;
+                    println!("Switched to shared profile '{}' (read-only).", name);
+                } else if let Some(profiles) = &config.profiles {
                     if profiles.contains_key(name) {
                         config.selected_profile = Some(name.to_string());
 
@@ -231,10 +1168,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
             Some(("delete", profile_matches)) => {
-                let name = profile_matches
-                    .get_one::<String>("NAME")
-                    .map(|s| s.as_str())
-                    .unwrap_or("");
+                let name = resolve_profile(
+                    &profile_names(&config),
+                    profile_matches
+                        .get_one::<String>("NAME")
+                        .expect("NAME is required"),
+                )?;
+                let name = name.as_str();
+
+                ensure_profile_writable(&config, name)?;
 
                 if config.profiles.is_none() {
                     println!("Profile '{}' does not exist.", name);
@@ -243,6 +1185,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                 if let Some(profiles) = &mut config.profiles {
                     if profiles.remove(name).is_some() {
+                        if let Some(targets) = &mut config.profile_targets {
+                            targets.remove(name);
+                        }
                         if config.selected_profile.as_deref() == Some(name) {
                             config.selected_profile = None;
                             config.profiles.as_mut().unwrap().remove(name);
@@ -257,8 +1202,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             _ => unreachable!(),
         },
         Some(("path", sub_matches)) => match sub_matches.subcommand() {
-            Some(("list", _)) => {
-                list_paths()?;
+            Some(("list", sub_matches)) => {
+                list_paths(
+                    sub_matches.get_flag("check"),
+                    sub_matches.get_one::<String>("group").map(|s| s.as_str()),
+                )?;
             }
             Some(("add", sub_matches)) => {
                 let paths: Vec<PathBuf> = sub_matches
@@ -273,7 +1221,36 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     return Ok(());
                 }
 
-                add_paths(&paths)?;
+                let sensitive: Vec<(&PathBuf, &str)> = paths
+                    .iter()
+                    .filter(|p| !is_secret_path(&config, &p.to_string_lossy()))
+                    .filter_map(|p| sensitive_match(p).map(|pattern| (p, pattern)))
+                    .collect();
+                if !sensitive.is_empty() {
+                    for (path, pattern) in &sensitive {
+                        println!(
+                            "{}",
+                            style::warning(format!(
+                                "Warning: {} looks sensitive (matches ~/{}).",
+                                path.display(),
+                                pattern
+                            ))
+                        );
+                    }
+                    if !sub_matches.get_flag("force") {
+                        return Err("Refusing to track sensitive paths in plain text. Use 'fuxi secret add' to encrypt them, or --force to add them anyway.".into());
+                    }
+                }
+
+                let preset = sub_matches.get_one::<String>("preset").map(|s| s.as_str());
+                add_paths(
+                    &paths,
+                    preset,
+                    sub_matches.get_flag("template"),
+                    sub_matches.get_flag("xattrs"),
+                    sub_matches.get_one::<String>("group").map(|s| s.as_str()),
+                    sub_matches.get_one::<String>("note").map(|s| s.as_str()),
+                )?;
             }
             Some(("remove", sub_matches)) => {
                 let paths: Vec<PathBuf> = sub_matches
@@ -284,90 +1261,147 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .collect();
                 remove_paths(&paths)?;
             }
+            Some(("move", sub_matches)) => {
+                let path = |name| {
+                    sub_matches
+                        .get_one::<PathBuf>(name)
+                        .map(|path| path.to_string_lossy().to_string())
+                        .unwrap_or_default()
+                };
+                move_path(&path("FROM"), &path("TO"))?;
+            }
             _ => unreachable!(),
         },
         Some(("backup", sub_matches)) => {
-            let backup_id = format!("backup_{}", chrono::Utc::now().format("%Y%m%d_%H%M%S"));
-            update_last_backup_id(&backup_id)?;
-
-            let repo_path = config
-                .backup_repo_path
-                .as_ref()
-                .ok_or("Backup repository path is not set. Please run 'fuxi init' first.")?;
-            let repo_path = Path::new(repo_path);
-
-            if config.github_repo.is_none() {
-                return Err("GitHub repository is not set. Please run 'fuxi init' first.".into());
-            }
-
-            if config.selected_profile.is_none() {
-                return Err(
-                    "No profile selected. Please select a profile before backing up.".into(),
-                );
+            let notify = sub_matches.get_flag("notify");
+            if let Some(group) = sub_matches.get_one::<String>("group") {
+                config.selected_group = Some(group.clone());
+                if get_selected_profile_paths(&config).is_empty() {
+                    return Err(exit::config_error(format!(
+                        "No paths of the selected profile are in group '{}'.",
+                        group
+                    )));
+                }
             }
-
-            let paths = get_selected_profile_paths(&config);
-            if paths.is_empty() {
-                return Err("No paths configured for the selected profile.".into());
+            if sub_matches.get_flag("dry-run") {
+                preview_backup(&config)?;
+                return Ok(());
             }
-
-            for path in paths {
-                let src_path = Path::new(&path);
-                if !src_path.exists() {
-                    println!(
-                        "Warning: Source path does not exist: {}",
-                        src_path.display()
-                    );
-                    continue;
-                }
-
-                // use just the last path component (file or folder)
-                let relative_path: PathBuf = src_path
-                    .components()
-                    .rev()
-                    .find_map(|c| {
-                        if let std::path::Component::Normal(os_str) = c {
-                            Some(PathBuf::from(os_str))
-                        } else {
-                            None
+            let backup_id =
+                match create_backup(&config, sub_matches.get_one::<String>("message").cloned()) {
+                    Ok(id) => id,
+                    Err(e) => {
+                        if notify {
+                            notify_failure(&config, &format!("Backup failed: {}", e));
                         }
-                    })
-                    .unwrap_or_else(|| PathBuf::from(""));
-
-                let selected_profile = config
-                    .selected_profile
-                    .as_ref()
-                    .expect("Selected profile should be present");
-
-                let dst_path = repo_path.join(selected_profile).join(&relative_path);
-
-                copy_file_or_path(src_path, &dst_path, false)?;
-                println!("Backed up {} to {}", src_path.display(), dst_path.display());
+                        return Err(e);
+                    }
+                };
+            let repo_path = PathBuf::from(repo_path(&config)?);
+            let repo_path = repo_path.as_path();
+            let tags: Vec<String> = sub_matches
+                .get_many::<String>("tag")
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect();
+            if !tags.is_empty() {
+                add_tags(repo_path, &backup_id, &tags)?;
+                println!("Tagged {}: {}", backup_id, tags.join(", "));
             }
 
-            println!("Backup '{}' created successfully!", backup_id);
-
-            if sub_matches.get_flag("push") {
-                let message = sub_matches
-                    .get_one::<String>("message")
-                    .cloned()
-                    .unwrap_or_else(|| format!("Backup {}", backup_id));
-                let branch = &config.git_branch;
-                let result = push_to_github(repo_path, branch, Some(message));
+            if sub_matches.get_flag("push") || push_due(&config, sub_matches.get_flag("auto-push"))
+            {
+                let message = sub_matches.get_one::<String>("message").cloned();
+                let mut divergence = config.divergence_strategy.unwrap_or_default();
+                if sub_matches.get_flag("yes") {
+                    divergence = divergence.unattended();
+                }
+                let result = github::check_before_first_push(
+                    &mut config,
+                    repo_path,
+                    sub_matches.get_flag("public-ok"),
+                )
+                .and_then(|_| {
+                    snapshot_and_push(
+                        &config,
+                        Some(commit_message(
+                            &config,
+                            message,
+                            Some(&backup_id),
+                            get_selected_profile_paths(&config).len(),
+                        )?),
+                        divergence,
+                        sub_matches.get_flag("allow-secrets"),
+                    )
+                });
+                report_push(
+                    &config,
+                    repo_path,
+                    Some(&backup_id),
+                    get_selected_profile_paths(&config).len(),
+                    result.as_ref().err().map(|e| e.to_string()).as_deref(),
+                );
                 if let Err(e) = result {
-                    println!("Error during push: {}", e);
+                    if notify {
+                        notify_failure(&config, &format!("Pushing {} failed: {}", backup_id, e));
+                    }
+                    return Err(exit::context("Error during push", e));
                 } else {
-                    println!("Backup pushed to GitHub successfully!");
+                    println!("{}", style::success("Backup pushed successfully!"));
+                    if notify {
+                        notify_success(&config, &format!("Backup {} pushed.", backup_id));
+                    }
+                    if sub_matches.get_flag("prune")
+                        && let Some(retention) = config.retention.filter(|r| !r.is_empty())
+                        && let Err(e) =
+                            prune_backups(&config, &retention, false, PruneConsent::Unattended)
+                    {
+                        if notify {
+                            notify_failure(
+                                &config,
+                                &format!("Applying the retention policy failed: {}", e),
+                            );
+                        }
+                        return Err(exit::context("Error applying the retention policy", e));
+                    }
                 }
             } else {
                 println!("Save the backup using the 'fuxi save' command.");
+                if notify {
+                    notify_success(&config, &format!("Backup {} created.", backup_id));
+                }
             }
         }
+        Some(("apply", sub_matches)) if applies_from => bootstrap::apply_from(
+            &config,
+            sub_matches
+                .get_one::<String>("from")
+                .expect("checked by applies_from"),
+            matches.get_one::<String>("profile").map(|s| s.as_str()),
+            sub_matches.get_one::<String>("preset").map(|s| s.as_str()),
+            match sub_matches.get_one::<String>("strategy") {
+                Some(name) => name.parse::<ConflictStrategy>()?,
+                None => config.conflict_strategy.unwrap_or_default(),
+            },
+            sub_matches.get_flag("dryrun"),
+            sub_matches.get_flag("verify"),
+        )?,
         Some(("apply", sub_matches)) => {
-            let id = sub_matches
-                .get_one::<String>("ID")
-                .map(|s| s.as_str())
-                .unwrap_or("");
+            let picked;
+            let id = match sub_matches.get_one::<String>("ID") {
+                Some(id) => id.as_str(),
+                None => match pick_backup(&config)? {
+                    Some(backup) => {
+                        picked = backup;
+                        picked.as_str()
+                    }
+                    None => {
+                        println!("Apply cancelled.");
+                        return Ok(());
+                    }
+                },
+            };
             update_last_backup_id(id)?;
 
             if id == "latest" {
@@ -383,109 +1417,139 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
 
-            let repo_path = config
-                .backup_repo_path
-                .as_ref()
-                .ok_or("Backup repository path is not set. Please run 'fuxi init' first.")?;
+            let repo_path = repo_path(&config)?;
             let repo_path = Path::new(repo_path);
-            let branch = &config.git_branch;
+            let backend = storage::backend(&config)?;
 
-            let log = run_git_command(repo_path, &["log", "--oneline"])?;
-            if log.is_empty() {
+            let backups = backend.list()?;
+            if backups.is_empty() {
                 return Err("No backups found in the repository.".into());
             }
 
             if id == "latest" {
-                // fetch latest from GitHub
-                if let Err(e) = fetch_from_github(repo_path, branch, None) {
-                    println!("Error during fetch: {}", e);
-                    return Ok(());
+                if let Err(e) = backend.fetch(None) {
+                    return Err(exit::context("Error during fetch", e));
                 } else {
-                    println!("Fetched the latest backup from git repository.");
+                    println!(
+                        "Fetched the latest backup from the {} storage.",
+                        backend.name()
+                    );
                 }
             } else {
-                if !log.contains(id) {
+                if !backups.iter().any(|b| b.matches(id)) {
                     return Err(format!("Backup ID or commit hash '{}' not found.", id).into());
                 }
 
-                if let Err(e) = fetch_from_github(repo_path, branch, Some(id)) {
-                    println!("Error during fetch: {}", e);
-                    return Ok(());
+                if let Err(e) = backend.fetch(Some(id)) {
+                    return Err(exit::context("Error during fetch", e));
                 } else {
-                    println!("Fetched the specified backup from git repository.");
+                    println!(
+                        "Fetched the specified backup from the {} storage.",
+                        backend.name()
+                    );
                 }
             }
 
-            // pull latest changes
-            if let Err(e) = pull_from_github(repo_path, branch) {
-                println!("Error during pull: {}", e);
-            } else {
-                println!("Configuration updated from git repository.");
-            }
-
-            let paths = get_selected_profile_paths(&config);
-            if paths.is_empty() {
-                return Err("No paths configured for the selected profile.".into());
-            }
-
             let selected_profile = config
                 .selected_profile
                 .as_ref()
-                .expect("Selected profile should be present");
-
-            let dry_run = sub_matches.get_flag("dryrun");
-
-            for path in paths {
-                let dst_path: &Path = Path::new(&path);
-                if !dst_path.exists() {
-                    println!(
-                        "Warning: Source path does not exist: {}",
-                        dst_path.display()
-                    );
-                    continue;
+                .ok_or("No profile selected. Please select a profile before applying.")?;
+            let from_machine = sub_matches
+                .get_one::<String>("from-machine")
+                .map(|s| s.as_str());
+            let profile_dir = get_profile_dir(&config, repo_path, selected_profile, from_machine);
+            if let Some(machine) = from_machine {
+                if !profile_dir.exists() {
+                    return Err(format!(
+                        "Machine '{}' has no backups for profile '{}'.",
+                        machine, selected_profile
+                    )
+                    .into());
                 }
+                println!("Applying files backed up by machine '{}'.", machine);
+            }
 
-                let src_path = Path::new(&path);
-                let relative_path: PathBuf = src_path
-                    .components()
-                    .rev()
-                    .find_map(|c| {
-                        if let std::path::Component::Normal(os_str) = c {
-                            Some(PathBuf::from(os_str))
-                        } else {
-                            None
-                        }
-                    })
-                    .unwrap_or_else(|| PathBuf::from(""));
-
-                // if repo_path.exists() {
-                //     fs::remove_dir_all(&repo_path)?;
-                // }
+            let manifest = match load_manifest(&profile_dir)? {
+                Some(manifest) => manifest,
+                None => {
+                    let paths = get_selected_profile_paths(&config);
+                    if paths.is_empty() {
+                        return Err("No paths configured for the selected profile.".into());
+                    }
+                    println!("Backup has no manifest, using the configured paths instead.");
+                    Manifest::from_paths(&paths)
+                }
+            };
 
-                let src_path = repo_path.join(selected_profile).join(&relative_path);
-                if !src_path.exists() {
+            let entries = match sub_matches.get_one::<String>("preset") {
+                Some(preset) => {
+                    let entries = manifest.preset_entries(preset);
+                    if entries.is_empty() {
+                        return Err(format!(
+                            "No files belong to preset '{}' in this backup.",
+                            preset
+                        )
+                        .into());
+                    }
                     println!(
-                        "Warning: Backup path does not exist in repository: {}",
-                        src_path.display()
+                        "Applying {} path(s) from preset '{}'.",
+                        entries.len(),
+                        preset
                     );
-                    continue;
+                    entries
                 }
-
-                if !dry_run {
-                    copy_file_or_path(&src_path, dst_path, true)?;
-                    println!("Applied {} to {}", src_path.display(), dst_path.display());
-                } else {
+                None => manifest.entries.clone(),
+            };
+            let target_dir = sub_matches.get_one::<PathBuf>("target-dir");
+            let entries = match target_dir {
+                Some(target) => {
                     println!(
-                        "[Dry Run] Would apply {} to {}",
-                        src_path.display(),
-                        dst_path.display()
+                        "Restoring below {} instead of the live system.",
+                        style::path(target.display())
                     );
+                    retarget(&entries, target)
                 }
+                None => entries,
+            };
+
+            let dry_run = sub_matches.get_flag("dryrun");
+            let strategy = match sub_matches.get_one::<String>("strategy") {
+                Some(name) => name.parse::<ConflictStrategy>()?,
+                None => config.conflict_strategy.unwrap_or_default(),
+            };
+
+            let layout = ProfileLayout::new(&profile_dir, &get_machine_name(&config));
+            apply_backup(
+                &config,
+                &layout,
+                &entries,
+                &manifest.backup_id,
+                strategy,
+                dry_run,
+                sub_matches.get_flag("verify"),
+            )?;
+            // registry keys don't belong to any preset, nor to a target directory
+            if sub_matches.get_one::<String>("preset").is_none() && target_dir.is_none() {
+                registry::import_keys(
+                    &layout.root,
+                    &manifest.registry,
+                    &manifest.backup_id,
+                    dry_run,
+                )?;
             }
 
-            println!("Backup '{}' applied successfully!", id);
+            println!(
+                "{}",
+                style::success(format!("Backup '{}' applied successfully!", id))
+            );
         }
         Some(("save", sub_matches)) => {
+            let repo_path = PathBuf::from(repo_path(&config)?);
+            let repo_path = repo_path.as_path();
+            if config.backend.as_deref().unwrap_or("git") == "git" {
+                print_pending_changes(repo_path, sub_matches.get_flag("diff"))?;
+            }
+
             let force = sub_matches.get_flag("force");
             if !force
                 && !(confirm("Are you sure you want to save the current configuration state?")?)
@@ -494,39 +1558,756 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 return Ok(());
             }
 
-            let repo_path = config
-                .backup_repo_path
-                .as_ref()
-                .ok_or("Backup repository path is not set. Please run 'fuxi init' first.")?;
-            let repo_path = Path::new(repo_path);
-            let branch = &config.git_branch;
-            let message = sub_matches
-                .get_one::<String>("message")
-                .cloned()
-                .unwrap_or_else(|| "Save configuration".to_string());
+            let message = sub_matches.get_one::<String>("message").cloned();
 
-            let result = push_to_github(repo_path, branch, Some(message));
+            let result = github::check_before_first_push(
+                &mut config,
+                repo_path,
+                sub_matches.get_flag("public-ok"),
+            )
+            .and_then(|_| {
+                save_repo_config(repo_path, &config)?;
+                snapshot_and_push(
+                    &config,
+                    Some(commit_message(
+                        &config,
+                        message,
+                        config.last_backup_id.as_deref(),
+                        get_selected_profile_paths(&config).len(),
+                    )?),
+                    config.divergence_strategy.unwrap_or_default(),
+                    sub_matches.get_flag("allow-secrets"),
+                )
+            });
+            report_push(
+                &config,
+                repo_path,
+                config.last_backup_id.as_deref(),
+                get_selected_profile_paths(&config).len(),
+                result.as_ref().err().map(|e| e.to_string()).as_deref(),
+            );
             if let Err(e) = result {
-                println!("Error during push: {}", e);
-            } else {
-                println!("Configuration saved successfully!");
+                return Err(exit::context("Error during push", e));
             }
+            println!("{}", style::success("Configuration saved successfully!"));
         }
-        Some(("list", _)) => {
-            let repo_path = config
-                .backup_repo_path
-                .as_ref()
-                .ok_or("Backup repository path is not set. Please run 'fuxi init' first.")?;
+        Some(("list", sub_matches)) => {
+            let repo_path = repo_path(&config)?;
             let repo_path = Path::new(repo_path);
-            let log = run_git_command(repo_path, &["log", "--oneline"])?;
-            if log.is_empty() {
+            let machine_filter = sub_matches.get_one::<String>("machine");
+            // `--profile` also narrows the list down to that profile's backups
+            let profile_filter = sub_matches
+                .get_one::<String>("profile")
+                .and(config.selected_profile.as_ref());
+            let since = sub_matches.get_one::<DateTime<Utc>>("since");
+            let limit = sub_matches
+                .get_one::<usize>("limit")
+                .copied()
+                .unwrap_or(usize::MAX);
+            let remote = sub_matches.get_flag("remote");
+            let tag = sub_matches.get_one::<String>("tag");
+            let backend = storage::backend(&config)?;
+            let backups = if remote {
+                backend.list_remote()?
+            } else {
+                backend.list()?
+            };
+            // only git backups can be matched to their metadata
+            let metadata = metadata_by_commit(repo_path).unwrap_or_default();
+
+            let rows: Vec<[String; 7]> = backups
+                .iter()
+                .filter_map(|backup| {
+                    let metadata = metadata.get(&backup.id);
+                    let machine = metadata.and_then(|m| m.machine.as_ref());
+                    let hostname = machine.map(|m| &m.hostname).or(backup.hostname.as_ref());
+                    let profile = metadata
+                        .and_then(|m| m.profile.as_ref())
+                        .or(backup.profile.as_ref());
+                    let created = metadata.map(|m| m.timestamp).or(backup.created);
+                    let tags = metadata.map(|m| m.tags.join(",")).unwrap_or_default();
+                    let machine_matches = |filter: &str| match machine {
+                        Some(machine) => machine.matches(filter),
+                        None => hostname.is_some_and(|h| h.eq_ignore_ascii_case(filter)),
+                    };
+                    if machine_filter.is_some_and(|filter| !machine_matches(filter))
+                        || profile_filter.is_some_and(|filter| profile != Some(filter))
+                        || since.is_some_and(|since| created.is_none_or(|c| c < *since))
+                        || tag.is_some_and(|tag| metadata.is_none_or(|m| !m.tags.contains(tag)))
+                    {
+                        return None;
+                    }
+                    if porcelain::enabled() {
+                        return Some([
+                            backup.name().to_string(),
+                            created
+                                .map(|c| c.to_rfc3339_opts(SecondsFormat::Secs, true))
+                                .unwrap_or_default(),
+                            profile.cloned().unwrap_or_default(),
+                            hostname.cloned().unwrap_or_default(),
+                            backup
+                                .files_changed
+                                .map(|n| n.to_string())
+                                .unwrap_or_default(),
+                            backup.summary.clone(),
+                            tags,
+                        ]);
+                    }
+                    Some([
+                        backup.name().to_string(),
+                        created.map_or("-".to_string(), |c| c.format("%Y-%m-%d %H:%M").to_string()),
+                        profile.map_or("-", |p| p.as_str()).to_string(),
+                        hostname.map_or("-", |h| h.as_str()).to_string(),
+                        backup
+                            .files_changed
+                            .map_or("-".to_string(), |n| n.to_string()),
+                        backup.summary.clone(),
+                        if tags.is_empty() {
+                            "-".to_string()
+                        } else {
+                            tags.replace(',', ", ")
+                        },
+                    ])
+                })
+                .take(limit)
+                .collect();
+
+            if porcelain::enabled() {
+                for row in &rows {
+                    porcelain::record("backup", &row.each_ref().map(String::as_str));
+                }
+            } else if rows.is_empty()
+                && let Some(tag) = tag
+            {
+                println!("No backups tagged '{}'.", tag);
+            } else if rows.is_empty() && remote {
+                println!("The local repository has every backup on the remote.");
+            } else if rows.is_empty() {
                 println!("No backups found.");
             } else {
-                println!("Backups:");
-                for line in log.lines() {
-                    println!("  {}", line);
+                if remote {
+                    println!(
+                        "On the remote but not pulled yet, 'fuxi sync' or 'fuxi apply latest' brings them in:"
+                    );
+                }
+                print_table(
+                    [
+                        "ID", "DATE", "PROFILE", "MACHINE", "FILES", "MESSAGE", "TAGS",
+                    ],
+                    &rows,
+                );
+            }
+        }
+        Some(("log", sub_matches)) => show_log(
+            sub_matches.get_one::<String>("COMMAND").map(|s| s.as_str()),
+            sub_matches.get_flag("failed"),
+            *sub_matches
+                .get_one::<usize>("limit")
+                .expect("has a default value"),
+        ),
+        Some(("tag", sub_matches)) => {
+            let tags: Vec<String> = sub_matches
+                .get_many::<String>("TAG")
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect();
+            tag_backup(
+                &config,
+                sub_matches.get_one::<String>("ID").expect("ID is required"),
+                &tags,
+            )?;
+        }
+        Some(("daemon", _)) => daemon::run()?,
+        Some(("plugins", _)) => plugin::list_plugins(&config),
+        Some(("migrate", sub_matches)) => {
+            if let Some(profile) = &config.selected_profile {
+                ensure_profile_writable(&config, profile)?;
+            }
+            migrate::migrate(
+                &mut config,
+                sub_matches
+                    .get_one::<String>("from")
+                    .expect("--from is required"),
+                sub_matches.get_one::<PathBuf>("DIR").map(|p| p.as_path()),
+                sub_matches.get_flag("backup"),
+            )?
+        }
+        Some(("search", sub_matches)) => search::search(
+            &config,
+            sub_matches
+                .get_one::<String>("PATTERN")
+                .expect("PATTERN is required"),
+            sub_matches.get_flag("ignore-case"),
+            sub_matches.get_flag("history"),
+        )?,
+        Some(("sync", sub_matches)) => {
+            let dry_run = sub_matches.get_flag("dry-run");
+
+            let repo_path = PathBuf::from(repo_path(&config)?);
+            let repo_path = repo_path.as_path();
+            if !dry_run {
+                github::check_before_first_push(
+                    &mut config,
+                    repo_path,
+                    sub_matches.get_flag("public-ok"),
+                )?;
+            }
+            let backend = storage::backend(&config)?;
+            let selected_profile = config
+                .selected_profile
+                .as_ref()
+                .ok_or("No profile selected. Please select a profile before syncing.")?;
+            let profile_dir = get_profile_dir(&config, repo_path, selected_profile, None);
+
+            if dry_run {
+                println!("[Dry Run] Would pull from the remote repository.");
+            } else if let Err(e) = backend.update() {
+                return Err(format!("Error during pull: {}", e).into());
+            }
+            ensure_unlocked(repo_path)?;
+
+            // entries from the stored manifest plus any paths added since the last backup
+            let mut manifest = match load_manifest(&profile_dir)? {
+                Some(manifest) => manifest,
+                None => Manifest::new("sync"),
+            };
+            for path in get_selected_profile_paths(&config) {
+                if !manifest.entries.iter().any(|e| e.source == path) {
+                    manifest.entries.push(manifest_entry(&config, &path));
+                }
+            }
+            if manifest.entries.is_empty() {
+                return Err("No paths configured for the selected profile.".into());
+            }
+
+            let sync_id = format!("sync_{}", chrono::Utc::now().format("%Y%m%d_%H%M%S"));
+            let mut snapshot = Snapshot::new(&sync_id)?;
+            let layout = ProfileLayout::new(&profile_dir, &get_machine_name(&config));
+            let summary = sync_entries(
+                &config,
+                repo_path,
+                &layout,
+                &manifest.entries,
+                dry_run,
+                &mut snapshot,
+            )?;
+            println!(
+                "{} file(s) applied, {} backed up, {} unchanged.",
+                summary.applied, summary.backed_up, summary.unchanged
+            );
+
+            if dry_run {
+                if summary.backed_up > 0 {
+                    println!("[Dry Run] Would commit and push the backed up files.");
+                }
+                return Ok(());
+            }
+
+            if snapshot.saved > 0 {
+                println!(
+                    "Previous versions of {} file(s) saved to {}",
+                    snapshot.saved,
+                    style::path(snapshot.dir.display())
+                );
+            }
+
+            if summary.backed_up > 0 {
+                manifest.backup_id = sync_id.clone();
+                manifest.timestamp = chrono::Utc::now();
+                save_manifest(&profile_dir, &manifest)?;
+                update_last_backup_id(&sync_id)?;
+
+                let metadata = BackupMetadata::new(
+                    &sync_id,
+                    selected_profile,
+                    manifest.entries.iter().map(|e| e.source.clone()).collect(),
+                    None,
+                )?;
+                save_metadata(repo_path, &metadata)?;
+
+                let message = commit_message(
+                    &config,
+                    Some(format!("Sync {}", sync_id)),
+                    Some(&sync_id),
+                    manifest.entries.len(),
+                )?;
+                let result = snapshot_and_push(
+                    &config,
+                    Some(message),
+                    config.divergence_strategy.unwrap_or_default(),
+                    sub_matches.get_flag("allow-secrets"),
+                );
+                report_push(
+                    &config,
+                    repo_path,
+                    Some(&sync_id),
+                    manifest.entries.len(),
+                    result.as_ref().err().map(|e| e.to_string()).as_deref(),
+                );
+                if let Err(e) = result {
+                    return Err(format!("Error during push: {}", e).into());
+                }
+            }
+            println!("{}", style::success("Sync complete!"));
+        }
+        Some(("watch", sub_matches)) => {
+            watch::watch(&config, sub_matches.get_flag("push"))?;
+        }
+        Some(("schedule", sub_matches)) => match sub_matches.subcommand() {
+            Some(("install", install_matches)) => {
+                let every = install_matches
+                    .get_one::<String>("every")
+                    .expect("has a default value");
+                let seconds = schedule::parse_interval(every)?;
+                schedule::install(seconds)?;
+                println!(
+                    "Scheduled 'fuxi backup --auto-push -y --notify --prune --wait' every {}.",
+                    every
+                );
+            }
+            Some(("remove", _)) => {
+                schedule::remove()?;
+                println!("Scheduled backups removed.");
+            }
+            Some(("status", _)) => match schedule::status()? {
+                Some(status) => println!("{}", status.trim_end()),
+                None => println!("No scheduled backups installed."),
+            },
+            _ => unreachable!(),
+        },
+        Some(("packages", sub_matches)) => {
+            let repo_path = repo_path(&config)?;
+            let repo_path = Path::new(repo_path);
+            let selected_profile = config
+                .selected_profile
+                .as_ref()
+                .ok_or("No profile selected. Please select a profile first.")?;
+
+            match sub_matches.subcommand() {
+                Some(("capture", capture_matches)) => {
+                    let only: Vec<String> = capture_matches
+                        .get_many::<String>("manager")
+                        .into_iter()
+                        .flatten()
+                        .cloned()
+                        .collect();
+                    let dir = get_profile_dir(&config, repo_path, selected_profile, None)
+                        .join(PACKAGES_DIR);
+                    let captured = capture_packages(&dir, &only)?;
+                    if captured.is_empty() {
+                        println!("No supported package managers found.");
+                    } else {
+                        println!("Save the package lists using the 'fuxi save' command.");
+                    }
                 }
+                Some(("apply", apply_matches)) => {
+                    let only: Vec<String> = apply_matches
+                        .get_many::<String>("manager")
+                        .into_iter()
+                        .flatten()
+                        .cloned()
+                        .collect();
+                    let from_machine = apply_matches
+                        .get_one::<String>("from-machine")
+                        .map(|s| s.as_str());
+                    let dir = get_profile_dir(&config, repo_path, selected_profile, from_machine)
+                        .join(PACKAGES_DIR);
+                    if !dir.exists() {
+                        return Err(
+                            "No captured package lists found. Run 'fuxi packages capture' first."
+                                .into(),
+                        );
+                    }
+                    let dry_run = apply_matches.get_flag("dryrun");
+                    let installed = install_packages(&dir, &only, dry_run)?;
+                    println!("Processed {} package list(s).", installed);
+                }
+                _ => unreachable!(),
+            }
+        }
+        Some(("crypt", sub_matches)) => {
+            let repo_path = repo_path(&config)?;
+            let repo_path = Path::new(repo_path);
+
+            match sub_matches.subcommand() {
+                Some(("init", init_matches)) => {
+                    let gpg_users: Vec<String> = init_matches
+                        .get_many::<String>("gpg-user")
+                        .into_iter()
+                        .flatten()
+                        .cloned()
+                        .collect();
+                    crypt::init_git_crypt(repo_path, &gpg_users)?;
+                    println!(
+                        "git-crypt enabled, every file in the repository is now encrypted on push."
+                    );
+                    println!(
+                        "{}",
+                        style::warning(
+                            "Warning: Files pushed before are still readable in the repository history."
+                        )
+                    );
+                    if gpg_users.is_empty() {
+                        println!(
+                            "Export the key with 'fuxi crypt export-key <PATH>' to unlock other machines."
+                        );
+                    }
+                    println!("Save the changes using the 'fuxi save' command.");
+                }
+                Some(("unlock", unlock_matches)) => {
+                    let key = unlock_matches.get_one::<PathBuf>("KEY");
+                    crypt::unlock(repo_path, key.map(|k| k.as_path()))?;
+                    println!("Repository unlocked.");
+                }
+                Some(("lock", _)) => {
+                    crypt::lock(repo_path)?;
+                    println!("Repository locked.");
+                }
+                Some(("export-key", export_matches)) => {
+                    let key = export_matches
+                        .get_one::<PathBuf>("PATH")
+                        .expect("PATH is required");
+                    crypt::export_key(repo_path, key)?;
+                    println!(
+                        "Key exported to {}, keep it somewhere safe.",
+                        style::path(key.display())
+                    );
+                }
+                Some(("status", _)) => {
+                    if !crypt::is_git_crypt_enabled(repo_path) {
+                        println!("git-crypt is not enabled for this repository.");
+                    } else if crypt::is_unlocked(repo_path) {
+                        println!("git-crypt is enabled and the repository is unlocked.");
+                    } else {
+                        println!("git-crypt is enabled and the repository is locked.");
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+        Some(("secret", sub_matches)) => match sub_matches.subcommand() {
+            Some(("list", _)) => match config.secrets.as_ref().filter(|s| !s.is_empty()) {
+                Some(secrets) => {
+                    println!("Secret paths:");
+                    for path in secrets {
+                        println!("- {}", path);
+                    }
+                }
+                None => println!("No secret paths configured."),
+            },
+            Some(("add", secret_matches)) => {
+                let paths: Vec<PathBuf> = secret_matches
+                    .get_many::<PathBuf>("PATH")
+                    .into_iter()
+                    .flatten()
+                    .cloned()
+                    .collect();
+
+                let tracked = get_profile_entries(
+                    &config,
+                    config.selected_profile.as_deref().unwrap_or_default(),
+                );
+                let untracked: Vec<PathBuf> = paths
+                    .iter()
+                    .filter(|p| {
+                        !tracked
+                            .iter()
+                            .any(|entry| entry.source == p.to_string_lossy())
+                    })
+                    .cloned()
+                    .collect();
+                if !untracked.is_empty() {
+                    add_paths(&untracked, None, false, false, None, None)?;
+                }
+
+                let mut config = load_config()?;
+                let secrets = config.secrets.get_or_insert_with(Vec::new);
+                for path in &paths {
+                    let path_str = path.to_string_lossy().to_string();
+                    if !secrets.contains(&path_str) {
+                        secrets.push(path_str);
+                        println!("Marked as secret: {}", style::path(path.display()));
+                    }
+                }
+                save_config(&config)?;
+                println!(
+                    "Secret paths will be encrypted with {} on the next backup.",
+                    encryption_backend(&config).name()
+                );
+            }
+            Some(("remove", secret_matches)) => {
+                let mut config = config;
+                let secrets = config.secrets.get_or_insert_with(Vec::new);
+                for path in secret_matches
+                    .get_many::<PathBuf>("PATH")
+                    .into_iter()
+                    .flatten()
+                {
+                    let path_str = path.to_string_lossy().to_string();
+                    if let Some(pos) = secrets.iter().position(|s| s == &path_str) {
+                        secrets.remove(pos);
+                        println!("No longer secret: {}", style::path(path.display()));
+                    } else {
+                        println!("Path is not secret: {}", style::path(path.display()));
+                    }
+                }
+                save_config(&config)?;
+            }
+            _ => unreachable!(),
+        },
+        Some(("registry", sub_matches)) => {
+            let profile = config
+                .selected_profile
+                .clone()
+                .ok_or("No profile selected")?;
+            let mut keys = registry::profile_keys(&config, &profile);
+            match sub_matches.subcommand() {
+                Some(("list", _)) => {
+                    if keys.is_empty() {
+                        println!("No registry keys tracked by profile '{}'.", profile);
+                    } else {
+                        println!("Registry keys tracked by profile '{}':", profile);
+                        for key in &keys {
+                            println!("- {}", key);
+                        }
+                    }
+                    return Ok(());
+                }
+                Some(("add", key_matches)) => {
+                    if !cfg!(windows) {
+                        return Err("Registry keys can only be backed up on Windows.".into());
+                    }
+                    ensure_profile_writable(&config, &profile)?;
+                    for key in key_matches.get_many::<String>("KEY").into_iter().flatten() {
+                        let key = registry::normalize_key(key)?;
+                        if keys.contains(&key) {
+                            println!("Registry key already tracked: {}", key);
+                        } else {
+                            println!("Added registry key: {}", key);
+                            keys.push(key);
+                        }
+                    }
+                }
+                Some(("remove", key_matches)) => {
+                    for key in key_matches.get_many::<String>("KEY").into_iter().flatten() {
+                        let key = key.trim_end_matches('\\');
+                        if let Some(pos) = keys.iter().position(|k| k.eq_ignore_ascii_case(key)) {
+                            keys.remove(pos);
+                            println!("Removed registry key: {}", key);
+                        } else {
+                            println!("Registry key is not tracked: {}", key);
+                        }
+                    }
+                }
+                _ => unreachable!(),
+            }
+            let mut config = config;
+            config
+                .registry_keys
+                .get_or_insert_with(HashMap::new)
+                .insert(profile, keys);
+            save_config(&config)?;
+        }
+        Some(("remote", sub_matches)) => match sub_matches.subcommand() {
+            Some(("list", _)) => {
+                match &config.remote_url {
+                    Some(url) => println!("origin: {}", url),
+                    None => println!("No remote configured. Please run 'fuxi init' first."),
+                }
+                for url in config.mirrors.iter().flatten() {
+                    println!("mirror: {}", url);
+                }
+            }
+            Some(("add", remote_matches)) => {
+                let url = remote_url(
+                    remote_matches
+                        .get_one::<String>("URL")
+                        .expect("URL is required"),
+                );
+                let mirrors = config.mirrors.get_or_insert_with(Vec::new);
+                if config.remote_url.as_ref() == Some(&url) || mirrors.contains(&url) {
+                    println!("{} is already a remote.", url);
+                    return Ok(());
+                }
+                mirrors.push(url.clone());
+                save_config(&config)?;
+                println!("Added mirror {}", url);
+            }
+            Some(("remove", remote_matches)) => {
+                let url = remote_url(
+                    remote_matches
+                        .get_one::<String>("URL")
+                        .expect("URL is required"),
+                );
+                let mirrors = config.mirrors.get_or_insert_with(Vec::new);
+                let Some(pos) = mirrors.iter().position(|m| *m == url) else {
+                    return Err(format!("{} is not a mirror.", url).into());
+                };
+                mirrors.remove(pos);
+                save_config(&config)?;
+                println!("Removed mirror {}", url);
+            }
+            _ => unreachable!(),
+        },
+        Some(("clean", sub_matches)) => {
+            let dry_run = sub_matches.get_flag("dry-run");
+            let evicted = prune_snapshots(&config, dry_run)?;
+            if !evicted.is_empty() {
+                let reclaimed: u64 = evicted.iter().map(|s| s.size).sum();
+                for snapshot in &evicted {
+                    let age_days =
+                        snapshot.created.elapsed().unwrap_or_default().as_secs() / 86_400;
+                    if dry_run {
+                        println!(
+                            "[Dry Run] Would evict {} ({} bytes, {} day(s) old)",
+                            style::path(snapshot.path.display()),
+                            snapshot.size,
+                            age_days
+                        );
+                    } else {
+                        println!(
+                            "Evicted {} ({} bytes, {} day(s) old)",
+                            style::path(snapshot.path.display()),
+                            snapshot.size,
+                            age_days
+                        );
+                    }
+                }
+                println!(
+                    "{} snapshot(s), {} bytes {}.",
+                    evicted.len(),
+                    reclaimed,
+                    if dry_run {
+                        "would be reclaimed"
+                    } else {
+                        "reclaimed"
+                    }
+                );
+            }
+            let orphans = orphans::clean_orphans(&config, dry_run, sub_matches.get_flag("yes"))?;
+            if evicted.is_empty() && orphans == 0 {
+                println!("Nothing to clean.");
+            }
+        }
+        Some(("gc", sub_matches)) => gc::gc(&config, sub_matches.get_flag("dry-run"))?,
+        Some(("export", sub_matches)) => {
+            let repo_path = PathBuf::from(repo_path(&config)?);
+            let backup = match sub_matches.get_one::<String>("backup") {
+                Some(id) => {
+                    let backend = storage::backend(&config)?;
+                    if !backend.list()?.iter().any(|b| b.matches(id)) {
+                        return Err(format!("Backup ID or commit hash '{}' not found.", id).into());
+                    }
+                    backend.fetch(Some(id))?;
+                    id.clone()
+                }
+                None => config
+                    .last_backup_id
+                    .clone()
+                    .unwrap_or_else(|| "latest".to_string()),
+            };
+            match sub_matches.get_one::<PathBuf>("script") {
+                Some(script) => archive::export_script(&config, &repo_path, &backup, script)?,
+                None => archive::export_archive(
+                    &config,
+                    &repo_path,
+                    &backup,
+                    sub_matches
+                        .get_one::<PathBuf>("output")
+                        .expect("output is required without --script"),
+                )?,
+            }
+        }
+        Some(("import", sub_matches)) => {
+            let repo_path = PathBuf::from(repo_path(&config)?);
+            let file = sub_matches
+                .get_one::<PathBuf>("FILE")
+                .expect("FILE is required");
+            archive::import_archive(&mut config, &repo_path, file)?;
+        }
+        Some(("flush", _)) => {
+            if !queue::flush(&config, config.divergence_strategy.unwrap_or_default())? {
+                println!("No pushes are queued.");
+            }
+        }
+        Some(("repair", sub_matches)) => {
+            repair::repair(&config, sub_matches.get_flag("dry-run"))?;
+        }
+        Some(("stats", sub_matches)) => {
+            stats::stats(
+                &config,
+                *sub_matches.get_one::<usize>("backups").unwrap(),
+                *sub_matches.get_one::<usize>("top").unwrap(),
+            )?;
+        }
+        Some(("verify", _)) => {
+            let repo_path = repo_path(&config)?;
+            let profile = config
+                .selected_profile
+                .as_ref()
+                .ok_or("No profile selected. Please select a profile or pass --profile.")?;
+            let profile_dir = get_profile_dir(&config, Path::new(repo_path), profile, None);
+            let problems = verify::verify_profile(&profile_dir)?;
+            if problems > 0 {
+                return Err(format!(
+                    "{} file(s) of profile '{}' don't match the checksums recorded at backup time.",
+                    problems, profile
+                )
+                .into());
+            }
+            println!(
+                "{}",
+                style::success(format!(
+                    "Every file of profile '{}' matches its checksum.",
+                    profile
+                ))
+            );
+        }
+        Some(("validate", _)) => {
+            let problems = validate::validate(&config)?;
+            if problems > 0 {
+                return Err(exit::config_error(format!(
+                    "Found {} problem(s) in the profiles and the config file.",
+                    problems
+                )));
+            }
+            println!("{}", style::success("No problems found."));
+        }
+        Some(("prune", sub_matches)) => {
+            let mut retention = Retention {
+                keep_last: sub_matches.get_one::<usize>("keep-last").copied(),
+                keep_days: sub_matches.get_one::<u64>("keep-days").copied(),
+                ..Retention::default()
+            };
+            if retention.is_empty() {
+                retention = config.retention.unwrap_or_default();
             }
+            if retention.is_empty() {
+                return Err(
+                    "Pass --keep-last or --keep-days, or set a [retention] policy in the config."
+                        .into(),
+                );
+            }
+            prune_backups(
+                &config,
+                &retention,
+                sub_matches.get_flag("dry-run"),
+                if sub_matches.get_flag("yes") {
+                    PruneConsent::Given
+                } else {
+                    PruneConsent::Ask
+                },
+            )?;
+        }
+        Some((name, sub_matches)) => {
+            let args: Vec<OsString> = sub_matches
+                .get_many::<OsString>("")
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect();
+            plugin::run_external(&config, name, &args)?;
         }
         _ => unreachable!(),
     }
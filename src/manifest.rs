@@ -0,0 +1,353 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::apply::reroot;
+use crate::cfg::{FuxiConfig, get_path_entry, get_path_preset, is_template_path, is_xattr_path};
+use crate::hardlink::hardlink_groups;
+use crate::overlay::layers;
+use crate::ownership::{Ownership, ownership_of};
+use crate::secret::{ENCRYPTED_EXTENSIONS, is_secret_path};
+use crate::style;
+
+pub const MANIFEST_FILE: &str = ".fuxi-manifest.toml";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ManifestEntry {
+    pub source: String,
+    pub repo_path: String,
+    pub preset: Option<String>,
+    /// Rendered with template variables on apply instead of copied verbatim.
+    #[serde(default)]
+    pub template: bool,
+    /// Stored encrypted in the repo and decrypted on apply.
+    #[serde(default)]
+    pub encrypted: bool,
+    /// Restored to the files of the entry, when they belong to someone else
+    /// than the user backing them up.
+    #[serde(default)]
+    pub owner: Option<Ownership>,
+    /// Extended attributes and ACLs are recorded and restored with the files.
+    #[serde(default)]
+    pub xattrs: bool,
+    /// Files of a tracked directory that are hard links of each other,
+    /// relinked on apply.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hardlinks: Vec<Vec<String>>,
+    /// Copied with sudo on apply without trying without it first.
+    #[serde(default)]
+    pub sudo: bool,
+    /// Names below the entry that Windows can't store, by the encoded path
+    /// they're stored at relative to `repo_path`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub encoded_names: BTreeMap<String, String>,
+}
+
+/// Describes which source path each item under `repo/<profile>/` was backed up from.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Manifest {
+    pub backup_id: String,
+    pub timestamp: DateTime<Utc>,
+    /// Windows registry keys exported to `.fuxi-registry/`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub registry: Vec<String>,
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn new(backup_id: &str) -> Self {
+        Self {
+            backup_id: backup_id.to_string(),
+            timestamp: Utc::now(),
+            registry: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Builds a manifest for backups created before manifests were written,
+    /// assuming the `repo/<profile>/<last component>` layout.
+    pub fn from_paths(paths: &[String]) -> Self {
+        let mut manifest = Self::new("unknown");
+        manifest.entries = paths
+            .iter()
+            .map(|path| ManifestEntry {
+                source: path.clone(),
+                repo_path: legacy_repo_path(Path::new(path))
+                    .to_string_lossy()
+                    .to_string(),
+                preset: None,
+                template: false,
+                encrypted: false,
+                owner: None,
+                xattrs: false,
+                hardlinks: Vec::new(),
+                sudo: false,
+                encoded_names: BTreeMap::new(),
+            })
+            .collect();
+        manifest
+    }
+
+    pub fn preset_entries(&self, preset: &str) -> Vec<ManifestEntry> {
+        self.entries
+            .iter()
+            .filter(|e| e.preset.as_deref() == Some(preset))
+            .cloned()
+            .collect()
+    }
+}
+
+/// The directory paths outside the home directory are stored below, by
+/// their full path, e.g. `_root/etc/hosts`.
+pub const ROOT_DIR: &str = "_root";
+
+/// Location of a source path inside `repo/<profile>/`: its path relative to
+/// the home directory, e.g. `.config/nvim` for `~/.config/nvim`, or its full
+/// path below [`ROOT_DIR`] for paths outside of it.
+pub fn repo_relative_path(src_path: &Path) -> PathBuf {
+    if let Some(home) = dirs::home_dir()
+        && let Ok(rel) = src_path.strip_prefix(&home)
+        && !rel.as_os_str().is_empty()
+    {
+        return reroot(Path::new(""), rel);
+    }
+    reroot(Path::new(ROOT_DIR), src_path)
+}
+
+/// Where backups made before [`repo_relative_path`] kept the full path
+/// stored a source path: its last component, so same-named paths collided.
+pub fn legacy_repo_path(src_path: &Path) -> PathBuf {
+    src_path
+        .components()
+        .rev()
+        .find_map(|c| {
+            if let Component::Normal(os_str) = c {
+                Some(PathBuf::from(os_str))
+            } else {
+                None
+            }
+        })
+        .unwrap_or_else(|| PathBuf::from(""))
+}
+
+pub fn manifest_entry(config: &FuxiConfig, source: &str) -> ManifestEntry {
+    ManifestEntry {
+        source: source.to_string(),
+        repo_path: repo_relative_path(Path::new(source))
+            .to_string_lossy()
+            .to_string(),
+        preset: get_path_preset(config, source),
+        template: is_template_path(config, source),
+        encrypted: is_secret_path(config, source),
+        owner: ownership_of(Path::new(source)),
+        xattrs: is_xattr_path(config, source),
+        hardlinks: hardlink_groups(Path::new(source)),
+        sudo: get_path_entry(config, source).is_some_and(|entry| entry.sudo.unwrap_or(false)),
+        encoded_names: BTreeMap::new(),
+    }
+}
+
+pub fn load_manifest(profile_dir: &Path) -> Result<Option<Manifest>, Box<dyn std::error::Error>> {
+    let manifest_path = profile_dir.join(MANIFEST_FILE);
+    if !manifest_path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(manifest_path)?;
+    Ok(Some(toml::from_str(&content)?))
+}
+
+pub fn save_manifest(
+    profile_dir: &Path,
+    manifest: &Manifest,
+) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(profile_dir)?;
+    let content = toml::to_string_pretty(manifest)?;
+    fs::write(profile_dir.join(MANIFEST_FILE), content)?;
+    Ok(())
+}
+
+/// Moves what older backups stored under each path's last component to where
+/// [`repo_relative_path`] keeps it now, in every layer of the profile, and
+/// points the manifest at the new places. Backups without a manifest are
+/// taken to hold `sources`. Returns how many items were moved.
+pub fn migrate_layout(
+    profile_dir: &Path,
+    sources: &[String],
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut manifest = match load_manifest(profile_dir)? {
+        Some(manifest) => manifest,
+        None => Manifest::from_paths(sources),
+    };
+    let mut moved = 0;
+    for entry in &mut manifest.entries {
+        let repo_path = repo_relative_path(Path::new(&entry.source))
+            .to_string_lossy()
+            .to_string();
+        if entry.repo_path == repo_path {
+            continue;
+        }
+        // encrypted files are stored with the backend's extension
+        let names = std::iter::once((entry.repo_path.clone(), repo_path.clone())).chain(
+            ENCRYPTED_EXTENSIONS.iter().map(|extension| {
+                (
+                    format!("{}.{}", entry.repo_path, extension),
+                    format!("{}.{}", repo_path, extension),
+                )
+            }),
+        );
+        for (old, new) in names {
+            for layer in layers(profile_dir) {
+                let (from, to) = (layer.join(&old), layer.join(&new));
+                if !from.exists() || to.exists() {
+                    continue;
+                }
+                // the new place can be inside the old one, e.g. `x` to `x/x`
+                let moving = layer.join(".fuxi-moving");
+                fs::rename(&from, &moving)?;
+                if let Some(parent) = to.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::rename(&moving, &to)?;
+                println!(
+                    "Moved {} to {}",
+                    style::path(from.display()),
+                    style::path(to.display())
+                );
+                moved += 1;
+            }
+        }
+        entry.repo_path = repo_path;
+    }
+    if moved > 0 {
+        save_manifest(profile_dir, &manifest)?;
+    }
+    Ok(moved)
+}
+
+/// Profiles stored at the top level of a backup repository, with their manifests.
+pub fn find_profiles(repo_path: &Path) -> Vec<(String, Manifest)> {
+    let Ok(dir) = fs::read_dir(repo_path) else {
+        return Vec::new();
+    };
+    let mut profiles: Vec<(String, Manifest)> = dir
+        .flatten()
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                return None;
+            }
+            let manifest = load_manifest(&e.path()).ok()??;
+            Some((name, manifest))
+        })
+        .collect();
+    profiles.sort_by(|a, b| a.0.cmp(&b.0));
+    profiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every file below `dir` but the manifest, by path relative to `dir`, with its content.
+    fn files(dir: &Path) -> BTreeMap<PathBuf, String> {
+        let mut found = BTreeMap::new();
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(current) = pending.pop() {
+            for entry in fs::read_dir(&current).unwrap().flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else if entry.file_name() != MANIFEST_FILE {
+                    let rel = path.strip_prefix(dir).unwrap().to_path_buf();
+                    found.insert(rel, fs::read_to_string(&path).unwrap());
+                }
+            }
+        }
+        found
+    }
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn migrates_the_legacy_layout_without_losing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let profile_dir = dir.path();
+        let home = dirs::home_dir().unwrap();
+        let sources = vec![
+            "/srv/app/config".to_string(),
+            "/etc/secret".to_string(),
+            // new place inside the old one: `tool` to `tool/tool`
+            home.join("tool/tool").to_string_lossy().to_string(),
+        ];
+        let base = profile_dir.join("base");
+        let overlay = profile_dir.join("overlays/laptop");
+        write(&base.join("config/settings.toml"), "base settings");
+        write(&base.join("config/nested/deep.txt"), "deep");
+        write(&base.join("secret.age"), "ciphertext");
+        write(&base.join("tool/run.sh"), "run");
+        write(&overlay.join("config/settings.toml"), "laptop settings");
+
+        let moved = migrate_layout(profile_dir, &sources).unwrap();
+        assert_eq!(moved, 4);
+
+        let tool = repo_relative_path(Path::new(&sources[2]));
+        let expected: BTreeMap<PathBuf, String> = [
+            ("base/_root/srv/app/config/settings.toml", "base settings"),
+            ("base/_root/srv/app/config/nested/deep.txt", "deep"),
+            ("base/_root/etc/secret.age", "ciphertext"),
+            (
+                "overlays/laptop/_root/srv/app/config/settings.toml",
+                "laptop settings",
+            ),
+        ]
+        .into_iter()
+        .map(|(path, content)| (PathBuf::from(path), content.to_string()))
+        .chain(std::iter::once((
+            Path::new("base").join(&tool).join("run.sh"),
+            "run".to_string(),
+        )))
+        .collect();
+        assert_eq!(files(profile_dir), expected);
+
+        let manifest = load_manifest(profile_dir).unwrap().unwrap();
+        for (entry, source) in manifest.entries.iter().zip(&sources) {
+            assert_eq!(entry.source, *source);
+            assert_eq!(
+                PathBuf::from(&entry.repo_path),
+                repo_relative_path(Path::new(source))
+            );
+        }
+
+        // migrating again finds everything in place
+        assert_eq!(migrate_layout(profile_dir, &sources).unwrap(), 0);
+        assert_eq!(files(profile_dir), expected);
+    }
+
+    #[test]
+    fn keeps_files_already_at_their_new_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let profile_dir = dir.path();
+        let sources = vec!["/srv/app/config".to_string()];
+        write(&profile_dir.join("config"), "old");
+        write(&profile_dir.join("_root/srv/app/config"), "new");
+
+        assert_eq!(migrate_layout(profile_dir, &sources).unwrap(), 0);
+        assert_eq!(
+            fs::read_to_string(profile_dir.join("config")).unwrap(),
+            "old"
+        );
+        assert_eq!(
+            fs::read_to_string(profile_dir.join("_root/srv/app/config")).unwrap(),
+            "new"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::git::commits_adding;
+use crate::machine::{MachineIdentity, machine_identity};
+use crate::style;
+
+pub const METADATA_DIR: &str = ".fuxi/backups";
+
+/// Stored as `.fuxi/backups/<id>.toml` in the repo for every backup.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupMetadata {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub profile: Option<String>,
+    pub paths: Vec<String>,
+    pub machine: Option<MachineIdentity>,
+    pub commit_hash: Option<String>,
+    pub description: Option<String>,
+    /// Which rules of the retention policy kept this backup when it was last applied.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub retained_by: Vec<String>,
+    /// Labels given with `fuxi backup --tag` or `fuxi tag`, e.g. `pre-upgrade`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl BackupMetadata {
+    pub fn new(
+        id: &str,
+        profile: &str,
+        paths: Vec<String>,
+        description: Option<String>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            id: id.to_string(),
+            timestamp: Utc::now(),
+            profile: Some(profile.to_string()),
+            paths,
+            machine: Some(machine_identity()?),
+            commit_hash: None,
+            description,
+            retained_by: Vec::new(),
+            tags: Vec::new(),
+        })
+    }
+}
+
+pub fn save_metadata(
+    repo_path: &Path,
+    metadata: &BackupMetadata,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = repo_path.join(METADATA_DIR);
+    fs::create_dir_all(&dir)?;
+    let content = toml::to_string_pretty(metadata)?;
+    fs::write(dir.join(format!("{}.toml", metadata.id)), content)?;
+    Ok(())
+}
+
+/// Adds `tags` to the metadata of the backup `id`. Returns the ones it
+/// didn't have yet.
+pub fn add_tags(
+    repo_path: &Path,
+    id: &str,
+    tags: &[String],
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let path = repo_path.join(METADATA_DIR).join(format!("{}.toml", id));
+    let mut metadata: BackupMetadata = toml::from_str(&fs::read_to_string(&path)?)?;
+    let mut added = Vec::new();
+    for tag in tags {
+        if !metadata.tags.contains(tag) {
+            metadata.tags.push(tag.clone());
+            added.push(tag.clone());
+        }
+    }
+    if !added.is_empty() {
+        save_metadata(repo_path, &metadata)?;
+    }
+    Ok(added)
+}
+
+pub fn load_all_metadata(
+    repo_path: &Path,
+) -> Result<Vec<BackupMetadata>, Box<dyn std::error::Error>> {
+    let dir = repo_path.join(METADATA_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut all = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("toml") {
+            continue;
+        }
+        let content = fs::read_to_string(&path)?;
+        match toml::from_str::<BackupMetadata>(&content) {
+            Ok(metadata) => all.push(metadata),
+            Err(e) => println!(
+                "{}",
+                style::warning(format!(
+                    "Warning: Invalid backup metadata {}: {}",
+                    path.display(),
+                    e
+                ))
+            ),
+        }
+    }
+    all.sort_by_key(|m| m.timestamp);
+    Ok(all)
+}
+
+/// Maps abbreviated commit hashes to the metadata of the backups they added.
+pub fn metadata_by_commit(
+    repo_path: &Path,
+) -> Result<HashMap<String, BackupMetadata>, Box<dyn std::error::Error>> {
+    let by_id: HashMap<String, BackupMetadata> = load_all_metadata(repo_path)?
+        .into_iter()
+        .map(|m| (m.id.clone(), m))
+        .collect();
+    if by_id.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let mut by_commit = HashMap::new();
+    for (hash, file) in commits_adding(repo_path, METADATA_DIR)? {
+        if let Some(id) = Path::new(&file).file_stem().and_then(|s| s.to_str())
+            && let Some(metadata) = by_id.get(id)
+        {
+            let mut metadata = metadata.clone();
+            metadata.commit_hash = Some(hash.clone());
+            by_commit.insert(hash, metadata);
+        }
+    }
+    Ok(by_commit)
+}
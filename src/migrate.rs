@@ -0,0 +1,477 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use git2::Repository;
+use yaml_rust2::{Yaml, YamlLoader};
+
+use crate::backup::create_backup;
+use crate::cfg::{FuxiConfig, PathEntry, save_config};
+use crate::cli::confirm;
+use crate::storage::snapshot_and_push;
+use crate::style;
+use crate::template::commit_message;
+
+/// Tools `fuxi migrate --from` reads.
+pub const TOOLS: [&str; 4] = ["chezmoi", "yadm", "stow", "dotbot"];
+
+/// A live path the other tool manages.
+struct Migrated {
+    path: PathBuf,
+    /// Stored encrypted by the other tool, so it becomes a fuxi secret.
+    secret: bool,
+    /// The stow package it belongs to.
+    preset: Option<String>,
+}
+
+impl Migrated {
+    fn plain(path: PathBuf) -> Self {
+        Self {
+            path,
+            secret: false,
+            preset: None,
+        }
+    }
+}
+
+fn home() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(dirs::home_dir().ok_or("Could not determine home directory")?)
+}
+
+/// `~/rest` and paths relative to the home directory, made absolute.
+fn expand_home(path: &str, home: &Path) -> PathBuf {
+    match path.strip_prefix("~/").or_else(|| path.strip_prefix("~\\")) {
+        Some(rest) => home.join(rest),
+        None if path == "~" => home.to_path_buf(),
+        None => home.join(path),
+    }
+}
+
+/// The first of `candidates` that exists.
+fn find_dir(tool: &str, candidates: &[PathBuf]) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    candidates
+        .iter()
+        .find(|dir| dir.exists())
+        .cloned()
+        .ok_or_else(|| {
+            format!(
+                "Could not find the {} directory, pass it after --from {}.",
+                tool, tool
+            )
+            .into()
+        })
+}
+
+/// The entries of `dir`, by name.
+fn sorted_entries(dir: &Path) -> std::io::Result<Vec<fs::DirEntry>> {
+    let mut entries = fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    Ok(entries)
+}
+
+/// Decodes a chezmoi source state name, like `private_dot_ssh` or
+/// `encrypted_dot_netrc.age`. Returns the target name and whether it's
+/// encrypted and a template, or None for scripts and removals.
+fn chezmoi_name(name: &str, is_dir: bool) -> Option<(String, bool, bool)> {
+    const ATTRIBUTES: [&str; 10] = [
+        "create_",
+        "modify_",
+        "symlink_",
+        "encrypted_",
+        "private_",
+        "readonly_",
+        "empty_",
+        "executable_",
+        "exact_",
+        "external_",
+    ];
+    let mut rest = name;
+    let mut encrypted = false;
+    loop {
+        if rest.starts_with("run_") || rest.starts_with("remove_") {
+            return None;
+        }
+        match ATTRIBUTES.iter().find(|a| rest.starts_with(*a)) {
+            Some(attribute) => {
+                encrypted |= *attribute == "encrypted_";
+                rest = &rest[attribute.len()..];
+            }
+            None => break,
+        }
+    }
+    let mut target = match rest.strip_prefix("literal_") {
+        Some(literal) => literal.to_string(),
+        None => match rest.strip_prefix("dot_") {
+            Some(dotted) => format!(".{}", dotted),
+            None => rest.to_string(),
+        },
+    };
+    let mut template = false;
+    if !is_dir {
+        if let Some(stripped) = target.strip_suffix(".literal") {
+            target = stripped.to_string();
+        } else {
+            if let Some(stripped) = target.strip_suffix(".tmpl") {
+                target = stripped.to_string();
+                template = true;
+            }
+            if encrypted
+                && let Some(stripped) = target
+                    .strip_suffix(".age")
+                    .or_else(|| target.strip_suffix(".asc"))
+            {
+                target = stripped.to_string();
+            }
+        }
+    }
+    Some((target, encrypted, template))
+}
+
+fn chezmoi_dir(
+    source: &Path,
+    target: &Path,
+    migrated: &mut Vec<Migrated>,
+    templates: &mut usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for entry in sorted_entries(source)? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        // chezmoi ignores its own files and everything else starting with a dot
+        if name.starts_with('.') {
+            continue;
+        }
+        let is_dir = entry.path().is_dir();
+        let Some((target_name, secret, template)) = chezmoi_name(&name, is_dir) else {
+            continue;
+        };
+        let path = target.join(target_name);
+        if is_dir && !name.starts_with("external_") {
+            chezmoi_dir(&entry.path(), &path, migrated, templates)?;
+        } else {
+            *templates += usize::from(template);
+            migrated.push(Migrated {
+                path,
+                secret,
+                preset: None,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The targets of chezmoi's source directory.
+fn from_chezmoi(dir: Option<&Path>) -> Result<Vec<Migrated>, Box<dyn std::error::Error>> {
+    let home = home()?;
+    let mut source = match dir {
+        Some(dir) => dir.to_path_buf(),
+        None => find_dir(
+            "chezmoi",
+            &[dirs::data_dir().unwrap_or_default().join("chezmoi")],
+        )?,
+    };
+    if let Ok(root) = fs::read_to_string(source.join(".chezmoiroot")) {
+        source = source.join(root.trim());
+    }
+    let mut migrated = Vec::new();
+    let mut templates = 0;
+    chezmoi_dir(&source, &home, &mut migrated, &mut templates)?;
+    if templates > 0 {
+        println!(
+            "{} chezmoi template(s) are tracked as the files they render to. Use 'fuxi path add --template' to turn them into fuxi templates.",
+            templates
+        );
+    }
+    Ok(migrated)
+}
+
+/// The files of yadm's repository. Alternates like `.zshrc##os.Linux` are
+/// tracked as the file yadm links them to.
+fn from_yadm(dir: Option<&Path>) -> Result<Vec<Migrated>, Box<dyn std::error::Error>> {
+    let home = home()?;
+    let repo_path = match dir {
+        Some(dir) => dir.to_path_buf(),
+        None => find_dir(
+            "yadm",
+            &[
+                dirs::data_dir().unwrap_or_default().join("yadm/repo.git"),
+                home.join(".config/yadm/repo.git"),
+                home.join(".yadm/repo.git"),
+            ],
+        )?,
+    };
+    let repo = Repository::open(&repo_path)
+        .map_err(|e| format!("Failed to open {}: {}", repo_path.display(), e.message()))?;
+    let mut paths: Vec<String> = Vec::new();
+    for entry in repo.index()?.iter() {
+        let path = String::from_utf8_lossy(&entry.path).to_string();
+        // yadm's own bootstrap, encrypt list and config
+        if path.starts_with(".config/yadm/") || path.starts_with(".yadm/") {
+            continue;
+        }
+        let path = path
+            .split('/')
+            .map(|part| part.split("##").next().unwrap_or(part))
+            .collect::<Vec<_>>()
+            .join("/");
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    Ok(paths
+        .into_iter()
+        .map(|path| Migrated::plain(home.join(path)))
+        .collect())
+}
+
+/// Where stow links packages to, from `--target` in a `.stowrc`, and whether
+/// `--dotfiles` turns `dot-` prefixes into dots.
+fn stow_options(stow_dir: &Path, home: &Path) -> (PathBuf, bool) {
+    let mut target = stow_dir
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| home.to_path_buf());
+    let mut dotfiles = false;
+    for stowrc in [home.join(".stowrc"), stow_dir.join(".stowrc")] {
+        let Ok(content) = fs::read_to_string(stowrc) else {
+            continue;
+        };
+        let mut words = content.split_whitespace();
+        while let Some(word) = words.next() {
+            if let Some(dir) = word.strip_prefix("--target=") {
+                target = expand_home(dir, home);
+            } else if word == "-t" || word == "--target" {
+                if let Some(dir) = words.next() {
+                    target = expand_home(dir, home);
+                }
+            } else if word == "--dotfiles" {
+                dotfiles = true;
+            }
+        }
+    }
+    (target, dotfiles)
+}
+
+fn stow_package(
+    source: &Path,
+    target: &Path,
+    dotfiles: bool,
+    package: &str,
+    migrated: &mut Vec<Migrated>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for entry in sorted_entries(source)? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if matches!(name.as_str(), ".git" | ".stow-local-ignore") {
+            continue;
+        }
+        let name = match name.strip_prefix("dot-") {
+            Some(rest) if dotfiles => format!(".{}", rest),
+            _ => name,
+        };
+        let path = target.join(name);
+        // stow links whole directories when it can, those are tracked as one
+        let folded = path.is_symlink();
+        if entry.path().is_dir() && !folded {
+            stow_package(&entry.path(), &path, dotfiles, package, migrated)?;
+        } else {
+            migrated.push(Migrated {
+                path,
+                secret: false,
+                preset: Some(package.to_string()),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The files stow links from each package of the stow directory, with the
+/// package as their preset.
+fn from_stow(dir: Option<&Path>) -> Result<Vec<Migrated>, Box<dyn std::error::Error>> {
+    let home = home()?;
+    let stow_dir = match dir {
+        Some(dir) => dir.to_path_buf(),
+        None => find_dir("stow", &[home.join("dotfiles"), home.join(".dotfiles")])?,
+    };
+    let (target, dotfiles) = stow_options(&stow_dir, &home);
+    let mut migrated = Vec::new();
+    for package in sorted_entries(&stow_dir)? {
+        if !package.path().is_dir() || package.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let name = package.file_name().to_string_lossy().to_string();
+        stow_package(&package.path(), &target, dotfiles, &name, &mut migrated)?;
+    }
+    Ok(migrated)
+}
+
+/// The files matching a dotbot glob, which only has `*` in its last part.
+fn dotbot_glob(base: &Path, pattern: &str) -> Vec<String> {
+    let (dir, file) = pattern.rsplit_once('/').unwrap_or(("", pattern));
+    let Some((prefix, suffix)) = file.split_once('*') else {
+        return vec![file.to_string()];
+    };
+    let Ok(entries) = fs::read_dir(base.join(dir)) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|name| {
+            name.len() >= prefix.len() + suffix.len()
+                && name.starts_with(prefix)
+                && name.ends_with(suffix)
+                && (prefix.starts_with('.') || !name.starts_with('.'))
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// The targets of the `link` directives in dotbot's install config.
+fn from_dotbot(dir: Option<&Path>) -> Result<Vec<Migrated>, Box<dyn std::error::Error>> {
+    let home = home()?;
+    let base = match dir {
+        Some(dir) => dir.to_path_buf(),
+        None => std::env::current_dir()?,
+    };
+    let config_path = ["install.conf.yaml", "install.conf.yml", "install.conf.json"]
+        .iter()
+        .map(|name| base.join(name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| format!("No dotbot install.conf.yaml in {}.", base.display()))?;
+    let content = fs::read_to_string(&config_path)?;
+    let documents = YamlLoader::load_from_str(&content)
+        .map_err(|e| format!("Invalid YAML in {}: {}", config_path.display(), e))?;
+
+    let mut migrated = Vec::new();
+    let directives = documents.first().and_then(Yaml::as_vec).cloned();
+    for directive in directives.unwrap_or_default() {
+        let Some(links) = directive["link"].as_hash() else {
+            continue;
+        };
+        for (target, options) in links {
+            let Some(target) = target.as_str() else {
+                continue;
+            };
+            let target = expand_home(target.trim_end_matches('/'), &home);
+            let glob = options["glob"].as_bool().unwrap_or(false);
+            let source = options
+                .as_str()
+                .or_else(|| options["path"].as_str())
+                .unwrap_or_default();
+            if glob && source.contains('*') {
+                for name in dotbot_glob(&base, source) {
+                    migrated.push(Migrated::plain(target.join(name)));
+                }
+            } else {
+                migrated.push(Migrated::plain(target));
+            }
+        }
+    }
+    Ok(migrated)
+}
+
+/// Tracks the paths `tool` manages with the selected profile, or a new
+/// `main` profile, and backs them up right away with `backup`.
+pub fn migrate(
+    config: &mut FuxiConfig,
+    tool: &str,
+    dir: Option<&Path>,
+    backup: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let migrated = match tool {
+        "chezmoi" => from_chezmoi(dir)?,
+        "yadm" => from_yadm(dir)?,
+        "stow" => from_stow(dir)?,
+        "dotbot" => from_dotbot(dir)?,
+        _ => return Err(format!("Can't migrate from '{}'.", tool).into()),
+    };
+    if migrated.is_empty() {
+        println!("{} doesn't manage any files.", tool);
+        return Ok(());
+    }
+
+    let profile = config
+        .selected_profile
+        .clone()
+        .unwrap_or_else(|| "main".to_string());
+    println!("{} manages {} path(s):", tool, migrated.len());
+    for m in &migrated {
+        let mut line = format!("  {}", m.path.display());
+        if let Some(preset) = &m.preset {
+            line.push_str(&format!("  [{}]", preset));
+        }
+        if m.secret {
+            line.push_str("  (secret)");
+        }
+        println!("{}", line);
+    }
+    if !confirm(&format!("Track them with profile '{}'?", profile))? {
+        println!("Migration cancelled.");
+        return Ok(());
+    }
+
+    let mut added = 0;
+    for m in &migrated {
+        let path = m.path.to_string_lossy().to_string();
+        let paths = config
+            .profiles
+            .get_or_insert_with(HashMap::new)
+            .entry(profile.clone())
+            .or_default();
+        if !paths.iter().any(|entry| entry.source == path) {
+            paths.push(PathEntry::new(path.clone()));
+            added += 1;
+        }
+        if let Some(preset) = &m.preset {
+            let members = config
+                .presets
+                .get_or_insert_with(HashMap::new)
+                .entry(preset.clone())
+                .or_default();
+            if !members.contains(&path) {
+                members.push(path.clone());
+            }
+        }
+        if m.secret {
+            let secrets = config.secrets.get_or_insert_with(Vec::new);
+            if !secrets.contains(&path) {
+                secrets.push(path);
+            }
+        }
+    }
+    config.selected_profile = Some(profile.clone());
+    save_config(config)?;
+    println!(
+        "Added {} path(s) to profile '{}', {} were already tracked.",
+        added,
+        profile,
+        migrated.len() - added
+    );
+    if matches!(tool, "stow" | "dotbot") {
+        println!(
+            "The live files are still links into the {} directory and fuxi backs up what they point to. Keep the directory until the links are replaced with real files.",
+            tool
+        );
+    }
+
+    if !backup {
+        println!("Run 'fuxi backup --push' to store them in the backup repository.");
+        return Ok(());
+    }
+    let backup_id = create_backup(config, None)?;
+    let message = commit_message(
+        config,
+        Some(format!("Migrate from {}", tool)),
+        Some(&backup_id),
+        migrated.len(),
+    )?;
+    snapshot_and_push(
+        config,
+        Some(message),
+        config.divergence_strategy.unwrap_or_default(),
+        false,
+    )?;
+    println!("{}", style::success("Backup pushed successfully!"));
+    Ok(())
+}
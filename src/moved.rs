@@ -0,0 +1,120 @@
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::cfg::{FuxiConfig, is_template_path};
+use crate::copy::is_skipped_vcs_dir;
+use crate::manifest::repo_relative_path;
+use crate::object_store::hash_file;
+use crate::overlay::ProfileLayout;
+use crate::secret::is_secret_path;
+
+/// How many directory levels below the home directory are searched, enough
+/// for `~/.config/<app>/<dir>`.
+const SEARCH_DEPTH: usize = 4;
+
+/// Directories that hold copies of tracked paths, which would always match.
+fn copies_dirs(config: &FuxiConfig) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = [dirs::data_dir(), dirs::cache_dir(), dirs::config_dir()]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join("fuxi"))
+        .collect();
+    dirs.extend(config.backup_repo_path.as_ref().map(PathBuf::from));
+    dirs.extend(dirs::data_dir().map(|dir| dir.join("Trash")));
+    dirs
+}
+
+/// The size and hash of a file.
+type Fingerprint = (u64, String);
+
+fn fingerprint(path: &Path) -> Option<Fingerprint> {
+    Some((fs::metadata(path).ok()?.len(), hash_file(path).ok()?))
+}
+
+/// Whether `path` has the contents of the file `fingerprint` was taken of,
+/// hashing it only when its size matches.
+fn has_contents(path: &Path, (size, hash): &Fingerprint) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| metadata.len() == *size)
+        && hash_file(path).is_ok_and(|h| h == *hash)
+}
+
+/// Whether `dir` holds every file of a backed up directory, with the same
+/// contents. Files the backup left out may be there too.
+fn same_tree(dir: &Path, stored: &[(PathBuf, Fingerprint)], top_level: &HashSet<OsString>) -> bool {
+    // most directories are ruled out by their names, without reading any file
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+    let names: HashSet<OsString> = entries.flatten().map(|entry| entry.file_name()).collect();
+    if !top_level.is_subset(&names) {
+        return false;
+    }
+    stored
+        .iter()
+        .all(|(rel, fingerprint)| has_contents(&dir.join(rel), fingerprint))
+}
+
+/// Where the tracked path `source`, which no longer exists, seems to have
+/// moved: a file or directory up to a few levels below the home directory
+/// with the same contents as its copy in the backup, e.g. `~/.config/app`
+/// for an app that stopped using `~/.appconfig`.
+pub fn find_moved(config: &FuxiConfig, layout: &ProfileLayout, source: &str) -> Option<PathBuf> {
+    // templates and secrets aren't stored as they are on disk
+    if is_template_path(config, source) || is_secret_path(config, source) {
+        return None;
+    }
+    let repo_path = repo_relative_path(Path::new(source));
+    let stored: Vec<(PathBuf, Fingerprint)> = layout
+        .resolve(&repo_path.to_string_lossy())
+        .ok()?
+        .into_iter()
+        .map(|(rel, file)| Some((rel, fingerprint(&file)?)))
+        .collect::<Option<_>>()?;
+    let file = match stored.as_slice() {
+        [] => return None,
+        [(rel, fingerprint)] if rel.as_os_str().is_empty() => Some(fingerprint),
+        _ => None,
+    };
+    let top_level: HashSet<OsString> = stored
+        .iter()
+        .filter_map(|(rel, _)| match rel.components().next() {
+            Some(Component::Normal(name)) => Some(name.to_os_string()),
+            _ => None,
+        })
+        .collect();
+
+    let skipped = copies_dirs(config);
+    let mut pending = vec![(dirs::home_dir()?, 1)];
+    while let Some((dir, depth)) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(kind) = entry.file_type() else {
+                continue;
+            };
+            if kind.is_symlink()
+                || is_skipped_vcs_dir(&entry.file_name())
+                || skipped.contains(&path)
+            {
+                continue;
+            }
+            match &file {
+                Some(file) if kind.is_file() && has_contents(&path, file) => return Some(path),
+                None if kind.is_dir() && same_tree(&path, &stored, &top_level) => {
+                    return Some(path);
+                }
+                _ => {}
+            }
+            if kind.is_dir() && depth < SEARCH_DEPTH {
+                pending.push((path, depth + 1));
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,41 @@
+use notify_rust::Notification;
+use serde::{Deserialize, Serialize};
+
+use crate::cfg::FuxiConfig;
+use crate::style;
+
+/// Which results of background backups show a desktop notification.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotificationLevel {
+    Off,
+    #[default]
+    ErrorsOnly,
+    All,
+}
+
+fn send(summary: &str, body: &str) {
+    if let Err(e) = Notification::new()
+        .appname("fuxi")
+        .summary(summary)
+        .body(body)
+        .show()
+    {
+        println!(
+            "{}",
+            style::warning(format!("Warning: Could not show notification: {}", e))
+        );
+    }
+}
+
+pub fn notify_success(config: &FuxiConfig, body: &str) {
+    if config.notifications.unwrap_or_default() == NotificationLevel::All {
+        send("fuxi backup succeeded", body);
+    }
+}
+
+pub fn notify_failure(config: &FuxiConfig, body: &str) {
+    if config.notifications.unwrap_or_default() != NotificationLevel::Off {
+        send("fuxi backup failed", body);
+    }
+}
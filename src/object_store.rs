@@ -0,0 +1,469 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::copy::collect_files;
+use crate::git::DivergenceStrategy;
+use crate::hash_cache::HashCache;
+use crate::scan::{format_findings, scan_content};
+use crate::storage::{Backend, BackupRef};
+use crate::style;
+
+/// Local bookkeeping, never part of a snapshot.
+pub const STATE_DIR: &str = ".fuxi/store";
+
+/// A flat key/value store that snapshots are uploaded to. Keys look like
+/// `objects/<hash>`, `snapshots/<id>.toml` and `index.toml`.
+pub trait ObjectStore {
+    /// Where the objects go, for messages.
+    fn describe(&self) -> String;
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>>;
+    fn exists(&self, key: &str) -> Result<bool, Box<dyn std::error::Error>>;
+    fn put(&self, key: &str, file: &Path) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Uploads the objects in `dir`, named by hash, that the store doesn't have yet.
+    fn upload_objects(
+        &self,
+        dir: &Path,
+        hashes: &[String],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for hash in hashes {
+            let key = format!("objects/{}", hash);
+            if !self.exists(&key)? {
+                self.put(&key, &dir.join(hash))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Downloads objects into `dir`, named by hash.
+    fn download_objects(
+        &self,
+        hashes: &[String],
+        dir: &Path,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for hash in hashes {
+            let body = self
+                .get(&format!("objects/{}", hash))?
+                .ok_or_else(|| format!("Object {} is missing from {}.", hash, self.describe()))?;
+            fs::write(dir.join(hash), body)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IndexEntry {
+    pub id: String,
+    pub message: String,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+/// Every snapshot in the store, oldest first.
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct Index {
+    snapshots: Vec<IndexEntry>,
+}
+
+/// The files of one snapshot, mapped to the hash of their content.
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotFiles {
+    #[serde(flatten)]
+    entry: IndexEntry,
+    files: BTreeMap<String, String>,
+}
+
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    Ok(format!("{:x}", Sha256::digest(fs::read(path)?)))
+}
+
+/// Hashes every file in `root` except git and backend bookkeeping. Files whose
+/// size and mtime didn't change since the last run reuse their cached hash.
+pub fn hash_tree(root: &Path) -> Result<BTreeMap<String, String>, Box<dyn std::error::Error>> {
+    let mut cache = HashCache::load();
+    let files = hash_tree_with(root, Some(&mut cache))?;
+    cache.save()?;
+    Ok(files)
+}
+
+/// Like `hash_tree`, but reads every file, for when the content may have
+/// changed without its metadata.
+pub fn rehash_tree(root: &Path) -> Result<BTreeMap<String, String>, Box<dyn std::error::Error>> {
+    hash_tree_with(root, None)
+}
+
+fn hash_tree_with(
+    root: &Path,
+    mut cache: Option<&mut HashCache>,
+) -> Result<BTreeMap<String, String>, Box<dyn std::error::Error>> {
+    if !root.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let root = std::path::absolute(root)?;
+    let mut files = BTreeMap::new();
+    let mut seen = HashSet::new();
+    for rel in collect_files(&root)? {
+        if rel.starts_with(".git") || rel.starts_with(STATE_DIR) {
+            continue;
+        }
+        // keys always use forward slashes so snapshots work across platforms
+        let key: Vec<String> = rel
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+        let path = root.join(&rel);
+        let hash = match cache.as_deref_mut() {
+            Some(cache) => cache.hash(&path)?,
+            None => hash_file(&path)?,
+        };
+        files.insert(key.join("/"), hash);
+        seen.insert(path);
+    }
+    if let Some(cache) = cache {
+        cache.forget_missing(&root, &seen);
+    }
+    Ok(files)
+}
+
+/// Scans the files that changed since `previous` and refuses to continue if
+/// any of them look like they contain secrets.
+pub fn check_secrets(
+    root: &Path,
+    files: &BTreeMap<String, String>,
+    previous: &BTreeMap<String, String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("Scanning for secrets...");
+    let mut findings = Vec::new();
+    for (rel, hash) in files {
+        if previous.get(rel) == Some(hash) {
+            continue;
+        }
+        // binary files can't be scanned line by line
+        if let Ok(content) = fs::read_to_string(root.join(rel)) {
+            findings.extend(scan_content(rel, &content));
+        }
+    }
+    if !findings.is_empty() {
+        return Err(format!(
+            "{}\nNo snapshot was taken. Mark these paths with 'fuxi secret add' or rerun with --allow-secrets.",
+            format_findings(&findings)
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Stores backups as content-addressed snapshots: each file's content is
+/// uploaded once under its SHA-256 hash and a snapshot maps paths to hashes.
+pub struct ObjectBackend {
+    name: &'static str,
+    local: PathBuf,
+    store: Box<dyn ObjectStore>,
+}
+
+impl ObjectBackend {
+    pub fn new(name: &'static str, local: &Path, store: Box<dyn ObjectStore>) -> Self {
+        Self {
+            name,
+            local: local.to_path_buf(),
+            store,
+        }
+    }
+
+    fn state(&self) -> PathBuf {
+        self.local.join(STATE_DIR)
+    }
+
+    fn remote_index(&self) -> Result<Index, Box<dyn std::error::Error>> {
+        match self.store.get("index.toml")? {
+            Some(body) => Ok(toml::from_str(&String::from_utf8(body)?)?),
+            None => Ok(Index::default()),
+        }
+    }
+
+    /// Snapshots recorded on this machine, including ones not pushed yet.
+    fn local_snapshots(&self) -> Result<Vec<SnapshotFiles>, Box<dyn std::error::Error>> {
+        let dir = self.state().join("snapshots");
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut snapshots = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let content = fs::read_to_string(entry?.path())?;
+            snapshots.push(toml::from_str::<SnapshotFiles>(&content)?);
+        }
+        snapshots.sort_by(|a, b| a.entry.id.cmp(&b.entry.id));
+        Ok(snapshots)
+    }
+
+    fn snapshot_path(&self, id: &str) -> PathBuf {
+        self.state().join("snapshots").join(format!("{}.toml", id))
+    }
+
+    fn load_snapshot(&self, id: &str) -> Result<SnapshotFiles, Box<dyn std::error::Error>> {
+        let local = self.snapshot_path(id);
+        let content = if local.exists() {
+            fs::read_to_string(local)?
+        } else {
+            let body = self
+                .store
+                .get(&format!("snapshots/{}.toml", id))?
+                .ok_or_else(|| {
+                    format!("Snapshot {} is missing from {}.", id, self.store.describe())
+                })?;
+            String::from_utf8(body)?
+        };
+        Ok(toml::from_str(&content)?)
+    }
+
+    fn save_snapshot(&self, snapshot: &SnapshotFiles) -> Result<(), Box<dyn std::error::Error>> {
+        let path = self.snapshot_path(&snapshot.entry.id);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, toml::to_string_pretty(snapshot)?)?;
+        Ok(())
+    }
+
+    /// The snapshot the local directory was last brought in line with.
+    fn head(&self) -> Option<String> {
+        fs::read_to_string(self.state().join("HEAD"))
+            .ok()
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+    }
+
+    fn set_head(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(self.state())?;
+        fs::write(self.state().join("HEAD"), id)?;
+        Ok(())
+    }
+
+    fn head_files(&self) -> Result<BTreeMap<String, String>, Box<dyn std::error::Error>> {
+        match self.head() {
+            Some(id) => Ok(self.load_snapshot(&id)?.files),
+            None => Ok(BTreeMap::new()),
+        }
+    }
+
+    /// Writes `snapshot` into the local directory. With `keep_local`, files
+    /// changed locally since `base` are left alone.
+    fn checkout(
+        &self,
+        snapshot: &SnapshotFiles,
+        base: &BTreeMap<String, String>,
+        keep_local: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let current = hash_tree(&self.local)?;
+        let unchanged = |rel: &str| !keep_local || current.get(rel) == base.get(rel);
+
+        let wanted: Vec<(&String, &String)> = snapshot
+            .files
+            .iter()
+            .filter(|(rel, hash)| current.get(*rel) != Some(*hash) && unchanged(rel))
+            .collect();
+        let hashes: BTreeSet<String> = wanted.iter().map(|(_, hash)| (*hash).clone()).collect();
+        let downloads = self.state().join("download");
+        fs::create_dir_all(&downloads)?;
+        self.store
+            .download_objects(&hashes.into_iter().collect::<Vec<_>>(), &downloads)?;
+        for (rel, hash) in wanted {
+            let target = self.local.join(rel);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(downloads.join(hash), target)?;
+        }
+        fs::remove_dir_all(&downloads)?;
+
+        for rel in current.keys() {
+            if !snapshot.files.contains_key(rel) && unchanged(rel) {
+                fs::remove_file(self.local.join(rel))?;
+            }
+        }
+        self.set_head(&snapshot.entry.id)
+    }
+}
+
+impl Backend for ObjectBackend {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn prepare(&self) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(&self.local)?;
+        Ok(())
+    }
+
+    fn snapshot(
+        &self,
+        message: Option<String>,
+        allow_secrets: bool,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let files = hash_tree(&self.local)?;
+        let previous = self.head_files()?;
+        if files == previous {
+            println!("No changes to snapshot.");
+            return Ok(false);
+        }
+
+        if !allow_secrets {
+            check_secrets(&self.local, &files, &previous)?;
+        }
+
+        // keep the content until it is uploaded, the files may change before the next push
+        let objects = self.state().join("objects");
+        fs::create_dir_all(&objects)?;
+        for (rel, hash) in &files {
+            let object = objects.join(hash);
+            if previous.get(rel) != Some(hash) && !object.exists() {
+                fs::copy(self.local.join(rel), object)?;
+            }
+        }
+
+        let timestamp = chrono::Utc::now();
+        let snapshot = SnapshotFiles {
+            entry: IndexEntry {
+                id: format!("snap_{}", timestamp.format("%Y%m%d_%H%M%S")),
+                message: message.unwrap_or_else(|| "Automated backup".to_string()),
+                timestamp,
+            },
+            files,
+        };
+        self.save_snapshot(&snapshot)?;
+        self.set_head(&snapshot.entry.id)?;
+        println!("Recorded snapshot {}", snapshot.entry.id);
+        Ok(true)
+    }
+
+    fn push(&self, _divergence: DivergenceStrategy) -> Result<(), Box<dyn std::error::Error>> {
+        // snapshots never conflict, so there is nothing to reconcile
+        let mut index = self.remote_index()?;
+        let pending: Vec<SnapshotFiles> = self
+            .local_snapshots()?
+            .into_iter()
+            .filter(|s| !index.snapshots.iter().any(|e| e.id == s.entry.id))
+            .collect();
+        if pending.is_empty() {
+            println!("Nothing to upload.");
+            return Ok(());
+        }
+
+        let uploaded_path = self.state().join("uploaded");
+        let mut uploaded: BTreeSet<String> = fs::read_to_string(&uploaded_path)
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        let objects = self.state().join("objects");
+        let mut hashes = BTreeSet::new();
+        for snapshot in &pending {
+            for (rel, hash) in &snapshot.files {
+                if uploaded.contains(hash) || !hashes.insert(hash.clone()) {
+                    continue;
+                }
+                if !objects.join(hash).exists() {
+                    return Err(format!(
+                        "The content of {} in snapshot {} is missing locally.",
+                        rel, snapshot.entry.id
+                    )
+                    .into());
+                }
+            }
+        }
+        println!("Uploading {} object(s)...", hashes.len());
+        let hashes: Vec<String> = hashes.into_iter().collect();
+        self.store.upload_objects(&objects, &hashes)?;
+        uploaded.extend(hashes);
+        fs::write(
+            &uploaded_path,
+            uploaded.into_iter().collect::<Vec<_>>().join("\n"),
+        )?;
+        // uploaded objects no longer need a local copy
+        let _ = fs::remove_dir_all(&objects);
+
+        for snapshot in &pending {
+            println!("Uploading snapshot {}...", snapshot.entry.id);
+            self.store.put(
+                &format!("snapshots/{}.toml", snapshot.entry.id),
+                &self.snapshot_path(&snapshot.entry.id),
+            )?;
+            index.snapshots.push(snapshot.entry.clone());
+        }
+
+        index.snapshots.sort_by(|a, b| a.id.cmp(&b.id));
+        let index_path = self.state().join("index.toml");
+        fs::write(&index_path, toml::to_string_pretty(&index)?)?;
+        self.store.put("index.toml", &index_path)?;
+        println!(
+            "{}",
+            style::success(format!(
+                "Successfully uploaded {} snapshot(s) to {}",
+                pending.len(),
+                self.store.describe()
+            ))
+        );
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<BackupRef>, Box<dyn std::error::Error>> {
+        let mut entries = self.remote_index()?.snapshots;
+        for snapshot in self.local_snapshots()? {
+            if !entries.iter().any(|e| e.id == snapshot.entry.id) {
+                entries.push(snapshot.entry);
+            }
+        }
+        entries.sort_by(|a, b| b.id.cmp(&a.id));
+        Ok(entries
+            .into_iter()
+            .map(|e| BackupRef {
+                id: e.id,
+                summary: e.message.lines().next().unwrap_or_default().to_string(),
+                tag: None,
+                created: Some(e.timestamp),
+                files_changed: None,
+                profile: None,
+                hostname: None,
+            })
+            .collect())
+    }
+
+    fn fetch(&self, id: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        let backups = self.list()?;
+        let backup = match id {
+            Some(id) => backups.iter().find(|b| b.matches(id)),
+            None => backups.first(),
+        }
+        .ok_or("No matching snapshot found.")?;
+        println!("Downloading snapshot {}...", backup.id);
+        let snapshot = self.load_snapshot(&backup.id)?;
+        self.checkout(&snapshot, &BTreeMap::new(), false)?;
+        println!(
+            "{}",
+            style::success(format!("Successfully downloaded snapshot {}", backup.id))
+        );
+        Ok(())
+    }
+
+    fn update(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(latest) = self.remote_index()?.snapshots.pop() else {
+            return Ok(());
+        };
+        if self.head().as_deref() >= Some(latest.id.as_str()) {
+            println!("Already up to date with {}", self.store.describe());
+            return Ok(());
+        }
+        println!("Downloading snapshot {}...", latest.id);
+        let snapshot = self.load_snapshot(&latest.id)?;
+        let base = self.head_files()?;
+        self.checkout(&snapshot, &base, true)
+    }
+}
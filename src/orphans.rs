@@ -0,0 +1,185 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::cfg::{FuxiConfig, get_profile_dir, get_profile_entries};
+use crate::cli::{DEFAULT_DELETE_THRESHOLD, confirm, confirm_large};
+use crate::discard::{discard, uses_trash};
+use crate::manifest::{load_manifest, repo_relative_path};
+use crate::overlay::layers;
+use crate::secret::decrypted_name;
+use crate::snapshot::dir_size;
+use crate::stats::format_size;
+use crate::storage;
+use crate::style;
+
+/// Items under `profile_dir` that no configured path of the selected profile
+/// backs up anymore, e.g. because the path was removed from the profile.
+/// Disabled paths and paths of other platforms keep their backups.
+pub fn find_orphans(
+    config: &FuxiConfig,
+    profile_dir: &Path,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let profile = config.selected_profile.as_deref().unwrap_or_default();
+    // a backup that hasn't moved to the current layout yet keeps its paths
+    // where the manifest says they are
+    let stored: HashMap<String, String> = load_manifest(profile_dir)?
+        .map(|manifest| {
+            manifest
+                .entries
+                .into_iter()
+                .map(|entry| (entry.source, entry.repo_path))
+                .collect()
+        })
+        .unwrap_or_default();
+    let tracked: HashSet<PathBuf> = get_profile_entries(config, profile)
+        .iter()
+        .flat_map(|entry| {
+            std::iter::once(repo_relative_path(Path::new(&entry.source)))
+                .chain(stored.get(&entry.source).map(PathBuf::from))
+        })
+        .collect();
+    let mut orphans = Vec::new();
+    for layer in layers(profile_dir) {
+        collect_untracked(&layer, Path::new(""), &tracked, &mut orphans);
+    }
+    orphans.sort();
+    Ok(orphans)
+}
+
+/// Collects the items of the directory `rel` inside `layer` that are neither
+/// a tracked path nor a directory holding one.
+fn collect_untracked(
+    layer: &Path,
+    rel: &Path,
+    tracked: &HashSet<PathBuf>,
+    orphans: &mut Vec<PathBuf>,
+) {
+    let Ok(entries) = fs::read_dir(layer.join(rel)) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        // manifest, checksums and captured packages
+        if rel.as_os_str().is_empty() && name.to_string_lossy().starts_with(".fuxi") {
+            continue;
+        }
+        let path = rel.join(&name);
+        let plain = decrypted_name(&path);
+        if tracked.contains(&path) || plain.is_some_and(|plain| tracked.contains(&plain)) {
+            continue;
+        }
+        if entry.path().is_dir() && tracked.iter().any(|t| t.starts_with(&path)) {
+            collect_untracked(layer, &path, tracked, orphans);
+        } else {
+            orphans.push(entry.path());
+        }
+    }
+}
+
+fn selected_profile_dir(config: &FuxiConfig) -> Option<PathBuf> {
+    let repo_path = config.backup_repo_path.as_ref()?;
+    let profile = config.selected_profile.as_ref()?;
+    Some(get_profile_dir(config, Path::new(repo_path), profile, None))
+}
+
+/// Points out orphaned items after a backup.
+pub fn warn_orphans(config: &FuxiConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(profile_dir) = selected_profile_dir(config) else {
+        return Ok(());
+    };
+    let orphans = find_orphans(config, &profile_dir)?;
+    if !orphans.is_empty() {
+        println!(
+            "{}",
+            style::warning(format!(
+                "Warning: {} item(s) in {} no longer belong to a tracked path. Run 'fuxi clean' to remove them.",
+                orphans.len(),
+                profile_dir.display()
+            ))
+        );
+    }
+    Ok(())
+}
+
+/// Deletes the orphaned items of the selected profile from the repo and
+/// records that as a backup. Returns how many were found.
+pub fn clean_orphans(
+    config: &FuxiConfig,
+    dry_run: bool,
+    yes: bool,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let Some(profile_dir) = selected_profile_dir(config) else {
+        return Ok(0);
+    };
+    let orphans = find_orphans(config, &profile_dir)?;
+    if orphans.is_empty() {
+        return Ok(0);
+    }
+
+    println!("No tracked path backs up these anymore:");
+    for orphan in &orphans {
+        let size = if orphan.is_dir() {
+            dir_size(orphan)
+        } else {
+            orphan.metadata().map(|meta| meta.len()).unwrap_or(0)
+        };
+        println!(
+            "  {:>10}  {}",
+            format_size(size),
+            style::path(orphan.display())
+        );
+    }
+    if dry_run {
+        println!(
+            "[Dry Run] Would delete {} orphaned item(s) from the repository.",
+            orphans.len()
+        );
+        return Ok(orphans.len());
+    }
+    let threshold = config
+        .confirm_delete_threshold
+        .unwrap_or(DEFAULT_DELETE_THRESHOLD);
+    let profile = config.selected_profile.as_deref().unwrap_or_default();
+    let summary = format!(
+        "Cleaning will delete {} orphaned item(s) from profile '{}'.",
+        orphans.len(),
+        profile
+    );
+    let confirmed = yes
+        || if orphans.len() > threshold {
+            confirm_large(&summary, orphans.len(), threshold, profile)?
+        } else {
+            confirm("Delete them from the repository?")?
+        };
+    if !confirmed {
+        println!("Kept the orphaned items.");
+        return Ok(orphans.len());
+    }
+
+    for orphan in &orphans {
+        discard(config, orphan)?;
+    }
+    storage::backend(config)?.snapshot(
+        Some(format!(
+            "Remove {} orphaned item(s) from {}",
+            orphans.len(),
+            config.selected_profile.as_deref().unwrap_or_default()
+        )),
+        false,
+    )?;
+    if uses_trash(config) {
+        println!(
+            "Moved {} orphaned item(s) to the trash. Their removal goes out with the next push.",
+            orphans.len()
+        );
+    } else {
+        println!(
+            "Deleted {} orphaned item(s). They go out with the next push.",
+            orphans.len()
+        );
+    }
+    Ok(orphans.len())
+}
@@ -0,0 +1,105 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::copy::collect_files;
+
+pub const BASE_DIR: &str = "base";
+pub const OVERLAYS_DIR: &str = "overlays";
+
+/// Where a profile's files live inside the repo. Profiles are flat by default;
+/// once they contain a `base/` directory, `overlays/<machine>/` is merged on
+/// top of it with the overlay winning.
+pub struct ProfileLayout {
+    pub root: PathBuf,
+    pub overlay: Option<PathBuf>,
+}
+
+/// The directories a profile stores its items in: the profile itself, or
+/// `base/` and every machine's overlay.
+pub fn layers(profile_dir: &Path) -> Vec<PathBuf> {
+    let base = profile_dir.join(BASE_DIR);
+    if !base.is_dir() {
+        return vec![profile_dir.to_path_buf()];
+    }
+    let mut layers = vec![base];
+    if let Ok(overlays) = fs::read_dir(profile_dir.join(OVERLAYS_DIR)) {
+        layers.extend(
+            overlays
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir()),
+        );
+    }
+    layers
+}
+
+/// Joins a path relative to a tracked directory, leaving tracked files untouched.
+pub fn join_rel(base: &Path, rel: &Path) -> PathBuf {
+    if rel.as_os_str().is_empty() {
+        base.to_path_buf()
+    } else {
+        base.join(rel)
+    }
+}
+
+impl ProfileLayout {
+    pub fn new(profile_dir: &Path, machine: &str) -> Self {
+        let base = profile_dir.join(BASE_DIR);
+        if base.is_dir() {
+            Self {
+                root: base,
+                overlay: Some(profile_dir.join(OVERLAYS_DIR).join(machine)),
+            }
+        } else {
+            Self::flat(profile_dir)
+        }
+    }
+
+    pub fn flat(profile_dir: &Path) -> Self {
+        Self {
+            root: profile_dir.to_path_buf(),
+            overlay: None,
+        }
+    }
+
+    fn layers(&self) -> impl Iterator<Item = &PathBuf> {
+        std::iter::once(&self.root).chain(self.overlay.iter())
+    }
+
+    pub fn exists(&self, repo_path: &str) -> bool {
+        self.layers().any(|layer| layer.join(repo_path).exists())
+    }
+
+    /// Lists the files stored for `repo_path` as `(relative path, repo file)`,
+    /// taking each file from the last layer that has it. Tracked files have an
+    /// empty relative path.
+    pub fn resolve(&self, repo_path: &str) -> std::io::Result<Vec<(PathBuf, PathBuf)>> {
+        let mut files: BTreeMap<PathBuf, PathBuf> = BTreeMap::new();
+        for layer in self.layers() {
+            let src = layer.join(repo_path);
+            if src.is_dir() {
+                for rel in collect_files(&src)? {
+                    files.insert(rel.clone(), src.join(rel));
+                }
+            } else if src.is_file() {
+                files.insert(PathBuf::new(), src);
+            }
+        }
+        Ok(files.into_iter().collect())
+    }
+
+    /// Where a backed up file belongs: this machine's overlay if it already
+    /// overrides the file there, the shared root otherwise.
+    pub fn backup_target(&self, repo_path: &str, rel: &Path) -> PathBuf {
+        if let Some(overlay) = &self.overlay {
+            let target = join_rel(&overlay.join(repo_path), rel);
+            if target.exists() {
+                return target;
+            }
+        }
+        join_rel(&self.root.join(repo_path), rel)
+    }
+}
@@ -0,0 +1,145 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::copy::defer_chown;
+use crate::style;
+
+/// The user and group owning a backed up path, by name so they can be found
+/// on machines where the IDs differ. IDs without a name are kept as numbers.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Ownership {
+    pub user: String,
+    pub group: String,
+}
+
+/// `[ownership_map]` in the config: users and groups that are called
+/// something else on this machine, e.g. `users = { alice = "alice2" }`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct OwnershipMap {
+    pub users: Option<HashMap<String, String>>,
+    pub groups: Option<HashMap<String, String>>,
+}
+
+/// `(name, id)` of the entries in `/etc/passwd` or `/etc/group`, in order.
+fn database(file: &str) -> Vec<(String, u32)> {
+    let Ok(content) = fs::read_to_string(file) else {
+        return Vec::new();
+    };
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((name.to_string(), id))
+        })
+        .collect()
+}
+
+fn name_of(database: &[(String, u32)], id: u32) -> String {
+    database
+        .iter()
+        .find(|(_, other)| *other == id)
+        .map(|(name, _)| name.clone())
+        .unwrap_or_else(|| id.to_string())
+}
+
+fn id_of(database: &[(String, u32)], name: &str) -> Option<u32> {
+    database
+        .iter()
+        .find(|(other, _)| other == name)
+        .map(|(_, id)| *id)
+        .or_else(|| name.parse().ok())
+}
+
+/// The owner of `path` when it isn't the user whose home fuxi runs in, as
+/// for system files under `/etc`. Files of that user are restored as
+/// whoever applies them, so they aren't recorded.
+#[cfg(unix)]
+pub fn ownership_of(path: &Path) -> Option<Ownership> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::symlink_metadata(path).ok()?;
+    let home = fs::metadata(dirs::home_dir()?).ok()?;
+    if metadata.uid() == home.uid() && metadata.gid() == home.gid() {
+        return None;
+    }
+    Some(Ownership {
+        user: name_of(&database("/etc/passwd"), metadata.uid()),
+        group: name_of(&database("/etc/group"), metadata.gid()),
+    })
+}
+
+#[cfg(not(unix))]
+pub fn ownership_of(_path: &Path) -> Option<Ownership> {
+    None
+}
+
+/// Gives `path` back to the recorded owner, translated by `map`. When that
+/// takes root, or the file itself is still waiting to be copied as root,
+/// it's left to [`crate::copy::run_privileged`].
+#[cfg(unix)]
+pub fn restore_ownership(
+    path: &Path,
+    ownership: &Ownership,
+    map: &OwnershipMap,
+    deferred: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let user = map
+        .users
+        .as_ref()
+        .and_then(|users| users.get(&ownership.user))
+        .unwrap_or(&ownership.user);
+    let group = map
+        .groups
+        .as_ref()
+        .and_then(|groups| groups.get(&ownership.group))
+        .unwrap_or(&ownership.group);
+    let (Some(uid), Some(gid)) = (
+        id_of(&database("/etc/passwd"), user),
+        id_of(&database("/etc/group"), group),
+    ) else {
+        println!(
+            "{}",
+            style::warning(format!(
+                "Warning: Not restoring the owner of {}, there's no {}:{} on this machine. Map them to local names in [ownership_map].",
+                path.display(),
+                user,
+                group
+            ))
+        );
+        return Ok(());
+    };
+
+    if !deferred {
+        let metadata = fs::symlink_metadata(path)?;
+        if metadata.uid() == uid && metadata.gid() == gid {
+            return Ok(());
+        }
+        match std::os::unix::fs::lchown(path, Some(uid), Some(gid)) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() != std::io::ErrorKind::PermissionDenied => {
+                return Err(
+                    format!("Failed to restore the owner of {}: {}", path.display(), e).into(),
+                );
+            }
+            Err(_) => {}
+        }
+    }
+    defer_chown(path, uid, gid);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn restore_ownership(
+    _path: &Path,
+    _ownership: &Ownership,
+    _map: &OwnershipMap,
+    _deferred: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Ok(())
+}
@@ -0,0 +1,301 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::style;
+
+pub const PACKAGES_DIR: &str = ".fuxi-packages";
+
+type StepResult = Result<(), Box<dyn std::error::Error>>;
+
+/// A package manager whose installed package list can be captured and reinstalled.
+pub struct PackageManager {
+    pub name: &'static str,
+    pub binary: &'static str,
+    pub file: &'static str,
+    capture: fn(&Path) -> StepResult,
+    install: fn(&Path, bool) -> StepResult,
+}
+
+pub const MANAGERS: [PackageManager; 6] = [
+    PackageManager {
+        name: "brew",
+        binary: "brew",
+        file: "Brewfile",
+        capture: capture_brew,
+        install: install_brew,
+    },
+    PackageManager {
+        name: "apt",
+        binary: "apt-mark",
+        file: "apt.txt",
+        capture: capture_apt,
+        install: install_apt,
+    },
+    PackageManager {
+        name: "winget",
+        binary: "winget",
+        file: "winget.json",
+        capture: capture_winget,
+        install: install_winget,
+    },
+    PackageManager {
+        name: "cargo",
+        binary: "cargo",
+        file: "cargo.txt",
+        capture: capture_cargo,
+        install: install_cargo,
+    },
+    PackageManager {
+        name: "pip",
+        binary: "python3",
+        file: "pip.txt",
+        capture: capture_pip,
+        install: install_pip,
+    },
+    PackageManager {
+        name: "npm",
+        binary: "npm",
+        file: "npm.txt",
+        capture: capture_npm,
+        install: install_npm,
+    },
+];
+
+pub fn command_exists(binary: &str) -> bool {
+    let Some(path) = env::var_os("PATH") else {
+        return false;
+    };
+    env::split_paths(&path).any(|dir| {
+        let candidate = dir.join(binary);
+        candidate.is_file() || (cfg!(windows) && candidate.with_extension("exe").is_file())
+    })
+}
+
+fn output_of(program: &str, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new(program)
+        .args(args)
+        .stderr(Stdio::piped())
+        .output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn run(program: &str, args: &[&str], dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if dry_run {
+        println!("[Dry Run] Would run: {} {}", program, args.join(" "));
+        return Ok(());
+    }
+    let status = Command::new(program).args(args).status()?;
+    if !status.success() {
+        return Err(format!("{} {} failed", program, args.join(" ")).into());
+    }
+    Ok(())
+}
+
+fn read_list(file: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    Ok(fs::read_to_string(file)?
+        .lines()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect())
+}
+
+fn capture_brew(file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let file = file.to_string_lossy();
+    output_of(
+        "brew",
+        &["bundle", "dump", "--force", "--file", file.as_ref()],
+    )?;
+    Ok(())
+}
+
+fn install_brew(file: &Path, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let file = file.to_string_lossy();
+    run(
+        "brew",
+        &["bundle", "install", "--file", file.as_ref()],
+        dry_run,
+    )
+}
+
+fn capture_apt(file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    fs::write(file, output_of("apt-mark", &["showmanual"])?)?;
+    Ok(())
+}
+
+fn install_apt(file: &Path, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let packages = read_list(file)?;
+    let mut args = vec!["apt-get", "install", "-y"];
+    args.extend(packages.iter().map(|p| p.as_str()));
+    run("sudo", &args, dry_run)
+}
+
+fn capture_winget(file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let file = file.to_string_lossy();
+    output_of(
+        "winget",
+        &["export", "-o", file.as_ref(), "--accept-source-agreements"],
+    )?;
+    Ok(())
+}
+
+fn install_winget(file: &Path, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let file = file.to_string_lossy();
+    run(
+        "winget",
+        &[
+            "import",
+            "-i",
+            file.as_ref(),
+            "--accept-package-agreements",
+            "--accept-source-agreements",
+        ],
+        dry_run,
+    )
+}
+
+fn capture_cargo(file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    // top level lines look like `ripgrep v14.1.0:`, binaries are indented below
+    let listing = output_of("cargo", &["install", "--list"])?;
+    let crates: Vec<&str> = listing
+        .lines()
+        .filter(|l| !l.starts_with(char::is_whitespace))
+        .filter_map(|l| l.split_whitespace().next())
+        .collect();
+    fs::write(file, crates.join("\n") + "\n")?;
+    Ok(())
+}
+
+fn install_cargo(file: &Path, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    for name in read_list(file)? {
+        run("cargo", &["install", &name], dry_run)?;
+    }
+    Ok(())
+}
+
+fn capture_pip(file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let packages = output_of(
+        "python3",
+        &[
+            "-m",
+            "pip",
+            "list",
+            "--user",
+            "--not-required",
+            "--format=freeze",
+        ],
+    )?;
+    fs::write(file, packages)?;
+    Ok(())
+}
+
+fn install_pip(file: &Path, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let file = file.to_string_lossy();
+    run(
+        "python3",
+        &["-m", "pip", "install", "--user", "-r", file.as_ref()],
+        dry_run,
+    )
+}
+
+fn capture_npm(file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    // the first parseable line is the global node_modules directory itself
+    let listing = output_of("npm", &["ls", "-g", "--depth=0", "--parseable"])?;
+    let packages: Vec<String> = listing
+        .lines()
+        .skip(1)
+        .filter_map(|l| l.split_once("node_modules").map(|(_, name)| name))
+        .map(|name| name.trim_start_matches(['/', '\\']).replace('\\', "/"))
+        .filter(|name| !name.is_empty() && name != "npm")
+        .collect();
+    fs::write(file, packages.join("\n") + "\n")?;
+    Ok(())
+}
+
+fn install_npm(file: &Path, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let packages = read_list(file)?;
+    let mut args = vec!["install", "-g"];
+    args.extend(packages.iter().map(|p| p.as_str()));
+    run("npm", &args, dry_run)
+}
+
+fn selected_managers(only: &[String]) -> Vec<&'static PackageManager> {
+    MANAGERS
+        .iter()
+        .filter(|m| only.is_empty() || only.iter().any(|o| o == m.name))
+        .collect()
+}
+
+/// Captures the package lists of every available manager into `dir`.
+pub fn capture_packages(
+    dir: &Path,
+    only: &[String],
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    fs::create_dir_all(dir)?;
+    let mut captured = Vec::new();
+    for manager in selected_managers(only) {
+        if !command_exists(manager.binary) {
+            continue;
+        }
+        let file = dir.join(manager.file);
+        match (manager.capture)(&file) {
+            Ok(()) => {
+                println!(
+                    "Captured {} packages to {}",
+                    manager.name,
+                    style::path(file.display())
+                );
+                captured.push(file);
+            }
+            Err(e) => println!(
+                "{}",
+                style::warning(format!(
+                    "Warning: Could not capture {} packages: {}",
+                    manager.name, e
+                ))
+            ),
+        }
+    }
+    Ok(captured)
+}
+
+/// Reinstalls every captured package list found in `dir` whose manager is available.
+pub fn install_packages(
+    dir: &Path,
+    only: &[String],
+    dry_run: bool,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut installed = 0;
+    for manager in selected_managers(only) {
+        let file = dir.join(manager.file);
+        if !file.exists() {
+            continue;
+        }
+        if !command_exists(manager.binary) {
+            println!(
+                "Skipping {} packages: '{}' is not installed.",
+                manager.name, manager.binary
+            );
+            continue;
+        }
+        println!(
+            "Installing {} packages from {}",
+            manager.name,
+            style::path(file.display())
+        );
+        (manager.install)(&file, dry_run)?;
+        installed += 1;
+    }
+    Ok(installed)
+}
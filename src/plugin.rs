@@ -0,0 +1,301 @@
+use std::{
+    env,
+    ffi::OsString,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+};
+
+use serde_json::{Value, json};
+
+use crate::cfg::{FuxiConfig, get_config_path};
+use crate::exit::config_error;
+use crate::object_store::ObjectStore;
+use crate::script;
+use crate::style;
+
+/// Events `[hooks]` programs can run on.
+pub const HOOK_EVENTS: [&str; 5] = [
+    "pre-backup",
+    "post-backup",
+    "pre-apply",
+    "post-apply",
+    "post-push",
+];
+
+/// The exit code a store plugin returns for a key it doesn't have.
+const NOT_FOUND: i32 = 3;
+
+/// Tells a plugin about the fuxi running it, through `FUXI_*` variables.
+fn plugin_command(config: &FuxiConfig, program: impl AsRef<std::ffi::OsStr>) -> Command {
+    let mut command = Command::new(program);
+    command.env("FUXI_VERSION", env!("CARGO_PKG_VERSION"));
+    if let Ok(exe) = env::current_exe() {
+        command.env("FUXI_BIN", exe);
+    }
+    if let Ok(path) = get_config_path() {
+        command.env("FUXI_CONFIG", path);
+    }
+    if let Some(profile) = &config.selected_profile {
+        command.env("FUXI_PROFILE", profile);
+    }
+    if let Some(repo) = &config.backup_repo_path {
+        command.env("FUXI_REPO", repo);
+    }
+    command
+}
+
+/// Runs one hook program with `payload` on its stdin.
+fn run_hook(
+    config: &FuxiConfig,
+    event: &str,
+    program: &str,
+    payload: &Value,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut child = plugin_command(config, program)
+        .env("FUXI_EVENT", event)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run {} hook {}: {}", event, program, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // a hook that doesn't read its input is fine
+        let _ = writeln!(stdin, "{}", payload);
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(format!("{} hook {} failed with {}.", event, program, status).into());
+    }
+    Ok(())
+}
+
+/// Runs the hook script's function for `event`, then the programs configured
+/// for it in `[hooks]` with the event and `details` as JSON on stdin. A failing
+/// `pre-*` hook stops the command, other failures are only reported.
+pub fn run_hooks(
+    config: &FuxiConfig,
+    event: &str,
+    details: Value,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut payload = json!({
+        "event": event,
+        "profile": config.selected_profile,
+        "repository": config.backup_repo_path,
+    });
+    if let (Some(payload), Value::Object(details)) = (payload.as_object_mut(), details) {
+        payload.extend(details);
+    }
+    let programs = config.hooks.as_ref().and_then(|hooks| hooks.get(event));
+    let results = std::iter::once(script::run_event(config, event, &payload)).chain(
+        programs
+            .into_iter()
+            .flatten()
+            .map(|program| run_hook(config, event, program, &payload)),
+    );
+    for result in results {
+        match result {
+            Ok(()) => {}
+            Err(e) if event.starts_with("pre-") => return Err(e),
+            Err(e) => println!("{}", style::warning(format!("Warning: {}", e))),
+        }
+    }
+    Ok(())
+}
+
+/// The directories of `PATH`.
+fn path_dirs() -> Vec<PathBuf> {
+    env::var_os("PATH")
+        .map(|path| env::split_paths(&path).collect())
+        .unwrap_or_default()
+}
+
+/// Every `fuxi-<name>` program on `PATH`, by name. Store plugins are named
+/// `fuxi-store-<name>`.
+pub fn find_plugins() -> Vec<(String, PathBuf)> {
+    let mut plugins: Vec<(String, PathBuf)> = Vec::new();
+    for dir in path_dirs() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let name = if cfg!(windows) {
+                file_name
+                    .strip_suffix(".exe")
+                    .or_else(|| file_name.strip_suffix(".cmd"))
+                    .or_else(|| file_name.strip_suffix(".bat"))
+                    .unwrap_or(&file_name)
+            } else {
+                &file_name
+            };
+            let Some(name) = name.strip_prefix("fuxi-") else {
+                continue;
+            };
+            if !path.is_file() || !is_executable(&path) {
+                continue;
+            }
+            // the first one on PATH wins, like the shell
+            if !plugins.iter().any(|(n, _)| n == name) {
+                plugins.push((name.to_string(), path));
+            }
+        }
+    }
+    plugins.sort();
+    plugins
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    true
+}
+
+/// Prints the plugins found on `PATH`, the hook script and the hooks in the config.
+pub fn list_plugins(config: &FuxiConfig) {
+    let plugins = find_plugins();
+    if plugins.is_empty() {
+        println!("No fuxi-<name> plugins found on PATH.");
+    } else {
+        println!("Plugins on PATH:");
+        for (name, path) in &plugins {
+            match name.strip_prefix("store-") {
+                Some(store) => println!(
+                    "  {:<20} backend = \"plugin:{}\"  ({})",
+                    name,
+                    store,
+                    style::path(path.display())
+                ),
+                None => println!(
+                    "  {:<20} fuxi {}  ({})",
+                    name,
+                    name,
+                    style::path(path.display())
+                ),
+            }
+        }
+    }
+
+    if let Some(path) = script::script_path(config) {
+        println!("Hook script: {}", style::path(path.display()));
+    }
+    let hooks = config.hooks.clone().unwrap_or_default();
+    if hooks.is_empty() {
+        println!("No hooks configured.");
+        return;
+    }
+    println!("Hooks:");
+    let mut events: Vec<_> = hooks.iter().collect();
+    events.sort();
+    for (event, programs) in events {
+        let unknown = if HOOK_EVENTS.contains(&event.as_str()) {
+            ""
+        } else {
+            "  (unknown event, never runs)"
+        };
+        println!("  {}: {}{}", event, programs.join(", "), unknown);
+    }
+}
+
+/// Runs `fuxi-<name>` from `PATH` for `fuxi <name>`, like git does for its
+/// subcommands, and exits with its exit code.
+pub fn run_external(
+    config: &FuxiConfig,
+    name: &str,
+    args: &[OsString],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let program = format!("fuxi-{}", name);
+    let status = match plugin_command(config, &program).args(args).status() {
+        Ok(status) => status,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(config_error(format!(
+                "'{}' is not a fuxi command, and no {} was found on PATH. See 'fuxi --help'.",
+                name, program
+            )));
+        }
+        Err(e) => return Err(format!("Failed to run {}: {}", program, e).into()),
+    };
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+/// Stores backups through a `fuxi-store-<name>` program, for
+/// `backend = "plugin:<name>"`. It's run as `fuxi-store-<name> get|put|exists
+/// <key>`: `get` writes the object to stdout, `put` reads it from stdin, and
+/// a key that doesn't exist makes `get` and `exists` exit with 3.
+pub struct PluginStore {
+    config: FuxiConfig,
+    program: String,
+}
+
+impl PluginStore {
+    pub fn new(config: &FuxiConfig, name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        if name.is_empty() {
+            return Err(
+                "The plugin backend needs a name, e.g. backend = \"plugin:restic\" runs fuxi-store-restic."
+                    .into(),
+            );
+        }
+        Ok(Self {
+            config: config.clone(),
+            program: format!("fuxi-store-{}", name),
+        })
+    }
+
+    fn run(
+        &self,
+        op: &str,
+        key: &str,
+        input: Option<&Path>,
+    ) -> Result<Output, Box<dyn std::error::Error>> {
+        let stdin = match input {
+            Some(file) => Stdio::from(std::fs::File::open(file)?),
+            None => Stdio::null(),
+        };
+        let output = plugin_command(&self.config, &self.program)
+            .args([op, key])
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|e| format!("Failed to run {} (is it on PATH?): {}", self.program, e))?;
+        if !output.status.success() && output.status.code() != Some(NOT_FOUND) {
+            return Err(format!(
+                "{} {} failed: {}",
+                self.program,
+                op,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        Ok(output)
+    }
+}
+
+impl ObjectStore for PluginStore {
+    fn describe(&self) -> String {
+        self.program.clone()
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        let output = self.run("get", key, None)?;
+        Ok(output.status.success().then_some(output.stdout))
+    }
+
+    fn exists(&self, key: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(self.run("exists", key, None)?.status.success())
+    }
+
+    fn put(&self, key: &str, file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let output = self.run("put", key, Some(file))?;
+        if !output.status.success() {
+            return Err(format!("{} put {} failed.", self.program, key).into());
+        }
+        Ok(())
+    }
+}
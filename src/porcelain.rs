@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Commands that print records instead of their usual output with `--porcelain`.
+pub const COMMANDS: [&str; 7] = [
+    "version",
+    "config",
+    "profile list",
+    "path list",
+    "list",
+    "search",
+    "log",
+];
+
+/// Whether `--porcelain` asked for output meant for scripts. Set once the
+/// command line is parsed.
+static PORCELAIN: AtomicBool = AtomicBool::new(false);
+
+pub fn set_enabled(enabled: bool) {
+    PORCELAIN.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    PORCELAIN.load(Ordering::Relaxed)
+}
+
+/// Escapes the characters that would split a field or a record.
+pub fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Prints one record: its kind, then its fields, separated by tabs.
+pub fn record(kind: &str, fields: &[&str]) {
+    let mut line = kind.to_string();
+    for field in fields {
+        line.push('\t');
+        line.push_str(&escape(field));
+    }
+    println!("{}", line);
+}
@@ -0,0 +1,276 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::manifest::ManifestEntry;
+use crate::overlay::layers;
+use crate::style;
+
+/// Characters Windows doesn't allow in file names.
+const INVALID_CHARS: [char; 8] = ['<', '>', ':', '"', '|', '?', '*', '\\'];
+
+/// Device names Windows reserves, with any extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn is_reserved(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name);
+    RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+}
+
+/// Why Windows can't store a file called `name`, if it can't.
+pub fn invalid_reason(name: &str) -> Option<&'static str> {
+    if name
+        .chars()
+        .any(|c| INVALID_CHARS.contains(&c) || c.is_control())
+    {
+        Some("it contains a character Windows doesn't allow")
+    } else if name.ends_with(['.', ' ']) {
+        Some("it ends with a dot or a space")
+    } else if is_reserved(name) {
+        Some("it's a device name Windows reserves")
+    } else {
+        None
+    }
+}
+
+/// `name` with the characters that make it invalid on Windows written as
+/// `%XX`, like a URL, or `None` when it's valid as is. `%` itself is encoded
+/// too, so the encoding can be reversed.
+pub fn encode_name(name: &str) -> Option<String> {
+    invalid_reason(name)?;
+    let trailing = name.len() - name.trim_end_matches(['.', ' ']).len();
+    let mut encoded = String::with_capacity(name.len() * 3);
+    for (i, c) in name.char_indices() {
+        let escape = INVALID_CHARS.contains(&c)
+            || c.is_control()
+            || c == '%'
+            || i >= name.len() - trailing
+            || (i == 0 && is_reserved(name));
+        if escape {
+            encoded.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            encoded.push(c);
+        }
+    }
+    Some(encoded)
+}
+
+/// Where a file stored at `rel` below an entry's repo path is restored to,
+/// relative to its source. Windows can't have the original names, so files
+/// keep their encoded names there.
+pub fn decode_path(entry: &ManifestEntry, rel: &Path) -> PathBuf {
+    if entry.encoded_names.is_empty() || cfg!(windows) {
+        return rel.to_path_buf();
+    }
+    let mut stored = String::new();
+    let mut decoded = PathBuf::new();
+    for component in rel.components() {
+        let name = component.as_os_str().to_string_lossy();
+        if !stored.is_empty() {
+            stored.push('/');
+        }
+        stored.push_str(&name);
+        match entry.encoded_names.get(&stored) {
+            Some(original) => decoded.push(original),
+            None => decoded.push(component),
+        }
+    }
+    decoded
+}
+
+/// `name` below the directory at `stored`, relative to an entry's repo path.
+fn stored_path(stored: &str, name: &str) -> String {
+    if stored.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", stored, name)
+    }
+}
+
+/// Encodes or warns about the names below one directory of an entry, then
+/// the directories below it. `stored` is the directory's path relative to the
+/// entry's repo path, and `previous` the names the last backup encoded.
+fn check_dir(
+    dir: &Path,
+    stored: &str,
+    previous: &BTreeMap<String, String>,
+    encode: bool,
+    encoded_names: &mut BTreeMap<String, String>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return Ok(0);
+    };
+    let mut names: Vec<String> = read_dir
+        .flatten()
+        .map(|item| item.file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+
+    let mut encoded = 0;
+    let mut kept = Vec::with_capacity(names.len());
+    for name in names {
+        let Some(encoded_name) = encode_name(&name) else {
+            // encoded by an older backup, unless this one kept the real name
+            if let Some(original) = previous.get(&stored_path(stored, &name))
+                && (encode || !dir.join(original).exists())
+            {
+                encoded_names.insert(stored_path(stored, &name), original.clone());
+            }
+            if !kept.contains(&name) {
+                kept.push(name);
+            }
+            continue;
+        };
+        let reason = invalid_reason(&name).unwrap_or_default();
+        let (from, to) = (dir.join(&name), dir.join(&encoded_name));
+        let known = previous.contains_key(&stored_path(stored, &encoded_name));
+        // the copy just backed up replaces the one an older backup encoded
+        if (encode || known) && to.is_dir() {
+            fs::remove_dir_all(&to)?;
+        } else if (encode || known) && to.exists() {
+            fs::remove_file(&to)?;
+        }
+        if !encode {
+            println!(
+                "{}",
+                style::warning(format!(
+                    "Warning: {} can't be checked out on Windows, {}.",
+                    from.display(),
+                    reason
+                ))
+            );
+            kept.retain(|kept| *kept != encoded_name);
+            kept.push(name);
+            continue;
+        }
+        fs::rename(&from, &to)?;
+        if !known {
+            println!(
+                "Stored {} as {}, {}",
+                style::path(from.display()),
+                encoded_name,
+                reason
+            );
+        }
+        encoded_names.insert(stored_path(stored, &encoded_name), name);
+        encoded += 1;
+        if !kept.contains(&encoded_name) {
+            kept.push(encoded_name);
+        }
+    }
+
+    let mut by_case: HashMap<String, Vec<&String>> = HashMap::new();
+    for name in &kept {
+        by_case.entry(name.to_lowercase()).or_default().push(name);
+    }
+    let mut collisions: Vec<_> = by_case.values().filter(|names| names.len() > 1).collect();
+    collisions.sort();
+    for names in collisions {
+        let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+        println!(
+            "{}",
+            style::warning(format!(
+                "Warning: {} in {} differ only by case, Windows and macOS can only check out one of them.",
+                names.join(" and "),
+                dir.display()
+            ))
+        );
+    }
+
+    for name in &kept {
+        let path = dir.join(name);
+        if !path.is_dir() {
+            continue;
+        }
+        let stored = stored_path(stored, name);
+        encoded += check_dir(&path, &stored, previous, encode, encoded_names)?;
+    }
+    Ok(encoded)
+}
+
+/// Looks for names in the backed up copy of `entry`, in every layer of the
+/// profile, that a Windows checkout of the repository would fail on: names
+/// Windows doesn't allow, and names that differ only by case. The invalid
+/// ones are encoded with [`encode_name`] and recorded in the entry when
+/// `encode` is set, and only warned about otherwise. `previous` is the entry
+/// in the last backup's manifest. Returns how many names were encoded.
+pub fn make_portable(
+    profile_dir: &Path,
+    entry: &mut ManifestEntry,
+    previous: Option<&ManifestEntry>,
+    encode: bool,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    // the entry's own path is where the manifest looks for it, so it stays
+    for component in Path::new(&entry.repo_path).components() {
+        if let Component::Normal(name) = component
+            && let Some(reason) = invalid_reason(&name.to_string_lossy())
+        {
+            println!(
+                "{}",
+                style::warning(format!(
+                    "Warning: {} can't be checked out on Windows, {}. Rename it to back it up portably.",
+                    entry.source, reason
+                ))
+            );
+            break;
+        }
+    }
+
+    let previous = previous
+        .map(|previous| previous.encoded_names.clone())
+        .unwrap_or_default();
+    let mut encoded_names = BTreeMap::new();
+    let mut encoded = 0;
+    for layer in layers(profile_dir) {
+        let root = layer.join(&entry.repo_path);
+        if root.is_dir() {
+            encoded += check_dir(&root, "", &previous, encode, &mut encoded_names)?;
+        }
+    }
+    entry.encoded_names = encoded_names;
+    Ok(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_names_windows_rejects() {
+        assert_eq!(encode_name("notes.md"), None);
+        assert_eq!(encode_name("a:b?").as_deref(), Some("a%3Ab%3F"));
+        assert_eq!(encode_name("100%:").as_deref(), Some("100%25%3A"));
+        assert_eq!(encode_name("draft..").as_deref(), Some("draft%2E%2E"));
+        assert_eq!(encode_name("aux.h").as_deref(), Some("%61ux.h"));
+        assert_eq!(encode_name("auxiliary"), None);
+    }
+
+    #[test]
+    fn encoded_names_are_valid_and_distinct() {
+        let names = [
+            "a:b?",
+            "a%3Ab?",
+            "draft. ",
+            "draft%2E ",
+            "CON",
+            "x<y>|\"z\"*",
+        ];
+        let encoded: Vec<String> = names
+            .iter()
+            .map(|name| encode_name(name).unwrap())
+            .collect();
+        for name in &encoded {
+            assert!(invalid_reason(name).is_none(), "{}", name);
+        }
+        for (i, name) in encoded.iter().enumerate() {
+            assert!(!encoded[..i].contains(name), "{}", name);
+        }
+    }
+}
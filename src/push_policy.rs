@@ -0,0 +1,110 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::cfg::{FuxiConfig, repo_path};
+
+const DEFAULT_BATCH_SIZE: u32 = 5;
+
+/// When backups are pushed without `--push`, the `push_policy` setting.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PushPolicy {
+    EveryBackup,
+    /// Once `push_batch_size` backups are waiting.
+    Batch,
+    /// Once a day, with the first backup after the last push is a day old.
+    Daily,
+    Never,
+}
+
+/// Backups made and the last push, for one backup directory.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct PushState {
+    #[serde(default)]
+    unpushed: u32,
+    last_push: Option<DateTime<Utc>>,
+}
+
+fn state_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let data_dir = dirs::data_dir().ok_or("Could not determine data directory")?;
+    Ok(data_dir.join("fuxi").join("push-state.toml"))
+}
+
+fn load_states() -> HashMap<String, PushState> {
+    state_path()
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| toml::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Changes the state of this config's backup directory with `update`.
+fn update_state(
+    config: &FuxiConfig,
+    update: impl FnOnce(&mut PushState),
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo_path = repo_path(config)?;
+    let mut states = load_states();
+    update(states.entry(repo_path.to_string()).or_default());
+    let path = state_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, toml::to_string_pretty(&states)?)?;
+    Ok(())
+}
+
+/// Counts a backup towards the next push.
+pub fn record_backup(config: &FuxiConfig) -> Result<(), Box<dyn std::error::Error>> {
+    update_state(config, |state| state.unpushed += 1)
+}
+
+/// Notes that every backup made so far reached the remote.
+pub fn record_push(config: &FuxiConfig) -> Result<(), Box<dyn std::error::Error>> {
+    update_state(config, |state| {
+        state.unpushed = 0;
+        state.last_push = Some(Utc::now());
+    })
+}
+
+/// Whether the backup just made should be pushed. Without a `push_policy`
+/// that's `default`: the `--push` of watch and the daemon, or `--auto-push`
+/// of scheduled backups. Says why when the policy holds the push back.
+pub fn push_due(config: &FuxiConfig, default: bool) -> bool {
+    let Some(policy) = config.push_policy else {
+        return default;
+    };
+    let state = repo_path(config)
+        .ok()
+        .and_then(|repo_path| load_states().remove(repo_path))
+        .unwrap_or_default();
+    let batch_size = config.push_batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1);
+    match policy {
+        PushPolicy::EveryBackup => true,
+        PushPolicy::Batch if state.unpushed >= batch_size => true,
+        PushPolicy::Batch => {
+            println!(
+                "Not pushing yet, push_policy 'batch' waits for {} backups, {} made since the last push.",
+                batch_size, state.unpushed
+            );
+            false
+        }
+        PushPolicy::Daily
+            if state
+                .last_push
+                .is_none_or(|last| Utc::now() - last >= TimeDelta::days(1)) =>
+        {
+            true
+        }
+        PushPolicy::Daily => {
+            println!("Not pushing yet, push_policy 'daily' pushed less than a day ago.");
+            false
+        }
+        PushPolicy::Never => {
+            println!("Not pushing, push_policy is 'never'. Push with 'fuxi save'.");
+            false
+        }
+    }
+}
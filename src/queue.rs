@@ -0,0 +1,106 @@
+use std::{fs, path::PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::cfg::{FuxiConfig, repo_path};
+use crate::git::DivergenceStrategy;
+use crate::push_policy::record_push;
+use crate::storage;
+
+/// A push that failed because the remote was unreachable. What it would have
+/// pushed is already committed in the local backup directory.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QueuedPush {
+    pub repo_path: String,
+    pub queued_at: DateTime<Utc>,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct Queue {
+    #[serde(default)]
+    pushes: Vec<QueuedPush>,
+}
+
+fn queue_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let data_dir = dirs::data_dir().ok_or("Could not determine data directory")?;
+    Ok(data_dir.join("fuxi").join("push-queue.toml"))
+}
+
+fn load_queue() -> Queue {
+    queue_path()
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| toml::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_queue(queue: &Queue) -> Result<(), Box<dyn std::error::Error>> {
+    let path = queue_path()?;
+    if queue.pushes.is_empty() {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, toml::to_string_pretty(queue)?)?;
+    Ok(())
+}
+
+/// The push waiting for this config's backup directory, if any.
+pub fn queued_push(config: &FuxiConfig) -> Option<QueuedPush> {
+    let repo_path = config.backup_repo_path.as_deref()?;
+    load_queue()
+        .pushes
+        .into_iter()
+        .find(|push| push.repo_path == repo_path)
+}
+
+/// Records that the backup directory has commits the remote is missing. One
+/// entry per directory is enough, pushing it sends every commit.
+pub fn enqueue(config: &FuxiConfig, reason: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let repo_path = repo_path(config)?;
+    let mut queue = load_queue();
+    queue.pushes.retain(|push| push.repo_path != repo_path);
+    queue.pushes.push(QueuedPush {
+        repo_path: repo_path.to_string(),
+        queued_at: Utc::now(),
+        reason: reason.to_string(),
+    });
+    save_queue(&queue)
+}
+
+/// Forgets the queued push of this config's backup directory once it made it.
+pub fn dequeue(config: &FuxiConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let repo_path = repo_path(config)?;
+    let mut queue = load_queue();
+    let before = queue.pushes.len();
+    queue.pushes.retain(|push| push.repo_path != repo_path);
+    if queue.pushes.len() != before {
+        save_queue(&queue)?;
+    }
+    Ok(())
+}
+
+/// Pushes the queued commits, if any. Returns whether there was anything to push.
+pub fn flush(
+    config: &FuxiConfig,
+    divergence: DivergenceStrategy,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let Some(queued) = queued_push(config) else {
+        return Ok(false);
+    };
+    println!(
+        "Pushing backups queued while offline since {}...",
+        queued.queued_at.format("%Y-%m-%d %H:%M")
+    );
+    storage::backend(config)?.push(divergence)?;
+    dequeue(config)?;
+    record_push(config)?;
+    println!("Queued backups pushed.");
+    Ok(true)
+}
@@ -0,0 +1,156 @@
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Output, Stdio},
+};
+
+use serde_json::Value;
+
+use crate::object_store::ObjectStore;
+
+/// rclone's exit code when a directory or file doesn't exist.
+const NOT_FOUND: i32 = 3;
+
+/// Stores backups under an rclone `remote:path`, so any provider rclone
+/// supports can hold them.
+pub struct RcloneStore {
+    remote: String,
+}
+
+impl RcloneStore {
+    pub fn new(remote: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        if remote.is_empty() {
+            return Err(
+                "The rclone backend needs a target, e.g. backend = \"rclone:drive:dotfiles\"."
+                    .into(),
+            );
+        }
+        Ok(Self {
+            remote: remote.trim_end_matches('/').to_string(),
+        })
+    }
+
+    fn path(&self, key: &str) -> String {
+        format!("{}/{}", self.remote, key)
+    }
+
+    fn rclone(
+        &self,
+        args: &[&str],
+        input: Option<&str>,
+    ) -> Result<Output, Box<dyn std::error::Error>> {
+        let mut child = Command::new("rclone")
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to run rclone (is it installed?): {}", e))?;
+        if let Some(input) = input {
+            child
+                .stdin
+                .take()
+                .ok_or("Failed to open rclone stdin")?
+                .write_all(input.as_bytes())?;
+        }
+        Ok(child.wait_with_output()?)
+    }
+
+    fn run(
+        &self,
+        args: &[&str],
+        input: Option<&str>,
+    ) -> Result<Output, Box<dyn std::error::Error>> {
+        let output = self.rclone(args, input)?;
+        if !output.status.success() {
+            return Err(format!(
+                "rclone {} failed: {}",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        Ok(output)
+    }
+}
+
+impl ObjectStore for RcloneStore {
+    fn describe(&self) -> String {
+        self.remote.clone()
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        if !self.exists(key)? {
+            return Ok(None);
+        }
+        Ok(Some(self.run(&["cat", &self.path(key)], None)?.stdout))
+    }
+
+    fn exists(&self, key: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let output = self.rclone(&["lsjson", "--files-only", &self.path(key)], None)?;
+        if output.status.code() == Some(NOT_FOUND) {
+            return Ok(false);
+        }
+        if !output.status.success() {
+            return Err(format!(
+                "rclone lsjson failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        let files: Vec<Value> = serde_json::from_slice(&output.stdout)?;
+        Ok(!files.is_empty())
+    }
+
+    fn put(&self, key: &str, file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        self.run(&["copyto", &file.to_string_lossy(), &self.path(key)], None)?;
+        Ok(())
+    }
+
+    // one rclone call per batch instead of per object, which matters for slow providers
+    fn upload_objects(
+        &self,
+        dir: &Path,
+        hashes: &[String],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if hashes.is_empty() {
+            return Ok(());
+        }
+        self.run(
+            &[
+                "copy",
+                "--ignore-existing",
+                "--files-from-raw",
+                "-",
+                &dir.to_string_lossy(),
+                &self.path("objects"),
+            ],
+            Some(&hashes.join("\n")),
+        )?;
+        Ok(())
+    }
+
+    fn download_objects(
+        &self,
+        hashes: &[String],
+        dir: &Path,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if hashes.is_empty() {
+            return Ok(());
+        }
+        self.run(
+            &[
+                "copy",
+                "--files-from-raw",
+                "-",
+                &self.path("objects"),
+                &dir.to_string_lossy(),
+            ],
+            Some(&hashes.join("\n")),
+        )?;
+        if let Some(missing) = hashes.iter().find(|hash| !dir.join(hash).exists()) {
+            return Err(format!("Object {} is missing from {}.", missing, self.remote).into());
+        }
+        Ok(())
+    }
+}
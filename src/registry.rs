@@ -0,0 +1,177 @@
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::cfg::FuxiConfig;
+use crate::copy::tolerate;
+use crate::snapshot::Snapshot;
+use crate::style;
+
+/// Where exported keys are kept in a profile, one `.reg` file per key.
+pub const REGISTRY_DIR: &str = ".fuxi-registry";
+
+const HIVES: [&str; 10] = [
+    "HKLM",
+    "HKEY_LOCAL_MACHINE",
+    "HKCU",
+    "HKEY_CURRENT_USER",
+    "HKCR",
+    "HKEY_CLASSES_ROOT",
+    "HKU",
+    "HKEY_USERS",
+    "HKCC",
+    "HKEY_CURRENT_CONFIG",
+];
+
+/// Checks that `key` starts with a registry hive, e.g. `HKCU\Software\Foo`.
+pub fn normalize_key(key: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let key = key.trim().trim_end_matches('\\').replace('/', "\\");
+    let hive = key.split('\\').next().unwrap_or_default();
+    if !HIVES.iter().any(|h| h.eq_ignore_ascii_case(hive)) {
+        return Err(format!(
+            "'{}' is not a registry key, it must start with a hive such as HKCU or HKLM.",
+            key
+        )
+        .into());
+    }
+    Ok(key)
+}
+
+/// The registry keys tracked by `profile`.
+pub fn profile_keys(config: &FuxiConfig, profile: &str) -> Vec<String> {
+    config
+        .registry_keys
+        .as_ref()
+        .and_then(|keys| keys.get(profile))
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn file_name(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}.reg", name)
+}
+
+fn reg(args: &[&std::ffi::OsStr]) -> Result<(), String> {
+    let output = Command::new("reg")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to run reg: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(())
+}
+
+/// Exports `keys` to `.reg` files in `root` and returns the ones that were.
+/// Outside Windows there's no registry, so nothing is exported.
+pub fn export_keys(
+    root: &Path,
+    keys: &[String],
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    if keys.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !cfg!(windows) {
+        println!(
+            "Skipped {} registry key(s), the registry only exists on Windows.",
+            keys.len()
+        );
+        return Ok(Vec::new());
+    }
+
+    let dir = root.join(REGISTRY_DIR);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+    let mut exported = Vec::new();
+    for key in keys {
+        let file = dir.join(file_name(key));
+        match reg(&[
+            "export".as_ref(),
+            key.as_ref(),
+            file.as_os_str(),
+            "/y".as_ref(),
+        ]) {
+            Ok(()) => {
+                println!("Exported registry key {}", key);
+                exported.push(key.clone());
+            }
+            Err(e) => tolerate(Path::new(key), e)?,
+        }
+    }
+    Ok(exported)
+}
+
+/// Imports the `.reg` files of `keys` from `root`, after exporting the
+/// values they replace to a snapshot.
+pub fn import_keys(
+    root: &Path,
+    keys: &[String],
+    backup_id: &str,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if keys.is_empty() {
+        return Ok(());
+    }
+    if !cfg!(windows) {
+        println!(
+            "Skipped {} registry key(s), they can only be imported on Windows.",
+            keys.len()
+        );
+        return Ok(());
+    }
+
+    let snapshot = Snapshot::new(&format!("{}_registry", backup_id))?;
+    let previous = snapshot.dir.join("registry");
+    for key in keys {
+        let file = root.join(REGISTRY_DIR).join(file_name(key));
+        if !file.is_file() {
+            println!(
+                "{}",
+                style::warning(format!(
+                    "Warning: Registry key {} is missing from the backup.",
+                    key
+                ))
+            );
+            continue;
+        }
+        if dry_run {
+            println!("[Dry Run] Would import registry key {}", key);
+            continue;
+        }
+
+        fs::create_dir_all(&previous)?;
+        let saved = previous.join(file_name(key));
+        // a key that doesn't exist yet has nothing to save
+        let _ = reg(&[
+            "export".as_ref(),
+            key.as_ref(),
+            saved.as_os_str(),
+            "/y".as_ref(),
+        ]);
+        reg(&["import".as_ref(), file.as_os_str()])
+            .map_err(|e| format!("Failed to import registry key {}: {}", key, e))?;
+        println!("Imported registry key {}", key);
+    }
+    if previous.exists() {
+        println!(
+            "Previous registry values saved to {}",
+            style::path(previous.display())
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,235 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use git2::{Repository, RepositoryState};
+
+use crate::cfg::{FuxiConfig, repo_path};
+use crate::git::{ensure_branch, open_repo};
+use crate::style;
+
+/// Lock files younger than this may belong to a git process that is still running.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(60);
+
+/// Counts what `fuxi repair` found, and prints each problem as it goes.
+struct Report {
+    dry_run: bool,
+    problems: usize,
+}
+
+impl Report {
+    fn found(&mut self, problem: &str, fix: &str) {
+        self.problems += 1;
+        if self.dry_run {
+            println!("[Dry Run] {}, would {}.", problem, fix);
+        } else {
+            println!("{}, going to {}.", problem, fix);
+        }
+    }
+}
+
+fn lock_files(dir: &Path, locks: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            // objects never hold locks and can be huge
+            if !path.ends_with("objects") {
+                lock_files(&path, locks)?;
+            }
+        } else if path.extension().is_some_and(|ext| ext == "lock") {
+            locks.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn remove_stale_locks(repo: &Repository, report: &mut Report) -> std::io::Result<()> {
+    let mut locks = Vec::new();
+    lock_files(repo.path(), &mut locks)?;
+    for lock in locks {
+        let age = fs::metadata(&lock)?
+            .modified()
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .unwrap_or_default();
+        if age < STALE_LOCK_AGE {
+            println!(
+                "{} was just created, leaving it alone in case git is running.",
+                style::path(lock.display())
+            );
+            continue;
+        }
+        report.found(&format!("Stale lock {}", lock.display()), "remove it");
+        if !report.dry_run {
+            fs::remove_file(&lock)?;
+        }
+    }
+    Ok(())
+}
+
+/// Rebuilds an unreadable index from HEAD, keeping the working tree.
+fn rebuild_index(repo: &Repository, report: &mut Report) -> Result<(), Box<dyn std::error::Error>> {
+    let broken = match repo.index() {
+        Ok(mut index) => index.read(true).err(),
+        Err(e) => Some(e),
+    };
+    let Some(error) = broken else {
+        return Ok(());
+    };
+    report.found(
+        &format!("The index is corrupted ({})", error.message()),
+        "rebuild it from the last commit",
+    );
+    if report.dry_run {
+        return Ok(());
+    }
+    fs::remove_file(repo.path().join("index"))?;
+    let mut index = repo.index()?;
+    if let Ok(tree) = repo.head().and_then(|head| head.peel_to_tree()) {
+        index.read_tree(&tree)?;
+    }
+    index.write()?;
+    Ok(())
+}
+
+fn finish_interrupted_operation(
+    repo: &Repository,
+    report: &mut Report,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if repo.state() == RepositoryState::Clean {
+        return Ok(());
+    }
+    let conflicted = repo.index()?.has_conflicts();
+    if conflicted {
+        println!(
+            "A {:?} was interrupted and left conflicts. Resolve them in {} and commit, then run 'fuxi repair' again.",
+            repo.state(),
+            repo.workdir().unwrap_or(repo.path()).display()
+        );
+        report.problems += 1;
+        return Ok(());
+    }
+    report.found(
+        &format!("A {:?} was interrupted", repo.state()),
+        "clear its leftover state",
+    );
+    if !report.dry_run {
+        repo.cleanup_state()?;
+    }
+    Ok(())
+}
+
+fn fix_remote(
+    repo: &Repository,
+    url: Option<&str>,
+    report: &mut Report,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let current = repo
+        .find_remote("origin")
+        .ok()
+        .and_then(|remote| remote.url().ok().map(str::to_string));
+    match (current.as_deref(), url) {
+        (Some(current), Some(url)) if current != url => {
+            report.found(
+                &format!("The remote points at {} instead of {}", current, url),
+                "update it",
+            );
+            if !report.dry_run {
+                repo.remote_set_url("origin", url)?;
+            }
+        }
+        (None, Some(url)) => {
+            report.found("The remote is missing", &format!("add {}", url));
+            if !report.dry_run {
+                repo.remote("origin", url)?;
+            }
+        }
+        (None, None) => {
+            println!("No remote is configured. Run 'fuxi init <remote> <path>' to set one.");
+            report.problems += 1;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Puts HEAD back on `branch`. Commits made on a detached HEAD that the
+/// branch doesn't contain are kept on a rescue branch first.
+fn fix_head(
+    repo_path: &Path,
+    repo: &Repository,
+    branch: &str,
+    report: &mut Report,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let head = match repo.head() {
+        Ok(head) => head,
+        // nothing committed yet
+        Err(_) => return Ok(()),
+    };
+    if head.is_branch() && head.shorthand() == Ok(branch) {
+        return Ok(());
+    }
+    let problem = if repo.head_detached()? {
+        "HEAD is detached".to_string()
+    } else {
+        format!(
+            "The repository is on branch '{}'",
+            head.shorthand().unwrap_or("?")
+        )
+    };
+    report.found(&problem, &format!("switch back to '{}'", branch));
+    if report.dry_run {
+        return Ok(());
+    }
+
+    let commit = head.peel_to_commit()?;
+    if let Ok(tip) = repo
+        .find_branch(branch, git2::BranchType::Local)
+        .and_then(|b| b.get().peel_to_commit())
+        && tip.id() != commit.id()
+        && !repo.graph_descendant_of(tip.id(), commit.id())?
+        && repo.head_detached()?
+    {
+        let short = commit.as_object().short_id()?;
+        let rescue = format!("fuxi-rescued-{}", short.as_str().unwrap_or_default());
+        repo.branch(&rescue, &commit, false)?;
+        println!(
+            "Commits made on the detached HEAD are kept on branch '{}'.",
+            rescue
+        );
+    }
+    ensure_branch(repo_path, branch)
+}
+
+/// Detects and fixes what leaves the backup repository unusable: stale locks,
+/// a corrupted index, an interrupted merge, a missing remote and a HEAD that
+/// isn't on the configured branch.
+pub fn repair(config: &FuxiConfig, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let repo_path = Path::new(repo_path(config)?);
+    if config.backend.as_deref().unwrap_or("git") != "git" {
+        return Err("'fuxi repair' only works with the git backend.".into());
+    }
+    let repo = open_repo(repo_path)?;
+    let mut report = Report {
+        dry_run,
+        problems: 0,
+    };
+
+    remove_stale_locks(&repo, &mut report)?;
+    rebuild_index(&repo, &mut report)?;
+    finish_interrupted_operation(&repo, &mut report)?;
+    fix_remote(&repo, config.remote_url.as_deref(), &mut report)?;
+    fix_head(repo_path, &repo, &config.git_branch, &mut report)?;
+
+    if report.problems == 0 {
+        println!("The backup repository is healthy.");
+    } else if !dry_run {
+        println!(
+            "Checked the backup repository, {} problem(s) found.",
+            report.problems
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use std::{
+    env,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::git::NetworkError;
+use crate::object_store::ObjectStore;
+
+/// Where the S3 backend stores backups. Works with AWS, MinIO, B2 and other
+/// S3-compatible services.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct S3Config {
+    pub bucket: String,
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+    pub prefix: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+}
+
+pub struct S3Store {
+    bucket: String,
+    endpoint: String,
+    region: String,
+    prefix: String,
+    access_key_id: String,
+    secret_access_key: String,
+}
+
+impl S3Store {
+    pub fn new(config: &S3Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let region = config
+            .region
+            .clone()
+            .or_else(|| env::var("AWS_REGION").ok())
+            .unwrap_or_else(|| "us-east-1".to_string());
+        let endpoint = config
+            .endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region));
+        let credential = |value: &Option<String>, var: &str| {
+            value
+                .clone()
+                .or_else(|| env::var(var).ok())
+                .ok_or_else(|| format!("S3 credentials missing, set {} or configure them.", var))
+        };
+        Ok(Self {
+            bucket: config.bucket.clone(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            region,
+            prefix: config
+                .prefix
+                .clone()
+                .unwrap_or_default()
+                .trim_matches('/')
+                .to_string(),
+            access_key_id: credential(&config.access_key_id, "AWS_ACCESS_KEY_ID")?,
+            secret_access_key: credential(&config.secret_access_key, "AWS_SECRET_ACCESS_KEY")?,
+        })
+    }
+
+    fn url(&self, key: &str) -> String {
+        if self.prefix.is_empty() {
+            format!("{}/{}/{}", self.endpoint, self.bucket, key)
+        } else {
+            format!("{}/{}/{}/{}", self.endpoint, self.bucket, self.prefix, key)
+        }
+    }
+
+    /// Runs a signed curl request and returns the HTTP status and body.
+    fn request(
+        &self,
+        args: &[&str],
+        key: &str,
+    ) -> Result<(u16, Vec<u8>), Box<dyn std::error::Error>> {
+        let sigv4 = format!("aws:amz:{}:s3", self.region);
+        let url = self.url(key);
+        let mut child = Command::new("curl")
+            .args(["--silent", "--show-error", "--max-time", "300"])
+            .args(["--aws-sigv4", &sigv4, "--config", "-"])
+            .args(["--write-out", "%{stderr}%{http_code}"])
+            .args(args)
+            .arg(&url)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to run curl (is it installed?): {}", e))?;
+        // credentials go through stdin so they don't show up in the process list
+        let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        child
+            .stdin
+            .take()
+            .ok_or("Failed to open curl stdin")?
+            .write_all(
+                format!(
+                    "user = \"{}:{}\"\n",
+                    escape(&self.access_key_id),
+                    escape(&self.secret_access_key)
+                )
+                .as_bytes(),
+            )?;
+        let output = child.wait_with_output()?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let (message, code) = stderr.split_at(stderr.len().saturating_sub(3));
+        match code.parse::<u16>() {
+            Ok(code) if code != 0 => Ok((code, output.stdout)),
+            _ => Err(Box::new(NetworkError(format!(
+                "Request to {} failed: {}",
+                url,
+                message.trim()
+            )))),
+        }
+    }
+}
+
+impl ObjectStore for S3Store {
+    fn describe(&self) -> String {
+        format!("s3://{}", self.bucket)
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        match self.request(&[], key)? {
+            (200, body) => Ok(Some(body)),
+            (404, _) => Ok(None),
+            (code, body) => Err(format!(
+                "Downloading {} failed with HTTP {}: {}",
+                key,
+                code,
+                String::from_utf8_lossy(&body).trim()
+            )
+            .into()),
+        }
+    }
+
+    fn exists(&self, key: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        match self.request(&["--head"], key)?.0 {
+            200 => Ok(true),
+            404 => Ok(false),
+            code => Err(format!("Checking {} failed with HTTP {}", key, code).into()),
+        }
+    }
+
+    fn put(&self, key: &str, file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let file = file.to_string_lossy();
+        match self.request(&["--upload-file", file.as_ref()], key)? {
+            (200..=299, _) => Ok(()),
+            (code, body) => Err(format!(
+                "Uploading {} failed with HTTP {}: {}",
+                key,
+                code,
+                String::from_utf8_lossy(&body).trim()
+            )
+            .into()),
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::{
+    env, fs,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+const UNIT_NAME: &str = "fuxi-backup";
+const LAUNCHD_LABEL: &str = "com.fuxi.backup";
+
+/// Parses intervals like `30m`, `6h` or `1d` into seconds.
+pub fn parse_interval(interval: &str) -> Result<u64, String> {
+    let interval = interval.trim();
+    let split = interval.char_indices().last().map_or(0, |(i, _)| i);
+    let (number, unit) = interval.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("Invalid interval '{}', use e.g. 30m, 6h or 1d", interval))?;
+    let seconds = match unit {
+        "m" => number * 60,
+        "h" => number * 3600,
+        "d" => number * 86_400,
+        _ => {
+            return Err(format!(
+                "Invalid interval '{}', use e.g. 30m, 6h or 1d",
+                interval
+            ));
+        }
+    };
+    if seconds < 60 {
+        return Err("The interval must be at least one minute.".to_string());
+    }
+    Ok(seconds)
+}
+
+fn run(program: &str, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new(program)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn fuxi_exe() -> Result<String, Box<dyn std::error::Error>> {
+    Ok(env::current_exe()?.to_string_lossy().to_string())
+}
+
+fn systemd_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(dirs::config_dir()
+        .ok_or("Could not determine config directory")?
+        .join("systemd")
+        .join("user"))
+}
+
+fn launchd_plist() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(dirs::home_dir()
+        .ok_or("Could not determine home directory")?
+        .join("Library")
+        .join("LaunchAgents")
+        .join(format!("{}.plist", LAUNCHD_LABEL)))
+}
+
+fn install_systemd(seconds: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = systemd_dir()?;
+    fs::create_dir_all(&dir)?;
+    fs::write(
+        dir.join(format!("{}.service", UNIT_NAME)),
+        format!(
+            "[Unit]\nDescription=fuxi backup\n\n[Service]\nType=oneshot\nExecStart=\"{}\" backup --auto-push -y --notify --prune --wait\n",
+            fuxi_exe()?
+        ),
+    )?;
+    fs::write(
+        dir.join(format!("{}.timer", UNIT_NAME)),
+        format!(
+            "[Unit]\nDescription=Run fuxi backup periodically\n\n[Timer]\nOnBootSec=5min\nOnUnitActiveSec={}s\n\n[Install]\nWantedBy=timers.target\n",
+            seconds
+        ),
+    )?;
+    run("systemctl", &["--user", "daemon-reload"])?;
+    run(
+        "systemctl",
+        &["--user", "enable", "--now", &format!("{}.timer", UNIT_NAME)],
+    )?;
+    Ok(())
+}
+
+fn install_launchd(seconds: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let plist = launchd_plist()?;
+    if let Some(parent) = plist.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let log = dirs::cache_dir()
+        .ok_or("Could not determine cache directory")?
+        .join("fuxi")
+        .join("schedule.log");
+    if let Some(parent) = log.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(
+        &plist,
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{}</string>
+        <string>backup</string>
+        <string>--auto-push</string>
+        <string>-y</string>
+        <string>--notify</string>
+        <string>--prune</string>
+        <string>--wait</string>
+    </array>
+    <key>StartInterval</key>
+    <integer>{}</integer>
+    <key>StandardOutPath</key>
+    <string>{}</string>
+    <key>StandardErrorPath</key>
+    <string>{}</string>
+</dict>
+</plist>
+"#,
+            LAUNCHD_LABEL,
+            fuxi_exe()?,
+            seconds,
+            log.display(),
+            log.display()
+        ),
+    )?;
+    // reloading picks up a changed interval
+    let plist = plist.to_string_lossy();
+    let _ = run("launchctl", &["unload", plist.as_ref()]);
+    run("launchctl", &["load", "-w", plist.as_ref()])?;
+    Ok(())
+}
+
+fn install_schtasks(seconds: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let minutes = seconds / 60;
+    // schtasks caps minute and hour modifiers below a day
+    let (schedule, modifier) = if minutes.is_multiple_of(1440) {
+        ("DAILY", minutes / 1440)
+    } else if minutes.is_multiple_of(60) && minutes < 1440 {
+        ("HOURLY", minutes / 60)
+    } else if minutes < 1440 {
+        ("MINUTE", minutes)
+    } else {
+        return Err("Intervals over a day must be whole days on Windows.".into());
+    };
+    let command = format!(
+        "\"{}\" backup --auto-push -y --notify --prune --wait",
+        fuxi_exe()?
+    );
+    run(
+        "schtasks",
+        &[
+            "/Create",
+            "/F",
+            "/TN",
+            UNIT_NAME,
+            "/SC",
+            schedule,
+            "/MO",
+            &modifier.to_string(),
+            "/TR",
+            &command,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Installs a systemd user timer, launchd agent or scheduled task running
+/// `fuxi backup --auto-push -y --notify --prune --wait` every `seconds`.
+pub fn install(seconds: u64) -> Result<(), Box<dyn std::error::Error>> {
+    match env::consts::OS {
+        "linux" => install_systemd(seconds),
+        "macos" => install_launchd(seconds),
+        "windows" => install_schtasks(seconds),
+        os => Err(format!("Scheduling is not supported on {}", os).into()),
+    }
+}
+
+pub fn remove() -> Result<(), Box<dyn std::error::Error>> {
+    match env::consts::OS {
+        "linux" => {
+            let _ = run(
+                "systemctl",
+                &[
+                    "--user",
+                    "disable",
+                    "--now",
+                    &format!("{}.timer", UNIT_NAME),
+                ],
+            );
+            let dir = systemd_dir()?;
+            for unit in ["service", "timer"] {
+                let file = dir.join(format!("{}.{}", UNIT_NAME, unit));
+                if file.exists() {
+                    fs::remove_file(file)?;
+                }
+            }
+            run("systemctl", &["--user", "daemon-reload"])?;
+        }
+        "macos" => {
+            let plist = launchd_plist()?;
+            if plist.exists() {
+                let _ = run("launchctl", &["unload", plist.to_string_lossy().as_ref()]);
+                fs::remove_file(plist)?;
+            }
+        }
+        "windows" => {
+            run("schtasks", &["/Delete", "/F", "/TN", UNIT_NAME])?;
+        }
+        os => return Err(format!("Scheduling is not supported on {}", os).into()),
+    }
+    Ok(())
+}
+
+/// Describes the installed schedule, or `None` when there is none.
+pub fn status() -> Result<Option<String>, Box<dyn std::error::Error>> {
+    match env::consts::OS {
+        "linux" => {
+            if !systemd_dir()?.join(format!("{}.timer", UNIT_NAME)).exists() {
+                return Ok(None);
+            }
+            Ok(Some(run(
+                "systemctl",
+                &[
+                    "--user",
+                    "list-timers",
+                    "--all",
+                    &format!("{}.timer", UNIT_NAME),
+                ],
+            )?))
+        }
+        "macos" => {
+            if !launchd_plist()?.exists() {
+                return Ok(None);
+            }
+            Ok(Some(
+                run("launchctl", &["list", LAUNCHD_LABEL])
+                    .unwrap_or_else(|_| "Installed but not loaded.".to_string()),
+            ))
+        }
+        "windows" => Ok(run(
+            "schtasks",
+            &["/Query", "/TN", UNIT_NAME, "/V", "/FO", "LIST"],
+        )
+        .ok()),
+        os => Err(format!("Scheduling is not supported on {}", os).into()),
+    }
+}
@@ -0,0 +1,233 @@
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "scripting")]
+use std::sync::OnceLock;
+
+#[cfg(feature = "scripting")]
+use rhai::{AST, CallFnOptions, Dynamic, Engine, FuncArgs, Map, Scope};
+use serde_json::Value;
+
+#[cfg(feature = "scripting")]
+use crate::cfg::get_machine_name;
+use crate::cfg::{FuxiConfig, get_config_path};
+use crate::manifest::ManifestEntry;
+
+/// Operations a script may run before giving up, so a runaway loop can't hang a backup.
+#[cfg(feature = "scripting")]
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// The Rhai script from `hook_script`, or `hooks.rhai` next to the config
+/// file when it exists.
+pub fn script_path(config: &FuxiConfig) -> Option<PathBuf> {
+    match &config.hook_script {
+        Some(path) => Some(PathBuf::from(path)),
+        None => get_config_path()
+            .ok()
+            .map(|path| path.with_file_name("hooks.rhai"))
+            .filter(|path| path.is_file()),
+    }
+}
+
+#[cfg(feature = "scripting")]
+struct Script {
+    engine: Engine,
+    ast: AST,
+    path: PathBuf,
+}
+
+#[cfg(feature = "scripting")]
+impl Script {
+    fn defines(&self, name: &str, arity: usize) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|f| f.name == name && f.params.len() == arity)
+    }
+
+    fn call(&self, name: &str, args: impl FuncArgs) -> Result<Dynamic, Box<dyn std::error::Error>> {
+        // the top level only defines functions, it isn't run for every call
+        let options = CallFnOptions::new().eval_ast(false);
+        self.engine
+            .call_fn_with_options(options, &mut Scope::new(), &self.ast, name, args)
+            .map_err(|e| format!("{} in {}: {}", name, self.path.display(), e).into())
+    }
+}
+
+/// Compiles the hook script once per process.
+#[cfg(feature = "scripting")]
+fn script(config: &FuxiConfig) -> Result<Option<&'static Script>, Box<dyn std::error::Error>> {
+    static SCRIPT: OnceLock<Option<Script>> = OnceLock::new();
+    if let Some(script) = SCRIPT.get() {
+        return Ok(script.as_ref());
+    }
+    let script = match script_path(config) {
+        Some(path) => {
+            let mut engine = Engine::new();
+            engine.set_max_operations(MAX_OPERATIONS);
+            engine.register_fn("env", |name: &str| std::env::var(name).unwrap_or_default());
+            let ast = engine
+                .compile_file(path.clone())
+                .map_err(|e| format!("Failed to load hook script {}: {}", path.display(), e))?;
+            Some(Script { engine, ast, path })
+        }
+        None => None,
+    };
+    Ok(SCRIPT.get_or_init(|| script).as_ref())
+}
+
+/// What every script function gets to look at: the profile, OS, machine and
+/// home directory, plus `details`.
+#[cfg(feature = "scripting")]
+fn context(config: &FuxiConfig, details: &Value) -> Result<Map, Box<dyn std::error::Error>> {
+    let mut context = Map::new();
+    context.insert(
+        "profile".into(),
+        config
+            .selected_profile
+            .clone()
+            .map_or(Dynamic::UNIT, Dynamic::from),
+    );
+    context.insert("os".into(), std::env::consts::OS.into());
+    context.insert("machine".into(), get_machine_name(config).into());
+    let home = dirs::home_dir().unwrap_or_default();
+    context.insert("home".into(), home.to_string_lossy().to_string().into());
+    if let Value::Object(details) = details {
+        for (key, value) in details {
+            context.insert(key.into(), rhai::serde::to_dynamic(value)?);
+        }
+    }
+    Ok(context)
+}
+
+#[cfg(feature = "scripting")]
+pub fn has_filter(config: &FuxiConfig) -> Result<bool, Box<dyn std::error::Error>> {
+    Ok(script(config)?.is_some_and(|script| script.defines("filter", 2)))
+}
+
+/// Whether a backup copies `path`, as decided by the script's `filter(path, ctx)`.
+#[cfg(feature = "scripting")]
+pub fn filter(config: &FuxiConfig, path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+    let Some(script) = script(config)?.filter(|script| script.defines("filter", 2)) else {
+        return Ok(true);
+    };
+    let context = context(config, &Value::Null)?;
+    script
+        .call("filter", (path.to_string_lossy().to_string(), context))?
+        .as_bool()
+        .map_err(|_| {
+            format!(
+                "filter in {} must return true or false.",
+                script.path.display()
+            )
+            .into()
+        })
+}
+
+/// Moves entries to where the script's `destination(path, ctx)` says they
+/// should be restored.
+#[cfg(feature = "scripting")]
+pub fn destinations(
+    config: &FuxiConfig,
+    entries: &[ManifestEntry],
+) -> Result<Vec<ManifestEntry>, Box<dyn std::error::Error>> {
+    let Some(script) = script(config)?.filter(|script| script.defines("destination", 2)) else {
+        return Ok(entries.to_vec());
+    };
+    let context = context(config, &Value::Null)?;
+    let mut moved = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let destination = script
+            .call("destination", (entry.source.clone(), context.clone()))?
+            .into_string()
+            .map_err(|_| {
+                format!(
+                    "destination in {} must return a path.",
+                    script.path.display()
+                )
+            })?;
+        if destination.is_empty() {
+            return Err(format!(
+                "destination in {} returned an empty path for {}.",
+                script.path.display(),
+                entry.source
+            )
+            .into());
+        }
+        if destination != entry.source {
+            println!("Restoring {} to {}", entry.source, destination);
+        }
+        moved.push(ManifestEntry {
+            source: destination,
+            ..entry.clone()
+        });
+    }
+    Ok(moved)
+}
+
+/// Calls the script's function for `event`, `pre_backup(ctx)` for
+/// `pre-backup`, if it defines one. A `pre_*` function vetoes the command by
+/// returning `false` or throwing.
+#[cfg(feature = "scripting")]
+pub fn run_event(
+    config: &FuxiConfig,
+    event: &str,
+    details: &Value,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let name = event.replace('-', "_");
+    let Some(script) = script(config)?.filter(|script| script.defines(&name, 1)) else {
+        return Ok(());
+    };
+    let result = script.call(&name, (context(config, details)?,))?;
+    if result.as_bool() == Ok(false) {
+        return Err(format!(
+            "{} in {} stopped the {}.",
+            name,
+            script.path.display(),
+            event.trim_start_matches("pre-")
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Refuses to run when a hook script is set up but fuxi can't run it.
+#[cfg(not(feature = "scripting"))]
+fn unsupported(config: &FuxiConfig) -> Result<(), Box<dyn std::error::Error>> {
+    match script_path(config) {
+        Some(path) => Err(format!(
+            "Can't run the hook script {}, fuxi was built without the scripting feature.",
+            path.display()
+        )
+        .into()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(feature = "scripting"))]
+pub fn has_filter(config: &FuxiConfig) -> Result<bool, Box<dyn std::error::Error>> {
+    unsupported(config)?;
+    Ok(false)
+}
+
+#[cfg(not(feature = "scripting"))]
+pub fn filter(config: &FuxiConfig, _path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+    unsupported(config)?;
+    Ok(true)
+}
+
+#[cfg(not(feature = "scripting"))]
+pub fn destinations(
+    config: &FuxiConfig,
+    entries: &[ManifestEntry],
+) -> Result<Vec<ManifestEntry>, Box<dyn std::error::Error>> {
+    unsupported(config)?;
+    Ok(entries.to_vec())
+}
+
+#[cfg(not(feature = "scripting"))]
+pub fn run_event(
+    config: &FuxiConfig,
+    _event: &str,
+    _details: &Value,
+) -> Result<(), Box<dyn std::error::Error>> {
+    unsupported(config)
+}
@@ -0,0 +1,179 @@
+use std::{collections::HashMap, path::Path, process::Command};
+
+use crate::cfg::{FuxiConfig, repo_path};
+use crate::git::{git_binary, log};
+use crate::porcelain;
+
+/// Leaves out fuxi's own files, like manifests and backup metadata.
+const EXCLUDES: [&str; 2] = [":(exclude,glob)**/.fuxi*", ":(exclude,glob)**/.fuxi*/**"];
+
+/// Revisions passed to one `git grep`, to stay below command line limits.
+const REVISIONS_PER_GREP: usize = 200;
+
+/// Longest part of a matching line that is shown.
+const MAX_LINE_LENGTH: usize = 200;
+
+struct Match {
+    revision: Option<String>,
+    path: String,
+    line: usize,
+    text: String,
+}
+
+/// Runs `git grep` for `pattern` in `revisions`, or in the files of
+/// `repo_path` when there are none.
+fn git_grep(
+    repo_path: &Path,
+    pattern: &str,
+    ignore_case: bool,
+    revisions: &[String],
+    no_index: bool,
+) -> Result<Vec<Match>, Box<dyn std::error::Error>> {
+    let git = git_binary()
+        .map_err(|_| "fuxi search runs 'git grep', install git or set git_binary in the config.")?;
+    let mut command = Command::new(git);
+    command
+        .current_dir(repo_path)
+        .args(["grep", "-n", "-I", "-z", "--no-color"]);
+    if no_index {
+        command.arg("--no-index");
+    }
+    if ignore_case {
+        command.arg("-i");
+    }
+    command
+        .arg("-e")
+        .arg(pattern)
+        .args(revisions)
+        .arg("--")
+        .arg(".")
+        .args(EXCLUDES);
+    let output = command
+        .output()
+        .map_err(|e| format!("Failed to run git grep: {}", e))?;
+    match output.status.code() {
+        Some(0) => {}
+        // nothing matched
+        Some(1) => return Ok(Vec::new()),
+        _ => {
+            return Err(format!(
+                "git grep failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\0');
+            let location = fields.next()?;
+            let line = fields.next()?.parse().ok()?;
+            let text = fields.next()?.trim();
+            let (revision, path) = if revisions.is_empty() {
+                (None, location)
+            } else {
+                let (revision, path) = location.split_once(':')?;
+                (Some(revision.to_string()), path)
+            };
+            Some(Match {
+                revision,
+                path: path.to_string(),
+                line,
+                text: text.chars().take(MAX_LINE_LENGTH).collect(),
+            })
+        })
+        .collect())
+}
+
+/// Searches the backed up files for `pattern`, a regular expression. With
+/// `history` every backup is searched and each matching line is reported
+/// once, in the newest backup that has it.
+pub fn search(
+    config: &FuxiConfig,
+    pattern: &str,
+    ignore_case: bool,
+    history: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo_path = Path::new(repo_path(config)?);
+    let is_git = config.backend.as_deref().unwrap_or("git") == "git";
+
+    if !history {
+        let matches = git_grep(repo_path, pattern, ignore_case, &[], !is_git)?;
+        if porcelain::enabled() {
+            for m in &matches {
+                porcelain::record("match", &["", &m.path, &m.line.to_string(), "0", &m.text]);
+            }
+            return Ok(());
+        }
+        if matches.is_empty() {
+            println!("No matches in the backup directory.");
+            return Ok(());
+        }
+        println!("Matches in the backup directory:");
+        for m in &matches {
+            println!("  {}:{}: {}", m.path, m.line, m.text);
+        }
+        return Ok(());
+    }
+
+    if !is_git {
+        return Err("Searching every backup with --history needs the git backend.".into());
+    }
+    let backups = log(repo_path)?;
+    let names: HashMap<&str, &str> = backups
+        .iter()
+        .map(|b| (b.hash.as_str(), b.tag.as_deref().unwrap_or(&b.hash)))
+        .collect();
+    let revisions: Vec<String> = backups.iter().map(|b| b.hash.clone()).collect();
+
+    // (path, text) -> newest match and how many backups have it
+    let mut found: Vec<(Match, usize)> = Vec::new();
+    let mut index: HashMap<(String, String), usize> = HashMap::new();
+    for chunk in revisions.chunks(REVISIONS_PER_GREP) {
+        for m in git_grep(repo_path, pattern, ignore_case, chunk, false)? {
+            match index.get(&(m.path.clone(), m.text.clone())) {
+                Some(&i) => found[i].1 += 1,
+                None => {
+                    index.insert((m.path.clone(), m.text.clone()), found.len());
+                    found.push((m, 1));
+                }
+            }
+        }
+    }
+
+    if porcelain::enabled() {
+        for (m, count) in &found {
+            let revision = m.revision.as_deref().unwrap_or_default();
+            let name = names.get(revision).copied().unwrap_or(revision);
+            porcelain::record(
+                "match",
+                &[
+                    name,
+                    &m.path,
+                    &m.line.to_string(),
+                    &(count - 1).to_string(),
+                    &m.text,
+                ],
+            );
+        }
+        return Ok(());
+    }
+    if found.is_empty() {
+        println!("No matches in any of the {} backup(s).", backups.len());
+        return Ok(());
+    }
+    println!("Newest backup containing each match:");
+    for (m, count) in &found {
+        let revision = m.revision.as_deref().unwrap_or_default();
+        let name = names.get(revision).copied().unwrap_or(revision);
+        let older = match count - 1 {
+            0 => String::new(),
+            older => format!(" (and {} older backup(s))", older),
+        };
+        println!("  {}  {}:{}: {}{}", name, m.path, m.line, m.text, older);
+    }
+    Ok(())
+}
@@ -0,0 +1,210 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cfg::{FuxiConfig, get_path_entry};
+
+/// Extensions of every backend, so encrypted files are recognised in the repo
+/// regardless of the backend currently configured.
+pub const ENCRYPTED_EXTENSIONS: [&str; 2] = ["age", "gpg"];
+
+/// Which tool secrets are encrypted with.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum EncryptionKind {
+    #[default]
+    Age,
+    Gpg,
+}
+
+/// Encrypts secrets before they are written to the repo and decrypts them on apply.
+pub trait EncryptionBackend {
+    fn name(&self) -> &'static str;
+    /// Extension appended to encrypted files, without the dot.
+    fn extension(&self) -> &'static str;
+    fn encrypt(&self, src: &Path, dst: &Path) -> Result<(), Box<dyn std::error::Error>>;
+    fn decrypt(&self, src: &Path, dst: &Path) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+pub struct AgeBackend {
+    recipients: Vec<String>,
+    recipients_file: Option<String>,
+    identity: Option<PathBuf>,
+}
+
+pub struct GpgBackend {
+    recipients: Vec<String>,
+}
+
+pub fn encryption_backend(config: &FuxiConfig) -> Box<dyn EncryptionBackend> {
+    match config.encryption_backend.unwrap_or_default() {
+        EncryptionKind::Age => Box::new(AgeBackend {
+            recipients: config.age_recipients.clone().unwrap_or_default(),
+            recipients_file: config.age_recipients_file.clone(),
+            identity: age_identity(config),
+        }),
+        EncryptionKind::Gpg => Box::new(GpgBackend {
+            recipients: config.gpg_recipients.clone().unwrap_or_default(),
+        }),
+    }
+}
+
+pub fn is_secret_path(config: &FuxiConfig, path: &str) -> bool {
+    config
+        .secrets
+        .as_ref()
+        .is_some_and(|secrets| secrets.iter().any(|s| s == path))
+        || get_path_entry(config, path).is_some_and(|entry| entry.encrypt.unwrap_or(false))
+}
+
+/// Name of the encrypted copy stored in the repo, e.g. `config` -> `config.age`.
+pub fn encrypted_name(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// Reverses [`encrypted_name`], returning `None` for files that aren't encrypted.
+pub fn decrypted_name(path: &Path) -> Option<PathBuf> {
+    let extension = path.extension()?.to_str()?;
+    if ENCRYPTED_EXTENSIONS.contains(&extension) {
+        Some(path.with_extension(""))
+    } else {
+        None
+    }
+}
+
+/// Default identity used for decryption: `<config dir>/age/keys.txt`, the
+/// location `age-keygen` is usually pointed at.
+fn age_identity(config: &FuxiConfig) -> Option<PathBuf> {
+    config
+        .age_identity
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(|| dirs::config_dir().map(|d| d.join("age").join("keys.txt")))
+}
+
+fn run_tool(program: &str, args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let output = Command::new(program)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| format!("Failed to run {} (is it installed?): {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn path_arg(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+impl EncryptionBackend for AgeBackend {
+    fn name(&self) -> &'static str {
+        "age"
+    }
+
+    fn extension(&self) -> &'static str {
+        "age"
+    }
+
+    fn encrypt(&self, src: &Path, dst: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let mut args = vec!["--encrypt".to_string()];
+        for recipient in &self.recipients {
+            args.push("-r".to_string());
+            args.push(recipient.clone());
+        }
+        if let Some(file) = &self.recipients_file {
+            args.push("-R".to_string());
+            args.push(file.clone());
+        }
+        if args.len() == 1 {
+            return Err(
+                "No age recipients configured. Set 'age_recipients' or 'age_recipients_file' in the config."
+                    .into(),
+            );
+        }
+
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        args.extend(["-o".to_string(), path_arg(dst), path_arg(src)]);
+        run_tool("age", &args)
+    }
+
+    fn decrypt(&self, src: &Path, dst: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let identity = self
+            .identity
+            .as_ref()
+            .ok_or("No age identity configured. Set 'age_identity' in the config.")?;
+        run_tool(
+            "age",
+            &[
+                "--decrypt".to_string(),
+                "-i".to_string(),
+                path_arg(identity),
+                "-o".to_string(),
+                path_arg(dst),
+                path_arg(src),
+            ],
+        )
+    }
+}
+
+impl EncryptionBackend for GpgBackend {
+    fn name(&self) -> &'static str {
+        "gpg"
+    }
+
+    fn extension(&self) -> &'static str {
+        "gpg"
+    }
+
+    fn encrypt(&self, src: &Path, dst: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if self.recipients.is_empty() {
+            return Err("No gpg recipients configured. Set 'gpg_recipients' in the config.".into());
+        }
+
+        let mut args = vec![
+            "--batch".to_string(),
+            "--yes".to_string(),
+            "--encrypt".to_string(),
+        ];
+        for recipient in &self.recipients {
+            args.push("--recipient".to_string());
+            args.push(recipient.clone());
+        }
+
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        args.extend(["--output".to_string(), path_arg(dst), path_arg(src)]);
+        run_tool("gpg", &args)
+    }
+
+    fn decrypt(&self, src: &Path, dst: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        // the gpg agent takes care of asking for the passphrase
+        run_tool(
+            "gpg",
+            &[
+                "--yes".to_string(),
+                "--decrypt".to_string(),
+                "--output".to_string(),
+                path_arg(dst),
+                path_arg(src),
+            ],
+        )
+    }
+}
@@ -0,0 +1,58 @@
+use std::path::{Component, Path};
+
+/// Paths relative to the home directory that should never be pushed in plain
+/// text. `*` matches anything within a single path component.
+const SENSITIVE_PATHS: [&str; 16] = [
+    ".ssh/id_*",
+    ".gnupg/private-keys-v1.d",
+    ".aws/credentials",
+    ".netrc",
+    ".pgpass",
+    ".git-credentials",
+    ".docker/config.json",
+    ".kube/config",
+    ".password-store",
+    ".mozilla/firefox/*/cookies.sqlite",
+    ".mozilla/firefox/*/logins.json",
+    ".config/google-chrome/*/Cookies",
+    ".config/chromium/*/Cookies",
+    "Library/Keychains",
+    "Library/Cookies",
+    "AppData/Local/Google/Chrome/User Data/*/Network/Cookies",
+];
+
+fn component_matches(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => {
+            name.len() >= prefix.len() + suffix.len()
+                && name.starts_with(prefix)
+                && name.ends_with(suffix)
+                // public keys sit next to the private ones
+                && !name.ends_with(".pub")
+        }
+        None => pattern == name,
+    }
+}
+
+/// Returns the built-in pattern a path matches, either because it is
+/// sensitive itself or because it is a directory containing something that is.
+pub fn sensitive_match(path: &Path) -> Option<&'static str> {
+    let home = dirs::home_dir()?;
+    let path = std::path::absolute(path).ok()?;
+    let relative = path.strip_prefix(&home).ok()?;
+    let names: Vec<String> = relative
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+
+    // zip stops at the shorter side, so parents and children of a sensitive path match too
+    SENSITIVE_PATHS.into_iter().find(|pattern| {
+        names
+            .iter()
+            .zip(pattern.split('/'))
+            .all(|(name, part)| component_matches(part, name))
+    })
+}
@@ -0,0 +1,66 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::cfg::PathEntry;
+use crate::style;
+
+/// A profile distributed by an administrator, e.g. `/etc/fuxi/profiles.d/base.toml`.
+#[derive(Debug, Deserialize)]
+struct SharedProfile {
+    name: Option<String>,
+    paths: Vec<PathEntry>,
+}
+
+pub fn default_shared_profiles_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        std::env::var_os("ProgramData").map(|p| PathBuf::from(p).join("fuxi").join("profiles.d"))
+    } else {
+        Some(PathBuf::from("/etc/fuxi/profiles.d"))
+    }
+}
+
+/// Reads every `*.toml` profile from the shared directory. Unreadable or invalid
+/// files are reported and skipped so a broken system file can't lock users out.
+pub fn load_shared_profiles(dir: &Path) -> HashMap<String, Vec<PathEntry>> {
+    let mut profiles = HashMap::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return profiles;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("toml") {
+            continue;
+        }
+
+        let parsed = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| {
+                toml::from_str::<SharedProfile>(&content).map_err(|e| e.to_string())
+            });
+        match parsed {
+            Ok(profile) => {
+                let name = profile.name.unwrap_or_else(|| {
+                    path.file_stem()
+                        .map(|s| s.to_string_lossy().to_string())
+                        .unwrap_or_default()
+                });
+                profiles.insert(name, profile.paths);
+            }
+            Err(e) => println!(
+                "{}",
+                style::warning(format!(
+                    "Warning: Ignoring invalid shared profile {}: {}",
+                    path.display(),
+                    e
+                ))
+            ),
+        }
+    }
+    profiles
+}
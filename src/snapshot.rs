@@ -0,0 +1,280 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{NaiveDateTime, Utc};
+
+use crate::cfg::FuxiConfig;
+
+pub const DEFAULT_MAX_AGE_DAYS: u64 = 30;
+pub const DEFAULT_MAX_SIZE_MB: u64 = 500;
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%d_%H%M%S";
+
+/// Copies of live files taken right before `apply` overwrites them.
+pub struct Snapshot {
+    pub dir: PathBuf,
+    pub saved: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct SnapshotInfo {
+    pub path: PathBuf,
+    pub created: SystemTime,
+    pub size: u64,
+}
+
+pub fn snapshots_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let cache_dir = dirs::cache_dir().ok_or("Could not determine cache directory")?;
+    Ok(cache_dir.join("fuxi").join("snapshots"))
+}
+
+impl Snapshot {
+    pub fn new(label: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let name = format!("{}_{}", Utc::now().format(TIMESTAMP_FORMAT), label);
+        Ok(Self {
+            dir: snapshots_dir()?.join(name),
+            saved: 0,
+        })
+    }
+
+    /// Where `live` is stored under the snapshot, mirroring its absolute path.
+    pub fn path_for(&self, live: &Path) -> PathBuf {
+        let mirrored: PathBuf = live
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(part) => Some(part.to_os_string()),
+                Component::Prefix(prefix) => Some(
+                    prefix
+                        .as_os_str()
+                        .to_string_lossy()
+                        .replace([':', '\\', '?'], "")
+                        .into(),
+                ),
+                _ => None,
+            })
+            .collect();
+        self.dir.join(mirrored)
+    }
+
+    /// Stores `live` under the snapshot.
+    pub fn save(&mut self, live: &Path) -> std::io::Result<()> {
+        let dst = self.path_for(live);
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(live, dst)?;
+        self.saved += 1;
+        Ok(())
+    }
+}
+
+pub fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+pub fn list_snapshots(dir: &Path) -> std::io::Result<Vec<SnapshotInfo>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.path().is_dir() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        // prefer the timestamp in the name since adding files bumps the dir mtime
+        let created = name
+            .get(..15)
+            .and_then(|ts| NaiveDateTime::parse_from_str(ts, TIMESTAMP_FORMAT).ok())
+            .map(|ts| SystemTime::from(ts.and_utc()))
+            .map_or_else(|| entry.metadata()?.modified(), Ok)?;
+        snapshots.push(SnapshotInfo {
+            size: dir_size(&entry.path()),
+            path: entry.path(),
+            created,
+        });
+    }
+    snapshots.sort_by_key(|s| s.created);
+    Ok(snapshots)
+}
+
+/// Picks snapshots to evict from `snapshots`, sorted oldest first:
+/// everything older than `max_age`, then more until the remaining total fits
+/// in `max_size` bytes. The newest is always kept, it's usually the one an
+/// apply just saved the files it overwrote to, even when it alone is too big.
+pub fn plan_evictions(
+    snapshots: &[SnapshotInfo],
+    max_age: Duration,
+    max_size: u64,
+    now: SystemTime,
+) -> Vec<SnapshotInfo> {
+    let Some((_newest, older)) = snapshots.split_last() else {
+        return Vec::new();
+    };
+    let mut total: u64 = snapshots.iter().map(|s| s.size).sum();
+    let mut evicted = Vec::new();
+
+    for snapshot in older {
+        let age = now.duration_since(snapshot.created).unwrap_or_default();
+        if age > max_age || total > max_size {
+            total -= snapshot.size;
+            evicted.push(snapshot.clone());
+        }
+    }
+    evicted
+}
+
+/// Enforces the configured snapshot limits, returning the evicted snapshots.
+pub fn prune_snapshots(
+    config: &FuxiConfig,
+    dry_run: bool,
+) -> Result<Vec<SnapshotInfo>, Box<dyn std::error::Error>> {
+    prune_snapshots_in(config, &snapshots_dir()?, dry_run)
+}
+
+fn prune_snapshots_in(
+    config: &FuxiConfig,
+    dir: &Path,
+    dry_run: bool,
+) -> Result<Vec<SnapshotInfo>, Box<dyn std::error::Error>> {
+    let max_age_days = config.snapshot_max_age_days.unwrap_or(DEFAULT_MAX_AGE_DAYS);
+    let max_size_mb = config.snapshot_max_size_mb.unwrap_or(DEFAULT_MAX_SIZE_MB);
+
+    let snapshots = list_snapshots(dir)?;
+    let evicted = plan_evictions(
+        &snapshots,
+        Duration::from_secs(max_age_days * 24 * 60 * 60),
+        max_size_mb * 1024 * 1024,
+        SystemTime::now(),
+    );
+
+    if !dry_run {
+        for snapshot in &evicted {
+            fs::remove_dir_all(&snapshot.path)?;
+        }
+    }
+    Ok(evicted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn snapshot(name: &str, age: Duration, size: u64, now: SystemTime) -> SnapshotInfo {
+        SnapshotInfo {
+            path: PathBuf::from(name),
+            created: now - age,
+            size,
+        }
+    }
+
+    fn names(evicted: &[SnapshotInfo]) -> Vec<&str> {
+        evicted.iter().map(|s| s.path.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn evicts_only_snapshots_past_the_max_age() {
+        let now = SystemTime::now();
+        let snapshots = [
+            snapshot("older", 30 * DAY + Duration::from_secs(1), 1, now),
+            snapshot("exactly", 30 * DAY, 1, now),
+            snapshot("younger", 30 * DAY - Duration::from_secs(1), 1, now),
+            snapshot("new", Duration::ZERO, 1, now),
+        ];
+        let evicted = plan_evictions(&snapshots, 30 * DAY, u64::MAX, now);
+        assert_eq!(names(&evicted), ["older"]);
+    }
+
+    #[test]
+    fn evicts_the_oldest_until_the_rest_fits() {
+        let now = SystemTime::now();
+        let snapshots = [
+            snapshot("a", 3 * DAY, 400, now),
+            snapshot("b", 2 * DAY, 300, now),
+            snapshot("c", DAY, 200, now),
+        ];
+        let evicted = plan_evictions(&snapshots, 30 * DAY, 500, now);
+        assert_eq!(names(&evicted), ["a"]);
+        let evicted = plan_evictions(&snapshots, 30 * DAY, 499, now);
+        assert_eq!(names(&evicted), ["a", "b"]);
+        assert!(plan_evictions(&snapshots, 30 * DAY, 900, now).is_empty());
+    }
+
+    #[test]
+    fn keeps_the_newest_snapshot() {
+        let now = SystemTime::now();
+        let snapshots = [
+            snapshot("old", 40 * DAY, 100, now),
+            snapshot("new", Duration::ZERO, 1000, now),
+        ];
+        let evicted = plan_evictions(&snapshots, 30 * DAY, 500, now);
+        assert_eq!(names(&evicted), ["old"]);
+
+        let only = [snapshot("only", 40 * DAY, 1000, now)];
+        assert!(plan_evictions(&only, 30 * DAY, 500, now).is_empty());
+    }
+
+    #[test]
+    fn deletes_the_snapshots_it_evicts() {
+        let tmp = tempfile::tempdir().unwrap();
+        let now = Utc::now();
+        let [old, recent, new] = [31, 29, 0].map(|days| {
+            let created = now - chrono::TimeDelta::days(days);
+            let dir = tmp.path().join(format!(
+                "{}_backup_{}",
+                created.format(TIMESTAMP_FORMAT),
+                days
+            ));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("file"), "x").unwrap();
+            dir
+        });
+        let config = FuxiConfig {
+            snapshot_max_age_days: Some(30),
+            ..FuxiConfig::default()
+        };
+
+        let evicted = prune_snapshots_in(&config, tmp.path(), true).unwrap();
+        assert_eq!(evicted.len(), 1);
+        assert!(old.exists());
+
+        let evicted = prune_snapshots_in(&config, tmp.path(), false).unwrap();
+        assert_eq!(evicted[0].path, old);
+        assert!(!old.exists());
+        assert!(recent.exists());
+        assert!(new.exists());
+    }
+
+    #[test]
+    fn dates_snapshots_by_their_name() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("20250131_120000_backup_1");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("file"), "12345").unwrap();
+
+        let snapshots = list_snapshots(tmp.path()).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].size, 5);
+        let expected = NaiveDateTime::parse_from_str("20250131_120000", TIMESTAMP_FORMAT)
+            .unwrap()
+            .and_utc();
+        assert_eq!(snapshots[0].created, SystemTime::from(expected));
+    }
+}
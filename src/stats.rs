@@ -0,0 +1,188 @@
+use std::{collections::HashMap, path::Path};
+
+use git2::{ObjectType, Oid, Repository, TreeWalkMode, TreeWalkResult};
+
+use crate::cfg::{FuxiConfig, repo_path};
+use crate::copy::collect_files;
+use crate::snapshot::dir_size;
+use crate::storage;
+use crate::style;
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+fn signed_size(delta: i64) -> String {
+    let sign = if delta < 0 { "-" } else { "+" };
+    format!("{}{}", sign, format_size(delta.unsigned_abs()))
+}
+
+/// Total size of the blobs in a commit's tree. Blob sizes are remembered
+/// across commits, most of them are shared.
+fn tree_size(
+    repo: &Repository,
+    tree: Oid,
+    blob_sizes: &mut HashMap<Oid, u64>,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let odb = repo.odb()?;
+    let mut total = 0;
+    let mut failed = None;
+    repo.find_tree(tree)?
+        .walk(TreeWalkMode::PreOrder, |_, entry| {
+            if entry.kind() != Some(ObjectType::Blob) {
+                return TreeWalkResult::Ok;
+            }
+            let size = match blob_sizes.get(&entry.id()) {
+                Some(size) => *size,
+                None => match odb.read_header(entry.id()) {
+                    Ok((size, _)) => {
+                        blob_sizes.insert(entry.id(), size as u64);
+                        size as u64
+                    }
+                    Err(e) => {
+                        failed = Some(e);
+                        return TreeWalkResult::Abort;
+                    }
+                },
+            };
+            total += size;
+            TreeWalkResult::Ok
+        })
+        .ok();
+    match failed {
+        Some(e) => Err(e.into()),
+        None => Ok(total),
+    }
+}
+
+/// Prints how the tracked content grew over the last `count` commits.
+fn print_growth(repo_path: &Path, count: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = Repository::open(repo_path)?;
+    let Ok(head) = repo.head().and_then(|head| head.peel_to_commit()) else {
+        println!("\nNo backups have been committed yet.");
+        return Ok(());
+    };
+    let mut walk = repo.revwalk()?;
+    walk.push(head.id())?;
+    walk.simplify_first_parent()?;
+
+    // one extra commit to compare the oldest shown one with
+    let mut blob_sizes = HashMap::new();
+    let mut commits = Vec::new();
+    for id in walk.take(count + 1) {
+        let commit = repo.find_commit(id?)?;
+        let size = tree_size(&repo, commit.tree_id(), &mut blob_sizes)?;
+        commits.push((commit, size));
+    }
+
+    println!(
+        "\nGrowth over the last {} backup(s):",
+        count.min(commits.len())
+    );
+    for (i, (commit, size)) in commits.iter().take(count).enumerate() {
+        let previous = commits.get(i + 1).map(|(_, size)| *size).unwrap_or(0);
+        println!(
+            "  {}  {:>10}  {:>11}  {}",
+            commit.as_object().short_id()?.as_str().unwrap_or_default(),
+            format_size(*size),
+            signed_size(*size as i64 - previous as i64),
+            commit.summary().ok().flatten().unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// Prints the size of the backup directory, per profile, its largest files
+/// and, for git, how the backups grew and how big the history is.
+pub fn stats(
+    config: &FuxiConfig,
+    backups: usize,
+    top: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo_path = Path::new(repo_path(config)?);
+    if !repo_path.exists() {
+        return Err(format!("{} does not exist.", repo_path.display()).into());
+    }
+
+    let mut files: Vec<(String, u64)> = collect_files(repo_path)?
+        .into_iter()
+        .filter(|rel| !rel.starts_with(".git"))
+        .map(|rel| {
+            let size = repo_path
+                .join(&rel)
+                .metadata()
+                .map(|m| m.len())
+                .unwrap_or(0);
+            (rel.to_string_lossy().to_string(), size)
+        })
+        .collect();
+    let total: u64 = files.iter().map(|(_, size)| size).sum();
+    let history = dir_size(&repo_path.join(".git"));
+
+    println!("Backup directory: {}", style::path(repo_path.display()));
+    println!(
+        "  Files:   {} in {} file(s)",
+        format_size(total),
+        files.len()
+    );
+    if history > 0 {
+        println!("  History: {} (.git)", format_size(history));
+    }
+    println!("  On disk: {}", format_size(total + history));
+
+    let mut profiles: Vec<(String, u64)> = std::fs::read_dir(repo_path)?
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| !name.starts_with('.'))
+        .map(|name| {
+            let size = dir_size(&repo_path.join(&name));
+            (name, size)
+        })
+        .collect();
+    profiles.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    if !profiles.is_empty() {
+        println!("\nPer profile:");
+        for (name, size) in &profiles {
+            println!("  {:>10}  {}", format_size(*size), name);
+        }
+    }
+
+    files.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    if !files.is_empty() && top > 0 {
+        println!("\nLargest files:");
+        for (file, size) in files.iter().take(top) {
+            println!("  {:>10}  {}", format_size(*size), file);
+        }
+    }
+
+    let backend = storage::backend(config)?;
+    match backend.name() {
+        "git" if backups > 0 => print_growth(repo_path, backups)?,
+        "git" => {}
+        "dir" => {
+            let root = config
+                .backend
+                .as_deref()
+                .and_then(|backend| backend.strip_prefix("dir:"))
+                .unwrap_or_default();
+            println!(
+                "\nStored backups: {} in {}",
+                format_size(dir_size(Path::new(root))),
+                root
+            );
+        }
+        name => println!("\nThe size of the {} storage isn't known locally.", name),
+    }
+    Ok(())
+}
@@ -0,0 +1,430 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::cfg::{FuxiConfig, repo_path};
+use crate::crypt::ensure_unlocked;
+use crate::directory::DirectoryBackend;
+use crate::exit::config_error;
+use crate::git::{
+    CommitIdentity, DirtyRepoPolicy, DivergenceStrategy, LogEntry, NetworkError, commit_changes,
+    ensure_branch, fetch_from_remote, guard_dirty, log, prune_history, pull_from_remote,
+    push_branch, push_to_mirrors, remote_log, restore_stash, tag_backup,
+};
+use crate::lfs::{DEFAULT_LARGE_FILE_THRESHOLD_MB, guard_large_files};
+use crate::object_store::ObjectBackend;
+use crate::plugin::{PluginStore, run_hooks};
+use crate::push_policy::record_push;
+use crate::queue;
+use crate::rclone::RcloneStore;
+use crate::s3::S3Store;
+
+/// A backup known to a storage backend.
+pub struct BackupRef {
+    pub id: String,
+    pub summary: String,
+    /// The git tag naming this backup, if it has one.
+    pub tag: Option<String>,
+    pub created: Option<DateTime<Utc>>,
+    /// How many files changed since the backup before it, when the backend knows.
+    pub files_changed: Option<usize>,
+    /// The profile and hostname recorded with the backup, when the backend
+    /// keeps them apart from the metadata.
+    pub profile: Option<String>,
+    pub hostname: Option<String>,
+}
+
+impl BackupRef {
+    /// Whether `query` names this backup, by ID (abbreviated or in full), by
+    /// its tag or by anything in its summary.
+    pub fn matches(&self, query: &str) -> bool {
+        self.id.starts_with(query)
+            || query.starts_with(&self.id)
+            || self.tag.as_deref() == Some(query)
+            || self.summary.contains(query)
+    }
+
+    /// How the backup is shown, its tag when it has one.
+    pub fn name(&self) -> &str {
+        self.tag.as_deref().unwrap_or(&self.id)
+    }
+}
+
+/// Which backups `prune` keeps, set on the command line or as the
+/// `[retention]` policy in the config. A backup is kept when any rule keeps it.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct Retention {
+    pub keep_last: Option<usize>,
+    pub keep_days: Option<u64>,
+    /// The newest backup of each day, for this many days. 0 keeps them forever.
+    pub daily: Option<u32>,
+    /// The newest backup of each week, for this many weeks. 0 keeps them forever.
+    pub weekly: Option<u32>,
+    /// The newest backup of each month, for this many months. 0 keeps them forever.
+    pub monthly: Option<u32>,
+}
+
+// consecutive days, weeks and months get consecutive numbers
+type PeriodNumber = fn(NaiveDate) -> i64;
+
+fn day_number(date: NaiveDate) -> i64 {
+    date.num_days_from_ce() as i64
+}
+
+fn week_number(date: NaiveDate) -> i64 {
+    (day_number(date) - date.weekday().num_days_from_monday() as i64) / 7
+}
+
+fn month_number(date: NaiveDate) -> i64 {
+    date.year() as i64 * 12 + date.month0() as i64
+}
+
+impl Retention {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// For every backup, given by creation time newest first, the rules that
+    /// keep it. Backups no rule keeps are the ones to prune.
+    pub fn reasons(&self, created: &[DateTime<Utc>]) -> Vec<Vec<&'static str>> {
+        let now = Utc::now();
+        let mut reasons = vec![Vec::new(); created.len()];
+        for (position, time) in created.iter().enumerate() {
+            if self.keep_last.is_some_and(|count| position < count) {
+                reasons[position].push("last");
+            }
+            if self
+                .keep_days
+                .is_some_and(|days| now - *time < TimeDelta::days(days as i64))
+            {
+                reasons[position].push("days");
+            }
+        }
+        let local_date = |time: &DateTime<Utc>| time.with_timezone(&Local).date_naive();
+        let periods: [(&str, Option<u32>, PeriodNumber); 3] = [
+            ("daily", self.daily, day_number),
+            ("weekly", self.weekly, week_number),
+            ("monthly", self.monthly, month_number),
+        ];
+        for (name, count, number) in periods {
+            let Some(count) = count else {
+                continue;
+            };
+            let current = number(local_date(&now));
+            let mut last = None;
+            for (position, time) in created.iter().enumerate() {
+                let period = number(local_date(time));
+                if count > 0 && current - period >= count as i64 {
+                    break;
+                }
+                // the first backup seen in a period is its newest
+                if last != Some(period) {
+                    reasons[position].push(name);
+                    last = Some(period);
+                }
+            }
+        }
+        reasons
+    }
+}
+
+/// Where backups are stored. Backups are always written to the local backup
+/// directory first; a backend records and ships that directory and brings
+/// stored backups back into it.
+pub trait Backend {
+    fn name(&self) -> &'static str;
+    /// Readies the local directory before a backup is written into it.
+    fn prepare(&self) -> Result<(), Box<dyn std::error::Error>>;
+    /// Records the local directory as a backup. Returns `false` if nothing changed.
+    fn snapshot(
+        &self,
+        message: Option<String>,
+        allow_secrets: bool,
+    ) -> Result<bool, Box<dyn std::error::Error>>;
+    /// Sends recorded backups to the remote storage.
+    fn push(&self, divergence: DivergenceStrategy) -> Result<(), Box<dyn std::error::Error>>;
+    /// Stored backups, newest first.
+    fn list(&self) -> Result<Vec<BackupRef>, Box<dyn std::error::Error>>;
+    /// Backups on the remote that haven't been brought into the local directory yet, newest first.
+    fn list_remote(&self) -> Result<Vec<BackupRef>, Box<dyn std::error::Error>> {
+        Err(format!(
+            "The {} backend can't list remote backups, only the git backend can.",
+            self.name()
+        )
+        .into())
+    }
+    /// Replaces the local directory with a stored backup, the latest when `id` is `None`.
+    fn fetch(&self, id: Option<&str>) -> Result<(), Box<dyn std::error::Error>>;
+    /// Brings in backups other machines stored without discarding local changes.
+    fn update(&self) -> Result<(), Box<dyn std::error::Error>>;
+    /// Deletes the backups `retention` doesn't keep and returns their IDs.
+    fn prune(
+        &self,
+        _retention: &Retention,
+        _dry_run: bool,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        Err(format!("The {} backend does not support pruning.", self.name()).into())
+    }
+}
+
+fn backup_ref(entry: LogEntry) -> BackupRef {
+    BackupRef {
+        id: entry.hash,
+        summary: entry.summary,
+        tag: entry.tag,
+        created: entry.time,
+        files_changed: Some(entry.files_changed),
+        profile: entry.profile,
+        hostname: entry.hostname,
+    }
+}
+
+pub struct GitBackend {
+    repo_path: PathBuf,
+    branch: String,
+    mirrors: Vec<String>,
+    identity: CommitIdentity,
+    tag_backups: bool,
+    dirty_policy: DirtyRepoPolicy,
+    large_file_threshold_mb: u64,
+    git_lfs: bool,
+}
+
+impl Backend for GitBackend {
+    fn name(&self) -> &'static str {
+        "git"
+    }
+
+    fn prepare(&self) -> Result<(), Box<dyn std::error::Error>> {
+        // a locked clone has no filter configured and would commit plain text
+        ensure_unlocked(&self.repo_path)?;
+        ensure_branch(&self.repo_path, &self.branch)
+    }
+
+    fn snapshot(
+        &self,
+        message: Option<String>,
+        allow_secrets: bool,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        guard_large_files(&self.repo_path, self.large_file_threshold_mb, self.git_lfs)?;
+        let committed = commit_changes(
+            &self.repo_path,
+            &self.branch,
+            &self.identity,
+            message,
+            allow_secrets,
+        )?;
+        if committed && self.tag_backups {
+            tag_backup(&self.repo_path, &self.identity)?;
+        }
+        Ok(committed)
+    }
+
+    fn push(&self, divergence: DivergenceStrategy) -> Result<(), Box<dyn std::error::Error>> {
+        push_branch(&self.repo_path, &self.branch, &self.identity, divergence)?;
+        push_to_mirrors(&self.repo_path, &self.branch, &self.mirrors)
+    }
+
+    fn list(&self) -> Result<Vec<BackupRef>, Box<dyn std::error::Error>> {
+        ensure_branch(&self.repo_path, &self.branch)?;
+        Ok(log(&self.repo_path)?.into_iter().map(backup_ref).collect())
+    }
+
+    fn list_remote(&self) -> Result<Vec<BackupRef>, Box<dyn std::error::Error>> {
+        Ok(remote_log(&self.repo_path, &self.branch)?
+            .into_iter()
+            .map(backup_ref)
+            .collect())
+    }
+
+    fn fetch(&self, id: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        let stashed = guard_dirty(&self.repo_path, self.dirty_policy, &self.identity, "apply")?;
+        let result = fetch_from_remote(&self.repo_path, &self.branch, id);
+        if stashed {
+            restore_stash(&self.repo_path)?;
+        }
+        result?;
+        ensure_unlocked(&self.repo_path)
+    }
+
+    fn update(&self) -> Result<(), Box<dyn std::error::Error>> {
+        ensure_branch(&self.repo_path, &self.branch)?;
+        pull_from_remote(&self.repo_path, &self.branch, &self.identity)?;
+        ensure_unlocked(&self.repo_path)
+    }
+
+    fn prune(
+        &self,
+        retention: &Retention,
+        dry_run: bool,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        prune_history(
+            &self.repo_path,
+            &self.branch,
+            &self.identity,
+            retention,
+            dry_run,
+        )
+    }
+}
+
+/// The backend selected by the `backend` setting, git unless set otherwise.
+pub fn backend(config: &FuxiConfig) -> Result<Box<dyn Backend>, Box<dyn std::error::Error>> {
+    let repo_path = repo_path(config)?;
+    match config.backend.as_deref().unwrap_or("git") {
+        "git" => Ok(Box::new(GitBackend {
+            repo_path: Path::new(repo_path).to_path_buf(),
+            branch: config.git_branch.clone(),
+            mirrors: config.mirrors.clone().unwrap_or_default(),
+            identity: CommitIdentity {
+                name: config.commit_author_name.clone(),
+                email: config.commit_author_email.clone(),
+                signing: config.commit_signing,
+                signing_key: config.commit_signing_key.clone(),
+            },
+            tag_backups: config.tag_backups.unwrap_or(false),
+            dirty_policy: config.dirty_repo_policy.unwrap_or_default(),
+            large_file_threshold_mb: config
+                .large_file_threshold_mb
+                .unwrap_or(DEFAULT_LARGE_FILE_THRESHOLD_MB),
+            git_lfs: config.git_lfs.unwrap_or(false),
+        })),
+        "s3" => Ok(Box::new(ObjectBackend::new(
+            "s3",
+            Path::new(repo_path),
+            Box::new(S3Store::new(config.s3.as_ref().ok_or(
+                "The s3 backend needs an [s3] section with at least a bucket.",
+            )?)?),
+        ))),
+        other if other.starts_with("dir:") => Ok(Box::new(DirectoryBackend::new(
+            Path::new(repo_path),
+            &other["dir:".len()..],
+        )?)),
+        other if other.starts_with("rclone:") => Ok(Box::new(ObjectBackend::new(
+            "rclone",
+            Path::new(repo_path),
+            Box::new(RcloneStore::new(&other["rclone:".len()..])?),
+        ))),
+        other if other.starts_with("plugin:") => Ok(Box::new(ObjectBackend::new(
+            "plugin",
+            Path::new(repo_path),
+            Box::new(PluginStore::new(config, &other["plugin:".len()..])?),
+        ))),
+        other => Err(config_error(format!(
+            "Unknown storage backend '{}'.",
+            other
+        ))),
+    }
+}
+
+/// Records the local directory as a backup and pushes it. When the remote
+/// is unreachable the push is queued for `fuxi flush` instead.
+pub fn snapshot_and_push(
+    config: &FuxiConfig,
+    message: Option<String>,
+    divergence: DivergenceStrategy,
+    allow_secrets: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let backend = backend(config)?;
+    backend.snapshot(message, allow_secrets)?;
+    match backend.push(divergence) {
+        Ok(()) => {
+            queue::dequeue(config)?;
+            record_push(config)?;
+            run_hooks(config, "post-push", json!({"remote": config.remote_url}))
+        }
+        Err(e) if e.is::<NetworkError>() => {
+            queue::enqueue(config, &e.to_string())?;
+            Err(Box::new(NetworkError(format!(
+                "{}\nThe backup is committed locally and its push is queued. It is retried on the next backup, save or sync, or run 'fuxi flush'.",
+                e
+            ))))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Noon `days` ago in local time, minus `hours`, away from midnight so
+    /// the date doesn't depend on when the test runs.
+    fn ago(days: i64, hours: i64) -> DateTime<Utc> {
+        let noon = Local::now()
+            .date_naive()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap();
+        (noon - TimeDelta::days(days) - TimeDelta::hours(hours)).with_timezone(&Utc)
+    }
+
+    #[test]
+    fn keeps_the_last_backups() {
+        let retention = Retention {
+            keep_last: Some(2),
+            ..Retention::default()
+        };
+        let created = [ago(0, 0), ago(1, 0), ago(2, 0), ago(3, 0)];
+        let reasons = retention.reasons(&created);
+        assert_eq!(reasons, [vec!["last"], vec!["last"], vec![], vec![]]);
+    }
+
+    #[test]
+    fn keeps_backups_younger_than_keep_days() {
+        let retention = Retention {
+            keep_days: Some(7),
+            ..Retention::default()
+        };
+        let created = [ago(1, 0), ago(5, 0), ago(8, 0), ago(30, 0)];
+        let reasons = retention.reasons(&created);
+        assert_eq!(reasons, [vec!["days"], vec!["days"], vec![], vec![]]);
+    }
+
+    #[test]
+    fn keeps_the_newest_backup_of_each_day() {
+        let retention = Retention {
+            daily: Some(2),
+            ..Retention::default()
+        };
+        // two backups today and yesterday, one the day before
+        let created = [ago(0, 0), ago(0, 1), ago(1, 0), ago(1, 1), ago(2, 0)];
+        let reasons = retention.reasons(&created);
+        assert_eq!(
+            reasons,
+            [vec!["daily"], vec![], vec!["daily"], vec![], vec![]]
+        );
+    }
+
+    #[test]
+    fn keeps_periods_forever_with_zero() {
+        let retention = Retention {
+            monthly: Some(0),
+            ..Retention::default()
+        };
+        let created = [ago(0, 0), ago(400, 0), ago(400, 1)];
+        let reasons = retention.reasons(&created);
+        assert_eq!(reasons, [vec!["monthly"], vec!["monthly"], vec![]]);
+    }
+
+    #[test]
+    fn lists_every_rule_that_keeps_a_backup() {
+        let retention = Retention {
+            keep_last: Some(1),
+            keep_days: Some(3),
+            weekly: Some(1),
+            ..Retention::default()
+        };
+        let reasons = retention.reasons(&[ago(0, 0), ago(60, 0)]);
+        assert_eq!(reasons, [vec!["last", "days", "weekly"], vec![]]);
+    }
+
+    #[test]
+    fn keeps_nothing_without_rules() {
+        let reasons = Retention::default().reasons(&[ago(0, 0), ago(1, 0)]);
+        assert!(reasons.iter().all(Vec::is_empty));
+    }
+}
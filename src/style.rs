@@ -0,0 +1,33 @@
+use console::{StyledObject, style};
+
+/// Turns colors off, for `--no-color` and `--porcelain`. Otherwise they're
+/// on in a terminal unless `NO_COLOR` is set, and off when piped.
+pub fn disable() {
+    console::set_colors_enabled(false);
+    console::set_colors_enabled_stderr(false);
+}
+
+/// A command or step that finished as asked, in green.
+pub fn success<D>(message: D) -> StyledObject<D> {
+    style(message).green()
+}
+
+/// Something the user should look at, though the command went on, in yellow.
+pub fn warning<D>(message: D) -> StyledObject<D> {
+    style(message).yellow()
+}
+
+/// The error a command failed with, in red on stderr.
+pub fn error<D>(message: D) -> StyledObject<D> {
+    style(message).red().for_stderr()
+}
+
+/// A file or directory path, dimmed so the words around it stand out.
+pub fn path<D>(path: D) -> StyledObject<D> {
+    style(path).dim()
+}
+
+/// The header row of a table, in bold.
+pub fn heading<D>(text: D) -> StyledObject<D> {
+    style(text).bold()
+}
@@ -0,0 +1,229 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use git2::{IndexEntry, IndexTime, Oid, Repository, build::CheckoutBuilder};
+
+use crate::git::{clone_repository, open_repo};
+use crate::manifest::ManifestEntry;
+use crate::overlay::ProfileLayout;
+use crate::style;
+
+const GITMODULES: &str = ".gitmodules";
+
+/// Whether the `nested_repo_submodules` setting or `backup --submodules`
+/// asked for nested repositories to be referenced instead of copied. Set
+/// once the command line is parsed.
+static REFERENCE_NESTED_REPOS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_reference_nested_repos(reference: bool) {
+    REFERENCE_NESTED_REPOS.store(reference, Ordering::Relaxed);
+}
+
+pub fn references_nested_repos() -> bool {
+    REFERENCE_NESTED_REPOS.load(Ordering::Relaxed)
+}
+
+/// The file mode git records submodules with.
+const GITLINK_MODE: u32 = 0o160000;
+
+/// Directories below `dir` (or `dir` itself) that are git repositories,
+/// relative to `dir`. Repositories nested in those aren't looked for.
+fn nested_repos(dir: &Path) -> Vec<PathBuf> {
+    let mut repos = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(rel) = pending.pop() {
+        let path = dir.join(&rel);
+        if path.join(".git").exists() {
+            repos.push(rel);
+            continue;
+        }
+        let Ok(entries) = fs::read_dir(&path) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if entry.path().is_dir() && !entry.path().is_symlink() {
+                pending.push(rel.join(entry.file_name()));
+            }
+        }
+    }
+    repos.sort();
+    repos
+}
+
+/// The URL of `origin`, or of the first remote when there's no `origin`.
+fn remote_of(repo: &Repository) -> Option<String> {
+    let remote = match repo.find_remote("origin") {
+        Ok(remote) => remote,
+        Err(_) => {
+            let names = repo.remotes().ok()?;
+            let name = names.iter().flatten().flatten().next()?.to_string();
+            repo.find_remote(&name).ok()?
+        }
+    };
+    remote.url().ok().map(str::to_string)
+}
+
+fn repo_relative(repo_path: &Path, path: &Path) -> Option<String> {
+    let rel = path.strip_prefix(repo_path).ok()?;
+    Some(
+        rel.components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+/// Replaces the copy of every git repository nested in `src` with a
+/// submodule of the backup repo at `repo_path`, recording the repository's
+/// remote and checked out commit instead of its content. Repositories
+/// without a remote or a commit stay copied.
+pub fn reference_nested_repos(
+    repo_path: &Path,
+    src: &Path,
+    dst: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !src.is_dir() {
+        return Ok(());
+    }
+    let nested = nested_repos(src);
+    if nested.is_empty() {
+        return Ok(());
+    }
+    let backup_repo = open_repo(repo_path)?;
+    let mut index = backup_repo.index()?;
+    let mut modules = git2::Config::open(&repo_path.join(GITMODULES))?;
+
+    for rel in nested {
+        let live = src.join(&rel);
+        let Ok(repo) = Repository::open(&live) else {
+            continue;
+        };
+        let commit = repo.head().and_then(|head| head.peel_to_commit());
+        let (Some(url), Ok(commit)) = (remote_of(&repo), commit) else {
+            println!(
+                "Copied nested repository {} as is, it has no remote or commit to reference.",
+                style::path(live.display())
+            );
+            continue;
+        };
+        let target = dst.join(&rel);
+        let Some(path) = repo_relative(repo_path, &target) else {
+            continue;
+        };
+
+        // an unpopulated submodule is an empty directory
+        if target.exists() {
+            fs::remove_dir_all(&target)?;
+        }
+        fs::create_dir_all(&target)?;
+        modules.set_str(&format!("submodule.{}.path", path), &path)?;
+        modules.set_str(&format!("submodule.{}.url", path), &url)?;
+        index.remove_dir(Path::new(&path), 0)?;
+        index.add(&gitlink(&path, commit.id()))?;
+        println!(
+            "Referenced {} as a submodule ({} at {})",
+            style::path(live.display()),
+            url,
+            commit.as_object().short_id()?.as_str().unwrap_or_default()
+        );
+    }
+    index.write()?;
+    Ok(())
+}
+
+fn gitlink(path: &str, id: Oid) -> IndexEntry {
+    IndexEntry {
+        ctime: IndexTime::new(0, 0),
+        mtime: IndexTime::new(0, 0),
+        dev: 0,
+        ino: 0,
+        mode: GITLINK_MODE,
+        uid: 0,
+        gid: 0,
+        file_size: 0,
+        id,
+        flags: 0,
+        flags_extended: 0,
+        path: path.as_bytes().to_vec(),
+    }
+}
+
+/// Clones the submodules of the applied entries to where their live
+/// repositories belong, checked out at the recorded commit. Live
+/// repositories that already exist are left alone.
+pub fn clone_submodules(
+    repo_path: &Path,
+    layout: &ProfileLayout,
+    entries: &[ManifestEntry],
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !repo_path.join(GITMODULES).is_file() {
+        return Ok(());
+    }
+    let backup_repo = open_repo(repo_path)?;
+    let modules = git2::Config::open(&repo_path.join(GITMODULES))?;
+    let gitlinks: Vec<(String, Oid)> = backup_repo
+        .index()?
+        .iter()
+        .filter(|entry| entry.mode == GITLINK_MODE)
+        .map(|entry| (String::from_utf8_lossy(&entry.path).to_string(), entry.id))
+        .collect();
+
+    for (path, commit) in gitlinks {
+        let Ok(in_profile) = repo_path
+            .join(&path)
+            .strip_prefix(&layout.root)
+            .map(Path::to_path_buf)
+        else {
+            continue;
+        };
+        let Some((entry, rest)) = entries.iter().find_map(|entry| {
+            let rest = in_profile.strip_prefix(&entry.repo_path).ok()?;
+            Some((entry, rest.to_path_buf()))
+        }) else {
+            continue;
+        };
+        let live = Path::new(&entry.source).join(rest);
+        if live.join(".git").exists() {
+            continue;
+        }
+        let Ok(url) = modules.get_string(&format!("submodule.{}.url", path)) else {
+            continue;
+        };
+        if live.exists() && fs::read_dir(&live)?.next().is_some() {
+            println!(
+                "{}",
+                style::warning(format!(
+                    "Warning: Not cloning {} into {}, the directory isn't empty.",
+                    url,
+                    live.display()
+                ))
+            );
+            continue;
+        }
+        if dry_run {
+            println!(
+                "[Dry Run] Would clone {} into {}",
+                url,
+                style::path(live.display())
+            );
+            continue;
+        }
+
+        clone_repository(&url, &live, None)?;
+        let repo = Repository::open(&live)?;
+        repo.set_head_detached(commit)
+            .map_err(|e| format!("{} has no commit {}: {}", url, commit, e.message()))?;
+        repo.checkout_head(Some(CheckoutBuilder::new().force()))?;
+        println!(
+            "Cloned {} into {} at {}",
+            url,
+            style::path(live.display()),
+            commit
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,312 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::apply::configured_entries;
+use crate::backup::{excludes, is_excluded};
+use crate::cfg::FuxiConfig;
+use crate::conflict::files_differ;
+use crate::copy::{collect_files, copy_file_or_path, copy_privileged, run_privileged};
+use crate::git::last_commit_time;
+use crate::hash_cache::HashCache;
+use crate::manifest::ManifestEntry;
+use crate::overlay::{ProfileLayout, join_rel};
+use crate::portable::decode_path;
+use crate::script;
+use crate::snapshot::Snapshot;
+use crate::style;
+
+#[derive(Debug, Default)]
+pub struct SyncSummary {
+    pub applied: usize,
+    pub backed_up: usize,
+    pub unchanged: usize,
+}
+
+fn mtime_secs(path: &Path) -> std::io::Result<i64> {
+    let modified = fs::metadata(path)?.modified()?;
+    Ok(modified
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0))
+}
+
+/// When the repo copy last changed: its last commit, or its mtime if uncommitted.
+fn repo_file_time(repo_path: &Path, repo_file: &Path) -> std::io::Result<i64> {
+    let relative = repo_file.strip_prefix(repo_path).unwrap_or(repo_file);
+    match last_commit_time(repo_path, relative) {
+        Some(time) => Ok(time),
+        None => mtime_secs(repo_file),
+    }
+}
+
+/// Pairs up every file on either side of an entry as `(repo file, live file)`.
+/// Files only present locally are paired with where a backup would put them.
+pub fn entry_pairs(
+    layout: &ProfileLayout,
+    entry: &ManifestEntry,
+) -> std::io::Result<Vec<(PathBuf, PathBuf)>> {
+    let live_root = PathBuf::from(&entry.source);
+
+    let mut files: BTreeMap<PathBuf, PathBuf> = layout
+        .resolve(&entry.repo_path)?
+        .into_iter()
+        .map(|(rel, repo_file)| (decode_path(entry, &rel), repo_file))
+        .collect();
+    if live_root.is_dir() {
+        for rel in collect_files(&live_root)? {
+            let target = layout.backup_target(&entry.repo_path, &rel);
+            files.entry(rel).or_insert(target);
+        }
+    } else if files.is_empty() {
+        files.insert(
+            PathBuf::new(),
+            layout.backup_target(&entry.repo_path, Path::new("")),
+        );
+    }
+
+    Ok(files
+        .into_iter()
+        .map(|(rel, repo_file)| (repo_file, join_rel(&live_root, &rel)))
+        .collect())
+}
+
+/// How a tracked path compares to its copy in the backup repository.
+pub enum PathStatus {
+    Unchanged,
+    Changed,
+    NotBackedUp,
+    Missing,
+    /// Templates and secrets are stored differently from the live files.
+    NotCompared,
+}
+
+impl PathStatus {
+    /// Describes the status for `fuxi path list --check`.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Self::Unchanged => "unchanged since the last backup",
+            Self::Changed => "changed since the last backup",
+            Self::NotBackedUp => "not backed up yet",
+            Self::Missing => "missing",
+            Self::NotCompared => "not compared, templates and secrets are stored differently",
+        }
+    }
+
+    /// The status's name in porcelain output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Unchanged => "unchanged",
+            Self::Changed => "changed",
+            Self::NotBackedUp => "not-backed-up",
+            Self::Missing => "missing",
+            Self::NotCompared => "not-compared",
+        }
+    }
+}
+
+pub fn path_status(
+    layout: &ProfileLayout,
+    entry: &ManifestEntry,
+    cache: &mut HashCache,
+) -> Result<PathStatus, Box<dyn std::error::Error>> {
+    if !Path::new(&entry.source).exists() {
+        return Ok(PathStatus::Missing);
+    }
+    if entry.template || entry.encrypted {
+        return Ok(PathStatus::NotCompared);
+    }
+    let pairs = entry_pairs(layout, entry)?;
+    if !pairs.iter().any(|(repo_file, _)| repo_file.is_file()) {
+        return Ok(PathStatus::NotBackedUp);
+    }
+    for (repo_file, live_file) in pairs {
+        let changed = match (repo_file.is_file(), live_file.is_file()) {
+            (true, true) => cache.differ(&repo_file, &live_file)?,
+            (false, false) => false,
+            _ => true,
+        };
+        if changed {
+            return Ok(PathStatus::Changed);
+        }
+    }
+    Ok(PathStatus::Unchanged)
+}
+
+/// Brings the repo and the live system up to date with each other, letting the
+/// most recently changed side win for every file. Entries go through the
+/// same per-path options as backups and applies: disabled ones are skipped,
+/// `target` is synced instead of `source`, and files left out by `excludes`
+/// or the hook script are left alone on both sides.
+pub fn sync_entries(
+    config: &FuxiConfig,
+    repo_path: &Path,
+    layout: &ProfileLayout,
+    entries: &[ManifestEntry],
+    dry_run: bool,
+    snapshot: &mut Snapshot,
+) -> Result<SyncSummary, Box<dyn std::error::Error>> {
+    let mut summary = SyncSummary::default();
+
+    for entry in entries {
+        if entry.template {
+            println!(
+                "Skipped template {}, use 'fuxi apply' to render it",
+                entry.source
+            );
+            continue;
+        }
+        if entry.encrypted {
+            println!(
+                "Skipped secret {}, use 'fuxi backup' and 'fuxi apply' for encrypted paths",
+                entry.source
+            );
+            continue;
+        }
+        // looked up before the entry is moved to its target
+        let excludes = excludes(config, &entry.source);
+        let Some(entry) = configured_entries(config, std::slice::from_ref(entry)).pop() else {
+            continue;
+        };
+
+        for (repo_file, live_file) in entry_pairs(layout, &entry)? {
+            let rel = live_file.strip_prefix(&entry.source).unwrap_or(&live_file);
+            if is_excluded(&excludes, rel) || !script::filter(config, &live_file)? {
+                continue;
+            }
+            let apply = match (repo_file.is_file(), live_file.is_file()) {
+                (false, false) => continue,
+                (true, false) => true,
+                (false, true) => false,
+                (true, true) => {
+                    if !files_differ(&repo_file, &live_file)? {
+                        summary.unchanged += 1;
+                        continue;
+                    }
+                    repo_file_time(repo_path, &repo_file)? > mtime_secs(&live_file)?
+                }
+            };
+
+            if apply {
+                if dry_run {
+                    println!(
+                        "[Dry Run] Would apply {} to {}",
+                        style::path(repo_file.display()),
+                        style::path(live_file.display())
+                    );
+                } else {
+                    if live_file.is_file() {
+                        snapshot.save(&live_file)?;
+                    }
+                    if entry.sudo {
+                        copy_privileged(&repo_file, &live_file);
+                    } else {
+                        copy_file_or_path(&repo_file, &live_file)?;
+                        println!(
+                            "Applied {} to {}",
+                            style::path(repo_file.display()),
+                            style::path(live_file.display())
+                        );
+                    }
+                }
+                summary.applied += 1;
+            } else {
+                if dry_run {
+                    println!(
+                        "[Dry Run] Would back up {} to {}",
+                        style::path(live_file.display()),
+                        style::path(repo_file.display())
+                    );
+                } else if entry.sudo {
+                    copy_privileged(&live_file, &repo_file);
+                } else {
+                    copy_file_or_path(&live_file, &repo_file)?;
+                    println!(
+                        "Backed up {} to {}",
+                        style::path(live_file.display()),
+                        style::path(repo_file.display())
+                    );
+                }
+                summary.backed_up += 1;
+            }
+        }
+    }
+    run_privileged()?;
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfg::PathEntry;
+
+    fn entry(source: &Path, repo_path: &str) -> ManifestEntry {
+        ManifestEntry {
+            source: source.to_string_lossy().to_string(),
+            repo_path: repo_path.to_string(),
+            preset: None,
+            template: false,
+            encrypted: false,
+            owner: None,
+            xattrs: false,
+            hardlinks: Vec::new(),
+            sudo: false,
+            encoded_names: Default::default(),
+        }
+    }
+
+    #[test]
+    fn skips_disabled_entries_and_excluded_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let profile_dir = tmp.path().join("repo/main");
+        fs::create_dir_all(&profile_dir).unwrap();
+        let nvim = tmp.path().join("home/nvim");
+        fs::create_dir_all(&nvim).unwrap();
+        fs::write(nvim.join("init.lua"), "init").unwrap();
+        fs::write(nvim.join("debug.log"), "log").unwrap();
+        let old = tmp.path().join("home/old.conf");
+        fs::write(&old, "old").unwrap();
+
+        let config = FuxiConfig {
+            selected_profile: Some("main".to_string()),
+            profiles: Some(
+                [(
+                    "main".to_string(),
+                    vec![
+                        PathEntry {
+                            excludes: Some(vec!["*.log".to_string()]),
+                            ..PathEntry::new(nvim.to_string_lossy())
+                        },
+                        PathEntry {
+                            disabled: Some(true),
+                            ..PathEntry::new(old.to_string_lossy())
+                        },
+                    ],
+                )]
+                .into(),
+            ),
+            ..FuxiConfig::default()
+        };
+        let entries = [entry(&nvim, "nvim"), entry(&old, "old.conf")];
+        let mut snapshot = Snapshot::new("sync_test").unwrap();
+
+        let summary = sync_entries(
+            &config,
+            &tmp.path().join("repo"),
+            &ProfileLayout::flat(&profile_dir),
+            &entries,
+            false,
+            &mut snapshot,
+        )
+        .unwrap();
+
+        assert_eq!(summary.backed_up, 1);
+        assert!(profile_dir.join("nvim/init.lua").is_file());
+        assert!(!profile_dir.join("nvim/debug.log").exists());
+        assert!(!profile_dir.join("old.conf").exists());
+    }
+}
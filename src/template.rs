@@ -0,0 +1,162 @@
+use std::{collections::HashMap, env};
+
+use crate::cfg::{FuxiConfig, get_machine_name};
+use crate::machine::hostname;
+
+/// Variables available to templates: built-ins, then `template_variables`,
+/// then `machine_variables.<machine>` from the config, later ones winning.
+pub fn template_variables(config: &FuxiConfig) -> HashMap<String, String> {
+    let mut vars = HashMap::new();
+    vars.insert("hostname".to_string(), hostname());
+    vars.insert("os".to_string(), env::consts::OS.to_string());
+    vars.insert(
+        "username".to_string(),
+        env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_default(),
+    );
+    if let Some(home) = dirs::home_dir() {
+        vars.insert("home".to_string(), home.to_string_lossy().to_string());
+    }
+    vars.insert("machine".to_string(), get_machine_name(config));
+
+    if let Some(user_vars) = &config.template_variables {
+        vars.extend(user_vars.clone());
+    }
+    if let Some(machine_vars) = config
+        .machine_variables
+        .as_ref()
+        .and_then(|m| m.get(&get_machine_name(config)))
+    {
+        vars.extend(machine_vars.clone());
+    }
+    vars
+}
+
+/// Replaces every `{{ name }}` with its value. `\{{` produces a literal `{{`.
+pub fn render(template: &str, vars: &HashMap<String, String>) -> Result<String, String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        if rest[..start].ends_with('\\') {
+            out.push_str(&rest[..start - 1]);
+            out.push_str("{{");
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| "Unclosed '{{' in template".to_string())?;
+        let name = after[..end].trim();
+        let value = vars
+            .get(name)
+            .ok_or_else(|| format!("Unknown template variable '{}'", name))?;
+        out.push_str(value);
+        rest = &after[end + 2..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// The commit subject without `-m` or a `commit_message_template`.
+const DEFAULT_COMMIT_MESSAGE: &str = "Backup {{ backup_id }} of {{ profile }} from {{ hostname }}";
+/// [`DEFAULT_COMMIT_MESSAGE`] for commits of no particular backup.
+const DEFAULT_SAVE_MESSAGE: &str = "Save {{ profile }} from {{ hostname }}";
+
+/// Builds a backup commit message from `commit_message_template`, where
+/// `{{ message }}` is the given message or else [`DEFAULT_COMMIT_MESSAGE`],
+/// followed by the backup's metadata as trailers.
+pub fn commit_message(
+    config: &FuxiConfig,
+    message: Option<String>,
+    backup_id: Option<&str>,
+    file_count: usize,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let timestamp = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let profile = config.selected_profile.clone().unwrap_or_default();
+    let backup_id = backup_id.unwrap_or_default().to_string();
+
+    let mut vars = template_variables(config);
+    vars.insert("profile".to_string(), profile.clone());
+    vars.insert("file_count".to_string(), file_count.to_string());
+    vars.insert("backup_id".to_string(), backup_id.clone());
+    vars.insert("timestamp".to_string(), timestamp.clone());
+    let message = match message {
+        Some(message) => message,
+        None if backup_id.is_empty() => render(DEFAULT_SAVE_MESSAGE, &vars)?,
+        None => render(DEFAULT_COMMIT_MESSAGE, &vars)?,
+    };
+    vars.insert("message".to_string(), message);
+    let template = config
+        .commit_message_template
+        .as_deref()
+        .unwrap_or("{{ message }}");
+    let subject =
+        render(template, &vars).map_err(|e| format!("Invalid commit_message_template: {}", e))?;
+
+    let mut trailers = vec![
+        ("Backup-Id", backup_id),
+        ("Profile", profile),
+        ("Machine", get_machine_name(config)),
+        ("Hostname", hostname()),
+        ("Files", file_count.to_string()),
+        ("Timestamp", timestamp),
+    ];
+    trailers.retain(|(_, value)| !value.is_empty());
+    let body: Vec<String> = trailers
+        .iter()
+        .map(|(key, value)| format!("Fuxi-{}: {}", key, value))
+        .collect();
+    Ok(format!("{}\n\n{}", subject.trim(), body.join("\n")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(template: Option<&str>) -> FuxiConfig {
+        FuxiConfig {
+            selected_profile: Some("main".to_string()),
+            commit_message_template: template.map(str::to_string),
+            ..FuxiConfig::default()
+        }
+    }
+
+    fn subject(message: &str) -> &str {
+        message.lines().next().unwrap()
+    }
+
+    #[test]
+    fn builds_a_message_without_one_given() {
+        let message = commit_message(&config(None), None, Some("backup_1"), 3).unwrap();
+        assert_eq!(
+            subject(&message),
+            format!("Backup backup_1 of main from {}", hostname())
+        );
+        assert!(message.contains("Fuxi-Backup-Id: backup_1"));
+
+        let message = commit_message(&config(None), None, None, 3).unwrap();
+        assert_eq!(subject(&message), format!("Save main from {}", hostname()));
+    }
+
+    #[test]
+    fn uses_the_given_message_in_the_template() {
+        let message =
+            commit_message(&config(None), Some("tweak zsh".to_string()), Some("b"), 1).unwrap();
+        assert_eq!(subject(&message), "tweak zsh");
+
+        let templated = config(Some("{{ profile }}: {{ message }}"));
+        let message = commit_message(&templated, Some("tweak zsh".to_string()), None, 1).unwrap();
+        assert_eq!(subject(&message), "main: tweak zsh");
+        let message = commit_message(&templated, None, Some("b"), 1).unwrap();
+        assert_eq!(
+            subject(&message),
+            format!("main: Backup b of main from {}", hostname())
+        );
+    }
+}
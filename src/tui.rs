@@ -0,0 +1,467 @@
+use std::{
+    env,
+    io::{self, IsTerminal},
+    path::Path,
+    process::Command,
+};
+
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Flex, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Clear, List, ListItem, ListState, Paragraph},
+};
+
+use crate::cfg::{
+    FuxiConfig, get_machine_name, get_profile_dir, get_selected_profile_paths, load_config,
+    profile_names, save_config,
+};
+use crate::cli::prompt;
+use crate::conflict::{files_differ, short_diff};
+use crate::hash_cache::HashCache;
+use crate::manifest::{ManifestEntry, manifest_entry};
+use crate::overlay::ProfileLayout;
+use crate::storage::{self, BackupRef};
+use crate::sync::{PathStatus, entry_pairs, path_status};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Pane {
+    Profiles,
+    Paths,
+    History,
+}
+
+impl PathStatus {
+    fn marker(&self) -> Span<'static> {
+        match self {
+            Self::Unchanged => Span::raw(" "),
+            Self::Changed => Span::styled("M", Style::new().fg(Color::Yellow)),
+            Self::NotBackedUp => Span::styled("?", Style::new().fg(Color::Blue)),
+            Self::Missing => Span::styled("!", Style::new().fg(Color::Red)),
+            Self::NotCompared => Span::styled("~", Style::new().fg(Color::DarkGray)),
+        }
+    }
+}
+
+/// What applying the backup would change for `entry`, `-` lines being
+/// this machine's and `+` lines the backup's.
+fn diff_text(
+    layout: &ProfileLayout,
+    entry: &ManifestEntry,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut text = String::new();
+    for (repo_file, live_file) in entry_pairs(layout, entry)? {
+        match (repo_file.is_file(), live_file.is_file()) {
+            (true, true) if files_differ(&repo_file, &live_file)? => {
+                text.push_str(&format!("{}\n", live_file.display()));
+                text.push_str(&short_diff(&live_file, &repo_file));
+            }
+            (true, false) => {
+                text.push_str(&format!("{}\n  only in the backup\n", live_file.display()))
+            }
+            (false, true) => {
+                text.push_str(&format!("{}\n  not backed up yet\n", live_file.display()))
+            }
+            _ => {}
+        }
+    }
+    if text.is_empty() {
+        text.push_str("No differences.");
+    }
+    Ok(text)
+}
+
+struct App {
+    config: FuxiConfig,
+    profiles: Vec<String>,
+    paths: Vec<(ManifestEntry, PathStatus)>,
+    backups: Vec<BackupRef>,
+    pane: Pane,
+    profiles_state: ListState,
+    paths_state: ListState,
+    history_state: ListState,
+    /// Title and text of the open diff, and how far it's scrolled.
+    diff: Option<(String, String)>,
+    diff_scroll: u16,
+    /// Backup waiting for the user to confirm applying it.
+    confirm_apply: Option<String>,
+    message: String,
+}
+
+impl App {
+    fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut app = Self {
+            config: FuxiConfig::default(),
+            profiles: Vec::new(),
+            paths: Vec::new(),
+            backups: Vec::new(),
+            pane: Pane::Profiles,
+            profiles_state: ListState::default(),
+            paths_state: ListState::default(),
+            history_state: ListState::default(),
+            diff: None,
+            diff_scroll: 0,
+            confirm_apply: None,
+            message: String::new(),
+        };
+        app.refresh()?;
+        Ok(app)
+    }
+
+    fn layout(&self) -> Option<ProfileLayout> {
+        let repo_path = Path::new(self.config.backup_repo_path.as_ref()?);
+        let profile = self.config.selected_profile.as_ref()?;
+        let profile_dir = get_profile_dir(&self.config, repo_path, profile, None);
+        Some(ProfileLayout::new(
+            &profile_dir,
+            &get_machine_name(&self.config),
+        ))
+    }
+
+    /// Reloads the config, compares the tracked paths and lists the backups.
+    fn refresh(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.config = load_config()?;
+
+        self.profiles = profile_names(&self.config);
+
+        self.paths = Vec::new();
+        let layout = self.layout();
+        let mut cache = HashCache::load();
+        for path in get_selected_profile_paths(&self.config) {
+            let entry = manifest_entry(&self.config, &path);
+            let status = match &layout {
+                Some(layout) => path_status(layout, &entry, &mut cache)?,
+                None => PathStatus::NotBackedUp,
+            };
+            self.paths.push((entry, status));
+        }
+        cache.save()?;
+
+        self.backups = match storage::backend(&self.config).and_then(|backend| backend.list()) {
+            Ok(backups) => backups,
+            Err(e) => {
+                self.message = format!("Could not list backups: {}", e);
+                Vec::new()
+            }
+        };
+
+        if self.profiles_state.selected().is_none() {
+            let selected = self
+                .config
+                .selected_profile
+                .as_ref()
+                .and_then(|name| self.profiles.iter().position(|p| p == name));
+            self.profiles_state.select(selected);
+        }
+        clamp(&mut self.profiles_state, self.profiles.len());
+        clamp(&mut self.paths_state, self.paths.len());
+        clamp(&mut self.history_state, self.backups.len());
+        Ok(())
+    }
+
+    /// Leaves the full-screen view to run `fuxi <args>` in the terminal, so
+    /// its output and prompts show up as usual.
+    fn run_fuxi(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        args: &[&str],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        ratatui::restore();
+        println!("$ fuxi {}", args.join(" "));
+        let status = Command::new(env::current_exe()?).args(args).status();
+        prompt("\nPress Enter to return")?;
+        let refreshed = self.refresh();
+        *terminal = ratatui::init();
+
+        self.message = match status {
+            Ok(status) if status.success() => format!("'fuxi {}' finished.", args.join(" ")),
+            Ok(status) => format!("'fuxi {}' failed ({}).", args.join(" "), status),
+            Err(e) => format!("Failed to run fuxi: {}", e),
+        };
+        refreshed
+    }
+
+    fn select_profile(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(name) = self
+            .profiles_state
+            .selected()
+            .and_then(|i| self.profiles.get(i))
+            .cloned()
+        else {
+            return Ok(());
+        };
+        self.config.selected_profile = Some(name.clone());
+        save_config(&self.config)?;
+        self.paths_state = ListState::default();
+        self.history_state = ListState::default();
+        self.refresh()?;
+        self.message = format!("Switched to profile '{}'.", name);
+        Ok(())
+    }
+
+    fn open_diff(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some((entry, status)) = self.paths_state.selected().and_then(|i| self.paths.get(i))
+        else {
+            return Ok(());
+        };
+        let text = match (status, self.layout()) {
+            (PathStatus::NotCompared, _) => {
+                "Templates and secrets are stored rendered or encrypted, so they aren't compared."
+                    .to_string()
+            }
+            (_, Some(layout)) => diff_text(&layout, entry)?,
+            (_, None) => "No backup repository is set up.".to_string(),
+        };
+        self.diff = Some((entry.source.clone(), text));
+        self.diff_scroll = 0;
+        Ok(())
+    }
+
+    /// Handles a key press, returning whether to quit.
+    fn handle_key(
+        &mut self,
+        code: KeyCode,
+        terminal: &mut DefaultTerminal,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.diff.is_some() {
+            match code {
+                KeyCode::Esc | KeyCode::Char('q' | 'd') => self.diff = None,
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.diff_scroll = self.diff_scroll.saturating_add(1)
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.diff_scroll = self.diff_scroll.saturating_sub(1)
+                }
+                KeyCode::PageDown => self.diff_scroll = self.diff_scroll.saturating_add(20),
+                KeyCode::PageUp => self.diff_scroll = self.diff_scroll.saturating_sub(20),
+                _ => {}
+            }
+            return Ok(false);
+        }
+        if let Some(id) = self.confirm_apply.take() {
+            if code == KeyCode::Char('y') {
+                self.run_fuxi(terminal, &["apply", &id])?;
+            } else {
+                self.message = "Apply cancelled.".to_string();
+            }
+            return Ok(false);
+        }
+
+        self.message.clear();
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(true),
+            KeyCode::Tab | KeyCode::Right | KeyCode::Char('l') => {
+                self.pane = match self.pane {
+                    Pane::Profiles => Pane::Paths,
+                    Pane::Paths => Pane::History,
+                    Pane::History => Pane::Profiles,
+                }
+            }
+            KeyCode::BackTab | KeyCode::Left | KeyCode::Char('h') => {
+                self.pane = match self.pane {
+                    Pane::Profiles => Pane::History,
+                    Pane::Paths => Pane::Profiles,
+                    Pane::History => Pane::Paths,
+                }
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.focused_state().select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.focused_state().select_previous(),
+            KeyCode::Enter if self.pane == Pane::Profiles => {
+                self.select_profile()?;
+                // switching branches prints, so the screen is redrawn
+                terminal.clear()?;
+            }
+            KeyCode::Enter | KeyCode::Char('d') if self.pane == Pane::Paths => self.open_diff()?,
+            KeyCode::Char('d') => self.message = "Pick a path to diff it.".to_string(),
+            KeyCode::Char('b') => self.run_fuxi(terminal, &["backup", "--push"])?,
+            KeyCode::Char('a') => match self
+                .history_state
+                .selected()
+                .and_then(|i| self.backups.get(i))
+            {
+                Some(backup) if self.pane == Pane::History => {
+                    self.confirm_apply = Some(backup.name().to_string())
+                }
+                _ => self.message = "Pick a backup in the history to apply it.".to_string(),
+            },
+            KeyCode::Char('r') => {
+                self.refresh()?;
+                terminal.clear()?;
+                self.message = "Refreshed.".to_string();
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    fn focused_state(&mut self) -> &mut ListState {
+        match self.pane {
+            Pane::Profiles => &mut self.profiles_state,
+            Pane::Paths => &mut self.paths_state,
+            Pane::History => &mut self.history_state,
+        }
+    }
+
+    fn block(&self, title: &str, pane: Pane) -> Block<'static> {
+        let style = if self.pane == pane {
+            Style::new().fg(Color::Cyan)
+        } else {
+            Style::new()
+        };
+        Block::bordered()
+            .title(format!(" {} ", title))
+            .border_style(style)
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, footer] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [profiles_area, paths_area, history_area] = Layout::horizontal([
+            Constraint::Percentage(20),
+            Constraint::Percentage(45),
+            Constraint::Percentage(35),
+        ])
+        .areas(main);
+        let highlight = Style::new().add_modifier(Modifier::REVERSED);
+
+        let selected = self.config.selected_profile.as_deref();
+        let profiles: Vec<ListItem> = self
+            .profiles
+            .iter()
+            .map(|name| {
+                let marker = if Some(name.as_str()) == selected {
+                    "* "
+                } else {
+                    "  "
+                };
+                ListItem::new(format!("{}{}", marker, name))
+            })
+            .collect();
+        frame.render_stateful_widget(
+            List::new(profiles)
+                .block(self.block("Profiles", Pane::Profiles))
+                .highlight_style(highlight),
+            profiles_area,
+            &mut self.profiles_state,
+        );
+
+        let paths: Vec<ListItem> = self
+            .paths
+            .iter()
+            .map(|(entry, status)| {
+                ListItem::new(Line::from(vec![
+                    status.marker(),
+                    Span::raw(" "),
+                    Span::raw(entry.source.clone()),
+                ]))
+            })
+            .collect();
+        frame.render_stateful_widget(
+            List::new(paths)
+                .block(
+                    self.block("Paths", Pane::Paths)
+                        .title_bottom(" M changed  ? not backed up  ! missing  ~ skipped "),
+                )
+                .highlight_style(highlight),
+            paths_area,
+            &mut self.paths_state,
+        );
+
+        let backups: Vec<ListItem> = self
+            .backups
+            .iter()
+            .map(|backup| ListItem::new(format!("{} {}", backup.name(), backup.summary)))
+            .collect();
+        frame.render_stateful_widget(
+            List::new(backups)
+                .block(self.block("History", Pane::History))
+                .highlight_style(highlight),
+            history_area,
+            &mut self.history_state,
+        );
+
+        let footer_text = if let Some(id) = &self.confirm_apply {
+            format!("Apply backup {} to this machine? (y/N)", id)
+        } else if !self.message.is_empty() {
+            self.message.clone()
+        } else {
+            "Tab pane  ↑↓ move  Enter select profile  d diff  b back up  a apply  r refresh  q quit"
+                .to_string()
+        };
+        frame.render_widget(Paragraph::new(footer_text), footer);
+
+        if let Some((path, text)) = &self.diff {
+            let area = popup_area(frame.area());
+            let lines: Vec<Line> = text
+                .lines()
+                .map(|line| {
+                    let style = if line.starts_with("  + ") {
+                        Style::new().fg(Color::Green)
+                    } else if line.starts_with("  - ") {
+                        Style::new().fg(Color::Red)
+                    } else {
+                        Style::new()
+                    };
+                    Line::styled(line.to_string(), style)
+                })
+                .collect();
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                Paragraph::new(lines)
+                    .block(
+                        Block::bordered()
+                            .title(format!(" Diff of {} ", path))
+                            .title_bottom(" - this machine  + backup  Esc close "),
+                    )
+                    .scroll((self.diff_scroll, 0)),
+                area,
+            );
+        }
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), Box<dyn std::error::Error>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind == KeyEventKind::Press && self.handle_key(key.code, terminal)? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Keeps the selection of a list within its `len` items.
+fn clamp(state: &mut ListState, len: usize) {
+    let selected = match len {
+        0 => None,
+        _ => Some(state.selected().unwrap_or(0).min(len - 1)),
+    };
+    state.select(selected);
+}
+
+fn popup_area(area: Rect) -> Rect {
+    let [area] = Layout::vertical([Constraint::Percentage(80)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::horizontal([Constraint::Percentage(80)])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
+
+/// Opens the full-screen view of the profiles, their paths and the backup
+/// history.
+pub fn run() -> Result<(), Box<dyn std::error::Error>> {
+    if !io::stdout().is_terminal() {
+        return Err("'fuxi tui' needs an interactive terminal.".into());
+    }
+    let mut app = App::new()?;
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,156 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::cfg::{FuxiConfig, get_config_path, get_profile_entries, profile_names, read_config};
+use crate::manifest::repo_relative_path;
+
+/// Keys older configs may still use for a setting that has a new name.
+const RENAMED_KEYS: [&str; 1] = ["github_repo"];
+
+/// Settings in the config file at `path` that fuxi doesn't know, usually
+/// typos, which would otherwise be ignored without a word.
+fn unknown_keys(
+    path: &Path,
+    config: &FuxiConfig,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let file: toml::Table = toml::from_str(&fs::read_to_string(path)?)?;
+    let known = serde_json::to_value(config)?;
+    let Some(known) = known.as_object() else {
+        return Ok(Vec::new());
+    };
+    let mut unknown: Vec<String> = file
+        .keys()
+        .filter(|key| !known.contains_key(*key) && !RENAMED_KEYS.contains(&key.as_str()))
+        .cloned()
+        .collect();
+    unknown.sort();
+    Ok(unknown)
+}
+
+/// The problems of the config file at `path`: why it can't be loaded, with
+/// the key that's wrong, or else the settings it has that fuxi doesn't know.
+pub fn config_problems(path: &Path) -> Vec<String> {
+    if !path.exists() {
+        return Vec::new();
+    }
+    match read_config(path) {
+        Ok(config) => match unknown_keys(path, &config) {
+            Ok(keys) => keys
+                .iter()
+                .map(|key| format!("unknown setting '{}'.", key))
+                .collect(),
+            Err(e) => vec![e.to_string()],
+        },
+        Err(e) => vec![e.to_string()],
+    }
+}
+
+/// Checks the paths of `profile` and prints each problem. Returns how many
+/// there were.
+fn validate_profile(config: &FuxiConfig, profile: &str) -> usize {
+    let home = dirs::home_dir().unwrap_or_default();
+    let entries: Vec<_> = get_profile_entries(config, profile)
+        .iter()
+        .filter(|entry| entry.is_active())
+        .collect();
+    let mut problems = Vec::new();
+
+    let mut stored_as: HashMap<_, &str> = HashMap::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let source = Path::new(&entry.source);
+        for other in &entries[..i] {
+            let other_source = Path::new(&other.source);
+            if source == other_source {
+                problems.push(format!("{} is listed twice.", entry.source));
+            } else if source.starts_with(other_source) {
+                problems.push(format!(
+                    "{} is inside {}, which is already tracked.",
+                    entry.source, other.source
+                ));
+            } else if other_source.starts_with(source) {
+                problems.push(format!(
+                    "{} is inside {}, which is already tracked.",
+                    other.source, entry.source
+                ));
+            }
+        }
+
+        let name = repo_relative_path(source);
+        match stored_as.get(&name) {
+            Some(other) if *other != entry.source => problems.push(format!(
+                "{} and {} would both be stored as '{}' in the repository.",
+                other,
+                entry.source,
+                name.display()
+            )),
+            Some(_) => {}
+            None => {
+                stored_as.insert(name, &entry.source);
+            }
+        }
+
+        if !source.exists() {
+            problems.push(format!("{} does not exist.", entry.source));
+        }
+        if !source.starts_with(&home) && !entry.sudo.unwrap_or(false) {
+            problems.push(format!(
+                "{} is outside your home directory, set sudo = true if only root can read it.",
+                entry.source
+            ));
+        }
+    }
+
+    for problem in &problems {
+        println!("Profile '{}': {}", profile, problem);
+    }
+    problems.len()
+}
+
+/// Checks every profile's paths and the config file's settings, printing each
+/// problem found. Returns how many there were.
+pub fn validate(config: &FuxiConfig) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut problems = 0;
+    for profile in profile_names(config) {
+        problems += validate_profile(config, &profile);
+    }
+    for problem in config_problems(&get_config_path()?) {
+        println!("Config: {}", problem);
+        problems += 1;
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(content: &str) -> Vec<String> {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("config.toml");
+        fs::write(&path, content).unwrap();
+        config_problems(&path)
+    }
+
+    #[test]
+    fn reports_invalid_setting_values() {
+        let problems = problems("conflict_strategy = \"newer\"\n");
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("conflict_strategy"), "{}", problems[0]);
+    }
+
+    #[test]
+    fn reports_unknown_path_entry_keys() {
+        let problems =
+            problems("[profiles]\nmain = [{ source = \"/etc/hostname\", exlude = [\"x\"] }]\n");
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("exlude"), "{}", problems[0]);
+        assert!(problems[0].contains("profiles.main"), "{}", problems[0]);
+    }
+
+    #[test]
+    fn reports_unknown_settings() {
+        assert_eq!(
+            problems("git_brnach = \"main\"\n"),
+            ["unknown setting 'git_brnach'."]
+        );
+    }
+}
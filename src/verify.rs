@@ -0,0 +1,71 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::object_store::rehash_tree;
+
+pub const CHECKSUM_FILE: &str = ".fuxi-checksums.toml";
+
+/// SHA-256 of every file in a profile directory when it was backed up.
+#[derive(Debug, Serialize, Deserialize)]
+struct Checksums {
+    files: BTreeMap<String, String>,
+}
+
+fn profile_hashes(
+    profile_dir: &Path,
+) -> Result<BTreeMap<String, String>, Box<dyn std::error::Error>> {
+    // bit rot doesn't touch the mtime, so the hash cache can't be trusted here
+    let mut files = rehash_tree(profile_dir)?;
+    files.remove(CHECKSUM_FILE);
+    Ok(files)
+}
+
+/// Records the content hash of every file the backup left in `profile_dir`.
+pub fn save_checksums(profile_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let checksums = Checksums {
+        files: profile_hashes(profile_dir)?,
+    };
+    fs::write(
+        profile_dir.join(CHECKSUM_FILE),
+        toml::to_string_pretty(&checksums)?,
+    )?;
+    Ok(())
+}
+
+/// Rehashes the files of `profile_dir` and prints each one that no longer
+/// matches its recorded checksum. Returns the number of problems found.
+pub fn verify_profile(profile_dir: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+    let path = profile_dir.join(CHECKSUM_FILE);
+    if !path.exists() {
+        return Err(format!(
+            "No checksums were recorded for {}. Run 'fuxi backup' to write them.",
+            profile_dir.display()
+        )
+        .into());
+    }
+    let recorded: Checksums = toml::from_str(&fs::read_to_string(path)?)?;
+    let current = profile_hashes(profile_dir)?;
+
+    let mut problems = 0;
+    for (file, hash) in &recorded.files {
+        match current.get(file) {
+            Some(current) if current == hash => {}
+            Some(_) => {
+                println!("Modified: {}", file);
+                problems += 1;
+            }
+            None => {
+                println!("Missing:  {}", file);
+                problems += 1;
+            }
+        }
+    }
+    for file in current.keys() {
+        if !recorded.files.contains_key(file) {
+            println!("Unknown:  {}", file);
+            problems += 1;
+        }
+    }
+    Ok(problems)
+}
@@ -0,0 +1,160 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Component, Path, PathBuf, Prefix},
+    process::{Command, Stdio},
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crate::style;
+
+/// The `use_shadow_copies` setting, recorded when the config is loaded.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// A Volume Shadow Copy: a read-only, point-in-time view of a volume that
+/// files held open by other programs can be read from.
+struct ShadowCopy {
+    id: String,
+    /// `\\?\GLOBALROOT\Device\HarddiskVolumeShadowCopyN`
+    device: String,
+}
+
+/// Shadow copies by volume, created on the first locked file of each.
+/// `None` when creating one failed, it isn't tried again.
+static SHADOWS: Mutex<Option<HashMap<String, Option<ShadowCopy>>>> = Mutex::new(None);
+
+pub fn powershell(script: &str) -> io::Result<String> {
+    let output = Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", script])
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Shadow copies can only be created from an elevated prompt.
+fn is_elevated() -> bool {
+    static ELEVATED: OnceLock<bool> = OnceLock::new();
+    *ELEVATED.get_or_init(|| {
+        // `net session` is refused without administrator rights
+        Command::new("net")
+            .arg("session")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    })
+}
+
+/// The drive `path` is on, e.g. `C:\`.
+fn volume_of(path: &Path) -> Option<String> {
+    match path.components().next()? {
+        Component::Prefix(prefix) => match prefix.kind() {
+            Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+                Some(format!("{}:\\", letter as char))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn create(volume: &str) -> io::Result<ShadowCopy> {
+    println!(
+        "Creating a shadow copy of {} to read locked files...",
+        volume
+    );
+    let output = powershell(&format!(
+        "$r = (Get-WmiObject -List Win32_ShadowCopy).Create('{}', 'ClientAccessible'); \
+         if ($r.ReturnValue -ne 0) {{ throw \"Win32_ShadowCopy.Create returned $($r.ReturnValue)\" }}; \
+         $s = Get-WmiObject Win32_ShadowCopy | Where-Object {{ $_.ID -eq $r.ShadowID }}; \
+         \"$($s.ID)|$($s.DeviceObject)\"",
+        volume
+    ))?;
+    let (id, device) = output
+        .split_once('|')
+        .filter(|(id, device)| !id.is_empty() && !device.is_empty())
+        .ok_or_else(|| io::Error::other(format!("unexpected output: {}", output)))?;
+    Ok(ShadowCopy {
+        id: id.to_string(),
+        device: device.to_string(),
+    })
+}
+
+/// Copies `src` from a shadow copy of its volume, for files other programs
+/// hold locked. `None` when shadow copies aren't enabled, available or
+/// running elevated.
+pub fn copy_from_shadow(src: &Path, dst: &Path) -> Option<io::Result<()>> {
+    if !cfg!(windows) || !ENABLED.load(Ordering::Relaxed) || !is_elevated() {
+        return None;
+    }
+    let volume = volume_of(src)?;
+    let mut shadows = SHADOWS.lock().ok()?;
+    let shadow = shadows
+        .get_or_insert_with(HashMap::new)
+        .entry(volume.clone())
+        .or_insert_with(|| match create(&volume) {
+            Ok(shadow) => Some(shadow),
+            Err(e) => {
+                println!(
+                    "{}",
+                    style::warning(format!(
+                        "Warning: Could not create a shadow copy of {}: {}",
+                        volume, e
+                    ))
+                );
+                None
+            }
+        })
+        .as_ref()?;
+
+    let rel: PathBuf = src
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect();
+    let shadowed = PathBuf::from(format!("{}\\{}", shadow.device, rel.display()));
+    Some(fs::copy(shadowed, dst).map(|_| ()))
+}
+
+/// Deletes the shadow copies created by this process. They'd otherwise
+/// take up disk space until Windows removes them.
+fn release() {
+    let Some(shadows) = SHADOWS.lock().ok().and_then(|mut shadows| shadows.take()) else {
+        return;
+    };
+    for shadow in shadows.into_values().flatten() {
+        let deleted = powershell(&format!(
+            "Get-WmiObject Win32_ShadowCopy | Where-Object {{ $_.ID -eq '{}' }} | ForEach-Object {{ $_.Delete() }}",
+            shadow.id
+        ));
+        if let Err(e) = deleted {
+            println!(
+                "{}",
+                style::warning(format!(
+                    "Warning: Could not delete shadow copy {}, remove it with 'vssadmin delete shadows /shadow={}': {}",
+                    shadow.id, shadow.id, e
+                ))
+            );
+        }
+    }
+}
+
+/// Releases the shadow copies when dropped, however the copy ends.
+pub struct ShadowCopies;
+
+impl Drop for ShadowCopies {
+    fn drop(&mut self) {
+        release();
+    }
+}
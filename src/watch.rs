@@ -0,0 +1,226 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::mpsc::{RecvTimeoutError, channel},
+    time::{Duration, Instant},
+};
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::backup::create_backup;
+use crate::cfg::{FuxiConfig, get_selected_profile_paths, load_config, repo_path};
+use crate::copy::is_skipped_vcs_dir;
+use crate::github::check_before_first_push;
+use crate::history;
+use crate::lock::RunLock;
+use crate::notification::{notify_failure, notify_success};
+use crate::push_policy::push_due;
+use crate::storage::snapshot_and_push;
+use crate::style;
+use crate::template::commit_message;
+use crate::webhook::report_push;
+
+const DEFAULT_QUIET_PERIOD_SECS: u64 = 30;
+const DEFAULT_CHURN_LIMIT: usize = 50;
+
+// editor swap files, lock files and the like never warrant a backup
+const DEFAULT_IGNORE: [&str; 7] = [
+    "*.swp",
+    "*.swx",
+    "*~",
+    ".#*",
+    "*.tmp",
+    "*.lock",
+    ".DS_Store",
+];
+
+/// Matches a file name against a pattern where `*` matches any run of characters.
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let Some(name) = name.strip_prefix(prefix) else {
+                return false;
+            };
+            if rest.is_empty() {
+                return true;
+            }
+            (0..=name.len())
+                .filter(|&i| name.is_char_boundary(i))
+                .any(|i| wildcard_match(rest, &name[i..]))
+        }
+    }
+}
+
+/// Whether a change to `path` is noise, like an editor's swap file.
+pub fn is_ignored(config: &FuxiConfig, path: &Path) -> bool {
+    if path.components().any(|c| is_skipped_vcs_dir(c.as_os_str())) {
+        return true;
+    }
+    let Some(name) = path.file_name().map(|n| n.to_string_lossy()) else {
+        return false;
+    };
+    DEFAULT_IGNORE
+        .iter()
+        .copied()
+        .chain(config.watch_ignore.iter().flatten().map(|p| p.as_str()))
+        .any(|pattern| wildcard_match(pattern, &name))
+}
+
+/// Backs up the tracked paths as an automatic backup, for `command`, and
+/// pushes them when `push_policy` says so, or with `push` without one.
+/// Returns the backup ID and whether it was pushed.
+pub fn backup_and_push(
+    command: &str,
+    push: bool,
+) -> Result<(String, bool), Box<dyn std::error::Error>> {
+    // reload so paths added while watching are picked up
+    let mut config = load_config()?;
+    history::start(
+        format!("{} backup", command),
+        config.selected_profile.clone(),
+    );
+    let started = Instant::now();
+    let result = automatic_backup(command, &mut config, push);
+    match &result {
+        Ok(_) => history::finish(started.elapsed(), "ok", None),
+        Err(e) => history::finish(started.elapsed(), "failed", Some(e.to_string())),
+    }
+    result
+}
+
+fn automatic_backup(
+    command: &str,
+    config: &mut FuxiConfig,
+    push: bool,
+) -> Result<(String, bool), Box<dyn std::error::Error>> {
+    // a manual or scheduled run goes first, this one follows
+    let _lock = RunLock::acquire(command, true)?;
+    let backup_id = create_backup(config, Some("Automatic backup".to_string()))?;
+    let push = push_due(config, push);
+    if push {
+        let repo_path = PathBuf::from(repo_path(config)?);
+        let result = check_before_first_push(config, &repo_path, false).and_then(|_| {
+            snapshot_and_push(
+                config,
+                Some(commit_message(
+                    config,
+                    Some(format!("Automatic backup {}", backup_id)),
+                    Some(&backup_id),
+                    get_selected_profile_paths(config).len(),
+                )?),
+                config.divergence_strategy.unwrap_or_default().unattended(),
+                false,
+            )
+        });
+        report_push(
+            config,
+            &repo_path,
+            Some(&backup_id),
+            get_selected_profile_paths(config).len(),
+            result.as_ref().err().map(|e| e.to_string()).as_deref(),
+        );
+        result?;
+    }
+    Ok((backup_id, push))
+}
+
+/// Watches the tracked paths and backs up once they have been quiet for the
+/// configured period. Files that change more than `watch_churn_limit` times in
+/// one period are muted so they can't postpone backups forever.
+pub fn watch(config: &FuxiConfig, push: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let quiet_period = Duration::from_secs(
+        config
+            .watch_quiet_period_secs
+            .unwrap_or(DEFAULT_QUIET_PERIOD_SECS),
+    );
+    let churn_limit = config.watch_churn_limit.unwrap_or(DEFAULT_CHURN_LIMIT);
+
+    let (tx, rx) = channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    let mut watched = 0;
+    for path in get_selected_profile_paths(config) {
+        let path = PathBuf::from(path);
+        if !path.exists() {
+            println!(
+                "{}",
+                style::warning(format!(
+                    "Warning: Not watching missing path {}",
+                    path.display()
+                ))
+            );
+            continue;
+        }
+        watcher.watch(&path, RecursiveMode::Recursive)?;
+        watched += 1;
+    }
+    if watched == 0 {
+        return Err("No existing paths to watch in the selected profile.".into());
+    }
+    println!(
+        "Watching {} path(s), backing up after {}s without changes. Press Ctrl+C to stop.",
+        watched,
+        quiet_period.as_secs()
+    );
+
+    let mut last_change: Option<Instant> = None;
+    let mut changes: HashMap<PathBuf, usize> = HashMap::new();
+    let mut muted: HashSet<PathBuf> = HashSet::new();
+
+    loop {
+        let timeout = match last_change {
+            Some(at) => quiet_period.saturating_sub(at.elapsed()),
+            None => Duration::from_secs(3600),
+        };
+
+        match rx.recv_timeout(timeout) {
+            Ok(Ok(event)) => {
+                if matches!(event.kind, EventKind::Access(_)) {
+                    continue;
+                }
+                for path in event.paths {
+                    if muted.contains(&path) || is_ignored(config, &path) {
+                        continue;
+                    }
+                    let count = changes.entry(path.clone()).or_insert(0);
+                    *count += 1;
+                    if *count > churn_limit {
+                        println!(
+                            "{}",
+                            style::warning(format!(
+                                "Warning: {} changes too often, ignoring it until the next backup.",
+                                path.display()
+                            ))
+                        );
+                        muted.insert(path);
+                    } else {
+                        last_change = Some(Instant::now());
+                    }
+                }
+            }
+            Ok(Err(e)) => println!("{}", style::warning(format!("Warning: Watch error: {}", e))),
+            Err(RecvTimeoutError::Timeout) => {
+                if last_change.take().is_none() {
+                    continue;
+                }
+                println!(
+                    "Changes detected in {} file(s), backing up...",
+                    changes.len()
+                );
+                match backup_and_push("watch", push) {
+                    Ok((backup_id, _)) => {
+                        notify_success(config, &format!("Automatic backup {} done.", backup_id))
+                    }
+                    Err(e) => {
+                        println!("Error during automatic backup: {}", e);
+                        notify_failure(config, &format!("Automatic backup failed: {}", e));
+                    }
+                }
+                changes.clear();
+                muted.clear();
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use serde_json::{Value, json};
+
+use crate::cfg::{FuxiConfig, get_machine_name};
+use crate::git::head_commit;
+use crate::style;
+
+/// Discord and Slack only accept their own message format, everything else
+/// gets the raw payload.
+fn format_for(url: &str, payload: &Value, text: &str) -> Value {
+    if url.contains("discord.com/api/webhooks") || url.contains("discordapp.com/api/webhooks") {
+        json!({ "content": text })
+    } else if url.contains("hooks.slack.com") {
+        json!({ "text": text })
+    } else {
+        payload.clone()
+    }
+}
+
+fn post(url: &str, body: &Value) -> Result<(), Box<dyn std::error::Error>> {
+    let mut data = tempfile::NamedTempFile::new()?;
+    data.write_all(body.to_string().as_bytes())?;
+    let mut child = Command::new("curl")
+        .args([
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--max-time",
+            "10",
+            "-H",
+            "Content-Type: application/json",
+            "--config",
+            "-",
+        ])
+        .arg("--data-binary")
+        .arg(format!("@{}", data.path().display()))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run curl (is it installed?): {}", e))?;
+    // webhook URLs carry their token, so the URL goes through stdin to stay
+    // out of the process list
+    let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+    child
+        .stdin
+        .take()
+        .ok_or("Failed to open curl stdin")?
+        .write_all(format!("url = \"{}\"\n", escape(url)).as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr)
+            .trim()
+            .to_string()
+            .into());
+    }
+    Ok(())
+}
+
+/// Reports the outcome of a push to the configured `webhook_url`, if any.
+/// Failing to deliver only prints a warning.
+pub fn report_push(
+    config: &FuxiConfig,
+    repo_path: &Path,
+    backup_id: Option<&str>,
+    paths: usize,
+    error: Option<&str>,
+) {
+    let Some(url) = &config.webhook_url else {
+        return;
+    };
+
+    let head = head_commit(repo_path);
+    let commit = head.as_ref().map(|(id, _)| id.clone());
+    let files_changed = error
+        .is_none()
+        .then_some(head.map(|(_, files)| files))
+        .flatten();
+
+    let profile = config.selected_profile.clone().unwrap_or_default();
+    let machine = get_machine_name(config);
+    let payload = json!({
+        "event": "push",
+        "status": if error.is_none() { "success" } else { "failure" },
+        "profile": profile,
+        "machine": machine,
+        "backup_id": backup_id,
+        "paths": paths,
+        "files_changed": files_changed,
+        "commit": commit,
+        "error": error,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    });
+    let text = match error {
+        None => format!(
+            "fuxi: pushed {} of profile '{}' from {} ({} file(s) changed, commit {})",
+            backup_id.unwrap_or("changes"),
+            profile,
+            machine,
+            files_changed.unwrap_or(0),
+            commit
+                .as_deref()
+                .map_or("unknown", |c| &c[..c.len().min(8)])
+        ),
+        Some(error) => format!(
+            "fuxi: pushing profile '{}' from {} failed: {}",
+            profile, machine, error
+        ),
+    };
+
+    if let Err(e) = post(url, &format_for(url, &payload, &text)) {
+        println!(
+            "{}",
+            style::warning(format!("Warning: Could not send webhook: {}", e))
+        );
+    }
+}
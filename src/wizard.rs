@@ -0,0 +1,222 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use crate::backup::create_backup;
+use crate::bootstrap::{default_bootstrap_path, restore_from_clone};
+use crate::cfg::{FuxiConfig, PathEntry, adopt_repo_config, load_repo_config, save_config};
+use crate::cli::{confirm, prompt};
+use crate::git::{clone_or_init, remote_branches, remote_url};
+use crate::github::{ensure_private, gh_available, gh_create_private, gh_login, github_slug};
+use crate::sensitive::sensitive_match;
+use crate::storage::snapshot_and_push;
+use crate::style;
+use crate::template::commit_message;
+
+/// Dotfiles offered on the first run when they exist, relative to the home
+/// directory.
+const COMMON_DOTFILES: [&str; 22] = [
+    ".bashrc",
+    ".bash_profile",
+    ".zshrc",
+    ".profile",
+    ".inputrc",
+    ".gitconfig",
+    ".vimrc",
+    ".tmux.conf",
+    ".ssh/config",
+    ".config/git",
+    ".config/nvim",
+    ".config/helix",
+    ".config/fish",
+    ".config/starship.toml",
+    ".config/alacritty",
+    ".config/kitty",
+    ".config/wezterm",
+    ".config/i3",
+    ".config/hypr",
+    ".config/Code/User/settings.json",
+    "Library/Application Support/Code/User/settings.json",
+    "AppData/Roaming/Code/User/settings.json",
+];
+
+/// The common dotfiles present in the home directory, leaving out anything
+/// that shouldn't be pushed in plain text.
+fn discover_dotfiles() -> Vec<PathBuf> {
+    let Some(home) = dirs::home_dir() else {
+        return Vec::new();
+    };
+    COMMON_DOTFILES
+        .iter()
+        .map(|rel| home.join(rel))
+        .filter(|path| path.exists() && sensitive_match(path).is_none())
+        .collect()
+}
+
+/// Asks `question`, answering `default` when the answer is left empty.
+fn ask(question: &str, default: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let answer = prompt(&format!("{} [{}]", question, default))?;
+    Ok(if answer.is_empty() {
+        default.to_string()
+    } else {
+        answer
+    })
+}
+
+/// Tries to reach `url`, helping with logging in to or creating a GitHub
+/// repository when it can't. Returns whether the remote was reached.
+fn connect(url: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    loop {
+        println!("Connecting to {}...", url);
+        if remote_branches(url).is_some() {
+            println!("Connected.");
+            return Ok(true);
+        }
+        println!("Could not connect to {}.", url);
+
+        match github_slug(url) {
+            Some(slug) if gh_available() => {
+                if confirm("Log in to GitHub with the gh CLI?")? && gh_login()? {
+                    continue;
+                }
+                if confirm(&format!(
+                    "Create github.com/{} as a private repository?",
+                    slug
+                ))? && gh_create_private(&slug)?
+                {
+                    continue;
+                }
+            }
+            _ => println!(
+                "SSH remotes are authenticated with ssh-agent or the keys in ~/.ssh, HTTPS remotes with git's credential helper. Make sure the repository exists and you can push to it."
+            ),
+        }
+        if !confirm("Try again?")? {
+            println!(
+                "Continuing offline, the repository is pushed once the remote can be reached."
+            );
+            return Ok(false);
+        }
+    }
+}
+
+/// Sets up fuxi step by step: the remote, the local repository, a first
+/// profile with the dotfiles found on this machine, and the first backup.
+pub fn run(config: &mut FuxiConfig) -> Result<(), Box<dyn std::error::Error>> {
+    println!("Welcome to fuxi! Let's set up your backups.\n");
+
+    println!("1. Backup repository");
+    let repo = loop {
+        let repo = prompt("Remote repository URL, or username/repo-name for GitHub")?;
+        if !repo.is_empty() {
+            break repo;
+        }
+    };
+    let url = remote_url(&repo);
+    connect(&url)?;
+    config.public_repo_ok = None;
+    ensure_private(config, &url, false)?;
+
+    let default_path = default_bootstrap_path().ok_or("Could not determine data directory")?;
+    let path = PathBuf::from(ask(
+        "Local backup repository path",
+        &default_path.to_string_lossy(),
+    )?);
+    config.backup_repo_path = Some(path.to_string_lossy().to_string());
+    config.remote_url = Some(url.clone());
+    save_config(config)?;
+
+    let is_empty = fs::read_dir(&path).map_or(true, |mut dir| dir.next().is_none());
+    if is_empty && clone_or_init(&url, &path, &config.git_branch)? {
+        if let Some(repo_config) = load_repo_config(&path)? {
+            adopt_repo_config(config, repo_config);
+            save_config(config)?;
+            println!("Imported the fuxi configuration stored in the repository.");
+        }
+        // the repository already has backups, from another machine
+        restore_from_clone(config, &path)?;
+        if config.selected_profile.is_some() {
+            println!("\nSetup complete.");
+            return Ok(());
+        }
+    }
+
+    println!("\n2. Profile");
+    let profile = ask("Name of your first profile", "main")?;
+    config.selected_profile = Some(profile.clone());
+
+    println!("\n3. Dotfiles");
+    let found = discover_dotfiles();
+    let mut selected = Vec::new();
+    if found.is_empty() {
+        println!("No common dotfiles found, add paths later with 'fuxi path add <PATH>'.");
+    } else {
+        println!("Found these on this machine:");
+        for (i, path) in found.iter().enumerate() {
+            println!("  {}: {}", i + 1, style::path(path.display()));
+        }
+        let answer = ask(
+            "Which ones to back up? Numbers separated by spaces, 'all' or 'none'",
+            "all",
+        )?;
+        selected = match answer.as_str() {
+            "all" => found.clone(),
+            "none" => Vec::new(),
+            _ => answer
+                .split([' ', ','])
+                .filter(|n| !n.is_empty())
+                .map(|n| {
+                    n.parse::<usize>()
+                        .ok()
+                        .and_then(|n| found.get(n.wrapping_sub(1)))
+                        .cloned()
+                        .ok_or_else(|| format!("'{}' is not one of the numbers listed.", n))
+                })
+                .collect::<Result<_, _>>()?,
+        };
+    }
+    let paths = config
+        .profiles
+        .get_or_insert_with(HashMap::new)
+        .entry(profile.clone())
+        .or_default();
+    for path in &selected {
+        let path = path.to_string_lossy().to_string();
+        if !paths.iter().any(|entry| entry.source == path) {
+            paths.push(PathEntry::new(path));
+        }
+    }
+    save_config(config)?;
+    println!(
+        "Profile '{}' tracks {} path(s). Add more with 'fuxi path add <PATH>'.",
+        profile,
+        paths_len(config, &profile)
+    );
+
+    println!("\n4. First backup");
+    if paths_len(config, &profile) == 0 || !confirm("Back up and push now?")? {
+        println!("\nSetup complete. Run 'fuxi backup --push' when you're ready.");
+        return Ok(());
+    }
+    let backup_id = create_backup(config, None)?;
+    let message = commit_message(config, None, Some(&backup_id), paths_len(config, &profile))?;
+    match snapshot_and_push(
+        config,
+        Some(message),
+        config.divergence_strategy.unwrap_or_default(),
+        false,
+    ) {
+        Ok(()) => println!("{}", style::success("Backup pushed successfully!")),
+        Err(e) => println!("Error during push: {}", e),
+    }
+    println!(
+        "\nSetup complete. Back up again with 'fuxi backup --push', or automatically with 'fuxi watch'."
+    );
+    Ok(())
+}
+
+fn paths_len(config: &FuxiConfig, profile: &str) -> usize {
+    config
+        .profiles
+        .as_ref()
+        .and_then(|profiles| profiles.get(profile))
+        .map_or(0, Vec::len)
+}
@@ -0,0 +1,248 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::copy::collect_files;
+use crate::manifest::ManifestEntry;
+use crate::overlay::join_rel;
+use crate::style;
+
+pub const XATTRS_FILE: &str = ".fuxi-xattrs.toml";
+
+/// Where `icacls /save` output is kept on Windows, one file per entry.
+const ACLS_DIR: &str = ".fuxi-acls";
+
+/// Set by macOS on downloaded files, it shouldn't follow them to another machine.
+const QUARANTINE: &str = "com.apple.quarantine";
+
+/// Extended attributes of the files of `xattrs` entries, hex encoded, by path
+/// in the profile. On Linux these include file capabilities
+/// (`security.capability`) and POSIX ACLs (`system.posix_acl_*`).
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct Attributes {
+    files: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn key(repo_path: &str, rel: &Path) -> String {
+    std::iter::once(repo_path.to_string())
+        .chain(
+            rel.components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string()),
+        )
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(unix)]
+fn read_attributes(path: &Path) -> BTreeMap<String, String> {
+    let Ok(names) = xattr::list(path) else {
+        return BTreeMap::new();
+    };
+    names
+        .filter_map(|name| {
+            let value = xattr::get(path, &name).ok()??;
+            let name = name.into_string().ok()?;
+            (name != QUARANTINE).then(|| (name, hex(&value)))
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+fn read_attributes(_path: &Path) -> BTreeMap<String, String> {
+    BTreeMap::new()
+}
+
+#[cfg(unix)]
+fn write_attribute(path: &Path, name: &str, value: &[u8]) -> std::io::Result<()> {
+    xattr::set(path, name, value)
+}
+
+#[cfg(not(unix))]
+fn write_attribute(_path: &Path, _name: &str, _value: &[u8]) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Removes the quarantine flag macOS puts on downloaded files, so restored
+/// configs and scripts aren't blocked by Gatekeeper.
+#[cfg(unix)]
+pub fn strip_quarantine(path: &Path) {
+    if cfg!(target_os = "macos") {
+        let _ = xattr::remove(path, QUARANTINE);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn strip_quarantine(_path: &Path) {}
+
+fn acl_file(root: &Path, entry: &ManifestEntry) -> PathBuf {
+    root.join(ACLS_DIR).join(format!("{}.acl", entry.repo_path))
+}
+
+/// Records the extended attributes of the live files of every `xattrs`
+/// entry in `root`, or on Windows their ACLs with `icacls /save`.
+pub fn record_attributes(
+    root: &Path,
+    entries: &[ManifestEntry],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let acls_dir = root.join(ACLS_DIR);
+    if acls_dir.exists() {
+        fs::remove_dir_all(&acls_dir)?;
+    }
+
+    let mut attributes = Attributes::default();
+    for entry in entries.iter().filter(|entry| entry.xattrs) {
+        let source = Path::new(&entry.source);
+        if cfg!(windows) {
+            let file = acl_file(root, entry);
+            fs::create_dir_all(file.parent().unwrap_or(&acls_dir))?;
+            let saved = Command::new("icacls")
+                .arg(source)
+                .arg("/save")
+                .arg(&file)
+                .args(["/T", "/C", "/Q"])
+                .status()
+                .is_ok_and(|status| status.success());
+            if !saved {
+                println!(
+                    "{}",
+                    style::warning(format!(
+                        "Warning: Could not save the ACLs of {}",
+                        source.display()
+                    ))
+                );
+            }
+            continue;
+        }
+
+        let mut paths = vec![PathBuf::new()];
+        if source.is_dir() {
+            for file in collect_files(source)? {
+                // directories carry default ACLs
+                paths.extend(
+                    file.ancestors()
+                        .skip(1)
+                        .filter(|dir| !dir.as_os_str().is_empty())
+                        .map(Path::to_path_buf),
+                );
+                paths.push(file);
+            }
+        }
+        paths.sort();
+        paths.dedup();
+        for rel in paths {
+            let found = read_attributes(&join_rel(source, &rel));
+            if !found.is_empty() {
+                attributes.files.insert(key(&entry.repo_path, &rel), found);
+            }
+        }
+    }
+
+    let path = root.join(XATTRS_FILE);
+    if attributes.files.is_empty() {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        return Ok(());
+    }
+    fs::write(path, toml::to_string_pretty(&attributes)?)?;
+    Ok(())
+}
+
+/// Sets the attributes recorded for `entry` back on the `applied` files and
+/// the directories they're in. Attributes that can't be set, such as
+/// capabilities without root, are warned about.
+pub fn restore_attributes(
+    root: &Path,
+    entry: &ManifestEntry,
+    applied: &[PathBuf],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !entry.xattrs || applied.is_empty() {
+        return Ok(());
+    }
+    if cfg!(windows) {
+        let file = acl_file(root, entry);
+        let Some(parent) = Path::new(&entry.source).parent() else {
+            return Ok(());
+        };
+        if !file.exists() {
+            return Ok(());
+        }
+        // icacls restores paths relative to the directory it's given
+        let restored = Command::new("icacls")
+            .arg(parent)
+            .arg("/restore")
+            .arg(&file)
+            .args(["/C", "/Q"])
+            .status()
+            .is_ok_and(|status| status.success());
+        if !restored {
+            println!(
+                "{}",
+                style::warning(format!(
+                    "Warning: Could not restore the ACLs of {}",
+                    entry.source
+                ))
+            );
+        }
+        return Ok(());
+    }
+
+    let path = root.join(XATTRS_FILE);
+    if !path.exists() {
+        return Ok(());
+    }
+    let attributes: Attributes = toml::from_str(&fs::read_to_string(path)?)?;
+    let source = Path::new(&entry.source);
+    let mut targets: Vec<PathBuf> = applied.to_vec();
+    for file in applied {
+        targets.extend(
+            file.ancestors()
+                .skip(1)
+                .take_while(|dir| dir.starts_with(source))
+                .map(Path::to_path_buf),
+        );
+    }
+    targets.sort();
+    targets.dedup();
+
+    for live in targets {
+        let Ok(rel) = live.strip_prefix(source) else {
+            continue;
+        };
+        let Some(recorded) = attributes.files.get(&key(&entry.repo_path, rel)) else {
+            continue;
+        };
+        for (name, value) in recorded {
+            let value = unhex(value)
+                .ok_or_else(|| format!("{} has an invalid value for {}", XATTRS_FILE, name))?;
+            if let Err(e) = write_attribute(&live, name, &value) {
+                println!(
+                    "{}",
+                    style::warning(format!(
+                        "Warning: Could not restore {} on {}: {}",
+                        name,
+                        live.display(),
+                        e
+                    ))
+                );
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,191 @@
+//! Walks every subcommand `fuxi --help` advertises, aliases included, and runs
+//! it against an empty home, so a command that parses but has no handler in
+//! `main.rs` fails here instead of panicking for a user.
+
+use std::{
+    path::Path,
+    process::{Command, Output, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+const FUXI: &str = env!("CARGO_BIN_EXE_fuxi");
+
+/// Commands that change things outside the home directory, like the system
+/// scheduler or installed packages.
+const SKIP: [&str; 3] = ["schedule install", "schedule remove", "packages apply"];
+
+/// How long a command may run before it's taken to be serving, like `daemon`.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn fuxi(home: &Path, args: &[String]) -> Command {
+    let mut command = Command::new(FUXI);
+    command
+        .args(args)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("XDG_CACHE_HOME", home.join(".cache"))
+        .env("XDG_DATA_HOME", home.join(".local/share"))
+        .env("XDG_STATE_HOME", home.join(".local/state"))
+        .env("XDG_RUNTIME_DIR", home)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    command
+}
+
+fn help(home: &Path, path: &[String]) -> String {
+    let mut args = path.to_vec();
+    args.push("--help".to_string());
+    let output = fuxi(home, &args).output().unwrap();
+    assert!(output.status.success(), "fuxi {} failed", args.join(" "));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// The names and aliases listed under `Commands:`.
+fn subcommands(help: &str) -> Vec<(String, Vec<String>)> {
+    help.lines()
+        .skip_while(|line| *line != "Commands:")
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| {
+            let name = line.split_whitespace().next()?.to_string();
+            let aliases = line
+                .split_once("[aliases: ")
+                .map(|(_, aliases)| {
+                    aliases
+                        .trim_end_matches(']')
+                        .split(", ")
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default();
+            (name != "help").then_some((name, aliases))
+        })
+        .collect()
+}
+
+/// A value clap accepts for the argument or option documented on the line
+/// starting with `prefix`: its first possible value, or else a path that
+/// doesn't exist.
+fn value(help: &str, prefix: &str, home: &Path) -> String {
+    help.lines()
+        .map(str::trim_start)
+        .find(|line| line.starts_with(prefix))
+        .and_then(|line| line.split_once("[possible values: "))
+        .and_then(|(_, values)| values.split([',', ']']).next())
+        .map(str::to_string)
+        .unwrap_or_else(|| home.join("missing").to_string_lossy().to_string())
+}
+
+/// Fills in the required arguments and options from the usage line.
+fn required_args(help: &str, depth: usize, home: &Path) -> Vec<String> {
+    let usage = help
+        .lines()
+        .find_map(|line| line.strip_prefix("Usage: "))
+        .unwrap();
+    let mut args: Vec<String> = Vec::new();
+    for token in usage.split_whitespace().skip(depth + 1) {
+        if token.starts_with('[') {
+            continue;
+        }
+        if token.starts_with("--") {
+            args.push(token.to_string());
+        } else if token.starts_with('<') {
+            let placeholder = token.trim_end_matches("...");
+            let prefix = match args.last() {
+                Some(option) if option.starts_with("--") => format!("{} {}", option, placeholder),
+                _ => placeholder.to_string(),
+            };
+            args.push(value(help, &prefix, home));
+        }
+    }
+    args
+}
+
+/// Runs a command, or kills it once it has run for `TIMEOUT` and returns `None`.
+fn run(mut command: Command) -> Option<Output> {
+    // only stderr is looked at, and a full stdout pipe would block the command
+    let mut child = command.stdout(Stdio::null()).spawn().unwrap();
+    let started = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if started.elapsed() > TIMEOUT {
+            child.kill().unwrap();
+            child.wait().unwrap();
+            return None;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    Some(child.wait_with_output().unwrap())
+}
+
+fn walk(home: &Path, path: Vec<String>, failures: &mut Vec<String>) {
+    let help = help(home, &path);
+    let subcommands = subcommands(&help);
+    if !subcommands.is_empty() {
+        for (name, aliases) in subcommands {
+            let mut command = path.clone();
+            command.push(name);
+            let about = help_about(home, &command);
+            for alias in aliases {
+                let mut aliased = path.clone();
+                aliased.push(alias);
+                if help_about(home, &aliased) != about {
+                    failures.push(format!(
+                        "fuxi {}: alias of another command",
+                        aliased.join(" ")
+                    ));
+                }
+            }
+            walk(home, command, failures);
+        }
+        return;
+    }
+
+    let name = path.join(" ");
+    if SKIP.contains(&name.as_str()) {
+        return;
+    }
+    let mut args = path.clone();
+    args.extend(required_args(&help, path.len(), home));
+    let Some(mut output) = run(fuxi(home, &args)) else {
+        return;
+    };
+    // arguments that are only required without another one aren't in the
+    // help's usage line, but are in the one clap prints when they're missing
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    if stderr.contains("required arguments were not provided") {
+        args = path.clone();
+        args.extend(required_args(&stderr, path.len(), home));
+        let Some(retried) = run(fuxi(home, &args)) else {
+            return;
+        };
+        output = retried;
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.code() == Some(101) || stderr.contains("panicked") {
+        failures.push(format!("fuxi {}: panicked\n{}", args.join(" "), stderr));
+    } else if stderr.starts_with("error:") {
+        failures.push(format!(
+            "fuxi {}: rejected by clap\n{}",
+            args.join(" "),
+            stderr
+        ));
+    }
+}
+
+fn help_about(home: &Path, path: &[String]) -> String {
+    help(home, path)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[test]
+fn every_command_has_a_handler() {
+    let home = tempfile::tempdir().unwrap();
+    let mut failures = Vec::new();
+    walk(home.path(), Vec::new(), &mut failures);
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}